@@ -5,7 +5,8 @@ use async_trait::async_trait;
 use blockchain_runtime::{
     BlockchainRuntime, EnvironmentState, ExecutionContext, ExecutionInputs, ExecutionResult,
     NetworkMode, RuntimeCapabilities, RuntimeConfig, RuntimeEnvironment, RuntimeEvent,
-    RuntimeMetricDefinition, RuntimeType, StateChange, MetricType,
+    RuntimeMetricDefinition, RuntimeType, MetricType, SecureExecutionContext, SecurityConfig,
+    SecurityEnforcer, SecurityReport, SecurityValidator, SecurityViolation,
 };
 use std::collections::HashMap;
 use std::path::Path;
@@ -66,6 +67,8 @@ impl BlockchainRuntime for MockRuntime {
             state_changes: vec![],
             events: vec![],
             execution_time_ms: 150,
+            security_context: SecureExecutionContext::default(),
+            security_violations: vec![],
         })
     }
 
@@ -130,11 +133,83 @@ impl BlockchainRuntime for MockRuntime {
             supports_function_calls: true,
             supports_state_inspection: true,
             supports_event_monitoring: true,
-            supports_gas_estimation: true,
+            supports_gas_estimation: false,
             supports_time_travel: false,
+            supports_snapshots: false,
+            supports_event_subscriptions: false,
+            supports_access_control: false,
+            supports_tracing: false,
             max_execution_time_seconds: 600,
         }
     }
+
+    async fn execute_secure(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+        _security_config: &SecurityConfig,
+    ) -> Result<ExecutionResult> {
+        self.execute(env, code_path, inputs).await
+    }
+
+    async fn check_reentrancy(
+        &self,
+        _env: &RuntimeEnvironment,
+        function_name: &str,
+        caller: &str,
+        call_stack: &[String],
+    ) -> Result<bool> {
+        let validator = SecurityValidator::new(SecurityConfig::default());
+        Ok(validator.check_reentrancy(function_name, caller, call_stack).is_err())
+    }
+
+    async fn detect_overflow(
+        &self,
+        _env: &RuntimeEnvironment,
+        operation: &str,
+        operands: &[i64],
+    ) -> Result<bool> {
+        let validator = SecurityValidator::new(SecurityConfig::default());
+        Ok(validator.detect_overflow(operation, operands).is_err())
+    }
+
+    async fn verify_access_control(
+        &self,
+        _env: &RuntimeEnvironment,
+        function_name: &str,
+        caller: &str,
+        required_role: Option<&str>,
+    ) -> Result<bool> {
+        let validator = SecurityValidator::new(SecurityConfig::default());
+        Ok(validator.verify_access_control(function_name, caller, required_role).is_ok())
+    }
+
+    async fn enforce_resource_limits(
+        &self,
+        _env: &RuntimeEnvironment,
+        gas_used: u64,
+        memory_used: u64,
+        call_depth: u32,
+        external_calls: u32,
+        security_config: &SecurityConfig,
+    ) -> Result<Vec<SecurityViolation>> {
+        Ok(SecurityEnforcer::new(security_config.clone()).check_resource_limits(
+            gas_used,
+            memory_used,
+            call_depth,
+            external_calls,
+        ))
+    }
+
+    async fn get_security_report(
+        &self,
+        _env: &RuntimeEnvironment,
+        execution_id: &str,
+    ) -> Result<SecurityReport> {
+        let result = ExecutionResult::new(execution_id.to_string(), true);
+        Ok(SecurityReport::new(&result, &SecurityConfig::default()))
+    }
 }
 
 #[tokio::main]
@@ -170,6 +245,8 @@ async fn main() -> Result<()> {
         network_mode: NetworkMode::Local,
         enable_monitoring: true,
         blockchain_config: HashMap::new(),
+        security_config: SecurityConfig::default(),
+        deterministic_seed: None,
     };
 
     let env = ethereum_runtime.create_environment(config).await?;
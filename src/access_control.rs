@@ -0,0 +1,140 @@
+//! Role-based access control model
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::error::{RuntimeError, RuntimeResult};
+
+/// Role and the roles whose permissions it also carries
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct RoleDefinition {
+    /// Junior roles held implicitly by anyone holding this role
+    #[serde(default)]
+    pub includes: Vec<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// Roles, their hierarchy, per-function requirements and role grants
+///
+/// Accounts are compared case-insensitively so checksummed and lower-case
+/// addresses refer to the same holder.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct AccessControlPolicy {
+    #[serde(default)]
+    pub roles: BTreeMap<String, RoleDefinition>,
+    /// Role required to call each function
+    #[serde(default)]
+    pub function_roles: BTreeMap<String, String>,
+    /// Roles granted directly to each account
+    #[serde(default)]
+    pub grants: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl AccessControlPolicy {
+    /// Create an empty policy
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Define or redefine a role, rejecting unknown or circular inclusions
+    pub fn define_role(&mut self, role: impl Into<String>, includes: &[&str]) -> RuntimeResult<()> {
+        let role = role.into();
+        for included in includes {
+            self.ensure_role(included)?;
+            if *included == role || self.role_includes(included, &role) {
+                return Err(invalid_role(format!("{} cannot include {} as it would form a cycle", role, included)));
+            }
+        }
+
+        let definition = self.roles.entry(role).or_default();
+        definition.includes = includes.iter().map(|r| r.to_string()).collect();
+        Ok(())
+    }
+
+    /// Require a role for calling a function
+    pub fn require_role(&mut self, function_name: impl Into<String>, role: &str) -> RuntimeResult<()> {
+        self.ensure_role(role)?;
+        self.function_roles.insert(function_name.into(), role.to_string());
+        Ok(())
+    }
+
+    /// Role required to call a function, if any
+    pub fn required_role(&self, function_name: &str) -> Option<&str> {
+        self.function_roles.get(function_name).map(String::as_str)
+    }
+
+    /// Grant a role to an account
+    pub fn grant_role(&mut self, account: &str, role: &str) -> RuntimeResult<()> {
+        self.ensure_role(role)?;
+        self.grants
+            .entry(account.to_ascii_lowercase())
+            .or_default()
+            .insert(role.to_string());
+        Ok(())
+    }
+
+    /// Revoke a directly granted role, returning whether the account held it
+    pub fn revoke_role(&mut self, account: &str, role: &str) -> bool {
+        let account = account.to_ascii_lowercase();
+        let Some(roles) = self.grants.get_mut(&account) else {
+            return false;
+        };
+        let revoked = roles.remove(role);
+        if roles.is_empty() {
+            self.grants.remove(&account);
+        }
+        revoked
+    }
+
+    /// Check whether an account holds a role, directly or through the hierarchy
+    pub fn has_role(&self, account: &str, role: &str) -> bool {
+        self.grants
+            .get(&account.to_ascii_lowercase())
+            .is_some_and(|granted| granted.iter().any(|held| self.role_includes(held, role)))
+    }
+
+    /// Check whether holding `held` implies holding `role`
+    pub fn role_includes(&self, held: &str, role: &str) -> bool {
+        let mut visited = BTreeSet::new();
+        let mut pending = vec![held];
+        while let Some(current) = pending.pop() {
+            if current == role {
+                return true;
+            }
+            if !visited.insert(current) {
+                continue;
+            }
+            if let Some(definition) = self.roles.get(current) {
+                pending.extend(definition.includes.iter().map(String::as_str));
+            }
+        }
+        false
+    }
+
+    /// Check that every role referenced by the policy is defined
+    pub fn validate(&self) -> RuntimeResult<()> {
+        let referenced = self
+            .roles
+            .values()
+            .flat_map(|definition| &definition.includes)
+            .chain(self.function_roles.values())
+            .chain(self.grants.values().flatten());
+        for role in referenced {
+            self.ensure_role(role)?;
+        }
+        Ok(())
+    }
+
+    fn ensure_role(&self, role: &str) -> RuntimeResult<()> {
+        if self.roles.contains_key(role) {
+            Ok(())
+        } else {
+            Err(invalid_role(format!("unknown role {}", role)))
+        }
+    }
+}
+
+fn invalid_role(message: String) -> RuntimeError {
+    RuntimeError::invalid_config("security_config.access_control", message)
+}
@@ -0,0 +1,444 @@
+//! Fixed-width integer arithmetic used by overflow detection
+
+use primitive_types::{U256, U512};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::RuntimeError;
+
+/// Signed or unsigned integer type between 8 and 256 bits wide
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct IntegerType {
+    signed: bool,
+    bits: u16,
+}
+
+impl IntegerType {
+    pub const U8: Self = Self { signed: false, bits: 8 };
+    pub const U16: Self = Self { signed: false, bits: 16 };
+    pub const U32: Self = Self { signed: false, bits: 32 };
+    pub const U64: Self = Self { signed: false, bits: 64 };
+    pub const U128: Self = Self { signed: false, bits: 128 };
+    pub const U256: Self = Self { signed: false, bits: 256 };
+    pub const I8: Self = Self { signed: true, bits: 8 };
+    pub const I16: Self = Self { signed: true, bits: 16 };
+    pub const I32: Self = Self { signed: true, bits: 32 };
+    pub const I64: Self = Self { signed: true, bits: 64 };
+    pub const I128: Self = Self { signed: true, bits: 128 };
+    pub const I256: Self = Self { signed: true, bits: 256 };
+
+    /// Integer type of any width that is a multiple of 8 up to 256, such as Solidity's `uint24`
+    pub fn new(signed: bool, bits: u16) -> Option<Self> {
+        (bits.is_multiple_of(8) && (8..=256).contains(&bits)).then_some(Self { signed, bits })
+    }
+
+    pub fn signed(&self) -> bool {
+        self.signed
+    }
+
+    pub fn bits(&self) -> u16 {
+        self.bits
+    }
+
+    /// Largest representable value
+    pub fn max_value(&self) -> IntegerValue {
+        let magnitude = if self.signed { self.sign_bit() - 1 } else { self.mask() };
+        IntegerValue::new(false, magnitude)
+    }
+
+    /// Smallest representable value
+    pub fn min_value(&self) -> IntegerValue {
+        if self.signed {
+            IntegerValue::new(true, self.sign_bit())
+        } else {
+            IntegerValue::default()
+        }
+    }
+
+    /// Check whether a value is representable
+    pub fn contains(&self, value: &IntegerValue) -> bool {
+        self.range_fault(value.negative, U512::from(value.magnitude)).is_none()
+    }
+
+    fn mask(&self) -> U256 {
+        if self.bits == 256 {
+            U256::MAX
+        } else {
+            (U256::one() << self.bits) - 1
+        }
+    }
+
+    fn sign_bit(&self) -> U256 {
+        U256::one() << (self.bits - 1)
+    }
+
+    fn range_fault(&self, negative: bool, magnitude: U512) -> Option<ArithmeticFault> {
+        if magnitude.is_zero() {
+            None
+        } else if negative {
+            let limit = if self.signed { U512::from(self.sign_bit()) } else { U512::zero() };
+            (magnitude > limit).then_some(ArithmeticFault::Underflow)
+        } else {
+            (magnitude > U512::from(self.max_value().magnitude)).then_some(ArithmeticFault::Overflow)
+        }
+    }
+
+    /// Two's complement bit pattern of a value, truncated to the width
+    fn bits_of(self, negative: bool, magnitude: U256) -> U256 {
+        let bits = if negative { (!magnitude).overflowing_add(U256::one()).0 } else { magnitude };
+        bits & self.mask()
+    }
+
+    /// Value of a bit pattern interpreted at this width
+    fn value_of(self, bits: U256) -> IntegerValue {
+        let bits = bits & self.mask();
+        if self.signed && !(bits & self.sign_bit()).is_zero() {
+            IntegerValue::new(true, ((!bits).overflowing_add(U256::one()).0) & self.mask())
+        } else {
+            IntegerValue::new(false, bits)
+        }
+    }
+}
+
+impl fmt::Display for IntegerType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", if self.signed { "i" } else { "u" }, self.bits)
+    }
+}
+
+impl FromStr for IntegerType {
+    type Err = RuntimeError;
+
+    /// Parse Rust (`u64`, `i128`) or Solidity (`uint256`, `int8`, `uint`) type names
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (signed, bits) = if let Some(bits) = s.strip_prefix("uint") {
+            (false, bits)
+        } else if let Some(bits) = s.strip_prefix("int") {
+            (true, bits)
+        } else if let Some(bits) = s.strip_prefix('u') {
+            (false, bits)
+        } else if let Some(bits) = s.strip_prefix('i') {
+            (true, bits)
+        } else {
+            return Err(invalid_integer_type(s));
+        };
+
+        let bits = if bits.is_empty() && s.contains("int") { Ok(256) } else { bits.parse() };
+        bits.ok()
+            .and_then(|bits| Self::new(signed, bits))
+            .ok_or_else(|| invalid_integer_type(s))
+    }
+}
+
+impl TryFrom<String> for IntegerType {
+    type Error = RuntimeError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<IntegerType> for String {
+    fn from(value: IntegerType) -> Self {
+        value.to_string()
+    }
+}
+
+fn invalid_integer_type(name: &str) -> RuntimeError {
+    RuntimeError::invalid_config(
+        "security_config.integer_type",
+        format!("{} is not an integer type between 8 and 256 bits", name),
+    )
+}
+
+/// Integer of up to 256 bits of magnitude, positive or negative
+///
+/// Serialized as a decimal string, optionally negative, and parsed from decimal
+/// or `0x`-prefixed hex.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct IntegerValue {
+    negative: bool,
+    magnitude: U256,
+}
+
+impl IntegerValue {
+    pub fn new(negative: bool, magnitude: U256) -> Self {
+        Self {
+            negative: negative && !magnitude.is_zero(),
+            magnitude,
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn magnitude(&self) -> U256 {
+        self.magnitude
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_zero()
+    }
+}
+
+impl fmt::Display for IntegerValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", if self.negative { "-" } else { "" }, self.magnitude)
+    }
+}
+
+impl FromStr for IntegerValue {
+    type Err = RuntimeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s),
+        };
+        let magnitude = match digits.strip_prefix("0x") {
+            Some(hex) => U256::from_str_radix(hex, 16).ok(),
+            None => U256::from_dec_str(digits).ok(),
+        };
+        magnitude
+            .map(|magnitude| Self::new(negative, magnitude))
+            .ok_or_else(|| RuntimeError::InvalidInput(format!("{} is not a valid integer", s)))
+    }
+}
+
+impl TryFrom<String> for IntegerValue {
+    type Error = RuntimeError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<IntegerValue> for String {
+    fn from(value: IntegerValue) -> Self {
+        value.to_string()
+    }
+}
+
+impl From<U256> for IntegerValue {
+    fn from(value: U256) -> Self {
+        Self::new(false, value)
+    }
+}
+
+macro_rules! integer_value_from {
+    (unsigned: $($unsigned:ty),*; signed: $($signed:ty),*) => {
+        $(impl From<$unsigned> for IntegerValue {
+            fn from(value: $unsigned) -> Self {
+                Self::new(false, U256::from(value))
+            }
+        })*
+        $(impl From<$signed> for IntegerValue {
+            fn from(value: $signed) -> Self {
+                Self::new(value < 0, U256::from(value.unsigned_abs()))
+            }
+        })*
+    };
+}
+
+integer_value_from!(unsigned: u8, u16, u32, u64, u128; signed: i8, i16, i32, i64, i128);
+
+/// Binary integer operation checked for overflow
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ArithmeticOperation {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Exp,
+    Shl,
+    Shr,
+}
+
+impl ArithmeticOperation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArithmeticOperation::Add => "addition",
+            ArithmeticOperation::Sub => "subtraction",
+            ArithmeticOperation::Mul => "multiplication",
+            ArithmeticOperation::Div => "division",
+            ArithmeticOperation::Mod => "modulo",
+            ArithmeticOperation::Exp => "exponentiation",
+            ArithmeticOperation::Shl => "left shift",
+            ArithmeticOperation::Shr => "right shift",
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            ArithmeticOperation::Add => "+",
+            ArithmeticOperation::Sub => "-",
+            ArithmeticOperation::Mul => "*",
+            ArithmeticOperation::Div => "/",
+            ArithmeticOperation::Mod => "%",
+            ArithmeticOperation::Exp => "**",
+            ArithmeticOperation::Shl => "<<",
+            ArithmeticOperation::Shr => ">>",
+        }
+    }
+
+    /// Whether checked arithmetic reverts on a fault; shifts always truncate
+    pub fn is_checked(&self) -> bool {
+        !matches!(self, ArithmeticOperation::Shl | ArithmeticOperation::Shr)
+    }
+
+    /// Evaluate the operation at a width, reporting the wrapped result and any fault
+    ///
+    /// The exponent and shift amount are never negative and are not bounded by
+    /// the width. Division rounds towards zero and the remainder takes the sign
+    /// of the dividend, as in the EVM; dividing by zero wraps to zero.
+    pub fn evaluate(&self, integer_type: IntegerType, lhs: IntegerValue, rhs: IntegerValue) -> ArithmeticOutcome {
+        let amount_operand = matches!(self, ArithmeticOperation::Exp | ArithmeticOperation::Shl | ArithmeticOperation::Shr);
+        let rhs_valid = if amount_operand { !rhs.negative } else { integer_type.contains(&rhs) };
+        if !integer_type.contains(&lhs) || !rhs_valid {
+            return ArithmeticOutcome {
+                wrapped: IntegerValue::default(),
+                fault: Some(ArithmeticFault::OperandOutOfRange),
+            };
+        }
+        if matches!(self, ArithmeticOperation::Div | ArithmeticOperation::Mod) && rhs.is_zero() {
+            return ArithmeticOutcome {
+                wrapped: IntegerValue::default(),
+                fault: Some(ArithmeticFault::DivisionByZero),
+            };
+        }
+
+        let exact = self.exact(lhs, rhs);
+        let (fault, wrapped) = match exact {
+            Some((negative, magnitude)) => {
+                let low = U256([magnitude.0[0], magnitude.0[1], magnitude.0[2], magnitude.0[3]]);
+                (
+                    integer_type.range_fault(negative, magnitude),
+                    integer_type.value_of(integer_type.bits_of(negative, low)),
+                )
+            }
+            None => {
+                // Only exponentiation and left shifts of non-zero values leave 512 bits
+                let negative = lhs.negative && (*self == ArithmeticOperation::Shl || rhs.magnitude.bit(0));
+                let bits = match self {
+                    ArithmeticOperation::Exp => {
+                        integer_type.bits_of(lhs.negative, lhs.magnitude).overflowing_pow(rhs.magnitude).0
+                    }
+                    _ => U256::zero(),
+                };
+                let fault = if negative { ArithmeticFault::Underflow } else { ArithmeticFault::Overflow };
+                (Some(fault), integer_type.value_of(bits))
+            }
+        };
+
+        ArithmeticOutcome { wrapped, fault }
+    }
+
+    /// Mathematically exact result as sign and magnitude, `None` beyond 512 bits
+    fn exact(&self, lhs: IntegerValue, rhs: IntegerValue) -> Option<(bool, U512)> {
+        let a = U512::from(lhs.magnitude);
+        let b = U512::from(rhs.magnitude);
+        let result = match self {
+            ArithmeticOperation::Add => signed_sum(lhs.negative, a, rhs.negative, b),
+            ArithmeticOperation::Sub => signed_sum(lhs.negative, a, !rhs.negative, b),
+            ArithmeticOperation::Mul => (lhs.negative != rhs.negative, lhs.magnitude.full_mul(rhs.magnitude)),
+            ArithmeticOperation::Div => (lhs.negative != rhs.negative, a / b),
+            ArithmeticOperation::Mod => (lhs.negative, a % b),
+            ArithmeticOperation::Exp => (lhs.negative && rhs.magnitude.bit(0), a.checked_pow(b)?),
+            ArithmeticOperation::Shl => {
+                if a.is_zero() {
+                    (false, a)
+                } else if rhs.magnitude >= U256::from(512) {
+                    return None;
+                } else {
+                    (lhs.negative, a.checked_mul(U512::one() << rhs.magnitude.low_u32())?)
+                }
+            }
+            ArithmeticOperation::Shr => {
+                // Arithmetic shift, rounding negative values towards negative infinity
+                if rhs.magnitude >= U256::from(512) {
+                    (lhs.negative, U512::from(lhs.negative as u8))
+                } else {
+                    let divisor = U512::one() << rhs.magnitude.low_u32();
+                    let rounding = if lhs.negative { divisor - 1 } else { U512::zero() };
+                    (lhs.negative, (a + rounding) / divisor)
+                }
+            }
+        };
+        Some(result)
+    }
+}
+
+fn signed_sum(a_negative: bool, a: U512, b_negative: bool, b: U512) -> (bool, U512) {
+    if a_negative == b_negative {
+        (a_negative, a + b)
+    } else if a >= b {
+        (a_negative, a - b)
+    } else {
+        (b_negative, b - a)
+    }
+}
+
+impl FromStr for ArithmeticOperation {
+    type Err = RuntimeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "add" | "+" => ArithmeticOperation::Add,
+            "sub" | "subtract" | "-" => ArithmeticOperation::Sub,
+            "mul" | "multiply" | "*" => ArithmeticOperation::Mul,
+            "div" | "divide" | "/" => ArithmeticOperation::Div,
+            "mod" | "modulo" | "%" => ArithmeticOperation::Mod,
+            "exp" | "pow" | "**" => ArithmeticOperation::Exp,
+            "shl" | "<<" => ArithmeticOperation::Shl,
+            "shr" | "sar" | ">>" => ArithmeticOperation::Shr,
+            _ => return Err(RuntimeError::InvalidInput(format!("Unknown arithmetic operation {}", s))),
+        })
+    }
+}
+
+/// How the analysed code treats arithmetic faults
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArithmeticMode {
+    /// Results silently wrap, as with raw EVM opcodes or Rust release builds
+    #[default]
+    Wrapping,
+    /// Faults revert, as with Solidity 0.8 or Rust `checked_*`
+    Checked,
+}
+
+/// Problem found when evaluating an operation
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArithmeticFault {
+    Overflow,
+    Underflow,
+    DivisionByZero,
+    /// An operand does not fit the integer type, or an exponent or shift amount is negative
+    OperandOutOfRange,
+}
+
+impl fmt::Display for ArithmeticFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ArithmeticFault::Overflow => "Integer overflow",
+            ArithmeticFault::Underflow => "Integer underflow",
+            ArithmeticFault::DivisionByZero => "Division by zero",
+            ArithmeticFault::OperandOutOfRange => "Operand out of range",
+        })
+    }
+}
+
+/// Result of evaluating an operation at a fixed width
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ArithmeticOutcome {
+    /// Result under wrapping semantics
+    pub wrapped: IntegerValue,
+    pub fault: Option<ArithmeticFault>,
+}
@@ -0,0 +1,96 @@
+//! Block clock shared by backends that support time travel
+
+use crate::config::RuntimeConfig;
+use crate::constants::DEFAULT_BLOCK_TIME_SECONDS;
+use crate::error::{RuntimeError, RuntimeResult};
+use crate::types::BlockInfo;
+
+/// Block number and timestamp of an environment, moved only on request
+#[derive(Debug, Clone)]
+pub(crate) struct BlockClock {
+    number: u64,
+    timestamp: u64,
+    block_time: u64,
+    next_timestamp: Option<u64>,
+}
+
+impl Default for BlockClock {
+    fn default() -> Self {
+        Self {
+            number: 0,
+            timestamp: 0,
+            block_time: DEFAULT_BLOCK_TIME_SECONDS,
+            next_timestamp: None,
+        }
+    }
+}
+
+impl BlockClock {
+    /// Start a clock at the given block, reading `block_time` from the blockchain config
+    pub(crate) fn new(number: u64, timestamp: u64, config: &RuntimeConfig) -> Self {
+        Self {
+            number,
+            timestamp,
+            block_time: config
+                .blockchain_config
+                .get("block_time")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(DEFAULT_BLOCK_TIME_SECONDS),
+            next_timestamp: None,
+        }
+    }
+
+    /// Current block number and timestamp
+    pub(crate) fn info(&self) -> BlockInfo {
+        BlockInfo {
+            number: self.number,
+            timestamp: self.timestamp,
+        }
+    }
+
+    /// Move the timestamp forward without mining a block
+    pub(crate) fn advance_time(&mut self, seconds: u64) -> RuntimeResult<BlockInfo> {
+        self.timestamp = self.timestamp.checked_add(seconds).ok_or_else(|| {
+            RuntimeError::InvalidInput(format!("Advancing time by {}s overflows the block timestamp", seconds))
+        })?;
+        // A pending timestamp that is no longer ahead of the clock would move time backwards
+        if self.next_timestamp.is_some_and(|next| next <= self.timestamp) {
+            self.next_timestamp = None;
+        }
+        Ok(self.info())
+    }
+
+    /// Fix the timestamp of the next mined block
+    pub(crate) fn set_next_block_timestamp(&mut self, timestamp: u64) -> RuntimeResult<()> {
+        if timestamp <= self.timestamp {
+            return Err(RuntimeError::InvalidInput(format!(
+                "Next block timestamp {} must be after the current timestamp {}",
+                timestamp, self.timestamp
+            )));
+        }
+        self.next_timestamp = Some(timestamp);
+        Ok(())
+    }
+
+    /// Mine `count` empty blocks, leaving the clock as it was if that overflows
+    pub(crate) fn mine(&mut self, count: u64) -> RuntimeResult<BlockInfo> {
+        if count == 0 {
+            return Ok(self.info());
+        }
+        let overflow =
+            |what: &str| RuntimeError::InvalidInput(format!("Mining {} blocks overflows the block {}", count, what));
+
+        // Blocks after the first follow `block_time` apart
+        let first = match self.next_timestamp {
+            Some(timestamp) => Some(timestamp),
+            None => self.timestamp.checked_add(self.block_time),
+        };
+        let timestamp = first
+            .and_then(|first| first.checked_add(self.block_time.checked_mul(count - 1)?))
+            .ok_or_else(|| overflow("timestamp"))?;
+        self.number = self.number.checked_add(count).ok_or_else(|| overflow("number"))?;
+        self.timestamp = timestamp;
+        self.next_timestamp = None;
+        Ok(self.info())
+    }
+}
@@ -1,11 +1,23 @@
 //! Configuration types for blockchain runtime
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
+use std::path::Path;
 
 use crate::constants::*;
+use crate::error::{RuntimeError, RuntimeResult};
 use crate::security::SecurityConfig;
-use crate::types::NetworkMode;
+use crate::types::{sorted_map, NetworkMode};
+
+/// Prefix of environment variables that override configuration values
+///
+/// The rest of the variable name is the field path in upper case, with `__`
+/// between nested fields, e.g. `BLOCKCHAIN_RUNTIME_TIMEOUT_SECONDS` or
+/// `BLOCKCHAIN_RUNTIME_SECURITY_CONFIG__MAX_GAS_LIMIT`. Keys under
+/// `blockchain_config` are free-form and keep their case, e.g.
+/// `BLOCKCHAIN_RUNTIME_BLOCKCHAIN_CONFIG__chain_id`.
+pub const CONFIG_ENV_PREFIX: &str = "BLOCKCHAIN_RUNTIME_";
 
 /// Runtime configuration with security features
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,9 +26,14 @@ pub struct RuntimeConfig {
     pub memory_limit_mb: u64,
     pub network_mode: NetworkMode,
     pub enable_monitoring: bool,
+    #[serde(serialize_with = "sorted_map")]
     pub blockchain_config: HashMap<String, serde_json::Value>,
     /// Security configuration
     pub security_config: SecurityConfig,
+    /// Seed for deterministic mode, where environment clocks stand still and
+    /// randomness is derived from the seed
+    #[serde(default)]
+    pub deterministic_seed: Option<u64>,
 }
 
 impl Default for RuntimeConfig {
@@ -28,6 +45,7 @@ impl Default for RuntimeConfig {
             enable_monitoring: true,
             blockchain_config: HashMap::new(),
             security_config: SecurityConfig::default(),
+            deterministic_seed: None,
         }
     }
 }
@@ -46,6 +64,7 @@ impl RuntimeConfig {
             enable_monitoring: true,
             blockchain_config: HashMap::new(),
             security_config: SecurityConfig::default(),
+            deterministic_seed: None,
         }
     }
 
@@ -58,6 +77,7 @@ impl RuntimeConfig {
             enable_monitoring: true,
             blockchain_config: HashMap::new(),
             security_config: SecurityConfig::permissive(),
+            deterministic_seed: None,
         }
     }
 
@@ -70,6 +90,7 @@ impl RuntimeConfig {
             enable_monitoring: true,
             blockchain_config: HashMap::new(),
             security_config: SecurityConfig::strict(),
+            deterministic_seed: None,
         }
     }
 
@@ -82,6 +103,7 @@ impl RuntimeConfig {
             enable_monitoring: false,
             blockchain_config: HashMap::new(),
             security_config: SecurityConfig::permissive(),
+            deterministic_seed: None,
         }
     }
 
@@ -97,6 +119,12 @@ impl RuntimeConfig {
         self
     }
 
+    /// Run environments in deterministic mode with a seed
+    pub fn with_deterministic_seed(mut self, seed: u64) -> Self {
+        self.deterministic_seed = Some(seed);
+        self
+    }
+
     /// Enable or disable monitoring
     pub fn with_monitoring(mut self, enabled: bool) -> Self {
         self.enable_monitoring = enabled;
@@ -104,35 +132,108 @@ impl RuntimeConfig {
     }
 
     /// Validate the configuration
-    pub fn validate(&self) -> Result<(), String> {
+    pub fn validate(&self) -> RuntimeResult<()> {
         if self.timeout_seconds == 0 {
-            return Err("Timeout cannot be zero".to_string());
+            return Err(RuntimeError::invalid_config("timeout_seconds", "Timeout cannot be zero"));
         }
 
         if self.memory_limit_mb == 0 {
-            return Err("Memory limit cannot be zero".to_string());
+            return Err(RuntimeError::invalid_config("memory_limit_mb", "Memory limit cannot be zero"));
         }
 
         // Validate security configuration
         if self.security_config.max_call_depth == 0 {
-            return Err("Maximum call depth cannot be zero".to_string());
+            return Err(RuntimeError::invalid_config("security_config.max_call_depth", "Maximum call depth cannot be zero"));
         }
 
         if self.security_config.max_external_calls == 0 {
-            return Err("Maximum external calls cannot be zero".to_string());
+            return Err(RuntimeError::invalid_config("security_config.max_external_calls", "Maximum external calls cannot be zero"));
         }
 
         if self.security_config.max_gas_limit == 0 {
-            return Err("Maximum gas limit cannot be zero".to_string());
+            return Err(RuntimeError::invalid_config("security_config.max_gas_limit", "Maximum gas limit cannot be zero"));
         }
 
         if self.security_config.max_memory_bytes == 0 {
-            return Err("Maximum memory bytes cannot be zero".to_string());
+            return Err(RuntimeError::invalid_config("security_config.max_memory_bytes", "Maximum memory bytes cannot be zero"));
         }
 
+        self.security_config.access_control.validate()?;
+
         Ok(())
     }
 
+    /// Load a configuration file layered over the defaults and validate it
+    ///
+    /// The format follows the extension: `.toml`, `.json`, `.yaml` or `.yml`.
+    /// Fields missing from the file keep their default value.
+    pub fn from_file(path: impl AsRef<Path>) -> RuntimeResult<Self> {
+        let path = path.as_ref();
+        let source = path.display().to_string();
+        let mut value = serde_json::to_value(Self::default())
+            .map_err(|e| RuntimeError::invalid_config(&source, e.to_string()))?;
+        merge_layer(&mut value, read_config_file(path)?, &[])
+            .map_err(|(field, message)| RuntimeError::invalid_config(field, message))?;
+        Self::from_layered(value, &source)
+    }
+
+    /// Load the defaults overridden by `BLOCKCHAIN_RUNTIME_*` environment variables
+    pub fn from_env() -> RuntimeResult<Self> {
+        Self::default().with_overrides(std::env::vars())
+    }
+
+    /// Load a configuration file, then apply environment variable overrides
+    pub fn load(path: impl AsRef<Path>) -> RuntimeResult<Self> {
+        Self::from_file(path)?.with_overrides(std::env::vars())
+    }
+
+    /// Apply overrides from variables named with `CONFIG_ENV_PREFIX`, ignoring all others
+    ///
+    /// Values are parsed as JSON when the field is not a string, so numbers,
+    /// booleans and arrays can be given in their JSON form. Unset optional
+    /// fields take either JSON or a plain string.
+    pub fn with_overrides<K, V>(self, vars: impl IntoIterator<Item = (K, V)>) -> RuntimeResult<Self>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut overrides: Vec<(String, String)> = vars
+            .into_iter()
+            .filter_map(|(name, raw)| {
+                let name = name.as_ref().strip_prefix(CONFIG_ENV_PREFIX)?;
+                Some((name.to_string(), raw.as_ref().to_string()))
+            })
+            .collect();
+        // Apply in a stable order regardless of how the environment is iterated
+        overrides.sort();
+
+        let mut value = serde_json::to_value(&self)
+            .map_err(|e| RuntimeError::invalid_config("environment", e.to_string()))?;
+        for (name, raw) in overrides {
+            // Field names are matched case-insensitively up to the free-form `blockchain_config`
+            let mut free_form = false;
+            let path: Vec<String> = name
+                .split("__")
+                .map(|segment| {
+                    let key = if free_form { segment.to_string() } else { segment.to_ascii_lowercase() };
+                    free_form |= key == "blockchain_config";
+                    key
+                })
+                .collect();
+            apply_override(&mut value, &path, &raw).map_err(|message| {
+                RuntimeError::invalid_config(format!("{}{}", CONFIG_ENV_PREFIX, name), message)
+            })?;
+        }
+        Self::from_layered(value, "environment")
+    }
+
+    fn from_layered(value: Value, source: &str) -> RuntimeResult<Self> {
+        let config: Self =
+            serde_json::from_value(value).map_err(|e| RuntimeError::invalid_config(source, e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
     /// Get a human-readable description of the configuration
     pub fn describe(&self) -> String {
         format!(
@@ -165,6 +266,75 @@ impl RuntimeConfig {
     }
 }
 
+/// Parse a configuration file into a JSON value according to its extension
+fn read_config_file(path: &Path) -> RuntimeResult<Value> {
+    let source = path.display().to_string();
+    let invalid = |message: String| RuntimeError::invalid_config(&source, message);
+    let contents = std::fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+
+    let extension = path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("toml") => toml::from_str(&contents).map_err(|e| invalid(e.to_string())),
+        Some("json") => serde_json::from_str(&contents).map_err(|e| invalid(e.to_string())),
+        Some("yaml" | "yml") => serde_yaml::from_str(&contents).map_err(|e| invalid(e.to_string())),
+        _ => Err(invalid("expected a .toml, .json, .yaml or .yml file".to_string())),
+    }
+}
+
+/// Whether a field path points into the free-form `blockchain_config` map
+fn is_free_form(path: &[String]) -> bool {
+    path.first().is_some_and(|field| field == "blockchain_config")
+}
+
+/// Merge a layer into the configuration, rejecting fields that do not exist
+fn merge_layer(base: &mut Value, layer: Value, path: &[String]) -> Result<(), (String, String)> {
+    match (base, layer) {
+        (Value::Object(base), Value::Object(layer)) => {
+            for (key, value) in layer {
+                let mut field = path.to_vec();
+                field.push(key.clone());
+                match base.get_mut(&key) {
+                    Some(existing) => merge_layer(existing, value, &field)?,
+                    None if is_free_form(path) => {
+                        base.insert(key, value);
+                    }
+                    None => return Err((field.join("."), "unknown configuration field".to_string())),
+                }
+            }
+            Ok(())
+        }
+        (base, layer) => {
+            *base = layer;
+            Ok(())
+        }
+    }
+}
+
+/// Set the field at `path` from the raw text of an environment variable
+fn apply_override(config: &mut Value, path: &[String], raw: &str) -> Result<(), String> {
+    let (field, parents) = path.split_last().ok_or("missing field name")?;
+    let mut target = config;
+    for parent in parents {
+        target = target
+            .get_mut(parent.as_str())
+            .filter(|value| value.is_object())
+            .ok_or_else(|| format!("unknown configuration section {}", parent))?;
+    }
+    let object = target.as_object_mut().ok_or("field is not in a section")?;
+
+    let value = match object.get(field.as_str()) {
+        Some(Value::String(_)) => Value::String(raw.to_string()),
+        Some(Value::Null) => serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string())),
+        Some(_) => serde_json::from_str(raw).map_err(|e| format!("{} is not valid JSON: {}", raw, e))?,
+        None if is_free_form(parents) => {
+            serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()))
+        }
+        None => return Err("unknown configuration field".to_string()),
+    };
+    object.insert(field.clone(), value);
+    Ok(())
+}
+
 /// Builder pattern for runtime configuration
 pub struct RuntimeConfigBuilder {
     config: RuntimeConfig,
@@ -215,7 +385,7 @@ impl RuntimeConfigBuilder {
     }
 
     /// Build the final configuration
-    pub fn build(self) -> Result<RuntimeConfig, String> {
+    pub fn build(self) -> RuntimeResult<RuntimeConfig> {
         self.config.validate()?;
         Ok(self.config)
     }
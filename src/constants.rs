@@ -26,3 +26,105 @@ pub const MAX_PATH_LENGTH: usize = 4096;
 
 /// Maximum symlink chain length to prevent infinite loops
 pub const MAX_SYMLINK_CHAIN_LENGTH: usize = 100;
+
+/// Sender assumed by the in-memory runtime when the execution context has none
+pub const DEFAULT_SENDER_ADDRESS: &str = "0x0000000000000000000000000000000000000000";
+
+/// Maximum depth of nested EVM call frames
+pub const EVM_MAX_CALL_DEPTH: u32 = 1024;
+
+/// Maximum number of items on the EVM operand stack
+pub const EVM_MAX_STACK_SIZE: usize = 1024;
+
+/// Maximum size of deployed EVM contract code (EIP-170)
+pub const EVM_MAX_CODE_SIZE: usize = 24_576;
+
+/// Maximum size of EVM contract creation code (EIP-3860)
+pub const EVM_MAX_INITCODE_SIZE: usize = 2 * EVM_MAX_CODE_SIZE;
+
+/// Hardfork whose opcodes and gas schedule the EVM backend implements
+pub const EVM_HARDFORK: &str = "cancun";
+
+/// Upper bound on EVM memory per frame, far beyond what gas allows in practice
+pub const EVM_MAX_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+
+/// Number of EVM instructions executed between checks of the execution deadline
+pub const EVM_DEADLINE_CHECK_INTERVAL: u64 = 4096;
+
+/// Default block gas limit for the EVM backend
+pub const DEFAULT_EVM_BLOCK_GAS_LIMIT: u64 = 30_000_000;
+
+/// Default chain id reported by the EVM backend
+pub const DEFAULT_EVM_CHAIN_ID: u64 = 31_337;
+
+/// Seconds between blocks mined by the time-travel API
+pub const DEFAULT_BLOCK_TIME_SECONDS: u64 = 12;
+
+/// Unix timestamp, in seconds, that clocks of deterministic environments start at
+pub const DETERMINISTIC_START_TIMESTAMP: u64 = 1_700_000_000;
+
+/// JSON-RPC endpoint used when a configuration does not set `blockchain_config.rpc_url`
+pub const DEFAULT_RPC_ENDPOINT_URL: &str = "http://localhost:8545";
+
+/// Milliseconds between polls for a transaction receipt
+pub const RPC_RECEIPT_POLL_INTERVAL_MS: u64 = 50;
+
+/// Directory under the system temporary directory holding fork caches when
+/// `blockchain_config.fork_cache_dir` is not set
+pub const DEFAULT_FORK_CACHE_DIR_NAME: &str = "blockchain-runtime-fork-cache";
+
+/// Chain id fork caches are filed under when there is no upstream to ask and
+/// `blockchain_config.chain_id` is not set
+pub const DEFAULT_FORK_CHAIN_ID: u64 = 1;
+
+/// Time one request for forked state may take before the execution fails
+pub const FORK_FETCH_TIMEOUT_SECONDS: u64 = 30;
+
+/// Time a managed node has to start answering JSON-RPC when
+/// `blockchain_config.startup_timeout_seconds` is not set
+pub const DEFAULT_NODE_STARTUP_TIMEOUT_SECONDS: u64 = 30;
+
+/// Interval between readiness probes of a starting node
+pub const NODE_READY_POLL_INTERVAL_MS: u64 = 100;
+
+/// Time a killed node has to exit before stopping it fails
+pub const NODE_STOP_TIMEOUT_SECONDS: u64 = 10;
+
+/// Interval between checks whether a killed node has exited
+pub const NODE_EXIT_POLL_INTERVAL_MS: u64 = 10;
+
+/// Most recent output lines kept per managed node
+pub const MAX_NODE_LOG_LINES: usize = 10_000;
+
+/// Unix socket of the Docker Engine API
+pub const DEFAULT_DOCKER_SOCKET_PATH: &str = "/var/run/docker.sock";
+
+/// Port a containerized node listens on when `blockchain_config.container_port` is not set
+pub const DEFAULT_NODE_CONTAINER_PORT: u16 = 8545;
+
+/// Compute units an SVM instruction may consume when `compute_unit_limit` is not set
+pub const SVM_DEFAULT_COMPUTE_UNIT_LIMIT: u64 = 200_000;
+
+/// Highest `compute_unit_limit` an SVM instruction may request
+pub const SVM_MAX_COMPUTE_UNIT_LIMIT: u64 = 1_400_000;
+
+/// Bytes of stack each SVM call frame gets
+pub const SVM_STACK_FRAME_SIZE: usize = 4096;
+
+/// Deepest chain of function calls an SVM program may make
+pub const SVM_MAX_CALL_DEPTH: usize = 64;
+
+/// Bytes of heap mapped for an SVM program
+pub const SVM_HEAP_SIZE: usize = 32 * 1024;
+
+/// Bytes an SVM program may grow an account's data by in one instruction
+pub const SVM_MAX_PERMITTED_DATA_INCREASE: usize = 10 * 1024;
+
+/// Largest account an SVM program may leave behind
+pub const SVM_MAX_ACCOUNT_DATA_LENGTH: usize = 10 * 1024 * 1024;
+
+/// Largest return data an SVM program may set
+pub const SVM_MAX_RETURN_DATA: usize = 1024;
+
+/// Largest loaded image of an SVM program
+pub const SVM_MAX_PROGRAM_SIZE: usize = 10 * 1024 * 1024;
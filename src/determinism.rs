@@ -0,0 +1,161 @@
+//! Clocks and random sources behind environment ids, timestamps and randomness
+//!
+//! Backends read the time and draw random values only through an
+//! [`Entropy`], so a seeded configuration produces byte-for-byte identical
+//! results from run to run.
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::RuntimeConfig;
+use crate::constants::DETERMINISTIC_START_TIMESTAMP;
+
+/// Source of the current time
+pub trait Clock: Send + Sync + fmt::Debug {
+    /// Milliseconds since the Unix epoch
+    fn now_millis(&self) -> u64;
+
+    /// Seconds since the Unix epoch
+    fn now_secs(&self) -> u64 {
+        self.now_millis() / 1000
+    }
+}
+
+/// Wall-clock time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64
+    }
+}
+
+/// Clock that only moves when told to
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    millis: AtomicU64,
+}
+
+impl ManualClock {
+    /// Create a clock stopped at a number of milliseconds since the Unix epoch
+    pub fn new(millis: u64) -> Self {
+        Self {
+            millis: AtomicU64::new(millis),
+        }
+    }
+
+    pub fn set(&self, millis: u64) {
+        self.millis.store(millis, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.millis.fetch_add(by.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_millis(&self) -> u64 {
+        self.millis.load(Ordering::SeqCst)
+    }
+}
+
+/// Source of random values
+pub trait RandomSource: Send + Sync + fmt::Debug {
+    fn next_u64(&self) -> u64;
+
+    /// Fill a buffer with random bytes
+    fn fill_bytes(&self, bytes: &mut [u8]) {
+        for chunk in bytes.chunks_mut(8) {
+            chunk.copy_from_slice(&self.next_u64().to_le_bytes()[..chunk.len()]);
+        }
+    }
+}
+
+/// SplitMix64 generator producing the same sequence for the same seed
+///
+/// Not suitable for cryptographic use.
+#[derive(Debug)]
+pub struct SeededRandom {
+    state: AtomicU64,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    /// Create a generator seeded from the process's hash randomness
+    pub fn from_system() -> Self {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(SystemClock.now_millis());
+        Self::new(hasher.finish())
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn next_u64(&self) -> u64 {
+        let mut z = self
+            .state
+            .fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::SeqCst)
+            .wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Clock and random source an environment takes its time and randomness from
+#[derive(Debug, Clone)]
+pub struct Entropy {
+    clock: Arc<dyn Clock>,
+    rng: Arc<dyn RandomSource>,
+}
+
+impl Entropy {
+    /// Use a custom clock and random source
+    pub fn new(clock: Arc<dyn Clock>, rng: Arc<dyn RandomSource>) -> Self {
+        Self { clock, rng }
+    }
+
+    /// Wall-clock time and unpredictable randomness
+    pub fn system() -> Self {
+        Self::new(Arc::new(SystemClock), Arc::new(SeededRandom::from_system()))
+    }
+
+    /// A clock stopped at `DETERMINISTIC_START_TIMESTAMP` and randomness derived from the seed
+    pub fn seeded(seed: u64) -> Self {
+        Self::new(
+            Arc::new(ManualClock::new(DETERMINISTIC_START_TIMESTAMP * 1000)),
+            Arc::new(SeededRandom::new(seed)),
+        )
+    }
+
+    /// Seeded when the configuration sets `deterministic_seed`, system otherwise
+    pub fn from_config(config: &RuntimeConfig) -> Self {
+        config.deterministic_seed.map_or_else(Self::system, Self::seeded)
+    }
+
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    pub fn rng(&self) -> &Arc<dyn RandomSource> {
+        &self.rng
+    }
+}
+
+impl Default for Entropy {
+    fn default() -> Self {
+        Self::system()
+    }
+}
@@ -0,0 +1,514 @@
+//! Docker backend
+//!
+//! Runs a node container for each environment through the Docker Engine API
+//! on its Unix socket, then drives the node through `RpcRuntime`. The node's
+//! JSON-RPC port is published on a local port Docker picks, `memory_limit_mb` becomes
+//! the container's memory limit and the container's output is followed into
+//! the environment's logs. `destroy` removes the container.
+//!
+//! `blockchain_config` understands the following keys:
+//!
+//! - `docker_image`: image to run instead of the runtime's, pulled when missing
+//! - `docker_cmd`: command to run instead of the image's default
+//! - `docker_network`: network to connect the container to
+//! - `container_port`: port the node listens on inside the container
+//! - `startup_timeout_seconds`: time the node has to answer JSON-RPC
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::net::UnixStream;
+use tokio::task::JoinHandle;
+
+use crate::config::RuntimeConfig;
+use crate::constants::{DEFAULT_DOCKER_SOCKET_PATH, DEFAULT_NODE_CONTAINER_PORT};
+use crate::error::RuntimeError;
+use crate::http::{self, HttpResponse};
+use crate::info;
+use crate::lifecycle::block_on_isolated;
+use crate::process::{startup_timeout, string_list, wait_for_node, NodeLogs};
+use crate::report::SecurityReport;
+use crate::rpc::RpcRuntime;
+use crate::runtime::BlockchainRuntime;
+use crate::security::SecurityConfig;
+use crate::types::{
+    ExecutionInputs, ExecutionResult, GasEstimate, LogLine, LogStream, RuntimeCapabilities, RuntimeEnvironment,
+    RuntimeEvent, RuntimeMetricDefinition, RuntimeType, SecurityViolation,
+};
+
+/// Container started for one environment
+struct Container {
+    id: String,
+    logs: NodeLogs,
+    follow: JoinHandle<()>,
+}
+
+/// Blockchain runtime running a node container per environment
+pub struct DockerRuntime {
+    rpc: RpcRuntime,
+    image: String,
+    cmd: Option<Vec<String>>,
+    socket_path: PathBuf,
+    containers: Mutex<HashMap<String, Container>>,
+}
+
+impl DockerRuntime {
+    /// Create a runtime starting containers from `image` with its default command
+    pub fn new(blockchain_id: String, image: impl Into<String>) -> Self {
+        Self {
+            rpc: RpcRuntime::new(blockchain_id),
+            image: image.into(),
+            cmd: None,
+            socket_path: PathBuf::from(DEFAULT_DOCKER_SOCKET_PATH),
+            containers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Talk to the Docker daemon listening on another socket
+    pub fn with_socket(mut self, socket_path: impl Into<PathBuf>) -> Self {
+        self.socket_path = socket_path.into();
+        self
+    }
+
+    /// Run containers with a command instead of the image's default
+    pub fn with_cmd<I, S>(mut self, cmd: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.cmd = Some(cmd.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Output the environment's container has written so far
+    pub fn logs(&self, env: &RuntimeEnvironment) -> Result<Vec<LogLine>> {
+        let containers = self.containers.lock().map_err(|_| poisoned())?;
+        containers
+            .get(&env.environment_id)
+            .map(|container| container.logs.lines())
+            .ok_or_else(|| RuntimeError::EnvironmentNotFound(env.environment_id.clone()).into())
+    }
+
+    /// JSON-RPC runtime driving the nodes, for access to their clients
+    pub fn rpc(&self) -> &RpcRuntime {
+        &self.rpc
+    }
+
+    /// Send a request to the Engine API
+    async fn request(&self, method: &str, path: &str, body: Option<&serde_json::Value>) -> Result<HttpResponse> {
+        let stream = UnixStream::connect(&self.socket_path)
+            .await
+            .with_context(|| format!("Failed to connect to Docker at {}", self.socket_path.display()))?;
+        let (headers, body): (&[(&str, &str)], Vec<u8>) = match body {
+            Some(body) => (&[("Content-Type", "application/json")], serde_json::to_vec(body)?),
+            None => (&[], Vec::new()),
+        };
+        http::send(stream, method, "docker", path, headers, &body)
+            .await
+            .with_context(|| format!("Docker {} {} failed", method, path))
+    }
+
+    /// Send a request to the Engine API and fail unless it succeeds
+    async fn call(&self, method: &str, path: &str, body: Option<&serde_json::Value>) -> Result<HttpResponse> {
+        let response = self.request(method, path, body).await?;
+        if !response.is_success() {
+            return Err(docker_error(method, path, &response).into());
+        }
+        Ok(response)
+    }
+
+    async fn create_container(&self, body: &serde_json::Value, image: &str) -> Result<String> {
+        let mut response = self.request("POST", "/containers/create", Some(body)).await?;
+        if response.status == 404 {
+            info!("Pulling {}", image);
+            let pulled = self.call("POST", &format!("/images/create?fromImage={}", image), None).await?;
+            // Pull failures arrive as a status line in the progress stream
+            if let Some(error) = pulled
+                .body
+                .split(|byte| *byte == b'\n')
+                .filter_map(|line| serde_json::from_slice::<serde_json::Value>(line).ok())
+                .find_map(|progress| progress.get("error").and_then(|e| e.as_str()).map(str::to_string))
+            {
+                return Err(RuntimeError::Backend(format!("Failed to pull {}: {}", image, error)).into());
+            }
+            response = self.request("POST", "/containers/create", Some(body)).await?;
+        }
+        if !response.is_success() {
+            return Err(docker_error("POST", "/containers/create", &response).into());
+        }
+        let created: serde_json::Value = serde_json::from_slice(&response.body)?;
+        created["Id"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("Docker created a container without an id"))
+    }
+
+    /// Host port Docker published a started container's `exposed` port on
+    async fn published_port(&self, id: &str, exposed: &str) -> Result<u16> {
+        let response = self.call("GET", &format!("/containers/{}/json", id), None).await?;
+        let container: serde_json::Value = serde_json::from_slice(&response.body)?;
+        container["NetworkSettings"]["Ports"][exposed]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find_map(|binding| binding["HostPort"].as_str()?.parse().ok())
+            .ok_or_else(|| anyhow!("Container {} has no host port published for {}", id, exposed))
+    }
+
+    async fn remove_container(&self, id: &str) -> Result<()> {
+        let path = format!("/containers/{}?force=true&v=true", id);
+        let response = self.request("DELETE", &path, None).await?;
+        // Containers that are already gone need no removal
+        if !response.is_success() && response.status != 404 {
+            return Err(docker_error("DELETE", &path, &response).into());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BlockchainRuntime for DockerRuntime {
+    fn blockchain_id(&self) -> &str {
+        self.rpc.blockchain_id()
+    }
+
+    async fn create_environment(&self, config: RuntimeConfig) -> Result<RuntimeEnvironment> {
+        config.validate()?;
+        let timeout = startup_timeout(&config)?;
+        let setting = |key: &str| config.blockchain_config.get(key);
+        let image = match setting("docker_image") {
+            Some(image) => image
+                .as_str()
+                .ok_or_else(|| RuntimeError::invalid_config("blockchain_config.docker_image", "must be a string"))?
+                .to_string(),
+            None => self.image.clone(),
+        };
+        let container_port = match setting("container_port") {
+            Some(port) => port
+                .as_u64()
+                .and_then(|port| u16::try_from(port).ok())
+                .ok_or_else(|| RuntimeError::invalid_config("blockchain_config.container_port", "must be a port"))?,
+            None => DEFAULT_NODE_CONTAINER_PORT,
+        };
+
+        // An empty host port lets Docker pick a free one when the container starts
+        let exposed = format!("{}/tcp", container_port);
+        let mut host_config = serde_json::json!({
+            "PortBindings": { &exposed: [{ "HostIp": "127.0.0.1", "HostPort": "" }] },
+            "Memory": config.memory_limit_mb * 1024 * 1024,
+        });
+        if let Some(network) = setting("docker_network") {
+            host_config["NetworkMode"] = network.clone();
+        }
+        let mut body = serde_json::json!({
+            "Image": image,
+            "ExposedPorts": { &exposed: {} },
+            "HostConfig": host_config,
+        });
+        if let Some(cmd) = string_list(&config, "docker_cmd")?.or_else(|| self.cmd.clone()) {
+            body["Cmd"] = serde_json::json!(cmd);
+        }
+
+        let id = self.create_container(&body, &image).await?;
+        info!("Starting container {} from {}", id, image);
+        let logs = NodeLogs::default();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let started = self.call("POST", &format!("/containers/{}/start", id), None).await;
+        let follow = tokio::spawn(follow_logs(
+            self.socket_path.clone(),
+            id.clone(),
+            logs.clone(),
+            stopped.clone(),
+        ));
+
+        let attached = async {
+            started?;
+            let port = self.published_port(&id, &exposed).await?;
+            let endpoint_url = format!("http://127.0.0.1:{}", port);
+            wait_for_node(&endpoint_url, timeout, || stopped.load(Ordering::SeqCst).then(|| "stopped".to_string()))
+                .await?;
+            let env = self.rpc.attach(&endpoint_url, config, RuntimeType::Docker).await?;
+            Ok::<_, anyhow::Error>((env, port))
+        }
+        .await;
+        let (mut env, port) = match attached {
+            Ok(attached) => attached,
+            Err(error) => {
+                // The startup failure is what the caller needs, a failed removal only adds to it
+                let removal = match self.remove_container(&id).await {
+                    Ok(()) => {
+                        let _ = follow.await;
+                        String::new()
+                    }
+                    Err(removal) => {
+                        follow.abort();
+                        format!(" and could not be removed ({:#})", removal)
+                    }
+                };
+                return Err(error.context(format!(
+                    "Container {} from {} failed to start{}, last output:\n{}",
+                    id,
+                    image,
+                    removal,
+                    logs.tail()
+                )));
+            }
+        };
+        info!("Container {} serves JSON-RPC on port {}", id, port);
+
+        env.metadata.insert("container_id".to_string(), serde_json::json!(id));
+        env.metadata.insert("image".to_string(), serde_json::json!(image));
+        env.metadata.insert("port".to_string(), serde_json::json!(port));
+        self.containers
+            .lock()
+            .map_err(|_| poisoned())?
+            .insert(env.environment_id.clone(), Container { id, logs, follow });
+        Ok(env)
+    }
+
+    async fn execute(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+    ) -> Result<ExecutionResult> {
+        self.rpc.execute(env, code_path, inputs).await
+    }
+
+    async fn estimate_gas(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+    ) -> Result<GasEstimate> {
+        self.rpc.estimate_gas(env, code_path, inputs).await
+    }
+
+    async fn deploy_contract(
+        &self,
+        env: &RuntimeEnvironment,
+        bytecode: &[u8],
+        constructor_args: &[u8],
+    ) -> Result<String> {
+        self.rpc.deploy_contract(env, bytecode, constructor_args).await
+    }
+
+    async fn call_function(
+        &self,
+        env: &RuntimeEnvironment,
+        contract_address: &str,
+        function: &str,
+        args: &[u8],
+    ) -> Result<Vec<u8>> {
+        self.rpc.call_function(env, contract_address, function, args).await
+    }
+
+    fn metrics_definition(&self) -> Vec<RuntimeMetricDefinition> {
+        self.rpc.metrics_definition()
+    }
+
+    async fn monitor(
+        &self,
+        env: &RuntimeEnvironment,
+        execution_id: &str,
+    ) -> Result<Vec<RuntimeEvent>> {
+        self.rpc.monitor(env, execution_id).await
+    }
+
+    async fn destroy(&self, env: RuntimeEnvironment) -> Result<()> {
+        info!("Removing container of environment {}", env.environment_id);
+        let container = self.containers.lock().map_err(|_| poisoned())?.remove(&env.environment_id);
+        self.rpc.destroy(env).await?;
+        match container {
+            Some(container) => {
+                container.follow.abort();
+                self.remove_container(&container.id).await
+            }
+            None => Ok(()),
+        }
+    }
+
+    async fn is_available(&self) -> bool {
+        self.call("GET", "/_ping", None).await.is_ok()
+    }
+
+    fn capabilities(&self) -> RuntimeCapabilities {
+        self.rpc.capabilities()
+    }
+
+    async fn grant_role(&self, env: &RuntimeEnvironment, account: &str, role: &str) -> Result<()> {
+        self.rpc.grant_role(env, account, role).await
+    }
+
+    async fn revoke_role(&self, env: &RuntimeEnvironment, account: &str, role: &str) -> Result<bool> {
+        self.rpc.revoke_role(env, account, role).await
+    }
+
+    async fn execute_secure(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+        security_config: &SecurityConfig,
+    ) -> Result<ExecutionResult> {
+        self.rpc.execute_secure(env, code_path, inputs, security_config).await
+    }
+
+    async fn check_reentrancy(
+        &self,
+        env: &RuntimeEnvironment,
+        function_name: &str,
+        caller: &str,
+        call_stack: &[String],
+    ) -> Result<bool> {
+        self.rpc.check_reentrancy(env, function_name, caller, call_stack).await
+    }
+
+    async fn detect_overflow(
+        &self,
+        env: &RuntimeEnvironment,
+        operation: &str,
+        operands: &[i64],
+    ) -> Result<bool> {
+        self.rpc.detect_overflow(env, operation, operands).await
+    }
+
+    async fn verify_access_control(
+        &self,
+        env: &RuntimeEnvironment,
+        function_name: &str,
+        caller: &str,
+        required_role: Option<&str>,
+    ) -> Result<bool> {
+        self.rpc
+            .verify_access_control(env, function_name, caller, required_role)
+            .await
+    }
+
+    async fn enforce_resource_limits(
+        &self,
+        env: &RuntimeEnvironment,
+        gas_used: u64,
+        memory_used: u64,
+        call_depth: u32,
+        external_calls: u32,
+        security_config: &SecurityConfig,
+    ) -> Result<Vec<SecurityViolation>> {
+        self.rpc
+            .enforce_resource_limits(env, gas_used, memory_used, call_depth, external_calls, security_config)
+            .await
+    }
+
+    async fn get_security_report(
+        &self,
+        env: &RuntimeEnvironment,
+        execution_id: &str,
+    ) -> Result<SecurityReport> {
+        self.rpc.get_security_report(env, execution_id).await
+    }
+}
+
+impl Drop for DockerRuntime {
+    fn drop(&mut self) {
+        // Containers of environments never destroyed are not left running
+        let containers: Vec<Container> = match self.containers.get_mut() {
+            Ok(containers) => containers.drain().map(|(_, container)| container).collect(),
+            Err(_) => return,
+        };
+        if containers.is_empty() {
+            return;
+        }
+        let runtime = &*self;
+        block_on_isolated(async move {
+            for container in containers {
+                container.follow.abort();
+                let _ = runtime.remove_container(&container.id).await;
+            }
+        });
+    }
+}
+
+/// Follow a container's output into `logs` until the container stops
+async fn follow_logs(socket_path: PathBuf, id: String, logs: NodeLogs, stopped: Arc<AtomicBool>) {
+    let followed: Result<()> = async {
+        let stream = UnixStream::connect(&socket_path).await?;
+        let path = format!("/containers/{}/logs?follow=true&stdout=true&stderr=true", id);
+        let mut response = http::send_streaming(stream, "GET", "docker", &path, &[], &[]).await?;
+        if !response.is_success() {
+            return Err(anyhow!("HTTP {}", response.status));
+        }
+        let mut output = LogDemultiplexer::default();
+        while let Some(chunk) = response.next_chunk().await? {
+            output.feed(&chunk, &logs);
+        }
+        output.finish(&logs);
+        Ok(())
+    }
+    .await;
+    if let Err(_error) = followed {
+        info!("Stopped following logs of container {}: {:#}", id, _error);
+    }
+    stopped.store(true, Ordering::SeqCst);
+}
+
+/// Splits the multiplexed stream Docker sends for containers without a TTY
+///
+/// Each frame is an 8 byte header, holding the stream in its first byte and
+/// the payload size big-endian in its last four, followed by the payload.
+#[derive(Default)]
+struct LogDemultiplexer {
+    pending: Vec<u8>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+impl LogDemultiplexer {
+    fn feed(&mut self, data: &[u8], logs: &NodeLogs) {
+        self.pending.extend_from_slice(data);
+        while self.pending.len() >= 8 {
+            let size = u32::from_be_bytes([self.pending[4], self.pending[5], self.pending[6], self.pending[7]]) as usize;
+            if self.pending.len() < 8 + size {
+                break;
+            }
+            let frame: Vec<u8> = self.pending.drain(..8 + size).collect();
+            let (stream, line) = match frame[0] {
+                2 => (LogStream::Stderr, &mut self.stderr),
+                _ => (LogStream::Stdout, &mut self.stdout),
+            };
+            line.extend_from_slice(&frame[8..]);
+            while let Some(end) = line.iter().position(|byte| *byte == b'\n') {
+                let text: Vec<u8> = line.drain(..=end).collect();
+                logs.push(stream, String::from_utf8_lossy(&text).trim_end().to_string());
+            }
+        }
+    }
+
+    /// Record output left without a trailing newline
+    fn finish(&mut self, logs: &NodeLogs) {
+        for (stream, line) in [(LogStream::Stdout, &mut self.stdout), (LogStream::Stderr, &mut self.stderr)] {
+            if !line.is_empty() {
+                logs.push(stream, String::from_utf8_lossy(line).trim_end().to_string());
+                line.clear();
+            }
+        }
+    }
+}
+
+/// Error for a failed Engine API request, with the daemon's message
+fn docker_error(method: &str, path: &str, response: &HttpResponse) -> RuntimeError {
+    let message = serde_json::from_slice::<serde_json::Value>(&response.body)
+        .ok()
+        .and_then(|body| body["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| String::from_utf8_lossy(&response.body).trim().to_string());
+    RuntimeError::Backend(format!(
+        "Docker {} {} returned HTTP {}: {}",
+        method, path, response.status, message
+    ))
+}
+
+fn poisoned() -> RuntimeError {
+    RuntimeError::Backend("Docker runtime state is poisoned".to_string())
+}
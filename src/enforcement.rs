@@ -0,0 +1,223 @@
+//! Enforcement layer running every security check against an execution
+
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::determinism::{Clock, SystemClock};
+use crate::error::{RuntimeError, RuntimeResult};
+use crate::reentrancy::CallFrame;
+use crate::security::{SecurityConfig, SecurityValidator};
+use crate::types::{ExecutionResult, SecurityViolation, SecuritySeverity};
+
+/// What `execute_secure` does about a violation
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ViolationAction {
+    /// Report the violation and keep the execution's effects
+    #[default]
+    Continue,
+    /// Fail the execution and roll back its state changes
+    Abort,
+}
+
+/// Action taken for violations of each severity
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EnforcementPolicy {
+    #[serde(default)]
+    pub critical: ViolationAction,
+    #[serde(default)]
+    pub high: ViolationAction,
+    #[serde(default)]
+    pub medium: ViolationAction,
+    #[serde(default)]
+    pub low: ViolationAction,
+}
+
+impl EnforcementPolicy {
+    /// Report every violation without aborting
+    pub fn report_only() -> Self {
+        Self::default()
+    }
+
+    /// Abort on violations of a severity or above
+    pub fn abort_at(severity: SecuritySeverity) -> Self {
+        let action = |level: SecuritySeverity| {
+            if level >= severity {
+                ViolationAction::Abort
+            } else {
+                ViolationAction::Continue
+            }
+        };
+        Self {
+            critical: action(SecuritySeverity::Critical),
+            high: action(SecuritySeverity::High),
+            medium: action(SecuritySeverity::Medium),
+            low: action(SecuritySeverity::Low),
+        }
+    }
+
+    /// Set the action for one severity
+    pub fn with_action(mut self, severity: SecuritySeverity, action: ViolationAction) -> Self {
+        *match severity {
+            SecuritySeverity::Critical => &mut self.critical,
+            SecuritySeverity::High => &mut self.high,
+            SecuritySeverity::Medium => &mut self.medium,
+            SecuritySeverity::Low => &mut self.low,
+        } = action;
+        self
+    }
+
+    /// Action for violations of a severity
+    pub fn action(&self, severity: &SecuritySeverity) -> ViolationAction {
+        match severity {
+            SecuritySeverity::Critical => self.critical,
+            SecuritySeverity::High => self.high,
+            SecuritySeverity::Medium => self.medium,
+            SecuritySeverity::Low => self.low,
+        }
+    }
+
+    /// Whether any severity aborts
+    pub fn can_abort(&self) -> bool {
+        [self.critical, self.high, self.medium, self.low].contains(&ViolationAction::Abort)
+    }
+}
+
+/// Runs all validators over an execution and applies the enforcement policy
+///
+/// Every check runs even after one fails, so a result lists all of its
+/// violations. Backends call `enforce` from `execute_secure`, roll back the
+/// execution's state when it returns an error, and return that error.
+pub struct SecurityEnforcer {
+    validator: SecurityValidator,
+    policy: EnforcementPolicy,
+}
+
+impl SecurityEnforcer {
+    /// Create an enforcer applying the configuration's `enforcement` policy
+    pub fn new(config: SecurityConfig) -> Self {
+        Self::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// Create an enforcer stamping violations with a custom clock
+    pub fn with_clock(config: SecurityConfig, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            policy: config.enforcement,
+            validator: SecurityValidator::with_clock(config, clock),
+        }
+    }
+
+    pub fn validator(&self) -> &SecurityValidator {
+        &self.validator
+    }
+
+    pub fn policy(&self) -> &EnforcementPolicy {
+        &self.policy
+    }
+
+    /// Check call depth, external calls, gas and memory, collecting every violation
+    pub fn check_resource_limits(
+        &self,
+        gas_used: u64,
+        memory_used: u64,
+        call_depth: u32,
+        external_calls: u32,
+    ) -> Vec<SecurityViolation> {
+        [
+            self.validator.validate_call_depth(call_depth),
+            self.validator.validate_external_calls(external_calls),
+            self.validator.validate_gas_usage(gas_used),
+            self.validator.validate_memory_usage(memory_used),
+        ]
+        .into_iter()
+        .filter_map(|check| check.err())
+        .collect()
+    }
+
+    /// Run resource limit, reentrancy and access control checks against an execution
+    ///
+    /// Reentrancy is detected from the call tree when the backend records one,
+    /// and from the call stack otherwise. The access control check is recorded
+    /// in the result's security context.
+    pub fn check_execution(
+        &self,
+        result: &mut ExecutionResult,
+        function_name: &str,
+        caller: &str,
+        call_tree: Option<&CallFrame>,
+    ) -> Vec<SecurityViolation> {
+        let context = &mut result.security_context;
+        let mut violations = self.check_resource_limits(
+            context.gas_used,
+            context.memory_used,
+            context.call_depth,
+            context.external_call_count,
+        );
+
+        match call_tree {
+            Some(call_tree) => violations.extend(self.validator.detect_reentrancy(call_tree)),
+            None => violations.extend(
+                self.validator
+                    .check_reentrancy(function_name, caller, &context.call_stack)
+                    .err(),
+            ),
+        }
+
+        violations.extend(
+            self.validator
+                .record_access_control(context, function_name, caller, None)
+                .err(),
+        );
+        violations
+    }
+
+    /// Action the policy takes for a set of violations, aborting if any one calls for it
+    pub fn decide(&self, violations: &[SecurityViolation]) -> ViolationAction {
+        if violations
+            .iter()
+            .any(|violation| self.policy.action(&violation.severity) == ViolationAction::Abort)
+        {
+            ViolationAction::Abort
+        } else {
+            ViolationAction::Continue
+        }
+    }
+
+    /// Run every check, record the violations in the result and apply the policy
+    ///
+    /// When the policy aborts, the result is marked as failed and loses its
+    /// state changes, and the most severe violation is returned as
+    /// `RuntimeError::SecurityViolation` with the execution id in its
+    /// context. The caller is responsible for rolling back the state itself.
+    pub fn enforce(
+        &self,
+        result: &mut ExecutionResult,
+        function_name: &str,
+        caller: &str,
+        call_tree: Option<&CallFrame>,
+    ) -> RuntimeResult<()> {
+        let violations = self.check_execution(result, function_name, caller, call_tree);
+        let outcome = match self.decide(&violations) {
+            ViolationAction::Abort => {
+                let mut cause = violations
+                    .iter()
+                    .filter(|violation| self.policy.action(&violation.severity) == ViolationAction::Abort)
+                    .max_by_key(|violation| &violation.severity)
+                    .cloned()
+                    .expect("an aborting policy has a violation to abort on");
+                result.success = false;
+                result.error = Some(format!("Execution aborted by security policy: {}", cause.description));
+                result.state_changes.clear();
+                cause
+                    .context
+                    .insert("execution_id".to_string(), result.execution_id.clone().into());
+                Err(RuntimeError::SecurityViolation(cause))
+            }
+            ViolationAction::Continue => Ok(()),
+        };
+
+        result.security_context.security_violations.extend(violations.iter().cloned());
+        result.security_violations.extend(violations);
+        outcome
+    }
+}
@@ -0,0 +1,77 @@
+//! Error types for runtime operations
+
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::types::{Capability, EnvironmentState, SecurityViolation, SnapshotId};
+
+/// Failure of a runtime operation
+///
+/// Trait methods return `anyhow::Result`; errors raised by this crate carry a
+/// `RuntimeError` that callers can recover with `downcast_ref::<RuntimeError>()`.
+#[derive(Debug, Clone, Error)]
+pub enum RuntimeError {
+    #[error("Environment {0} not found")]
+    EnvironmentNotFound(String),
+
+    #[error("Execution {0} not found")]
+    ExecutionNotFound(String),
+
+    #[error("Snapshot {} not found in {environment_id}", .snapshot.0)]
+    SnapshotNotFound {
+        environment_id: String,
+        snapshot: SnapshotId,
+    },
+
+    #[error("Runtime {blockchain_id} does not support {capability}")]
+    UnsupportedCapability {
+        blockchain_id: String,
+        capability: Capability,
+    },
+
+    #[error("Operation timed out after {0:?}")]
+    Timeout(Duration),
+
+    #[error("Security violation: {}", .0.description)]
+    SecurityViolation(SecurityViolation),
+
+    #[error("Backend failure: {0}")]
+    Backend(String),
+
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
+    #[error("Invalid configuration for {field}: {message}")]
+    InvalidConfig { field: String, message: String },
+
+    #[error("Environment {environment_id} cannot go from {from:?} to {to:?}")]
+    InvalidTransition {
+        environment_id: String,
+        from: EnvironmentState,
+        to: EnvironmentState,
+    },
+}
+
+impl RuntimeError {
+    /// Create an invalid configuration error
+    pub fn invalid_config(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::InvalidConfig {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+
+    /// Find the runtime error behind an `anyhow::Error`, if there is one
+    pub fn find(error: &anyhow::Error) -> Option<&RuntimeError> {
+        error.chain().find_map(|cause| cause.downcast_ref::<RuntimeError>())
+    }
+}
+
+impl From<SecurityViolation> for RuntimeError {
+    fn from(violation: SecurityViolation) -> Self {
+        Self::SecurityViolation(violation)
+    }
+}
+
+/// Result of a runtime operation
+pub type RuntimeResult<T> = std::result::Result<T, RuntimeError>;
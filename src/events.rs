@@ -0,0 +1,101 @@
+//! Streaming event subscriptions
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_stream::Stream;
+
+use crate::types::RuntimeEvent;
+
+/// Filter selecting which events a subscription receives
+///
+/// Empty fields match everything, so the default filter receives every event.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct EventFilter {
+    /// Accepted event types, any of which may match
+    #[serde(default)]
+    pub event_types: Vec<String>,
+    /// Keys that must all be present in the event data
+    #[serde(default)]
+    pub data_keys: Vec<String>,
+    /// Data entries that must all be present with exactly these values
+    #[serde(default)]
+    pub data_values: HashMap<String, serde_json::Value>,
+}
+
+impl EventFilter {
+    /// Create a filter that matches every event
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only match events of this type, in addition to any types already accepted
+    pub fn event_type(mut self, event_type: impl Into<String>) -> Self {
+        self.event_types.push(event_type.into());
+        self
+    }
+
+    /// Only match events whose data contains this key
+    pub fn data_key(mut self, key: impl Into<String>) -> Self {
+        self.data_keys.push(key.into());
+        self
+    }
+
+    /// Only match events whose data holds this value under the key
+    pub fn data_value(mut self, key: impl Into<String>, value: serde_json::Value) -> Self {
+        self.data_values.insert(key.into(), value);
+        self
+    }
+
+    /// Check whether an event passes the filter
+    pub fn matches(&self, event: &RuntimeEvent) -> bool {
+        (self.event_types.is_empty() || self.event_types.contains(&event.event_type))
+            && self.data_keys.iter().all(|key| event.data.contains_key(key))
+            && self
+                .data_values
+                .iter()
+                .all(|(key, value)| event.data.get(key) == Some(value))
+    }
+}
+
+/// Stream of events delivered to a subscriber as they occur
+///
+/// The stream ends once the environment it was opened on is destroyed.
+#[derive(Debug)]
+pub struct EventSubscription {
+    receiver: UnboundedReceiver<RuntimeEvent>,
+}
+
+impl Stream for EventSubscription {
+    type Item = RuntimeEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// Fan-out of an environment's events to its subscribers
+#[derive(Debug, Default)]
+pub(crate) struct EventBus {
+    subscribers: Vec<(EventFilter, UnboundedSender<RuntimeEvent>)>,
+}
+
+impl EventBus {
+    /// Open a subscription receiving every later event that passes the filter
+    pub(crate) fn subscribe(&mut self, filter: EventFilter) -> EventSubscription {
+        let (sender, receiver) = unbounded_channel();
+        self.subscribers.push((filter, sender));
+        EventSubscription { receiver }
+    }
+
+    /// Deliver events to matching subscribers, dropping those that went away
+    pub(crate) fn publish<'a>(&mut self, events: impl IntoIterator<Item = &'a RuntimeEvent>) {
+        for event in events {
+            self.subscribers.retain(|(filter, sender)| {
+                !sender.is_closed() && (!filter.matches(event) || sender.send(event.clone()).is_ok())
+            });
+        }
+    }
+}
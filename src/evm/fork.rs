@@ -0,0 +1,408 @@
+//! Remote state for forked EVM environments
+//!
+//! Accounts, code and storage are fetched from an upstream node at a pinned
+//! block the first time execution touches them, and written to a cache file
+//! for that block so later runs are served from disk without the network.
+//! Fetches run on a runtime owned by the fork and time out after
+//! `FORK_FETCH_TIMEOUT_SECONDS`, and the cache is written once per
+//! transaction rather than per fetch.
+
+use anyhow::{anyhow, bail, Context, Result};
+use primitive_types::{H160, U256};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::state::{format_address, format_word, EvmAccount};
+use crate::config::RuntimeConfig;
+use crate::constants::{DEFAULT_FORK_CACHE_DIR_NAME, DEFAULT_FORK_CHAIN_ID, FORK_FETCH_TIMEOUT_SECONDS};
+use crate::error::RuntimeError;
+use crate::rpc::JsonRpcClient;
+
+/// Header values of the pinned block
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub(crate) struct ForkBlock {
+    pub chain_id: u64,
+    pub timestamp: u64,
+    pub gas_limit: u64,
+    pub coinbase: String,
+    pub base_fee: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedAccount {
+    balance: String,
+    nonce: u64,
+    code: String,
+}
+
+/// Everything fetched at one block, as stored on disk
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ForkCache {
+    block_number: u64,
+    block: Option<ForkBlock>,
+    /// Accounts by address, `None` for accounts that do not exist
+    #[serde(default)]
+    accounts: BTreeMap<String, Option<CachedAccount>>,
+    /// Storage words by address and slot
+    #[serde(default)]
+    storage: BTreeMap<String, BTreeMap<String, String>>,
+    /// Changed since it was last written
+    #[serde(skip)]
+    dirty: bool,
+}
+
+/// Upstream node and the runtime synchronous fetches from it run on
+struct Upstream {
+    client: Arc<JsonRpcClient>,
+    executor: Option<tokio::runtime::Runtime>,
+}
+
+struct ForkInner {
+    upstream: Option<Upstream>,
+    block_number: u64,
+    cache_path: PathBuf,
+    cache: Mutex<ForkCache>,
+}
+
+impl Drop for ForkInner {
+    fn drop(&mut self) {
+        if let Ok(cache) = self.cache.get_mut() {
+            if cache.dirty {
+                let _ = write_cache(&self.cache_path, cache);
+            }
+        }
+        // Dropping a runtime blocks, which is not allowed inside another one
+        if let Some(executor) = self.upstream.as_mut().and_then(|upstream| upstream.executor.take()) {
+            executor.shutdown_background();
+        }
+    }
+}
+
+/// Upstream state at a pinned block, shared by every copy of a forked world
+#[derive(Clone)]
+pub(crate) struct ForkSource {
+    inner: Arc<ForkInner>,
+}
+
+impl fmt::Debug for ForkSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ForkSource")
+            .field("block_number", &self.inner.block_number)
+            .field("cache_path", &self.inner.cache_path)
+            .finish()
+    }
+}
+
+impl ForkSource {
+    /// Open the fork described by `blockchain_config`
+    ///
+    /// `fork_url` (or `rpc_url`) names the upstream node and may be left out
+    /// once the cache holds everything a run needs. `fork_block` pins
+    /// the block, defaulting to the upstream's latest. The cache lives under
+    /// `fork_cache_dir`, one file per chain and block, filed under the chain
+    /// the upstream reports or, without one, under `chain_id`.
+    pub(crate) async fn open(config: &RuntimeConfig) -> Result<Self> {
+        let setting = |key: &str| config.blockchain_config.get(key);
+        let client = setting("fork_url")
+            .or_else(|| setting("rpc_url"))
+            .map(|url| {
+                url.as_str()
+                    .ok_or_else(|| RuntimeError::invalid_config("blockchain_config.fork_url", "must be a string"))
+                    .map_err(anyhow::Error::from)
+                    .and_then(JsonRpcClient::new)
+            })
+            .transpose()?
+            .map(Arc::new);
+
+        let configured_chain_id = setting("chain_id")
+            .map(|id| {
+                id.as_u64()
+                    .ok_or_else(|| RuntimeError::invalid_config("blockchain_config.chain_id", "must be a number"))
+            })
+            .transpose()?;
+        let chain_id = match &client {
+            Some(client) => quantity(&call(client, "eth_chainId", serde_json::json!([])).await?)?,
+            None => configured_chain_id.unwrap_or(DEFAULT_FORK_CHAIN_ID),
+        };
+        if let Some(configured) = configured_chain_id.filter(|configured| *configured != chain_id) {
+            return Err(RuntimeError::invalid_config(
+                "blockchain_config.chain_id",
+                format!("fork is of chain {}, not {}", chain_id, configured),
+            )
+            .into());
+        }
+
+        let block_number = match setting("fork_block") {
+            Some(number) => number.as_u64().ok_or_else(|| {
+                RuntimeError::invalid_config("blockchain_config.fork_block", "must be a block number")
+            })?,
+            None => {
+                let client = client.as_ref().ok_or_else(|| {
+                    RuntimeError::invalid_config(
+                        "blockchain_config.fork_url",
+                        "a fork needs an upstream node or a pinned fork_block",
+                    )
+                })?;
+                quantity(&call(client, "eth_blockNumber", serde_json::json!([])).await?)?
+            }
+        };
+
+        let cache_dir = match setting("fork_cache_dir") {
+            Some(dir) => PathBuf::from(dir.as_str().ok_or_else(|| {
+                RuntimeError::invalid_config("blockchain_config.fork_cache_dir", "must be a path")
+            })?),
+            None => std::env::temp_dir().join(DEFAULT_FORK_CACHE_DIR_NAME),
+        };
+        let cache_path = cache_dir.join(chain_id.to_string()).join(format!("{}.json", block_number));
+        let cache = match tokio::fs::read(&cache_path).await {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .with_context(|| format!("Fork cache {} is corrupt", cache_path.display()))?,
+            Err(_) => ForkCache {
+                block_number,
+                ..ForkCache::default()
+            },
+        };
+
+        let upstream = match client {
+            Some(client) => {
+                let executor = tokio::runtime::Builder::new_multi_thread()
+                    .worker_threads(1)
+                    .enable_all()
+                    .build()
+                    .context("Failed to start a runtime for fork fetches")?;
+                Some(Upstream {
+                    client,
+                    executor: Some(executor),
+                })
+            }
+            None => None,
+        };
+        let source = Self {
+            inner: Arc::new(ForkInner {
+                upstream,
+                block_number,
+                cache_path,
+                cache: Mutex::new(cache),
+            }),
+        };
+        if source.cached(|cache| cache.block.is_none())? {
+            let block = source.fetch_block(chain_id).await?;
+            source.update(|cache| cache.block = Some(block))?;
+            source.save()?;
+        }
+        let cached_chain_id = source.block()?.chain_id;
+        if cached_chain_id != chain_id {
+            return Err(RuntimeError::invalid_config(
+                "blockchain_config.chain_id",
+                format!("fork is of chain {}, not {}", cached_chain_id, chain_id),
+            )
+            .into());
+        }
+        Ok(source)
+    }
+
+    pub(crate) fn block_number(&self) -> u64 {
+        self.inner.block_number
+    }
+
+    pub(crate) fn block(&self) -> Result<ForkBlock> {
+        self.cached(|cache| cache.block.clone())?
+            .ok_or_else(|| anyhow!("Fork block {} has no cached header", self.inner.block_number))
+    }
+
+    /// Account at the pinned block, `None` if it does not exist there
+    pub(crate) fn account(&self, address: &H160) -> Result<Option<EvmAccount>> {
+        let key = format_address(address);
+        let cached = match self.cached(|cache| cache.accounts.get(&key).cloned())? {
+            Some(cached) => cached,
+            None => {
+                let block = self.block_tag();
+                let balance = self.fetch("eth_getBalance", serde_json::json!([key, block]))?;
+                let nonce = self.fetch("eth_getTransactionCount", serde_json::json!([key, block]))?;
+                let code = self.fetch("eth_getCode", serde_json::json!([key, block]))?;
+                let account = CachedAccount {
+                    balance: string(&balance)?,
+                    nonce: quantity(&nonce)?,
+                    code: string(&code)?,
+                };
+                let exists = !parse_word(&account.balance)?.is_zero()
+                    || account.nonce != 0
+                    || !account.code.trim_start_matches("0x").is_empty();
+                let cached = exists.then_some(account);
+                self.update(|cache| {
+                    cache.accounts.insert(key, cached.clone());
+                })?;
+                cached
+            }
+        };
+
+        cached
+            .map(|account| {
+                Ok(EvmAccount {
+                    balance: parse_word(&account.balance)?,
+                    nonce: account.nonce,
+                    code: hex::decode(account.code.trim_start_matches("0x"))
+                        .context("Cached code is not hex")?,
+                    storage: Default::default(),
+                })
+            })
+            .transpose()
+    }
+
+    /// Storage word at the pinned block
+    pub(crate) fn storage(&self, address: &H160, slot: &U256) -> Result<U256> {
+        let (key, slot_key) = (format_address(address), format_word(slot));
+        let cached = self.cached(|cache| cache.storage.get(&key).and_then(|slots| slots.get(&slot_key)).cloned())?;
+        let word = match cached {
+            Some(word) => word,
+            None => {
+                let word = string(&self.fetch(
+                    "eth_getStorageAt",
+                    serde_json::json!([key, slot_key, self.block_tag()]),
+                )?)?;
+                self.update(|cache| {
+                    cache.storage.entry(key).or_default().insert(slot_key, word.clone());
+                })?;
+                word
+            }
+        };
+        parse_word(&word)
+    }
+
+    async fn fetch_block(&self, chain_id: u64) -> Result<ForkBlock> {
+        let header = call(
+            self.client()?,
+            "eth_getBlockByNumber",
+            serde_json::json!([self.block_tag(), false]),
+        )
+        .await?;
+        if header.is_null() {
+            bail!("Upstream has no block {}", self.inner.block_number);
+        }
+        Ok(ForkBlock {
+            chain_id,
+            timestamp: quantity(&header["timestamp"])?,
+            gas_limit: quantity(&header["gasLimit"])?,
+            coinbase: string(&header["miner"]).unwrap_or_else(|_| format_address(&H160::zero())),
+            base_fee: string(&header["baseFeePerGas"]).unwrap_or_else(|_| "0x0".to_string()),
+        })
+    }
+
+    /// Write what was fetched since the last save to the cache file
+    pub(crate) fn save(&self) -> Result<()> {
+        let mut cache = self.inner.cache.lock().map_err(|_| poisoned())?;
+        if cache.dirty {
+            write_cache(&self.inner.cache_path, &cache)?;
+            cache.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Call the upstream from synchronous code, whether or not inside an async runtime
+    fn fetch(&self, method: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+        let upstream = self.upstream()?;
+        let executor = upstream.executor.as_ref().ok_or_else(|| anyhow!("Fork fetch runtime has stopped"))?;
+        let client = upstream.client.clone();
+        let method = method.to_string();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        executor.spawn(async move {
+            let _ = sender.send(call(&client, &method, params).await);
+        });
+        receiver.recv().map_err(|_| anyhow!("Fork fetch runtime has stopped"))?
+    }
+
+    fn client(&self) -> Result<&JsonRpcClient> {
+        Ok(&self.upstream()?.client)
+    }
+
+    fn upstream(&self) -> Result<&Upstream> {
+        self.inner.upstream.as_ref().ok_or_else(|| {
+            RuntimeError::Backend(format!(
+                "Fork state missing from {} and no fork_url is configured",
+                self.inner.cache_path.display()
+            ))
+            .into()
+        })
+    }
+
+    fn block_tag(&self) -> String {
+        format!("{:#x}", self.inner.block_number)
+    }
+
+    fn cached<T>(&self, f: impl FnOnce(&ForkCache) -> T) -> Result<T> {
+        let cache = self.inner.cache.lock().map_err(|_| poisoned())?;
+        Ok(f(&cache))
+    }
+
+    /// Change the cache, leaving it to `save` to write it back to disk
+    fn update(&self, f: impl FnOnce(&mut ForkCache)) -> Result<()> {
+        let mut cache = self.inner.cache.lock().map_err(|_| poisoned())?;
+        f(&mut cache);
+        cache.dirty = true;
+        Ok(())
+    }
+}
+
+/// Call the upstream, failing with `RuntimeError::Timeout` if it does not answer in time
+async fn call(client: &JsonRpcClient, method: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+    let timeout = Duration::from_secs(FORK_FETCH_TIMEOUT_SECONDS);
+    tokio::time::timeout(timeout, client.call(method, params))
+        .await
+        .map_err(|_| anyhow::Error::new(RuntimeError::Timeout(timeout)).context(format!("{} timed out", method)))?
+}
+
+/// Write through a temporary file so readers never see a partial cache
+///
+/// Each write uses its own temporary file, as other environments and
+/// processes may be saving the same block at the same time.
+fn write_cache(path: &Path, cache: &ForkCache) -> Result<()> {
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create fork cache directory {}", dir.display()))?;
+    }
+    let write = WRITES.fetch_add(1, Ordering::Relaxed);
+    let partial = path.with_extension(format!("json.{}.{}.partial", std::process::id(), write));
+    let written = std::fs::write(&partial, serde_json::to_vec_pretty(cache)?)
+        .with_context(|| format!("Failed to write fork cache {}", partial.display()))
+        .and_then(|()| {
+            std::fs::rename(&partial, path).with_context(|| format!("Failed to write fork cache {}", path.display()))
+        });
+    if written.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    written
+}
+
+fn quantity(value: &serde_json::Value) -> Result<u64> {
+    let word = parse_word(&string(value)?)?;
+    if word > U256::from(u64::MAX) {
+        bail!("{} does not fit in 64 bits", value);
+    }
+    Ok(word.low_u64())
+}
+
+fn parse_word(text: &str) -> Result<U256> {
+    let digits = text.trim_start_matches("0x");
+    if digits.is_empty() {
+        return Ok(U256::zero());
+    }
+    U256::from_str_radix(digits, 16).map_err(|_| anyhow!("{} is not a hex word", text))
+}
+
+fn string(value: &serde_json::Value) -> Result<String> {
+    value
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("Expected a hex string, got {}", value))
+}
+
+fn poisoned() -> RuntimeError {
+    RuntimeError::Backend("Fork cache is poisoned".to_string())
+}
@@ -0,0 +1,1652 @@
+//! Bytecode interpreter for the EVM backend
+//!
+//! Call frames are kept on an explicit stack instead of the Rust call stack so
+//! the full 1024-frame depth allowed by the EVM cannot overflow a worker
+//! thread. Opcodes and gas follow the Cancun hardfork: warm and cold access
+//! costs (EIP-2929), net-metered `SSTORE` with its stipend check (EIP-2200)
+//! and capped refunds (EIP-3529), transient storage, `MCOPY` and `BLOBHASH`.
+//! Precompiled contracts are not implemented, so calls to them succeed with
+//! empty output, and transactions carry no blobs.
+
+use primitive_types::{H160, U256, U512};
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+use tiny_keccak::{Hasher, Keccak};
+
+use super::state::{format_address, format_word, BlockEnv, EvmState};
+use crate::constants::{
+    EVM_DEADLINE_CHECK_INTERVAL, EVM_MAX_CALL_DEPTH, EVM_MAX_CODE_SIZE, EVM_MAX_INITCODE_SIZE, EVM_MAX_MEMORY_BYTES,
+    EVM_MAX_STACK_SIZE,
+};
+use crate::reentrancy::{CallFrame, FrameStep};
+use crate::trace::TraceInstruction;
+use crate::types::{FrameGasUsage, StateChange};
+
+/// Cost of touching an account or slot already accessed in the transaction
+const WARM_ACCESS_COST: u64 = 100;
+/// Cost of the first access to an account in a transaction
+const COLD_ACCOUNT_ACCESS_COST: u64 = 2_600;
+/// Cost of the first access to a storage slot in a transaction
+const COLD_SLOAD_COST: u64 = 2_100;
+/// Cost of writing a zero slot to a non-zero value
+const SSTORE_SET_COST: u64 = 20_000;
+/// Cost of changing a non-zero slot, less the cold access charged separately
+const SSTORE_RESET_COST: u64 = 5_000 - COLD_SLOAD_COST;
+/// Refund for clearing a slot that was non-zero at the start of the transaction
+const SSTORE_CLEARS_REFUND: i64 = 4_800;
+/// Gas a frame must have more than to write storage, so a call stipend cannot (EIP-2200)
+const SSTORE_STIPEND: u64 = 2_300;
+/// Refunds are capped at this fraction of the gas used
+const MAX_REFUND_QUOTIENT: u64 = 5;
+/// Precompiled contracts, warm from the start of every transaction
+const PRECOMPILE_COUNT: u64 = 10;
+
+/// Transaction submitted to the interpreter
+#[derive(Debug, Clone)]
+pub struct TransactionRequest {
+    pub caller: H160,
+    /// Recipient, or `None` to create a contract from `data`
+    pub to: Option<H160>,
+    pub value: U256,
+    pub data: Vec<u8>,
+    pub gas_limit: u64,
+    /// Wall-clock time after which execution halts, whatever gas remains
+    pub deadline: Option<Instant>,
+    /// Record every instruction executed in `CallTrace::instructions`
+    pub record_instructions: bool,
+}
+
+/// Log emitted by `LOG0`..`LOG4`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Log {
+    pub address: H160,
+    pub topics: Vec<U256>,
+    pub data: Vec<u8>,
+}
+
+/// Call frame statistics gathered while executing a transaction
+#[derive(Debug, Clone, Default)]
+pub struct CallTrace {
+    /// Deepest call depth reached, the top-level frame being depth 1
+    pub max_depth: u32,
+    /// Number of CALL, CALLCODE, DELEGATECALL and STATICCALL instructions
+    pub external_calls: u32,
+    /// Live frames at the deepest point of execution
+    pub deepest_stack: Vec<String>,
+    /// Largest combined memory of all live frames in bytes
+    pub peak_memory: u64,
+    /// Storage accesses and sub-calls of the top-level frame and everything it called
+    pub call_tree: Option<CallFrame>,
+    /// Gas used by every frame, in the order the frames were entered
+    pub frames: Vec<FrameGasUsage>,
+    /// Instructions executed, when requested
+    pub instructions: Vec<TraceInstruction>,
+}
+
+/// Result of running a transaction
+#[derive(Debug, Clone)]
+pub struct TransactionOutcome {
+    pub success: bool,
+    pub output: Vec<u8>,
+    pub gas_used: u64,
+    pub created_address: Option<H160>,
+    pub logs: Vec<Log>,
+    pub error: Option<String>,
+    pub state_changes: Vec<StateChange>,
+    pub trace: CallTrace,
+    /// Whether execution halted because the deadline passed
+    pub timed_out: bool,
+    /// Forked state that could not be loaded; the transaction was rolled back
+    pub fork_error: Option<String>,
+}
+
+/// Why a frame stopped executing
+#[derive(Debug, Clone, PartialEq)]
+enum Halt {
+    Stop,
+    Return(Vec<u8>),
+    Revert(Vec<u8>),
+    Error(&'static str),
+}
+
+/// Work requested by a frame that the machine must perform
+enum Action {
+    Call(CallRequest),
+    Exit(Halt),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CallKind {
+    Call,
+    CallCode,
+    DelegateCall,
+    StaticCall,
+    Create,
+}
+
+/// Parameters of a sub-call or contract creation
+struct CallRequest {
+    kind: CallKind,
+    caller: H160,
+    address: H160,
+    code_address: H160,
+    value: U256,
+    /// Whether `value` moves funds, which `DELEGATECALL` only reports
+    transfers_value: bool,
+    input: Vec<u8>,
+    gas: u64,
+    is_static: bool,
+    ret_offset: usize,
+    ret_size: usize,
+}
+
+struct Frame {
+    kind: CallKind,
+    depth: u32,
+    caller: H160,
+    address: H160,
+    code: Vec<u8>,
+    jumpdests: Vec<bool>,
+    input: Vec<u8>,
+    value: U256,
+    is_static: bool,
+    pc: usize,
+    stack: Vec<U256>,
+    memory: Vec<u8>,
+    gas_remaining: u64,
+    return_data: Vec<u8>,
+    checkpoint: usize,
+    substate_checkpoint: usize,
+    logs_checkpoint: usize,
+    ret_offset: usize,
+    ret_size: usize,
+    /// Kind of the sub-call this frame is waiting on
+    pending: CallKind,
+    name: String,
+    gas_limit: u64,
+    /// Position of this frame in `CallTrace::frames`
+    trace_index: usize,
+    /// Storage accesses and finished sub-calls, for reentrancy analysis
+    call: CallFrame,
+}
+
+/// Outcome of a finished frame handed back to its parent
+struct FrameResult {
+    success: bool,
+    output: Vec<u8>,
+    gas_remaining: u64,
+    created: Option<H160>,
+    error: Option<&'static str>,
+}
+
+/// Transaction-wide bookkeeping rolled back together with failed frames
+#[derive(Default)]
+struct Substate {
+    warm_accounts: HashSet<H160>,
+    warm_slots: HashSet<(H160, U256)>,
+    transient: HashMap<(H160, U256), U256>,
+    /// Accounts created in this transaction, which `SELFDESTRUCT` may delete (EIP-6780)
+    created: HashSet<H160>,
+    /// Storage values from before the transaction first wrote them
+    original_storage: HashMap<(H160, U256), U256>,
+    refund: i64,
+    journal: Vec<SubstateEntry>,
+}
+
+enum SubstateEntry {
+    WarmAccount(H160),
+    WarmSlot(H160, U256),
+    Transient(H160, U256, U256),
+    Created(H160),
+    Refund(i64),
+}
+
+impl Substate {
+    fn checkpoint(&self) -> usize {
+        self.journal.len()
+    }
+
+    fn revert_to(&mut self, checkpoint: usize) {
+        while self.journal.len() > checkpoint {
+            match self.journal.pop().expect("journal is longer than checkpoint") {
+                SubstateEntry::WarmAccount(address) => {
+                    self.warm_accounts.remove(&address);
+                }
+                SubstateEntry::WarmSlot(address, slot) => {
+                    self.warm_slots.remove(&(address, slot));
+                }
+                SubstateEntry::Transient(address, slot, old) => {
+                    self.transient.insert((address, slot), old);
+                }
+                SubstateEntry::Created(address) => {
+                    self.created.remove(&address);
+                }
+                SubstateEntry::Refund(old) => self.refund = old,
+            }
+        }
+    }
+
+    /// Mark an account as accessed, returning whether it was cold
+    fn access_account(&mut self, address: H160) -> bool {
+        let cold = self.warm_accounts.insert(address);
+        if cold {
+            self.journal.push(SubstateEntry::WarmAccount(address));
+        }
+        cold
+    }
+
+    /// Mark a storage slot as accessed, returning whether it was cold
+    fn access_slot(&mut self, address: H160, slot: U256) -> bool {
+        let cold = self.warm_slots.insert((address, slot));
+        if cold {
+            self.journal.push(SubstateEntry::WarmSlot(address, slot));
+        }
+        cold
+    }
+
+    fn transient(&self, address: H160, slot: U256) -> U256 {
+        self.transient.get(&(address, slot)).copied().unwrap_or_default()
+    }
+
+    fn set_transient(&mut self, address: H160, slot: U256, value: U256) {
+        let old = self.transient.insert((address, slot), value).unwrap_or_default();
+        self.journal.push(SubstateEntry::Transient(address, slot, old));
+    }
+
+    fn mark_created(&mut self, address: H160) {
+        if self.created.insert(address) {
+            self.journal.push(SubstateEntry::Created(address));
+        }
+    }
+
+    fn add_refund(&mut self, amount: i64) {
+        self.journal.push(SubstateEntry::Refund(self.refund));
+        self.refund += amount;
+    }
+}
+
+/// Fill in the gas each frame used itself, excluding its sub-calls
+fn assign_self_gas(frames: &mut [FrameGasUsage]) {
+    for index in 0..frames.len() {
+        let depth = frames[index].depth;
+        let children: u64 = frames[index + 1..]
+            .iter()
+            .take_while(|f| f.depth > depth)
+            .filter(|f| f.depth == depth + 1)
+            .map(|f| f.gas_used)
+            .sum();
+        frames[index].self_gas_used = frames[index].gas_used.saturating_sub(children);
+    }
+}
+
+/// Execute a transaction against the state and commit its effects
+pub fn transact(state: &mut EvmState, block: &BlockEnv, tx: TransactionRequest) -> TransactionOutcome {
+    let intrinsic = intrinsic_gas(&tx.data, tx.to.is_none());
+    if tx.gas_limit < intrinsic {
+        return TransactionOutcome {
+            success: false,
+            output: Vec::new(),
+            gas_used: 0,
+            created_address: None,
+            logs: Vec::new(),
+            error: Some("intrinsic gas exceeds gas limit".to_string()),
+            state_changes: Vec::new(),
+            trace: CallTrace::default(),
+            timed_out: false,
+            fork_error: None,
+        };
+    }
+
+    let checkpoint = state.checkpoint();
+    let nonce = state.increment_nonce(&tx.caller);
+    let address = match tx.to {
+        Some(to) => to,
+        None => create_address(&tx.caller, nonce),
+    };
+    let mut substate = Substate::default();
+    for warm in [tx.caller, address, block.coinbase]
+        .into_iter()
+        .chain((1..=PRECOMPILE_COUNT).map(H160::from_low_u64_be))
+    {
+        substate.access_account(warm);
+    }
+    let mut machine = Machine {
+        state,
+        block,
+        origin: tx.caller,
+        frames: Vec::new(),
+        logs: Vec::new(),
+        trace: CallTrace::default(),
+        deadline: tx.deadline,
+        instructions: 0,
+        timed_out: false,
+        record_instructions: tx.record_instructions,
+        substate,
+    };
+
+    let gas = tx.gas_limit - intrinsic;
+    let request = match tx.to {
+        Some(_) => CallRequest {
+            kind: CallKind::Call,
+            caller: tx.caller,
+            address,
+            code_address: address,
+            value: tx.value,
+            transfers_value: true,
+            input: tx.data,
+            gas,
+            is_static: false,
+            ret_offset: 0,
+            ret_size: 0,
+        },
+        None => CallRequest {
+            kind: CallKind::Create,
+            caller: tx.caller,
+            address,
+            code_address: H160::zero(),
+            value: tx.value,
+            transfers_value: true,
+            input: tx.data,
+            gas,
+            is_static: false,
+            ret_offset: 0,
+            ret_size: 0,
+        },
+    };
+
+    let result = machine.run(request);
+    let timed_out = machine.timed_out;
+    let refund = machine.substate.refund;
+    let mut trace = machine.trace;
+    assign_self_gas(&mut trace.frames);
+    let logs = if result.success { machine.logs } else { Vec::new() };
+    let fork_error = state.take_fork_error();
+    if fork_error.is_some() {
+        state.revert_to(checkpoint);
+        state.commit();
+        return TransactionOutcome {
+            success: false,
+            output: Vec::new(),
+            gas_used: 0,
+            created_address: None,
+            logs: Vec::new(),
+            error: fork_error.clone(),
+            state_changes: Vec::new(),
+            trace,
+            timed_out,
+            fork_error,
+        };
+    }
+    let state_changes = state.changes_since(checkpoint);
+    state.commit();
+
+    let error = if result.success {
+        None
+    } else if let Some(error) = result.error {
+        Some(error.to_string())
+    } else {
+        Some(match decode_revert_reason(&result.output) {
+            Some(reason) => format!("execution reverted: {}", reason),
+            None => "execution reverted".to_string(),
+        })
+    };
+
+    let gas_used = tx.gas_limit - result.gas_remaining;
+    let refund = u64::try_from(refund).unwrap_or_default().min(gas_used / MAX_REFUND_QUOTIENT);
+    TransactionOutcome {
+        success: result.success,
+        output: result.output,
+        gas_used: gas_used - refund,
+        created_address: result.created,
+        logs,
+        error,
+        state_changes,
+        trace,
+        timed_out,
+        fork_error: None,
+    }
+}
+
+/// Gas charged before any code runs
+pub fn intrinsic_gas(data: &[u8], is_create: bool) -> u64 {
+    let data_cost: u64 = data.iter().map(|b| if *b == 0 { 4 } else { 16 }).sum();
+    21_000 + data_cost + if is_create { 32_000 + initcode_cost(data.len()) } else { 0 }
+}
+
+/// Cost of hashing creation code into jump analysis (EIP-3860)
+fn initcode_cost(size: usize) -> u64 {
+    2 * (size as u64).div_ceil(32)
+}
+
+/// Compute the Keccak-256 hash of some data
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    hasher.update(data);
+    let mut output = [0u8; 32];
+    hasher.finalize(&mut output);
+    output
+}
+
+/// Address of a contract created with `CREATE`
+pub fn create_address(sender: &H160, nonce: u64) -> H160 {
+    // RLP encoding of [sender, nonce]
+    let nonce_bytes: Vec<u8> = nonce.to_be_bytes().iter().copied().skip_while(|b| *b == 0).collect();
+    let mut nonce_rlp = Vec::new();
+    match nonce_bytes.as_slice() {
+        [] => nonce_rlp.push(0x80),
+        [b] if *b < 0x80 => nonce_rlp.push(*b),
+        bytes => {
+            nonce_rlp.push(0x80 + bytes.len() as u8);
+            nonce_rlp.extend_from_slice(bytes);
+        }
+    }
+
+    let mut rlp = vec![0xc0 + (21 + nonce_rlp.len()) as u8, 0x94];
+    rlp.extend_from_slice(sender.as_bytes());
+    rlp.extend_from_slice(&nonce_rlp);
+    H160::from_slice(&keccak256(&rlp)[12..])
+}
+
+/// Address of a contract created with `CREATE2`
+pub fn create2_address(sender: &H160, salt: &U256, init_code: &[u8]) -> H160 {
+    let mut preimage = Vec::with_capacity(85);
+    preimage.push(0xff);
+    preimage.extend_from_slice(sender.as_bytes());
+    preimage.extend_from_slice(&salt.to_big_endian());
+    preimage.extend_from_slice(&keccak256(init_code));
+    H160::from_slice(&keccak256(&preimage)[12..])
+}
+
+/// Mnemonic of an opcode, `INVALID(0x..)` for unassigned ones
+pub fn opcode_name(opcode: u8) -> String {
+    let name = match opcode {
+        0x00 => "STOP",
+        0x01 => "ADD",
+        0x02 => "MUL",
+        0x03 => "SUB",
+        0x04 => "DIV",
+        0x05 => "SDIV",
+        0x06 => "MOD",
+        0x07 => "SMOD",
+        0x08 => "ADDMOD",
+        0x09 => "MULMOD",
+        0x0a => "EXP",
+        0x0b => "SIGNEXTEND",
+        0x10 => "LT",
+        0x11 => "GT",
+        0x12 => "SLT",
+        0x13 => "SGT",
+        0x14 => "EQ",
+        0x15 => "ISZERO",
+        0x16 => "AND",
+        0x17 => "OR",
+        0x18 => "XOR",
+        0x19 => "NOT",
+        0x1a => "BYTE",
+        0x1b => "SHL",
+        0x1c => "SHR",
+        0x1d => "SAR",
+        0x20 => "SHA3",
+        0x30 => "ADDRESS",
+        0x31 => "BALANCE",
+        0x32 => "ORIGIN",
+        0x33 => "CALLER",
+        0x34 => "CALLVALUE",
+        0x35 => "CALLDATALOAD",
+        0x36 => "CALLDATASIZE",
+        0x37 => "CALLDATACOPY",
+        0x38 => "CODESIZE",
+        0x39 => "CODECOPY",
+        0x3a => "GASPRICE",
+        0x3b => "EXTCODESIZE",
+        0x3c => "EXTCODECOPY",
+        0x3d => "RETURNDATASIZE",
+        0x3e => "RETURNDATACOPY",
+        0x3f => "EXTCODEHASH",
+        0x40 => "BLOCKHASH",
+        0x41 => "COINBASE",
+        0x42 => "TIMESTAMP",
+        0x43 => "NUMBER",
+        0x44 => "PREVRANDAO",
+        0x45 => "GASLIMIT",
+        0x46 => "CHAINID",
+        0x47 => "SELFBALANCE",
+        0x48 => "BASEFEE",
+        0x49 => "BLOBHASH",
+        0x4a => "BLOBBASEFEE",
+        0x50 => "POP",
+        0x51 => "MLOAD",
+        0x52 => "MSTORE",
+        0x53 => "MSTORE8",
+        0x54 => "SLOAD",
+        0x55 => "SSTORE",
+        0x56 => "JUMP",
+        0x57 => "JUMPI",
+        0x58 => "PC",
+        0x59 => "MSIZE",
+        0x5a => "GAS",
+        0x5b => "JUMPDEST",
+        0x5c => "TLOAD",
+        0x5d => "TSTORE",
+        0x5e => "MCOPY",
+        0x5f => "PUSH0",
+        0x60..=0x7f => return format!("PUSH{}", opcode - 0x5f),
+        0x80..=0x8f => return format!("DUP{}", opcode - 0x7f),
+        0x90..=0x9f => return format!("SWAP{}", opcode - 0x8f),
+        0xa0..=0xa4 => return format!("LOG{}", opcode - 0xa0),
+        0xf0 => "CREATE",
+        0xf1 => "CALL",
+        0xf2 => "CALLCODE",
+        0xf3 => "RETURN",
+        0xf4 => "DELEGATECALL",
+        0xf5 => "CREATE2",
+        0xfa => "STATICCALL",
+        0xfd => "REVERT",
+        0xfe => "INVALID",
+        0xff => "SELFDESTRUCT",
+        _ => return format!("INVALID(0x{:02x})", opcode),
+    };
+    name.to_string()
+}
+
+/// Decode a Solidity `Error(string)` revert payload
+pub fn decode_revert_reason(output: &[u8]) -> Option<String> {
+    if output.len() < 68 || output[..4] != [0x08, 0xc3, 0x79, 0xa0] {
+        return None;
+    }
+    let length = U256::from_big_endian(&output[36..68]);
+    let length = usize::try_from(length.low_u64()).ok().filter(|_| length.bits() <= 64)?;
+    let bytes = output.get(68..68usize.checked_add(length)?)?;
+    String::from_utf8(bytes.to_vec()).ok()
+}
+
+struct Machine<'a> {
+    state: &'a mut EvmState,
+    block: &'a BlockEnv,
+    origin: H160,
+    frames: Vec<Frame>,
+    logs: Vec<Log>,
+    trace: CallTrace,
+    deadline: Option<Instant>,
+    /// Instructions executed so far, to check the deadline only every so often
+    instructions: u64,
+    /// Set once the deadline passes, failing every frame still live
+    timed_out: bool,
+    record_instructions: bool,
+    substate: Substate,
+}
+
+impl Machine<'_> {
+    fn run(&mut self, root: CallRequest) -> FrameResult {
+        let mut pending = Some(root);
+
+        loop {
+            if let Some(request) = pending.take() {
+                match self.enter(request) {
+                    Ok(()) => {}
+                    Err(result) => {
+                        if self.frames.is_empty() {
+                            return result;
+                        }
+                        self.resume(result);
+                    }
+                }
+            }
+
+            let halt = match self.step() {
+                Action::Call(request) => {
+                    pending = Some(request);
+                    continue;
+                }
+                Action::Exit(halt) => halt,
+            };
+
+            let frame = self.frames.pop().expect("a frame is executing");
+            let result = self.finish(frame, halt);
+            if self.frames.is_empty() {
+                return result;
+            }
+            self.resume(result);
+        }
+    }
+
+    /// Push a frame for a call request, or resolve it immediately
+    fn enter(&mut self, request: CallRequest) -> Result<(), FrameResult> {
+        let depth = self.frames.len() as u32 + 1;
+        let fail = |gas_remaining| FrameResult {
+            success: false,
+            output: Vec::new(),
+            gas_remaining,
+            created: None,
+            error: Some("call depth or balance check failed"),
+        };
+
+        if depth > EVM_MAX_CALL_DEPTH {
+            return Err(fail(request.gas));
+        }
+        if request.transfers_value && self.state.balance(&request.caller) < request.value {
+            return Err(fail(request.gas));
+        }
+
+        let checkpoint = self.state.checkpoint();
+        let substate_checkpoint = self.substate.checkpoint();
+        let code = if request.kind == CallKind::Create {
+            if self.state.nonce(&request.address) != 0 || !self.state.code(&request.address).is_empty() {
+                return Err(FrameResult {
+                    error: Some("contract address collision"),
+                    ..fail(0)
+                });
+            }
+            self.substate.mark_created(request.address);
+            self.state.touch(&request.address);
+            self.state.increment_nonce(&request.address);
+            request.input.clone()
+        } else {
+            self.state.code(&request.code_address).to_vec()
+        };
+
+        if request.transfers_value && !self.state.transfer(&request.caller, &request.address, request.value) {
+            self.state.revert_to(checkpoint);
+            self.substate.revert_to(substate_checkpoint);
+            return Err(fail(request.gas));
+        }
+
+        let name = frame_name(&request.address, &request.input, request.kind);
+        let live_stack: Vec<String> = self.frames.iter().map(|f| f.name.clone()).collect();
+        if depth > self.trace.max_depth {
+            self.trace.max_depth = depth;
+            self.trace.deepest_stack = live_stack;
+            self.trace.deepest_stack.push(name.clone());
+        }
+
+        // Calls into accounts without code succeed immediately
+        if code.is_empty() && request.kind != CallKind::Create {
+            return Err(FrameResult {
+                success: true,
+                output: Vec::new(),
+                gas_remaining: request.gas,
+                created: None,
+                error: None,
+            });
+        }
+
+        let trace_index = self.trace.frames.len();
+        self.trace.frames.push(FrameGasUsage {
+            frame: name.clone(),
+            depth,
+            gas_used: 0,
+            self_gas_used: 0,
+            success: false,
+        });
+
+        let mut call = CallFrame::new(
+            format_address(&request.address),
+            function_name(&request.input, request.kind),
+            format_address(&request.caller),
+        );
+        call.is_static = request.is_static;
+        call.is_delegate = matches!(request.kind, CallKind::DelegateCall | CallKind::CallCode);
+
+        let jumpdests = analyze_jumpdests(&code);
+        let (input, code) = if request.kind == CallKind::Create {
+            (Vec::new(), code)
+        } else {
+            (request.input, code)
+        };
+
+        self.frames.push(Frame {
+            kind: request.kind,
+            depth,
+            caller: request.caller,
+            address: request.address,
+            code,
+            jumpdests,
+            input,
+            value: request.value,
+            is_static: request.is_static,
+            pc: 0,
+            stack: Vec::with_capacity(32),
+            memory: Vec::new(),
+            gas_remaining: request.gas,
+            return_data: Vec::new(),
+            checkpoint,
+            substate_checkpoint,
+            logs_checkpoint: self.logs.len(),
+            ret_offset: request.ret_offset,
+            ret_size: request.ret_size,
+            pending: CallKind::Call,
+            name,
+            gas_limit: request.gas,
+            trace_index,
+            call,
+        });
+        Ok(())
+    }
+
+    /// Settle a halted frame and record the gas it used and the calls it made
+    fn finish(&mut self, mut frame: Frame, halt: Halt) -> FrameResult {
+        let (trace_index, gas_limit) = (frame.trace_index, frame.gas_limit);
+        let call = std::mem::take(&mut frame.call);
+        let result = self.settle(frame, halt);
+        let usage = &mut self.trace.frames[trace_index];
+        usage.gas_used = gas_limit - result.gas_remaining;
+        usage.success = result.success;
+        match self.frames.last_mut() {
+            Some(parent) => parent.call.steps.push(FrameStep::Call(call)),
+            None => self.trace.call_tree = Some(call),
+        }
+        result
+    }
+
+    /// Roll back a halted frame's effects on failure, or deploy its code on creation
+    fn settle(&mut self, mut frame: Frame, halt: Halt) -> FrameResult {
+        let (success, output, error) = match halt {
+            Halt::Stop => (true, Vec::new(), None),
+            Halt::Return(data) => (true, data, None),
+            Halt::Revert(data) => (false, data, None),
+            Halt::Error(error) => {
+                frame.gas_remaining = 0;
+                (false, Vec::new(), Some(error))
+            }
+        };
+
+        if !success {
+            self.state.revert_to(frame.checkpoint);
+            self.substate.revert_to(frame.substate_checkpoint);
+            self.logs.truncate(frame.logs_checkpoint);
+            return FrameResult {
+                success,
+                output,
+                gas_remaining: frame.gas_remaining,
+                created: None,
+                error,
+            };
+        }
+
+        if frame.kind != CallKind::Create {
+            return FrameResult {
+                success,
+                output,
+                gas_remaining: frame.gas_remaining,
+                created: None,
+                error,
+            };
+        }
+
+        let deposit = 200 * output.len() as u64;
+        let failure = if output.len() > EVM_MAX_CODE_SIZE {
+            Some("contract code size limit exceeded")
+        } else if output.first() == Some(&0xef) {
+            Some("contract code starts with 0xEF")
+        } else if frame.gas_remaining < deposit {
+            Some("out of gas")
+        } else {
+            None
+        };
+
+        if let Some(error) = failure {
+            self.state.revert_to(frame.checkpoint);
+            self.substate.revert_to(frame.substate_checkpoint);
+            self.logs.truncate(frame.logs_checkpoint);
+            return FrameResult {
+                success: false,
+                output: Vec::new(),
+                gas_remaining: 0,
+                created: None,
+                error: Some(error),
+            };
+        }
+
+        self.state.set_code(&frame.address, output);
+        FrameResult {
+            success: true,
+            output: Vec::new(),
+            gas_remaining: frame.gas_remaining - deposit,
+            created: Some(frame.address),
+            error: None,
+        }
+    }
+
+    /// Hand a child frame's result back to the frame that started it
+    fn resume(&mut self, result: FrameResult) {
+        let frame = self.frames.last_mut().expect("a parent frame is waiting");
+        frame.gas_remaining += result.gas_remaining;
+
+        match frame.pending {
+            CallKind::Create => {
+                frame.return_data = if result.success || result.error.is_some() {
+                    Vec::new()
+                } else {
+                    result.output
+                };
+                let address = result.created.map(address_to_word).unwrap_or_default();
+                frame.stack.push(address);
+            }
+            _ => {
+                let copy = frame.ret_size.min(result.output.len());
+                frame.memory[frame.ret_offset..frame.ret_offset + copy]
+                    .copy_from_slice(&result.output[..copy]);
+                frame.return_data = result.output;
+                frame.stack.push(if result.success { U256::one() } else { U256::zero() });
+            }
+        }
+    }
+
+    fn step(&mut self) -> Action {
+        let Machine {
+            state,
+            block,
+            origin,
+            frames,
+            logs,
+            trace,
+            deadline,
+            instructions,
+            timed_out,
+            record_instructions,
+            substate,
+        } = self;
+        let (frame, parents) = frames.split_last_mut().expect("a frame is executing");
+        let parent_memory: u64 = parents.iter().map(|f| f.memory.len() as u64).sum();
+
+        loop {
+            *instructions += 1;
+            if *timed_out
+                || (*instructions % EVM_DEADLINE_CHECK_INTERVAL == 0
+                    && deadline.is_some_and(|deadline| Instant::now() >= deadline))
+            {
+                *timed_out = true;
+                return Action::Exit(Halt::Error("execution timed out"));
+            }
+
+            if *record_instructions {
+                trace.instructions.push(TraceInstruction {
+                    depth: frame.depth,
+                    pc: frame.pc as u64,
+                    op: frame.code.get(frame.pc).map_or("STOP".into(), |opcode| opcode_name(*opcode)),
+                    gas_remaining: frame.gas_remaining,
+                });
+            }
+
+            let result = execute_instruction(frame, state, substate, block, origin, logs, trace);
+            trace.peak_memory = trace.peak_memory.max(parent_memory + frame.memory.len() as u64);
+            match result {
+                Ok(None) => continue,
+                Ok(Some(action)) => return action,
+                Err(halt) => return Action::Exit(halt),
+            }
+        }
+    }
+}
+
+impl Frame {
+    fn pop(&mut self) -> Result<U256, Halt> {
+        self.stack.pop().ok_or(Halt::Error("stack underflow"))
+    }
+
+    fn push(&mut self, value: U256) -> Result<(), Halt> {
+        if self.stack.len() >= EVM_MAX_STACK_SIZE {
+            return Err(Halt::Error("stack overflow"));
+        }
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn charge(&mut self, gas: u64) -> Result<(), Halt> {
+        if self.gas_remaining < gas {
+            self.gas_remaining = 0;
+            return Err(Halt::Error("out of gas"));
+        }
+        self.gas_remaining -= gas;
+        Ok(())
+    }
+
+    /// Charge for and perform memory expansion, returning the usable range
+    fn memory_range(&mut self, offset: U256, size: U256) -> Result<(usize, usize), Halt> {
+        if size.is_zero() {
+            return Ok((0, 0));
+        }
+        let offset = to_usize(offset).ok_or(Halt::Error("out of gas"))?;
+        let size = to_usize(size).ok_or(Halt::Error("out of gas"))?;
+        let end = offset.checked_add(size).ok_or(Halt::Error("out of gas"))?;
+        if end > EVM_MAX_MEMORY_BYTES {
+            return Err(Halt::Error("out of gas"));
+        }
+
+        if end > self.memory.len() {
+            let new_words = end.div_ceil(32) as u64;
+            let old_words = (self.memory.len() / 32) as u64;
+            self.charge(memory_cost(new_words) - memory_cost(old_words))?;
+            self.memory.resize(new_words as usize * 32, 0);
+        }
+        Ok((offset, size))
+    }
+
+    fn copy_cost(&mut self, size: U256) -> Result<(), Halt> {
+        let size = to_usize(size).ok_or(Halt::Error("out of gas"))? as u64;
+        self.charge(3 * size.div_ceil(32))
+    }
+
+    /// Charge the warm or cold cost of accessing an account
+    fn access_account(&mut self, substate: &mut Substate, address: H160) -> Result<(), Halt> {
+        self.charge(if substate.access_account(address) {
+            COLD_ACCOUNT_ACCESS_COST
+        } else {
+            WARM_ACCESS_COST
+        })
+    }
+}
+
+fn execute_instruction(
+    frame: &mut Frame,
+    state: &mut EvmState,
+    substate: &mut Substate,
+    block: &BlockEnv,
+    origin: &H160,
+    logs: &mut Vec<Log>,
+    trace: &mut CallTrace,
+) -> Result<Option<Action>, Halt> {
+    let Some(&opcode) = frame.code.get(frame.pc) else {
+        return Err(Halt::Stop);
+    };
+    frame.pc += 1;
+
+    match opcode {
+        // STOP
+        0x00 => return Err(Halt::Stop),
+        // ADD, MUL, SUB, DIV, SDIV, MOD, SMOD
+        0x01 => binary(frame, 3, |a, b| a.overflowing_add(b).0)?,
+        0x02 => binary(frame, 5, |a, b| a.overflowing_mul(b).0)?,
+        0x03 => binary(frame, 3, |a, b| a.overflowing_sub(b).0)?,
+        0x04 => binary(frame, 5, |a, b| a.checked_div(b).unwrap_or_default())?,
+        0x05 => binary(frame, 5, signed_div)?,
+        0x06 => binary(frame, 5, |a, b| a.checked_rem(b).unwrap_or_default())?,
+        0x07 => binary(frame, 5, signed_rem)?,
+        // ADDMOD, MULMOD
+        0x08 | 0x09 => {
+            frame.charge(8)?;
+            let (a, b, n) = (frame.pop()?, frame.pop()?, frame.pop()?);
+            let result = if n.is_zero() {
+                U256::zero()
+            } else {
+                let wide = if opcode == 0x08 {
+                    U512::from(a) + U512::from(b)
+                } else {
+                    a.full_mul(b)
+                };
+                U256::try_from(wide % U512::from(n)).expect("remainder fits in 256 bits")
+            };
+            frame.push(result)?;
+        }
+        // EXP
+        0x0a => {
+            let (base, exponent) = (frame.pop()?, frame.pop()?);
+            frame.charge(10 + 50 * exponent.bits().div_ceil(8) as u64)?;
+            frame.push(base.overflowing_pow(exponent).0)?;
+        }
+        // SIGNEXTEND
+        0x0b => binary(frame, 5, |b, x| {
+            if b >= U256::from(31) {
+                return x;
+            }
+            let bit = b.low_u64() as usize * 8 + 7;
+            let mask = (U256::one() << (bit + 1)) - 1;
+            if x.bit(bit) {
+                x | !mask
+            } else {
+                x & mask
+            }
+        })?,
+        // LT, GT, SLT, SGT, EQ
+        0x10 => binary(frame, 3, |a, b| bool_word(a < b))?,
+        0x11 => binary(frame, 3, |a, b| bool_word(a > b))?,
+        0x12 => binary(frame, 3, |a, b| bool_word(signed_lt(a, b)))?,
+        0x13 => binary(frame, 3, |a, b| bool_word(signed_lt(b, a)))?,
+        0x14 => binary(frame, 3, |a, b| bool_word(a == b))?,
+        // ISZERO
+        0x15 => {
+            frame.charge(3)?;
+            let a = frame.pop()?;
+            frame.push(bool_word(a.is_zero()))?;
+        }
+        // AND, OR, XOR
+        0x16 => binary(frame, 3, |a, b| a & b)?,
+        0x17 => binary(frame, 3, |a, b| a | b)?,
+        0x18 => binary(frame, 3, |a, b| a ^ b)?,
+        // NOT
+        0x19 => {
+            frame.charge(3)?;
+            let a = frame.pop()?;
+            frame.push(!a)?;
+        }
+        // BYTE
+        0x1a => binary(frame, 3, |i, x| {
+            if i >= U256::from(32) {
+                U256::zero()
+            } else {
+                U256::from(x.to_big_endian()[i.low_u64() as usize])
+            }
+        })?,
+        // SHL, SHR, SAR
+        0x1b => binary(frame, 3, |shift, value| {
+            if shift >= U256::from(256) {
+                U256::zero()
+            } else {
+                value << shift.low_u64() as usize
+            }
+        })?,
+        0x1c => binary(frame, 3, |shift, value| {
+            if shift >= U256::from(256) {
+                U256::zero()
+            } else {
+                value >> shift.low_u64() as usize
+            }
+        })?,
+        0x1d => binary(frame, 3, |shift, value| {
+            let negative = value.bit(255);
+            if shift >= U256::from(256) {
+                if negative {
+                    U256::MAX
+                } else {
+                    U256::zero()
+                }
+            } else if negative {
+                !((!value) >> shift.low_u64() as usize)
+            } else {
+                value >> shift.low_u64() as usize
+            }
+        })?,
+        // SHA3
+        0x20 => {
+            let (offset, size) = (frame.pop()?, frame.pop()?);
+            frame.charge(30)?;
+            frame.charge(6 * (to_usize(size).ok_or(Halt::Error("out of gas"))? as u64).div_ceil(32))?;
+            let (offset, size) = frame.memory_range(offset, size)?;
+            let hash = keccak256(&frame.memory[offset..offset + size]);
+            frame.push(U256::from_big_endian(&hash))?;
+        }
+        // ADDRESS
+        0x30 => push_word(frame, 2, address_to_word(frame.address))?,
+        // BALANCE
+        0x31 => {
+            let address = word_to_address(frame.pop()?);
+            frame.access_account(substate, address)?;
+            frame.push(state.balance(&address))?;
+        }
+        // ORIGIN, CALLER, CALLVALUE
+        0x32 => push_word(frame, 2, address_to_word(*origin))?,
+        0x33 => push_word(frame, 2, address_to_word(frame.caller))?,
+        0x34 => push_word(frame, 2, frame.value)?,
+        // CALLDATALOAD
+        0x35 => {
+            frame.charge(3)?;
+            let offset = frame.pop()?;
+            let word = read_padded(&frame.input, offset, 32);
+            frame.push(U256::from_big_endian(&word))?;
+        }
+        // CALLDATASIZE
+        0x36 => push_word(frame, 2, U256::from(frame.input.len()))?,
+        // CALLDATACOPY, CODECOPY, RETURNDATACOPY
+        0x37 | 0x39 | 0x3e => {
+            let (dest, offset, size) = (frame.pop()?, frame.pop()?, frame.pop()?);
+            frame.charge(3)?;
+            frame.copy_cost(size)?;
+            if opcode == 0x3e {
+                let end = offset.checked_add(size).ok_or(Halt::Error("return data out of bounds"))?;
+                if end > U256::from(frame.return_data.len()) {
+                    return Err(Halt::Error("return data out of bounds"));
+                }
+            }
+            let (dest, size) = frame.memory_range(dest, size)?;
+            let data = match opcode {
+                0x37 => read_padded(&frame.input, offset, size),
+                0x39 => read_padded(&frame.code, offset, size),
+                _ => read_padded(&frame.return_data, offset, size),
+            };
+            frame.memory[dest..dest + size].copy_from_slice(&data);
+        }
+        // CODESIZE
+        0x38 => push_word(frame, 2, U256::from(frame.code.len()))?,
+        // GASPRICE
+        0x3a => push_word(frame, 2, U256::zero())?,
+        // EXTCODESIZE
+        0x3b => {
+            let address = word_to_address(frame.pop()?);
+            frame.access_account(substate, address)?;
+            frame.push(U256::from(state.code(&address).len()))?;
+        }
+        // EXTCODECOPY
+        0x3c => {
+            let (address, dest, offset, size) = (frame.pop()?, frame.pop()?, frame.pop()?, frame.pop()?);
+            let address = word_to_address(address);
+            frame.access_account(substate, address)?;
+            frame.copy_cost(size)?;
+            let (dest, size) = frame.memory_range(dest, size)?;
+            let data = read_padded(state.code(&address), offset, size);
+            frame.memory[dest..dest + size].copy_from_slice(&data);
+        }
+        // RETURNDATASIZE
+        0x3d => push_word(frame, 2, U256::from(frame.return_data.len()))?,
+        // EXTCODEHASH
+        0x3f => {
+            let address = word_to_address(frame.pop()?);
+            frame.access_account(substate, address)?;
+            let hash = if state.exists(&address) {
+                U256::from_big_endian(&keccak256(state.code(&address)))
+            } else {
+                U256::zero()
+            };
+            frame.push(hash)?;
+        }
+        // BLOCKHASH
+        0x40 => {
+            frame.charge(20)?;
+            let number = frame.pop()?;
+            let current = U256::from(block.number);
+            let hash = if number < current && current - number <= U256::from(256) {
+                U256::from_big_endian(&keccak256(&number.to_big_endian()))
+            } else {
+                U256::zero()
+            };
+            frame.push(hash)?;
+        }
+        // COINBASE, TIMESTAMP, NUMBER, PREVRANDAO, GASLIMIT, CHAINID
+        0x41 => push_word(frame, 2, address_to_word(block.coinbase))?,
+        0x42 => push_word(frame, 2, U256::from(block.timestamp))?,
+        0x43 => push_word(frame, 2, U256::from(block.number))?,
+        0x44 => push_word(frame, 2, block.prevrandao)?,
+        0x45 => push_word(frame, 2, U256::from(block.gas_limit))?,
+        0x46 => push_word(frame, 2, U256::from(block.chain_id))?,
+        // SELFBALANCE
+        0x47 => push_word(frame, 5, state.balance(&frame.address))?,
+        // BASEFEE
+        0x48 => push_word(frame, 2, block.base_fee)?,
+        // BLOBHASH, with no blobs in any transaction
+        0x49 => {
+            frame.charge(3)?;
+            frame.pop()?;
+            frame.push(U256::zero())?;
+        }
+        // BLOBBASEFEE, at its minimum as no blob gas is ever used
+        0x4a => push_word(frame, 2, U256::one())?,
+        // POP
+        0x50 => {
+            frame.charge(2)?;
+            frame.pop()?;
+        }
+        // MLOAD
+        0x51 => {
+            frame.charge(3)?;
+            let offset = frame.pop()?;
+            let (offset, _) = frame.memory_range(offset, U256::from(32))?;
+            let word = U256::from_big_endian(&frame.memory[offset..offset + 32]);
+            frame.push(word)?;
+        }
+        // MSTORE
+        0x52 => {
+            frame.charge(3)?;
+            let (offset, value) = (frame.pop()?, frame.pop()?);
+            let (offset, _) = frame.memory_range(offset, U256::from(32))?;
+            frame.memory[offset..offset + 32].copy_from_slice(&value.to_big_endian());
+        }
+        // MSTORE8
+        0x53 => {
+            frame.charge(3)?;
+            let (offset, value) = (frame.pop()?, frame.pop()?);
+            let (offset, _) = frame.memory_range(offset, U256::one())?;
+            frame.memory[offset] = value.low_u64() as u8;
+        }
+        // SLOAD
+        0x54 => {
+            let slot = frame.pop()?;
+            frame.charge(if substate.access_slot(frame.address, slot) {
+                COLD_SLOAD_COST
+            } else {
+                WARM_ACCESS_COST
+            })?;
+            frame.call.steps.push(FrameStep::Read(format_word(&slot)));
+            frame.push(state.storage(&frame.address, &slot))?;
+        }
+        // SSTORE
+        0x55 => {
+            if frame.is_static {
+                return Err(Halt::Error("state modification in static call"));
+            }
+            if frame.gas_remaining <= SSTORE_STIPEND {
+                return Err(Halt::Error("out of gas"));
+            }
+            let (slot, value) = (frame.pop()?, frame.pop()?);
+            let cold = substate.access_slot(frame.address, slot);
+            let current = state.storage(&frame.address, &slot);
+            let original = *substate.original_storage.entry((frame.address, slot)).or_insert(current);
+            let cost = if current == value || original != current {
+                WARM_ACCESS_COST
+            } else if original.is_zero() {
+                SSTORE_SET_COST
+            } else {
+                SSTORE_RESET_COST
+            };
+            frame.charge(cost + if cold { COLD_SLOAD_COST } else { 0 })?;
+            frame.call.steps.push(FrameStep::Write(format_word(&slot)));
+            if current != value {
+                let refund = sstore_refund(original, current, value);
+                if refund != 0 {
+                    substate.add_refund(refund);
+                }
+                state.set_storage(&frame.address, slot, value);
+            }
+        }
+        // JUMP
+        0x56 => {
+            frame.charge(8)?;
+            let target = frame.pop()?;
+            jump(frame, target)?;
+        }
+        // JUMPI
+        0x57 => {
+            frame.charge(10)?;
+            let (target, condition) = (frame.pop()?, frame.pop()?);
+            if !condition.is_zero() {
+                jump(frame, target)?;
+            }
+        }
+        // PC
+        0x58 => push_word(frame, 2, U256::from(frame.pc - 1))?,
+        // MSIZE
+        0x59 => push_word(frame, 2, U256::from(frame.memory.len()))?,
+        // GAS
+        0x5a => {
+            frame.charge(2)?;
+            let gas = frame.gas_remaining;
+            frame.push(U256::from(gas))?;
+        }
+        // JUMPDEST
+        0x5b => frame.charge(1)?,
+        // TLOAD
+        0x5c => {
+            frame.charge(WARM_ACCESS_COST)?;
+            let slot = frame.pop()?;
+            frame.push(substate.transient(frame.address, slot))?;
+        }
+        // TSTORE
+        0x5d => {
+            if frame.is_static {
+                return Err(Halt::Error("state modification in static call"));
+            }
+            frame.charge(WARM_ACCESS_COST)?;
+            let (slot, value) = (frame.pop()?, frame.pop()?);
+            substate.set_transient(frame.address, slot, value);
+        }
+        // MCOPY
+        0x5e => {
+            let (dest, source, size) = (frame.pop()?, frame.pop()?, frame.pop()?);
+            frame.charge(3)?;
+            frame.copy_cost(size)?;
+            let (source, _) = frame.memory_range(source, size)?;
+            let (dest, size) = frame.memory_range(dest, size)?;
+            frame.memory.copy_within(source..source + size, dest);
+        }
+        // PUSH0
+        0x5f => push_word(frame, 2, U256::zero())?,
+        // PUSH1..PUSH32
+        0x60..=0x7f => {
+            frame.charge(3)?;
+            let size = (opcode - 0x5f) as usize;
+            let start = frame.pc.min(frame.code.len());
+            let end = (frame.pc + size).min(frame.code.len());
+            let mut bytes = frame.code[start..end].to_vec();
+            bytes.resize(size, 0);
+            frame.pc += size;
+            frame.push(U256::from_big_endian(&bytes))?;
+        }
+        // DUP1..DUP16
+        0x80..=0x8f => {
+            frame.charge(3)?;
+            let position = (opcode - 0x7f) as usize;
+            if frame.stack.len() < position {
+                return Err(Halt::Error("stack underflow"));
+            }
+            let value = frame.stack[frame.stack.len() - position];
+            frame.push(value)?;
+        }
+        // SWAP1..SWAP16
+        0x90..=0x9f => {
+            frame.charge(3)?;
+            let position = (opcode - 0x8f) as usize;
+            let len = frame.stack.len();
+            if len <= position {
+                return Err(Halt::Error("stack underflow"));
+            }
+            frame.stack.swap(len - 1, len - 1 - position);
+        }
+        // LOG0..LOG4
+        0xa0..=0xa4 => {
+            if frame.is_static {
+                return Err(Halt::Error("state modification in static call"));
+            }
+            let topic_count = (opcode - 0xa0) as usize;
+            let (offset, size) = (frame.pop()?, frame.pop()?);
+            let mut topics = Vec::with_capacity(topic_count);
+            for _ in 0..topic_count {
+                topics.push(frame.pop()?);
+            }
+            let byte_count = to_usize(size).ok_or(Halt::Error("out of gas"))? as u64;
+            frame.charge(375 + 375 * topic_count as u64 + 8 * byte_count)?;
+            let (offset, size) = frame.memory_range(offset, size)?;
+            logs.push(Log {
+                address: frame.address,
+                topics,
+                data: frame.memory[offset..offset + size].to_vec(),
+            });
+        }
+        // CREATE, CREATE2
+        0xf0 | 0xf5 => {
+            if frame.is_static {
+                return Err(Halt::Error("state modification in static call"));
+            }
+            let (value, offset, size) = (frame.pop()?, frame.pop()?, frame.pop()?);
+            let salt = if opcode == 0xf5 { Some(frame.pop()?) } else { None };
+            let init_size = to_usize(size).ok_or(Halt::Error("out of gas"))?;
+            if init_size > EVM_MAX_INITCODE_SIZE {
+                return Err(Halt::Error("contract creation code size limit exceeded"));
+            }
+            frame.charge(32_000 + initcode_cost(init_size))?;
+            if salt.is_some() {
+                frame.charge(6 * (init_size as u64).div_ceil(32))?;
+            }
+            let (offset, size) = frame.memory_range(offset, size)?;
+            let init_code = frame.memory[offset..offset + size].to_vec();
+
+            let nonce = state.nonce(&frame.address);
+            let address = match salt {
+                Some(salt) => create2_address(&frame.address, &salt, &init_code),
+                None => create_address(&frame.address, nonce),
+            };
+            if frame.depth >= EVM_MAX_CALL_DEPTH || state.balance(&frame.address) < value {
+                frame.return_data.clear();
+                frame.push(U256::zero())?;
+                return Ok(None);
+            }
+            state.increment_nonce(&frame.address);
+            substate.access_account(address);
+
+            let gas = all_but_one_64th(frame.gas_remaining);
+            frame.gas_remaining -= gas;
+            frame.pending = CallKind::Create;
+            return Ok(Some(Action::Call(CallRequest {
+                kind: CallKind::Create,
+                caller: frame.address,
+                address,
+                code_address: H160::zero(),
+                value,
+                transfers_value: true,
+                input: init_code,
+                gas,
+                is_static: false,
+                ret_offset: 0,
+                ret_size: 0,
+            })));
+        }
+        // CALL, CALLCODE, DELEGATECALL, STATICCALL
+        0xf1 | 0xf2 | 0xf4 | 0xfa => {
+            let kind = match opcode {
+                0xf1 => CallKind::Call,
+                0xf2 => CallKind::CallCode,
+                0xf4 => CallKind::DelegateCall,
+                _ => CallKind::StaticCall,
+            };
+            let requested_gas = frame.pop()?;
+            let target = word_to_address(frame.pop()?);
+            let value = if matches!(kind, CallKind::Call | CallKind::CallCode) {
+                frame.pop()?
+            } else {
+                U256::zero()
+            };
+            let (in_offset, in_size, out_offset, out_size) =
+                (frame.pop()?, frame.pop()?, frame.pop()?, frame.pop()?);
+
+            if kind == CallKind::Call && frame.is_static && !value.is_zero() {
+                return Err(Halt::Error("state modification in static call"));
+            }
+
+            frame.access_account(substate, target)?;
+            let mut cost = 0;
+            if !value.is_zero() {
+                cost += 9_000;
+                if kind == CallKind::Call && !state.exists(&target) {
+                    cost += 25_000;
+                }
+            }
+            frame.charge(cost)?;
+            let (in_offset, in_size) = frame.memory_range(in_offset, in_size)?;
+            let (out_offset, out_size) = frame.memory_range(out_offset, out_size)?;
+            trace.external_calls += 1;
+
+            let available = all_but_one_64th(frame.gas_remaining);
+            let mut gas = match to_usize(requested_gas) {
+                Some(requested) => available.min(requested as u64),
+                None => available,
+            };
+            frame.gas_remaining -= gas;
+            if !value.is_zero() {
+                gas += 2_300;
+            }
+
+            let input = frame.memory[in_offset..in_offset + in_size].to_vec();
+            frame.pending = kind;
+            let (caller, address, call_value, transfers_value) = match kind {
+                CallKind::Call | CallKind::StaticCall => (frame.address, target, value, kind == CallKind::Call),
+                CallKind::CallCode => (frame.address, frame.address, value, true),
+                _ => (frame.caller, frame.address, frame.value, false),
+            };
+            return Ok(Some(Action::Call(CallRequest {
+                kind,
+                caller,
+                address,
+                code_address: target,
+                value: call_value,
+                transfers_value,
+                input,
+                gas,
+                is_static: frame.is_static || kind == CallKind::StaticCall,
+                ret_offset: out_offset,
+                ret_size: out_size,
+            })));
+        }
+        // RETURN, REVERT
+        0xf3 | 0xfd => {
+            let (offset, size) = (frame.pop()?, frame.pop()?);
+            let (offset, size) = frame.memory_range(offset, size)?;
+            let data = frame.memory[offset..offset + size].to_vec();
+            return Err(if opcode == 0xf3 { Halt::Return(data) } else { Halt::Revert(data) });
+        }
+        // SELFDESTRUCT
+        0xff => {
+            if frame.is_static {
+                return Err(Halt::Error("state modification in static call"));
+            }
+            let beneficiary = word_to_address(frame.pop()?);
+            let balance = state.balance(&frame.address);
+            let mut cost = 5_000;
+            if substate.access_account(beneficiary) {
+                cost += COLD_ACCOUNT_ACCESS_COST;
+            }
+            if !balance.is_zero() && !state.exists(&beneficiary) {
+                cost += 25_000;
+            }
+            frame.charge(cost)?;
+            state.transfer(&frame.address, &beneficiary, balance);
+            // Only contracts created in the same transaction are deleted (EIP-6780)
+            if substate.created.contains(&frame.address) {
+                state.mark_selfdestruct(&frame.address);
+            }
+            return Err(Halt::Stop);
+        }
+        _ => return Err(Halt::Error("invalid opcode")),
+    }
+
+    Ok(None)
+}
+
+fn binary(frame: &mut Frame, gas: u64, op: impl FnOnce(U256, U256) -> U256) -> Result<(), Halt> {
+    frame.charge(gas)?;
+    let (a, b) = (frame.pop()?, frame.pop()?);
+    frame.push(op(a, b))
+}
+
+fn push_word(frame: &mut Frame, gas: u64, value: U256) -> Result<(), Halt> {
+    frame.charge(gas)?;
+    frame.push(value)
+}
+
+fn jump(frame: &mut Frame, target: U256) -> Result<(), Halt> {
+    match to_usize(target) {
+        Some(target) if frame.jumpdests.get(target) == Some(&true) => {
+            frame.pc = target;
+            Ok(())
+        }
+        _ => Err(Halt::Error("invalid jump destination")),
+    }
+}
+
+/// Mark every `JUMPDEST` that is not inside `PUSH` data
+fn analyze_jumpdests(code: &[u8]) -> Vec<bool> {
+    let mut jumpdests = vec![false; code.len()];
+    let mut pc = 0;
+    while pc < code.len() {
+        match code[pc] {
+            0x5b => jumpdests[pc] = true,
+            opcode @ 0x60..=0x7f => pc += (opcode - 0x5f) as usize,
+            _ => {}
+        }
+        pc += 1;
+    }
+    jumpdests
+}
+
+/// Refund change for an `SSTORE` from `current` to `value` of a slot that
+/// held `original` before the transaction (EIP-2200 with EIP-3529 values)
+fn sstore_refund(original: U256, current: U256, value: U256) -> i64 {
+    if original == current {
+        return if !original.is_zero() && value.is_zero() { SSTORE_CLEARS_REFUND } else { 0 };
+    }
+    let mut refund = 0;
+    if !original.is_zero() {
+        if current.is_zero() {
+            refund -= SSTORE_CLEARS_REFUND;
+        } else if value.is_zero() {
+            refund += SSTORE_CLEARS_REFUND;
+        }
+    }
+    if original == value {
+        refund += if original.is_zero() {
+            (SSTORE_SET_COST - WARM_ACCESS_COST) as i64
+        } else {
+            (SSTORE_RESET_COST - WARM_ACCESS_COST) as i64
+        };
+    }
+    refund
+}
+
+fn memory_cost(words: u64) -> u64 {
+    3 * words + words * words / 512
+}
+
+fn all_but_one_64th(gas: u64) -> u64 {
+    gas - gas / 64
+}
+
+fn to_usize(value: U256) -> Option<usize> {
+    if value.bits() > 64 {
+        return None;
+    }
+    usize::try_from(value.low_u64()).ok()
+}
+
+/// Read `size` bytes from `data` at `offset`, zero-padding past the end
+fn read_padded(data: &[u8], offset: U256, size: usize) -> Vec<u8> {
+    let mut output = vec![0u8; size];
+    if let Some(offset) = to_usize(offset) {
+        if offset < data.len() {
+            let available = (data.len() - offset).min(size);
+            output[..available].copy_from_slice(&data[offset..offset + available]);
+        }
+    }
+    output
+}
+
+fn bool_word(value: bool) -> U256 {
+    if value {
+        U256::one()
+    } else {
+        U256::zero()
+    }
+}
+
+fn address_to_word(address: H160) -> U256 {
+    U256::from_big_endian(address.as_bytes())
+}
+
+fn word_to_address(word: U256) -> H160 {
+    H160::from_slice(&word.to_big_endian()[12..])
+}
+
+fn negate(value: U256) -> U256 {
+    (!value).overflowing_add(U256::one()).0
+}
+
+fn abs(value: U256) -> U256 {
+    if value.bit(255) {
+        negate(value)
+    } else {
+        value
+    }
+}
+
+fn signed_lt(a: U256, b: U256) -> bool {
+    match (a.bit(255), b.bit(255)) {
+        (true, false) => true,
+        (false, true) => false,
+        _ => a < b,
+    }
+}
+
+fn signed_div(a: U256, b: U256) -> U256 {
+    if b.is_zero() {
+        return U256::zero();
+    }
+    let quotient = abs(a) / abs(b);
+    if a.bit(255) != b.bit(255) {
+        negate(quotient)
+    } else {
+        quotient
+    }
+}
+
+fn signed_rem(a: U256, b: U256) -> U256 {
+    if b.is_zero() {
+        return U256::zero();
+    }
+    let remainder = abs(a) % abs(b);
+    if a.bit(255) {
+        negate(remainder)
+    } else {
+        remainder
+    }
+}
+
+/// Human readable label for a frame, `address::selector`
+fn frame_name(address: &H160, input: &[u8], kind: CallKind) -> String {
+    format!("{}::{}", format_address(address), function_name(input, kind))
+}
+
+/// Selector of the called function, or `constructor` / `fallback`
+fn function_name(input: &[u8], kind: CallKind) -> String {
+    if kind == CallKind::Create {
+        return "constructor".to_string();
+    }
+    match input.get(..4) {
+        Some(selector) => format!("0x{}", hex::encode(selector)),
+        None => "fallback".to_string(),
+    }
+}
@@ -0,0 +1,1021 @@
+//! Embedded EVM backend
+//!
+//! Interprets EVM bytecode in process. `deploy_contract` runs creation code,
+//! `call_function` sends a message call, and `execute` deploys the creation
+//! bytecode stored at `code_path` on first use before calling
+//! `target_function` on it. Gas, call depth, external calls and the live call
+//! stack are taken from the interpreter, so the security checks run against
+//! real execution data. Environments created with `NetworkMode::MainnetFork`
+//! load accounts and storage from an upstream node as they are touched.
+//!
+//! `ExecutionInputs::parameters` understands the following keys:
+//!
+//! - `calldata`: hex encoded bytes appended to the function selector
+//! - `args`: array of static ABI words (numbers, decimal or hex strings, booleans)
+//! - `value`: wei sent with the call
+//! - `gas_limit`: gas limit of the call, defaulting to the block gas limit
+
+mod fork;
+mod interpreter;
+mod state;
+
+pub use state::{BlockEnv, EvmAccount};
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use primitive_types::{H160, U256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+
+use crate::access_control::AccessControlPolicy;
+use crate::arithmetic::IntegerType;
+use crate::block_clock::BlockClock;
+use crate::config::RuntimeConfig;
+use crate::determinism::Entropy;
+use crate::enforcement::SecurityEnforcer;
+use crate::error::RuntimeError;
+use crate::events::{EventBus, EventFilter, EventSubscription};
+use crate::limits::ResourceLimits;
+use crate::constants::{DEFAULT_EVM_BLOCK_GAS_LIMIT, DEFAULT_EVM_CHAIN_ID, DEFAULT_SENDER_ADDRESS, EVM_HARDFORK};
+use crate::info;
+use crate::report::SecurityReport;
+use crate::runtime::BlockchainRuntime;
+use crate::trace::ExecutionTrace;
+use crate::security::{validate_code_path, SecurityConfig, SecurityValidator};
+use crate::types::{
+    BlockInfo, EnvironmentState, ExecutionInputs, ExecutionResult, GasEstimate, MetricType, NetworkMode,
+    RuntimeCapabilities, RuntimeEnvironment, RuntimeEvent, RuntimeMetricDefinition, RuntimeType,
+    SecurityViolation, SnapshotId, StateChange,
+};
+use fork::ForkSource;
+use interpreter::{intrinsic_gas, transact, CallTrace, TransactionOutcome, TransactionRequest};
+pub(crate) use interpreter::keccak256;
+pub(crate) use state::{format_address, format_word};
+use state::EvmState;
+
+/// Everything about an environment that snapshots capture
+#[derive(Clone)]
+struct EvmWorld {
+    state: EvmState,
+    /// Block values other than the number and timestamp, which `clock` owns
+    block: BlockEnv,
+    clock: BlockClock,
+    deployments: HashMap<PathBuf, H160>,
+    access_control: AccessControlPolicy,
+}
+
+impl EvmWorld {
+    /// Block values with the number and timestamp taken from the clock
+    fn block_env(&self) -> BlockEnv {
+        let info = self.clock.info();
+        BlockEnv {
+            number: info.number,
+            timestamp: info.timestamp,
+            ..self.block.clone()
+        }
+    }
+}
+
+/// Environment bookkeeping for the EVM backend
+struct EvmEnvironment {
+    config: RuntimeConfig,
+    world: EvmWorld,
+    snapshots: BTreeMap<u64, EvmWorld>,
+    snapshot_nonce: u64,
+    /// Executions outlive reverts so their ids stay unique and resolvable
+    executions: HashMap<String, ExecutionResult>,
+    execution_nonce: u64,
+    event_bus: EventBus,
+    entropy: Entropy,
+}
+
+/// Blockchain runtime backed by an embedded EVM interpreter
+///
+/// Each environment has its own lock, so a long execution only holds up
+/// other operations on the same environment.
+pub struct EvmRuntime {
+    blockchain_id: String,
+    capabilities: RuntimeCapabilities,
+    environments: RwLock<HashMap<String, Arc<Mutex<EvmEnvironment>>>>,
+    environment_nonce: AtomicU64,
+    entropy: Option<Entropy>,
+}
+
+impl EvmRuntime {
+    /// Create a new EVM runtime
+    pub fn new(blockchain_id: String) -> Self {
+        Self {
+            blockchain_id,
+            capabilities: RuntimeCapabilities {
+                supports_gas_estimation: true,
+                supports_time_travel: true,
+                supports_snapshots: true,
+                supports_event_subscriptions: true,
+                supports_access_control: true,
+                supports_tracing: true,
+                ..RuntimeCapabilities::default()
+            },
+            environments: RwLock::new(HashMap::new()),
+            environment_nonce: AtomicU64::new(0),
+            entropy: None,
+        }
+    }
+
+    /// Take time and randomness for every environment from `entropy`,
+    /// ignoring `deterministic_seed`
+    pub fn with_entropy(mut self, entropy: Entropy) -> Self {
+        self.entropy = Some(entropy);
+        self
+    }
+
+    /// Get an account from the world state
+    pub fn account(&self, env: &RuntimeEnvironment, address: &str) -> Result<Option<EvmAccount>> {
+        let address = parse_address(address)?;
+        self.with_environment_mut(env, |environment| {
+            let state = &mut environment.world.state;
+            let account = state.account(&address).cloned();
+            match state.take_fork_error() {
+                Some(error) => Err(RuntimeError::Backend(format!("Failed to load forked state: {}", error)).into()),
+                None => Ok(account),
+            }
+        })
+    }
+
+    /// Set the balance of an account
+    pub fn set_balance(&self, env: &RuntimeEnvironment, address: &str, balance: u128) -> Result<()> {
+        let address = parse_address(address)?;
+        self.with_environment_mut(env, |environment| {
+            environment.world.state.set_balance(&address, U256::from(balance));
+            environment.world.state.commit();
+            Ok(())
+        })
+    }
+
+    /// Get the block values of an environment
+    pub fn block(&self, env: &RuntimeEnvironment) -> Result<BlockEnv> {
+        self.with_environment(env, |environment| Ok(environment.world.block_env()))
+    }
+
+    fn environment(&self, env: &RuntimeEnvironment) -> Result<Arc<Mutex<EvmEnvironment>>> {
+        self.environments
+            .read()
+            .map_err(|_| poisoned())?
+            .get(&env.environment_id)
+            .cloned()
+            .ok_or_else(|| RuntimeError::EnvironmentNotFound(env.environment_id.clone()).into())
+    }
+
+    fn with_environment<T>(
+        &self,
+        env: &RuntimeEnvironment,
+        f: impl FnOnce(&EvmEnvironment) -> Result<T>,
+    ) -> Result<T> {
+        let environment = self.environment(env)?;
+        let environment = environment.lock().map_err(|_| poisoned())?;
+        f(&environment)
+    }
+
+    fn with_environment_mut<T>(
+        &self,
+        env: &RuntimeEnvironment,
+        f: impl FnOnce(&mut EvmEnvironment) -> Result<T>,
+    ) -> Result<T> {
+        let environment = self.environment(env)?;
+        let mut environment = environment.lock().map_err(|_| poisoned())?;
+        f(&mut environment)
+    }
+
+    /// Run `f` on a blocking thread, as interpreting bytecode and fetching
+    /// forked state would otherwise stall the async workers
+    async fn with_environment_blocking<T: Send + 'static>(
+        &self,
+        env: &RuntimeEnvironment,
+        f: impl FnOnce(&mut EvmEnvironment) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let environment = self.environment(env)?;
+        run_blocking(move || {
+            let mut environment = environment.lock().map_err(|_| poisoned())?;
+            f(&mut environment)
+        })
+        .await
+    }
+
+    /// Environment security configuration carrying its live access control policy
+    fn security_config(&self, env: &RuntimeEnvironment) -> Result<SecurityConfig> {
+        self.with_environment(env, |environment| {
+            Ok(SecurityConfig {
+                access_control: environment.world.access_control.clone(),
+                ..environment.config.security_config.clone()
+            })
+        })
+    }
+
+    fn entropy(&self, env: &RuntimeEnvironment) -> Result<Entropy> {
+        self.with_environment(env, |environment| Ok(environment.entropy.clone()))
+    }
+
+    /// Stream an execution's events to subscribers once it is kept
+    fn publish(&self, env: &RuntimeEnvironment, result: &ExecutionResult) -> Result<()> {
+        self.with_environment_mut(env, |environment| {
+            environment.event_bus.publish(&result.events);
+            Ok(())
+        })
+    }
+
+    /// Execute inputs and keep the interpreter trace and block for analysis
+    ///
+    /// Events are recorded but not published, as the caller may still roll
+    /// the execution back.
+    async fn run_execution(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+        record_instructions: bool,
+    ) -> Result<(ExecutionResult, CallTrace, BlockInfo)> {
+        let started = Instant::now();
+        let call = PreparedCall::new(code_path, inputs).await?;
+        let (environment_id, code_path, inputs) = (env.environment_id.clone(), code_path.to_path_buf(), inputs.clone());
+
+        self.with_environment_blocking(env, move |environment| {
+            environment.run(&environment_id, &code_path, &inputs, &call, record_instructions, started)
+        })
+        .await
+    }
+}
+
+impl EvmEnvironment {
+    /// Interpret a prepared call, deploying its code on first use
+    fn run(
+        &mut self,
+        environment_id: &str,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+        call: &PreparedCall,
+        record_instructions: bool,
+        started: Instant,
+    ) -> Result<(ExecutionResult, CallTrace, BlockInfo)> {
+        let clock = self.entropy.clock().clone();
+        let started_ms = clock.now_millis();
+        let world = &mut self.world;
+        let block = world.execution_block(inputs);
+        let (address, state_changes) = world.ensure_deployed(&block, code_path, call)?;
+        let limits = ResourceLimits::from_config(&self.config);
+        let outcome = transact(
+            &mut world.state,
+            &block,
+            TransactionRequest {
+                deadline: Some(started + limits.timeout),
+                record_instructions,
+                ..call.request(address, call.gas_limit.unwrap_or(block.gas_limit))
+            },
+        );
+        fork_result(&outcome)?;
+        let timed_out = outcome.timed_out;
+
+        self.execution_nonce += 1;
+        let execution_id = format!("{}_exec_{}", environment_id, self.execution_nonce);
+        let trace = outcome.trace.clone();
+        let mut result = build_result(execution_id, outcome, &block);
+        result.state_changes.splice(0..0, state_changes);
+        result.execution_time_ms = clock.now_millis().saturating_sub(started_ms);
+        result
+            .metrics
+            .insert("execution_time".to_string(), serde_json::json!(result.execution_time_ms));
+        if timed_out {
+            let violation = limits.timeout_violation(&inputs.target_function, clock.as_ref());
+            result.security_context.security_violations.push(violation.clone());
+            result.add_security_violation(violation);
+        }
+
+        self.executions.insert(result.execution_id.clone(), result.clone());
+        Ok((result, trace, BlockInfo { number: block.number, timestamp: block.timestamp }))
+    }
+}
+
+/// Message call described by `ExecutionInputs`, with the creation code behind it
+struct PreparedCall {
+    bytecode: Vec<u8>,
+    calldata: Vec<u8>,
+    value: U256,
+    gas_limit: Option<u64>,
+    caller: H160,
+}
+
+impl PreparedCall {
+    async fn new(code_path: &Path, inputs: &ExecutionInputs) -> Result<Self> {
+        Ok(Self {
+            bytecode: load_bytecode(code_path).await?,
+            calldata: encode_call(&inputs.target_function, &inputs.parameters)?,
+            value: inputs
+                .parameters
+                .get("value")
+                .map(parse_u256)
+                .transpose()?
+                .unwrap_or_default(),
+            gas_limit: inputs
+                .parameters
+                .get("gas_limit")
+                .map(|v| parse_u256(v).map(|g| g.low_u64()))
+                .transpose()?,
+            caller: parse_address(inputs.context.sender.as_deref().unwrap_or(DEFAULT_SENDER_ADDRESS))?,
+        })
+    }
+
+    fn request(&self, address: H160, gas_limit: u64) -> TransactionRequest {
+        TransactionRequest {
+            caller: self.caller,
+            to: Some(address),
+            value: self.value,
+            data: self.calldata.clone(),
+            gas_limit,
+            deadline: None,
+            record_instructions: false,
+        }
+    }
+}
+
+impl EvmWorld {
+    /// Block for an execution, with overrides from its context applied
+    fn execution_block(&self, inputs: &ExecutionInputs) -> BlockEnv {
+        let mut block = self.block_env();
+        if let Some(number) = inputs.context.block_number {
+            block.number = number;
+        }
+        if let Some(timestamp) = inputs.context.timestamp {
+            block.timestamp = timestamp;
+        }
+        block
+    }
+
+    /// Deploy the creation code behind `code_path` on first use
+    fn ensure_deployed(
+        &mut self,
+        block: &BlockEnv,
+        code_path: &Path,
+        call: &PreparedCall,
+    ) -> Result<(H160, Vec<StateChange>)> {
+        if let Some(address) = self.deployments.get(code_path) {
+            return Ok((*address, Vec::new()));
+        }
+
+        let deployment = transact(
+            &mut self.state,
+            block,
+            TransactionRequest {
+                caller: call.caller,
+                to: None,
+                value: U256::zero(),
+                data: call.bytecode.clone(),
+                gas_limit: block.gas_limit,
+                deadline: None,
+                record_instructions: false,
+            },
+        );
+        fork_result(&deployment)?;
+        let address = match deployment.created_address {
+            Some(address) if deployment.success => address,
+            _ => bail!(
+                "Deployment of {} failed: {}",
+                code_path.display(),
+                deployment.error.unwrap_or_default()
+            ),
+        };
+        self.deployments.insert(code_path.to_path_buf(), address);
+        Ok((address, deployment.state_changes))
+    }
+}
+
+#[async_trait]
+impl BlockchainRuntime for EvmRuntime {
+    fn blockchain_id(&self) -> &str {
+        &self.blockchain_id
+    }
+
+    async fn create_environment(&self, config: RuntimeConfig) -> Result<RuntimeEnvironment> {
+        config.validate()?;
+        if let Some(hardfork) = config.blockchain_config.get("hardfork") {
+            if hardfork.as_str() != Some(EVM_HARDFORK) {
+                return Err(RuntimeError::invalid_config(
+                    "blockchain_config.hardfork",
+                    format!("only {} is supported, not {}", EVM_HARDFORK, hardfork),
+                )
+                .into());
+            }
+        }
+
+        let nonce = self.environment_nonce.fetch_add(1, Ordering::SeqCst) + 1;
+        let environment_id = format!("evm_{}", nonce);
+        info!("Creating EVM environment {}", environment_id);
+
+        let entropy = self.entropy.clone().unwrap_or_else(|| Entropy::from_config(&config));
+        let mut prevrandao = [0u8; 32];
+        entropy.rng().fill_bytes(&mut prevrandao);
+        let gas_limit = config.blockchain_config.get("gas_limit").and_then(|v| v.as_u64());
+        let mut metadata = HashMap::new();
+
+        // A fork continues from the block after the pinned one
+        let (state, clock, block) = if config.network_mode == NetworkMode::MainnetFork {
+            let fork = ForkSource::open(&config).await?;
+            let header = fork.block()?;
+            info!("Forking chain {} at block {}", header.chain_id, fork.block_number());
+            metadata.insert("fork_block".to_string(), serde_json::json!(fork.block_number()));
+            let block = BlockEnv {
+                number: 0,
+                timestamp: 0,
+                coinbase: parse_address(&header.coinbase)?,
+                gas_limit: gas_limit.unwrap_or(header.gas_limit),
+                chain_id: header.chain_id,
+                base_fee: parse_u256(&serde_json::json!(header.base_fee))?,
+                prevrandao: U256::from_big_endian(&prevrandao),
+            };
+            let clock = BlockClock::new(fork.block_number() + 1, header.timestamp, &config);
+            (EvmState::forked(fork), clock, block)
+        } else {
+            let block = BlockEnv {
+                number: 0,
+                timestamp: 0,
+                coinbase: H160::zero(),
+                gas_limit: gas_limit.unwrap_or(DEFAULT_EVM_BLOCK_GAS_LIMIT),
+                chain_id: config
+                    .blockchain_config
+                    .get("chain_id")
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(DEFAULT_EVM_CHAIN_ID),
+                base_fee: U256::zero(),
+                prevrandao: U256::from_big_endian(&prevrandao),
+            };
+            let clock = BlockClock::new(1, entropy.clock().now_secs(), &config);
+            (EvmState::default(), clock, block)
+        };
+
+        let access_control = config.security_config.access_control.clone();
+        self.environments
+            .write()
+            .map_err(|_| poisoned())?
+            .insert(
+                environment_id.clone(),
+                Arc::new(Mutex::new(EvmEnvironment {
+                    config,
+                    world: EvmWorld {
+                        state,
+                        block,
+                        clock,
+                        deployments: HashMap::new(),
+                        access_control,
+                    },
+                    snapshots: BTreeMap::new(),
+                    snapshot_nonce: 0,
+                    executions: HashMap::new(),
+                    execution_nonce: 0,
+                    event_bus: EventBus::default(),
+                    entropy,
+                })),
+            );
+
+        Ok(RuntimeEnvironment {
+            endpoint_url: format!("evm://{}", environment_id),
+            environment_id,
+            blockchain_id: self.blockchain_id.clone(),
+            runtime_type: RuntimeType::InMemory,
+            state: EnvironmentState::Ready,
+            metadata,
+        })
+    }
+
+    async fn execute(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+    ) -> Result<ExecutionResult> {
+        let (result, _, _) = self.run_execution(env, code_path, inputs, false).await?;
+        self.publish(env, &result)?;
+        Ok(result)
+    }
+
+    async fn execute_traced(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+    ) -> Result<ExecutionTrace> {
+        let config = self.with_environment(env, |environment| Ok(environment.config.clone()))?;
+        let (result, call_trace, block) = self.run_execution(env, code_path, inputs, true).await?;
+        self.publish(env, &result)?;
+        let mut trace = ExecutionTrace::record(&self.blockchain_id, config, code_path, inputs, block, result).await;
+        trace.call_tree = call_trace.call_tree;
+        trace.instructions = call_trace.instructions;
+        Ok(trace)
+    }
+
+    async fn estimate_gas(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+    ) -> Result<GasEstimate> {
+        let call = PreparedCall::new(code_path, inputs).await?;
+        // Every run works on a copy so the environment is left untouched and unlocked
+        let mut world = self.with_environment(env, |environment| Ok(environment.world.clone()))?;
+        let (code_path, inputs) = (code_path.to_path_buf(), inputs.clone());
+
+        run_blocking(move || {
+            let block = world.execution_block(&inputs);
+            let (address, _) = world.ensure_deployed(&block, &code_path, &call)?;
+            let run = |gas_limit| transact(&mut world.state.clone(), &block, call.request(address, gas_limit));
+
+            let cap = call.gas_limit.unwrap_or(block.gas_limit);
+            let outcome = run(cap);
+            fork_result(&outcome)?;
+            let mut estimate = GasEstimate {
+                gas_used: outcome.gas_used,
+                gas_limit: cap,
+                success: outcome.success,
+                error: outcome.error,
+                frames: outcome.trace.frames,
+            };
+            if !estimate.success {
+                return Ok(estimate);
+            }
+
+            // Forwarding rules can make a call need more gas than it ends up
+            // using, so search for the lowest limit that still succeeds
+            let (mut low, mut high) = (intrinsic_gas(&call.calldata, false) - 1, cap);
+            while low + 1 < high {
+                let mid = low + (high - low) / 2;
+                if run(mid).success {
+                    high = mid;
+                } else {
+                    low = mid;
+                }
+            }
+            estimate.gas_limit = high;
+            Ok(estimate)
+        })
+        .await
+    }
+
+    async fn deploy_contract(
+        &self,
+        env: &RuntimeEnvironment,
+        bytecode: &[u8],
+        constructor_args: &[u8],
+    ) -> Result<String> {
+        if bytecode.is_empty() {
+            bail!("Cannot deploy empty bytecode");
+        }
+        let mut data = bytecode.to_vec();
+        data.extend_from_slice(constructor_args);
+
+        self.with_environment_blocking(env, move |environment| {
+            let block = environment.world.block_env();
+            let outcome = transact(
+                &mut environment.world.state,
+                &block,
+                TransactionRequest {
+                    caller: parse_address(DEFAULT_SENDER_ADDRESS)?,
+                    to: None,
+                    value: U256::zero(),
+                    data,
+                    gas_limit: block.gas_limit,
+                    deadline: None,
+                    record_instructions: false,
+                },
+            );
+            fork_result(&outcome)?;
+            match outcome.created_address {
+                Some(address) if outcome.success => Ok(format_address(&address)),
+                _ => bail!("Deployment failed: {}", outcome.error.unwrap_or_default()),
+            }
+        })
+        .await
+    }
+
+    async fn call_function(
+        &self,
+        env: &RuntimeEnvironment,
+        contract_address: &str,
+        function: &str,
+        args: &[u8],
+    ) -> Result<Vec<u8>> {
+        let address = parse_address(contract_address)?;
+        let mut data = function_selector(function)?;
+        data.extend_from_slice(args);
+
+        let started = Instant::now();
+        let (contract_address, function) = (contract_address.to_string(), function.to_string());
+        self.with_environment_blocking(env, move |environment| {
+            let block = environment.world.block_env();
+            let limits = ResourceLimits::from_config(&environment.config);
+            let outcome = transact(
+                &mut environment.world.state,
+                &block,
+                TransactionRequest {
+                    caller: parse_address(DEFAULT_SENDER_ADDRESS)?,
+                    to: Some(address),
+                    value: U256::zero(),
+                    data,
+                    gas_limit: block.gas_limit,
+                    deadline: Some(started + limits.timeout),
+                    record_instructions: false,
+                },
+            );
+            fork_result(&outcome)?;
+            if outcome.timed_out {
+                return Err(RuntimeError::Timeout(limits.timeout).into());
+            }
+            if !outcome.success {
+                bail!(
+                    "Call to {}::{} failed: {}",
+                    contract_address,
+                    function,
+                    outcome.error.unwrap_or_default()
+                );
+            }
+            Ok(outcome.output)
+        })
+        .await
+    }
+
+    fn metrics_definition(&self) -> Vec<RuntimeMetricDefinition> {
+        vec![
+            RuntimeMetricDefinition {
+                name: "gas_used".to_string(),
+                description: "Gas consumed during execution".to_string(),
+                unit: "gas".to_string(),
+                metric_type: MetricType::Gas,
+            },
+            RuntimeMetricDefinition {
+                name: "execution_time".to_string(),
+                description: "Time taken to execute".to_string(),
+                unit: "ms".to_string(),
+                metric_type: MetricType::Time,
+            },
+        ]
+    }
+
+    async fn monitor(
+        &self,
+        env: &RuntimeEnvironment,
+        execution_id: &str,
+    ) -> Result<Vec<RuntimeEvent>> {
+        self.with_environment(env, |environment| {
+            environment
+                .executions
+                .get(execution_id)
+                .map(|result| result.events.clone())
+                .ok_or_else(|| RuntimeError::ExecutionNotFound(execution_id.to_string()).into())
+        })
+    }
+
+    async fn destroy(&self, env: RuntimeEnvironment) -> Result<()> {
+        info!("Destroying EVM environment {}", env.environment_id);
+        self.environments
+            .write()
+            .map_err(|_| poisoned())?
+            .remove(&env.environment_id)
+            .map(|_| ())
+            .ok_or_else(|| RuntimeError::EnvironmentNotFound(env.environment_id.clone()).into())
+    }
+
+    async fn is_available(&self) -> bool {
+        true
+    }
+
+    fn capabilities(&self) -> RuntimeCapabilities {
+        self.capabilities.clone()
+    }
+
+    async fn snapshot(&self, env: &RuntimeEnvironment) -> Result<SnapshotId> {
+        self.with_environment_mut(env, |environment| {
+            environment.snapshot_nonce += 1;
+            let id = environment.snapshot_nonce;
+            environment.snapshots.insert(id, environment.world.clone());
+            Ok(SnapshotId(id))
+        })
+    }
+
+    async fn revert(&self, env: &RuntimeEnvironment, snapshot: SnapshotId) -> Result<()> {
+        self.with_environment_mut(env, |environment| {
+            let world = environment
+                .snapshots
+                .get(&snapshot.0)
+                .cloned()
+                .ok_or_else(|| RuntimeError::SnapshotNotFound {
+                    environment_id: env.environment_id.clone(),
+                    snapshot,
+                })?;
+            environment.snapshots.split_off(&(snapshot.0 + 1));
+            environment.world = world;
+            Ok(())
+        })
+    }
+
+    async fn subscribe(&self, env: &RuntimeEnvironment, filter: EventFilter) -> Result<EventSubscription> {
+        self.with_environment_mut(env, |environment| Ok(environment.event_bus.subscribe(filter)))
+    }
+
+    async fn current_block(&self, env: &RuntimeEnvironment) -> Result<BlockInfo> {
+        self.with_environment(env, |environment| Ok(environment.world.clock.info()))
+    }
+
+    async fn advance_time(&self, env: &RuntimeEnvironment, seconds: u64) -> Result<BlockInfo> {
+        self.with_environment_mut(env, |environment| Ok(environment.world.clock.advance_time(seconds)?))
+    }
+
+    async fn set_next_block_timestamp(&self, env: &RuntimeEnvironment, timestamp: u64) -> Result<()> {
+        self.with_environment_mut(env, |environment| {
+            Ok(environment.world.clock.set_next_block_timestamp(timestamp)?)
+        })
+    }
+
+    async fn mine_blocks(&self, env: &RuntimeEnvironment, count: u64) -> Result<BlockInfo> {
+        self.with_environment_mut(env, |environment| Ok(environment.world.clock.mine(count)?))
+    }
+
+    async fn grant_role(&self, env: &RuntimeEnvironment, account: &str, role: &str) -> Result<()> {
+        self.with_environment_mut(env, |environment| Ok(environment.world.access_control.grant_role(account, role)?))
+    }
+
+    async fn revoke_role(&self, env: &RuntimeEnvironment, account: &str, role: &str) -> Result<bool> {
+        self.with_environment_mut(env, |environment| Ok(environment.world.access_control.revoke_role(account, role)))
+    }
+
+    async fn execute_secure(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+        security_config: &SecurityConfig,
+    ) -> Result<ExecutionResult> {
+        let started = Instant::now();
+        let call = PreparedCall::new(code_path, inputs).await?;
+        let (environment_id, code_path, inputs) = (env.environment_id.clone(), code_path.to_path_buf(), inputs.clone());
+        let security_config = security_config.clone();
+
+        // The world is held from the snapshot to the rollback, so no other
+        // operation can interleave and be lost when the execution is undone
+        let (result, verdict) = self
+            .with_environment_blocking(env, move |environment| {
+                let enforcer = SecurityEnforcer::with_clock(
+                    SecurityConfig {
+                        access_control: environment.world.access_control.clone(),
+                        ..security_config
+                    },
+                    environment.entropy.clock().clone(),
+                );
+                let before = enforcer.policy().can_abort().then(|| environment.world.clone());
+                let (mut result, trace, _) =
+                    environment.run(&environment_id, &code_path, &inputs, &call, false, started)?;
+
+                let caller = inputs.context.sender.as_deref().unwrap_or(DEFAULT_SENDER_ADDRESS);
+                let verdict = enforcer.enforce(&mut result, &inputs.target_function, caller, trace.call_tree.as_ref());
+                match (&verdict, before) {
+                    // Subscribers never see events of an execution rolled back
+                    (Err(_), Some(world)) => {
+                        environment.world = world;
+                        result.events.clear();
+                    }
+                    _ => environment.event_bus.publish(&result.events),
+                }
+                environment
+                    .executions
+                    .insert(result.execution_id.clone(), result.clone());
+                Ok((result, verdict))
+            })
+            .await?;
+
+        verdict?;
+        Ok(result)
+    }
+
+    async fn check_reentrancy(
+        &self,
+        env: &RuntimeEnvironment,
+        function_name: &str,
+        caller: &str,
+        call_stack: &[String],
+    ) -> Result<bool> {
+        let validator = SecurityValidator::new(self.security_config(env)?);
+        Ok(validator.check_reentrancy(function_name, caller, call_stack).is_err())
+    }
+
+    async fn detect_overflow(
+        &self,
+        env: &RuntimeEnvironment,
+        operation: &str,
+        operands: &[i64],
+    ) -> Result<bool> {
+        let mut config = self.security_config(env)?;
+        // Words are 256 bits, and only signed words hold negative operands
+        let word = if operands.iter().any(|operand| *operand < 0) {
+            IntegerType::I256
+        } else {
+            IntegerType::U256
+        };
+        config.integer_type.get_or_insert(word);
+        let validator = SecurityValidator::new(config);
+        Ok(validator.detect_overflow(operation, operands).is_err())
+    }
+
+    async fn verify_access_control(
+        &self,
+        env: &RuntimeEnvironment,
+        function_name: &str,
+        caller: &str,
+        required_role: Option<&str>,
+    ) -> Result<bool> {
+        let validator = SecurityValidator::new(self.security_config(env)?);
+        Ok(validator.verify_access_control(function_name, caller, required_role).is_ok())
+    }
+
+    async fn enforce_resource_limits(
+        &self,
+        env: &RuntimeEnvironment,
+        gas_used: u64,
+        memory_used: u64,
+        call_depth: u32,
+        external_calls: u32,
+        security_config: &SecurityConfig,
+    ) -> Result<Vec<SecurityViolation>> {
+        let clock = self.entropy(env)?.clock().clone();
+        Ok(SecurityEnforcer::with_clock(security_config.clone(), clock).check_resource_limits(
+            gas_used,
+            memory_used,
+            call_depth,
+            external_calls,
+        ))
+    }
+
+    async fn get_security_report(
+        &self,
+        env: &RuntimeEnvironment,
+        execution_id: &str,
+    ) -> Result<SecurityReport> {
+        self.with_environment(env, |environment| {
+            let result = environment
+                .executions
+                .get(execution_id)
+                .ok_or_else(|| RuntimeError::ExecutionNotFound(execution_id.to_string()))?;
+            let config = SecurityConfig {
+                access_control: environment.world.access_control.clone(),
+                ..environment.config.security_config.clone()
+            };
+            Ok(SecurityReport::with_clock(result, &config, environment.entropy.clock().as_ref()))
+        })
+    }
+}
+
+/// Run synchronous work on the blocking thread pool
+async fn run_blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| RuntimeError::Backend(format!("EVM task failed: {}", e)))?
+}
+
+fn poisoned() -> RuntimeError {
+    RuntimeError::Backend("EVM runtime state is poisoned".to_string())
+}
+
+/// Fail when forked state could not be loaded, rather than report a bogus execution
+fn fork_result(outcome: &TransactionOutcome) -> Result<()> {
+    match &outcome.fork_error {
+        Some(error) => Err(RuntimeError::Backend(format!("Failed to load forked state: {}", error)).into()),
+        None => Ok(()),
+    }
+}
+
+/// Convert an interpreter outcome into an execution result
+fn build_result(execution_id: String, outcome: TransactionOutcome, block: &BlockEnv) -> ExecutionResult {
+    let mut result = ExecutionResult::new(execution_id.clone(), outcome.success);
+    result.return_value = Some(serde_json::json!(format!("0x{}", hex::encode(&outcome.output))));
+    result.error = outcome.error;
+    result.state_changes = outcome.state_changes;
+    result
+        .metrics
+        .insert("gas_used".to_string(), serde_json::json!(outcome.gas_used));
+
+    result.events = outcome
+        .logs
+        .iter()
+        .enumerate()
+        .map(|(index, log)| RuntimeEvent {
+            event_id: format!("{}_log_{}", execution_id, index),
+            event_type: log
+                .topics
+                .first()
+                .map(format_word)
+                .unwrap_or_else(|| "anonymous".to_string()),
+            timestamp: block.timestamp,
+            data: HashMap::from([
+                ("address".to_string(), serde_json::json!(format_address(&log.address))),
+                (
+                    "topics".to_string(),
+                    serde_json::json!(log.topics.iter().map(format_word).collect::<Vec<_>>()),
+                ),
+                ("data".to_string(), serde_json::json!(format!("0x{}", hex::encode(&log.data)))),
+            ]),
+        })
+        .collect();
+
+    let context = &mut result.security_context;
+    context.call_depth = outcome.trace.max_depth;
+    context.external_call_count = outcome.trace.external_calls;
+    context.gas_used = outcome.gas_used;
+    context.memory_used = outcome.trace.peak_memory;
+    context.call_stack = outcome.trace.deepest_stack;
+    result
+}
+
+/// Read bytecode from a file holding either hex text or raw bytes
+pub(crate) async fn load_bytecode(code_path: &Path) -> Result<Vec<u8>> {
+    validate_code_path(code_path)?;
+    let contents = tokio::fs::read(code_path)
+        .await
+        .with_context(|| format!("Failed to read bytecode from {}", code_path.display()))?;
+
+    let bytecode = match std::str::from_utf8(&contents) {
+        Ok(text) => {
+            let text = text.trim();
+            let digits = text.strip_prefix("0x").unwrap_or(text);
+            hex::decode(digits).unwrap_or(contents)
+        }
+        Err(_) => contents,
+    };
+
+    if bytecode.is_empty() {
+        bail!("No bytecode found in {}", code_path.display());
+    }
+    Ok(bytecode)
+}
+
+/// Selector for a function signature such as `transfer(address,uint256)` or a raw `0x` selector
+pub(crate) fn function_selector(function: &str) -> Result<Vec<u8>> {
+    let function: String = function.chars().filter(|c| !c.is_whitespace()).collect();
+    if function.is_empty() {
+        return Ok(Vec::new());
+    }
+    if let Some(digits) = function.strip_prefix("0x") {
+        let selector = hex::decode(digits)?;
+        if selector.len() != 4 {
+            bail!("Selector {} must be 4 bytes", function);
+        }
+        return Ok(selector);
+    }
+    if function.contains('(') {
+        return Ok(keccak256(function.as_bytes())[..4].to_vec());
+    }
+    bail!(
+        "Function {} must be a signature such as transfer(address,uint256) or a 4-byte selector",
+        function
+    )
+}
+
+/// Build calldata from a target function and execution parameters
+pub(crate) fn encode_call(target_function: &str, parameters: &HashMap<String, serde_json::Value>) -> Result<Vec<u8>> {
+    let mut calldata = function_selector(target_function)?;
+
+    if let Some(raw) = parameters.get("calldata") {
+        let raw = raw.as_str().ok_or_else(|| anyhow!("calldata must be a hex string"))?;
+        calldata.extend(hex::decode(raw.trim_start_matches("0x"))?);
+    } else if let Some(args) = parameters.get("args") {
+        let args = args.as_array().ok_or_else(|| anyhow!("args must be an array"))?;
+        for arg in args {
+            calldata.extend_from_slice(&parse_u256(arg)?.to_big_endian());
+        }
+    }
+
+    Ok(calldata)
+}
+
+/// Parse a JSON number, boolean, or decimal/hex string into a 256-bit word
+pub(crate) fn parse_u256(value: &serde_json::Value) -> Result<U256> {
+    match value {
+        serde_json::Value::Number(number) => number
+            .as_u64()
+            .map(U256::from)
+            .ok_or_else(|| anyhow!("{} is not an unsigned integer", number)),
+        serde_json::Value::Bool(flag) => Ok(U256::from(*flag as u8)),
+        serde_json::Value::String(text) => match text.strip_prefix("0x") {
+            Some(digits) if digits.len() <= 64 => {
+                U256::from_str_radix(digits, 16).map_err(|_| anyhow!("{} is not a valid hex word", text))
+            }
+            Some(_) => bail!("{} does not fit in 32 bytes", text),
+            None => U256::from_dec_str(text).map_err(|_| anyhow!("{} is not a valid decimal word", text)),
+        },
+        other => bail!("Cannot encode {} as an ABI word", other),
+    }
+}
+
+pub(crate) fn parse_address(address: &str) -> Result<H160> {
+    let bytes = hex::decode(address.trim_start_matches("0x"))
+        .with_context(|| format!("Invalid address {}", address))?;
+    if bytes.len() != 20 {
+        bail!("Invalid address {}: expected 20 bytes", address);
+    }
+    Ok(H160::from_slice(&bytes))
+}
@@ -0,0 +1,371 @@
+//! Journaled world state for the EVM backend
+
+use primitive_types::{H160, U256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::fork::ForkSource;
+use crate::types::{StateChange, StateChangeType};
+
+/// Account stored in the EVM world state
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EvmAccount {
+    pub balance: U256,
+    pub nonce: u64,
+    pub code: Vec<u8>,
+    pub storage: HashMap<U256, U256>,
+}
+
+/// Block-level values visible to executing code
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockEnv {
+    pub number: u64,
+    pub timestamp: u64,
+    pub coinbase: H160,
+    pub gas_limit: u64,
+    pub chain_id: u64,
+    pub base_fee: U256,
+    pub prevrandao: U256,
+}
+
+/// Reversible modification of the world state
+#[derive(Debug, Clone)]
+enum JournalEntry {
+    AccountCreated(H160),
+    BalanceChanged(H160, U256),
+    NonceChanged(H160, u64),
+    StorageChanged(H160, U256, U256),
+    CodeChanged(H160, Vec<u8>),
+    SelfDestructed(H160),
+}
+
+/// World state with a journal so call frames can be rolled back
+///
+/// A forked state loads accounts and storage slots from its `ForkSource` the
+/// first time they are read, so every accessor takes `&mut self`. Loading
+/// failures are kept in `fork_error` for the transaction to report.
+#[derive(Debug, Clone, Default)]
+pub struct EvmState {
+    accounts: HashMap<H160, EvmAccount>,
+    journal: Vec<JournalEntry>,
+    selfdestructs: HashSet<H160>,
+    fork: Option<ForkSource>,
+    loaded_accounts: HashSet<H160>,
+    loaded_slots: HashSet<(H160, U256)>,
+    fork_error: Option<String>,
+}
+
+impl EvmState {
+    /// State backed by a fork, starting with nothing loaded
+    pub(crate) fn forked(fork: ForkSource) -> Self {
+        Self {
+            fork: Some(fork),
+            ..Self::default()
+        }
+    }
+
+    /// Get an account if it exists
+    pub fn account(&mut self, address: &H160) -> Option<&EvmAccount> {
+        self.load_account(address);
+        self.accounts.get(address)
+    }
+
+    /// Check whether an account exists
+    pub fn exists(&mut self, address: &H160) -> bool {
+        self.load_account(address);
+        self.accounts.contains_key(address)
+    }
+
+    /// Get the balance of an account
+    pub fn balance(&mut self, address: &H160) -> U256 {
+        self.load_account(address);
+        self.accounts.get(address).map(|a| a.balance).unwrap_or_default()
+    }
+
+    /// Get the nonce of an account
+    pub fn nonce(&mut self, address: &H160) -> u64 {
+        self.load_account(address);
+        self.accounts.get(address).map(|a| a.nonce).unwrap_or_default()
+    }
+
+    /// Get the code of an account
+    pub fn code(&mut self, address: &H160) -> &[u8] {
+        self.load_account(address);
+        self.accounts.get(address).map(|a| a.code.as_slice()).unwrap_or_default()
+    }
+
+    /// Read a storage slot
+    pub fn storage(&mut self, address: &H160, slot: &U256) -> U256 {
+        self.load_slot(address, slot);
+        self.loaded_storage(address, slot)
+    }
+
+    /// Take the first failure to load forked state since the last call,
+    /// after saving what was loaded to the fork cache
+    pub(crate) fn take_fork_error(&mut self) -> Option<String> {
+        if let Some(Err(error)) = self.fork.as_ref().map(ForkSource::save) {
+            self.fork_error.get_or_insert_with(|| format!("{:#}", error));
+        }
+        self.fork_error.take()
+    }
+
+    fn load_account(&mut self, address: &H160) {
+        let Some(fork) = self.fork.clone() else {
+            return;
+        };
+        if !self.loaded_accounts.insert(*address) {
+            return;
+        }
+        match fork.account(address) {
+            Ok(Some(account)) => {
+                self.accounts.insert(*address, account);
+            }
+            Ok(None) => {}
+            Err(error) => {
+                self.loaded_accounts.remove(address);
+                self.fork_error.get_or_insert_with(|| format!("{:#}", error));
+            }
+        }
+    }
+
+    fn load_slot(&mut self, address: &H160, slot: &U256) {
+        let Some(fork) = self.fork.clone() else {
+            return;
+        };
+        self.load_account(address);
+        if !self.loaded_slots.insert((*address, *slot)) {
+            return;
+        }
+        match fork.storage(address, slot) {
+            Ok(value) if !value.is_zero() => {
+                self.accounts.entry(*address).or_default().storage.insert(*slot, value);
+            }
+            Ok(_) => {}
+            Err(error) => {
+                self.loaded_slots.remove(&(*address, *slot));
+                self.fork_error.get_or_insert_with(|| format!("{:#}", error));
+            }
+        }
+    }
+
+    fn loaded_storage(&self, address: &H160, slot: &U256) -> U256 {
+        self.accounts
+            .get(address)
+            .and_then(|a| a.storage.get(slot).copied())
+            .unwrap_or_default()
+    }
+
+    /// Current journal position, used to revert a call frame
+    pub(crate) fn checkpoint(&self) -> usize {
+        self.journal.len()
+    }
+
+    /// Undo every change made since the checkpoint
+    pub(crate) fn revert_to(&mut self, checkpoint: usize) {
+        while self.journal.len() > checkpoint {
+            match self.journal.pop().expect("journal is longer than checkpoint") {
+                JournalEntry::AccountCreated(address) => {
+                    self.accounts.remove(&address);
+                }
+                JournalEntry::BalanceChanged(address, old) => self.account_mut(&address).balance = old,
+                JournalEntry::NonceChanged(address, old) => self.account_mut(&address).nonce = old,
+                JournalEntry::StorageChanged(address, slot, old) => {
+                    let storage = &mut self.account_mut(&address).storage;
+                    if old.is_zero() {
+                        storage.remove(&slot);
+                    } else {
+                        storage.insert(slot, old);
+                    }
+                }
+                JournalEntry::CodeChanged(address, old) => self.account_mut(&address).code = old,
+                JournalEntry::SelfDestructed(address) => {
+                    self.selfdestructs.remove(&address);
+                }
+            }
+        }
+    }
+
+    /// Forget the journal once a transaction is final
+    pub(crate) fn commit(&mut self) {
+        for address in std::mem::take(&mut self.selfdestructs) {
+            self.accounts.remove(&address);
+        }
+        self.journal.clear();
+    }
+
+    /// Create an account if it does not exist yet
+    pub(crate) fn touch(&mut self, address: &H160) {
+        self.load_account(address);
+        if !self.accounts.contains_key(address) {
+            self.accounts.insert(*address, EvmAccount::default());
+            self.journal.push(JournalEntry::AccountCreated(*address));
+        }
+    }
+
+    /// Set the balance of an account
+    pub(crate) fn set_balance(&mut self, address: &H160, balance: U256) {
+        self.touch(address);
+        let account = self.account_mut(address);
+        let old = std::mem::replace(&mut account.balance, balance);
+        self.journal.push(JournalEntry::BalanceChanged(*address, old));
+    }
+
+    /// Increment the nonce of an account, returning the previous nonce
+    pub(crate) fn increment_nonce(&mut self, address: &H160) -> u64 {
+        self.touch(address);
+        let account = self.account_mut(address);
+        let old = account.nonce;
+        account.nonce += 1;
+        self.journal.push(JournalEntry::NonceChanged(*address, old));
+        old
+    }
+
+    /// Write a storage slot
+    pub(crate) fn set_storage(&mut self, address: &H160, slot: U256, value: U256) {
+        self.load_slot(address, &slot);
+        self.touch(address);
+        let storage = &mut self.account_mut(address).storage;
+        let old = if value.is_zero() {
+            storage.remove(&slot)
+        } else {
+            storage.insert(slot, value)
+        };
+        self.journal
+            .push(JournalEntry::StorageChanged(*address, slot, old.unwrap_or_default()));
+    }
+
+    /// Replace the code of an account
+    pub(crate) fn set_code(&mut self, address: &H160, code: Vec<u8>) {
+        self.touch(address);
+        let old = std::mem::replace(&mut self.account_mut(address).code, code);
+        self.journal.push(JournalEntry::CodeChanged(*address, old));
+    }
+
+    /// Move value between accounts, failing if the sender cannot afford it
+    pub(crate) fn transfer(&mut self, from: &H160, to: &H160, value: U256) -> bool {
+        let from_balance = self.balance(from);
+        if from_balance < value {
+            return false;
+        }
+        self.touch(to);
+        if value.is_zero() || from == to {
+            return true;
+        }
+        self.set_balance(from, from_balance - value);
+        let to_balance = self.balance(to);
+        self.set_balance(to, to_balance.saturating_add(value));
+        true
+    }
+
+    /// Schedule an account for deletion at the end of the transaction
+    pub(crate) fn mark_selfdestruct(&mut self, address: &H160) {
+        if self.selfdestructs.insert(*address) {
+            self.journal.push(JournalEntry::SelfDestructed(*address));
+        }
+    }
+
+    /// Summarise the net effect of the journal since a checkpoint as state changes
+    pub(crate) fn changes_since(&self, checkpoint: usize) -> Vec<StateChange> {
+        // Keyed by a sortable tuple so the output order is stable
+        let mut originals: BTreeMap<(H160, u8, U256), Option<StateValue>> = BTreeMap::new();
+        let mut created: HashSet<H160> = HashSet::new();
+
+        for entry in &self.journal[checkpoint..] {
+            match entry {
+                JournalEntry::AccountCreated(address) => {
+                    created.insert(*address);
+                }
+                JournalEntry::BalanceChanged(address, old) => {
+                    originals
+                        .entry((*address, 0, U256::zero()))
+                        .or_insert_with(|| Some(StateValue::Balance(*old)));
+                }
+                JournalEntry::StorageChanged(address, slot, old) => {
+                    originals
+                        .entry((*address, 1, *slot))
+                        .or_insert_with(|| Some(StateValue::Storage(*old)));
+                }
+                JournalEntry::CodeChanged(address, old) => {
+                    originals
+                        .entry((*address, 2, U256::zero()))
+                        .or_insert_with(|| Some(StateValue::Code(old.clone())));
+                }
+                JournalEntry::NonceChanged(..) | JournalEntry::SelfDestructed(_) => {}
+            }
+        }
+
+        let mut changes = Vec::new();
+        for ((address, kind, slot), original) in originals {
+            let destroyed = self.selfdestructs.contains(&address);
+            let (key, old, new) = match (kind, original) {
+                (0, Some(StateValue::Balance(old))) => (
+                    format!("{}:balance", format_address(&address)),
+                    (!old.is_zero()).then(|| serde_json::json!(old.to_string())),
+                    (!destroyed)
+                        .then(|| self.accounts.get(&address).map(|a| a.balance).unwrap_or_default())
+                        .filter(|b| !b.is_zero())
+                        .map(|b| serde_json::json!(b.to_string())),
+                ),
+                (1, Some(StateValue::Storage(old))) => (
+                    format!("{}:storage:{}", format_address(&address), format_word(&slot)),
+                    (!old.is_zero()).then(|| serde_json::json!(format_word(&old))),
+                    (!destroyed)
+                        .then(|| self.loaded_storage(&address, &slot))
+                        .filter(|v| !v.is_zero())
+                        .map(|v| serde_json::json!(format_word(&v))),
+                ),
+                (2, Some(StateValue::Code(old))) => (
+                    format!("{}:code", format_address(&address)),
+                    (!old.is_empty()).then(|| serde_json::json!(format!("0x{}", hex::encode(&old)))),
+                    (!destroyed)
+                        .then(|| self.accounts.get(&address).map(|a| a.code.as_slice()).unwrap_or_default())
+                        .filter(|c| !c.is_empty())
+                        .map(|c| serde_json::json!(format!("0x{}", hex::encode(c)))),
+                ),
+                _ => continue,
+            };
+
+            let change_type = match (&old, &new) {
+                (None, None) => continue,
+                (Some(o), Some(n)) if o == n => continue,
+                (None, Some(_)) => StateChangeType::Created,
+                (Some(_), None) => StateChangeType::Deleted,
+                (Some(_), Some(_)) => StateChangeType::Updated,
+            };
+
+            // Accounts created and emptied within the same transaction leave no trace
+            if created.contains(&address) && change_type == StateChangeType::Deleted {
+                continue;
+            }
+
+            changes.push(StateChange {
+                key,
+                old_value: old,
+                new_value: new.unwrap_or(serde_json::Value::Null),
+                change_type,
+            });
+        }
+
+        changes
+    }
+
+    fn account_mut(&mut self, address: &H160) -> &mut EvmAccount {
+        self.accounts.entry(*address).or_default()
+    }
+}
+
+/// Original value captured for a state change
+enum StateValue {
+    Balance(U256),
+    Storage(U256),
+    Code(Vec<u8>),
+}
+
+/// Format an address as a lowercase 0x-prefixed hex string
+pub fn format_address(address: &H160) -> String {
+    format!("0x{}", hex::encode(address.as_bytes()))
+}
+
+/// Format a 256-bit word as a 0x-prefixed 64 character hex string
+pub fn format_word(word: &U256) -> String {
+    format!("0x{}", hex::encode(word.to_big_endian()))
+}
@@ -0,0 +1,302 @@
+//! Minimal HTTP/1.1 client for talking to nodes and daemons
+//!
+//! Each request runs on its own connection with `Connection: close`, so the
+//! response body ends when the peer closes the connection unless it is sized
+//! by `Content-Length` or sent chunked.
+
+use anyhow::{anyhow, bail, Context, Result};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use crate::error::RuntimeError;
+
+/// Response to an HTTP request
+#[derive(Debug, Clone)]
+pub(crate) struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// Value of a header, matched case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Host, port and path of an `http://` URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HttpUrl {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl HttpUrl {
+    pub fn parse(url: &str) -> Result<Self> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| RuntimeError::InvalidInput(format!("Unsupported URL {}: only http:// is supported", url)))?;
+        let (authority, path) = match rest.find('/') {
+            Some(index) => rest.split_at(index),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .map_err(|_| RuntimeError::InvalidInput(format!("Invalid port in URL {}", url)))?,
+            ),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(RuntimeError::InvalidInput(format!("Missing host in URL {}", url)).into());
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// Value of the `Host` header
+    pub fn authority(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+/// Send a request over TCP to the host of `url`
+pub(crate) async fn send_tcp(
+    url: &HttpUrl,
+    method: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> Result<HttpResponse> {
+    let stream = TcpStream::connect((url.host.as_str(), url.port))
+        .await
+        .with_context(|| format!("Failed to connect to {}", url.authority()))?;
+    send(stream, method, &url.authority(), &url.path, headers, body).await
+}
+
+/// Send a request over an established connection and read the whole response
+pub(crate) async fn send<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    method: &str,
+    host: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> Result<HttpResponse> {
+    write_request(&mut stream, method, host, path, headers, body).await?;
+    let mut raw = Vec::new();
+    stream.read_to_end(&mut raw).await?;
+    parse_response(&raw)
+}
+
+/// Send a request and return as soon as the response headers arrive, for
+/// bodies that keep streaming such as followed logs
+pub(crate) async fn send_streaming<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    method: &str,
+    host: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> Result<StreamingResponse<S>> {
+    write_request(&mut stream, method, host, path, headers, body).await?;
+    let mut reader = BufReader::new(stream);
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if reader.read_until(b'\n', &mut head).await? == 0 {
+            bail!("Incomplete HTTP response");
+        }
+    }
+    let (status, headers) = parse_head(&head[..head.len() - 4])?;
+    let response = HttpResponse {
+        status,
+        headers,
+        body: Vec::new(),
+    };
+    let body = if is_chunked(&response) {
+        BodyFraming::Chunked
+    } else if let Some(length) = content_length(&response) {
+        BodyFraming::Length(length)
+    } else {
+        BodyFraming::UntilClose
+    };
+    Ok(StreamingResponse {
+        status: response.status,
+        reader,
+        body,
+    })
+}
+
+/// How the end of a streamed body is found
+enum BodyFraming {
+    Chunked,
+    Length(usize),
+    UntilClose,
+}
+
+/// Response whose body is read piece by piece as it arrives
+pub(crate) struct StreamingResponse<S> {
+    pub status: u16,
+    reader: BufReader<S>,
+    body: BodyFraming,
+}
+
+impl<S: AsyncRead + Unpin> StreamingResponse<S> {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Next piece of the body, or `None` once it has ended
+    pub async fn next_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        match &mut self.body {
+            BodyFraming::Chunked => {
+                let mut line = String::new();
+                if self.reader.read_line(&mut line).await? == 0 {
+                    return Ok(None);
+                }
+                let size_digits = line.split(';').next().unwrap_or_default().trim();
+                let size = usize::from_str_radix(size_digits, 16)
+                    .map_err(|_| anyhow!("Invalid HTTP chunk size {:?}", line.trim_end()))?;
+                if size == 0 {
+                    return Ok(None);
+                }
+                let mut chunk = vec![0u8; size + 2];
+                self.reader.read_exact(&mut chunk).await.context("Truncated HTTP chunk")?;
+                chunk.truncate(size);
+                Ok(Some(chunk))
+            }
+            BodyFraming::Length(0) => Ok(None),
+            BodyFraming::Length(remaining) => {
+                let mut chunk = vec![0u8; (*remaining).min(8192)];
+                let read = self.reader.read(&mut chunk).await?;
+                if read == 0 {
+                    bail!("HTTP body ended {} bytes early", remaining);
+                }
+                *remaining -= read;
+                chunk.truncate(read);
+                Ok(Some(chunk))
+            }
+            BodyFraming::UntilClose => {
+                let mut chunk = vec![0u8; 8192];
+                let read = self.reader.read(&mut chunk).await?;
+                chunk.truncate(read);
+                Ok((read > 0).then_some(chunk))
+            }
+        }
+    }
+}
+
+async fn write_request<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    method: &str,
+    host: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> Result<()> {
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+        method,
+        path,
+        host,
+        body.len()
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+
+    stream.write_all(request.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+fn parse_response(raw: &[u8]) -> Result<HttpResponse> {
+    let header_end = raw
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| anyhow!("Incomplete HTTP response"))?;
+    let (status, headers) = parse_head(&raw[..header_end])?;
+
+    let mut response = HttpResponse {
+        status,
+        headers,
+        body: Vec::new(),
+    };
+    let body = &raw[header_end + 4..];
+    response.body = if is_chunked(&response) {
+        decode_chunked(body)?
+    } else if let Some(length) = content_length(&response) {
+        if body.len() < length {
+            bail!("HTTP body ended after {} of {} bytes", body.len(), length);
+        }
+        body[..length].to_vec()
+    } else {
+        body.to_vec()
+    };
+    Ok(response)
+}
+
+/// Status code and headers of a response head without its final blank line
+fn parse_head(head: &[u8]) -> Result<(u16, Vec<(String, String)>)> {
+    let head = std::str::from_utf8(head).context("HTTP headers are not valid UTF-8")?;
+    let mut lines = head.split("\r\n");
+
+    let status_line = lines.next().unwrap_or_default();
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| anyhow!("Invalid HTTP status line {:?}", status_line))?;
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    Ok((status, headers))
+}
+
+fn is_chunked(response: &HttpResponse) -> bool {
+    response
+        .header("transfer-encoding")
+        .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"))
+}
+
+fn content_length(response: &HttpResponse) -> Option<usize> {
+    response.header("content-length").and_then(|length| length.parse().ok())
+}
+
+fn decode_chunked(mut body: &[u8]) -> Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    loop {
+        let line_end = body
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .ok_or_else(|| anyhow!("Truncated HTTP chunk"))?;
+        let size_line = std::str::from_utf8(&body[..line_end])?;
+        let size_digits = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_digits, 16)
+            .map_err(|_| anyhow!("Invalid HTTP chunk size {:?}", size_line))?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Ok(decoded);
+        }
+        if body.len() < size {
+            bail!("Truncated HTTP chunk");
+        }
+        decoded.extend_from_slice(&body[..size]);
+        body = body.get(size + 2..).unwrap_or_default();
+    }
+}
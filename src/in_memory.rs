@@ -0,0 +1,692 @@
+//! In-memory blockchain runtime backend
+//!
+//! Keeps all account, contract and storage state in process memory so tests can
+//! run hermetically without a node. Execution applies the parameters of
+//! [`ExecutionInputs`] as storage writes namespaced by the code path, and
+//! contract functions are modelled as storage slots on the contract.
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use crate::access_control::AccessControlPolicy;
+use crate::block_clock::BlockClock;
+use crate::config::RuntimeConfig;
+use crate::determinism::Entropy;
+use crate::enforcement::SecurityEnforcer;
+use crate::error::RuntimeError;
+use crate::events::{EventBus, EventFilter, EventSubscription};
+use crate::constants::DEFAULT_SENDER_ADDRESS;
+use crate::info;
+use crate::report::SecurityReport;
+use crate::reentrancy::CallFrame;
+use crate::runtime::BlockchainRuntime;
+use crate::trace::ExecutionTrace;
+use crate::security::{SecurityConfig, SecurityValidator};
+use crate::types::{
+    EnvironmentState, ExecutionInputs, ExecutionResult, MetricType, RuntimeCapabilities,
+    RuntimeEnvironment, RuntimeEvent, RuntimeMetricDefinition, RuntimeType, SecurityViolation,
+    BlockInfo, SnapshotId, StateChange, StateChangeType,
+};
+
+/// Account tracked by the in-memory runtime
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct InMemoryAccount {
+    pub balance: u128,
+    pub nonce: u64,
+}
+
+/// Contract deployed to the in-memory runtime
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InMemoryContract {
+    pub address: String,
+    pub deployer: String,
+    pub bytecode: Vec<u8>,
+    pub constructor_args: Vec<u8>,
+    pub storage: HashMap<String, serde_json::Value>,
+}
+
+/// Complete state of a single in-memory environment
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryState {
+    pub accounts: HashMap<String, InMemoryAccount>,
+    pub contracts: HashMap<String, InMemoryContract>,
+    pub storage: HashMap<String, serde_json::Value>,
+    pub events: Vec<RuntimeEvent>,
+    clock: BlockClock,
+    access_control: AccessControlPolicy,
+}
+
+/// Environment bookkeeping kept alongside its state
+struct InMemoryEnvironment {
+    config: RuntimeConfig,
+    state: InMemoryState,
+    snapshots: BTreeMap<u64, InMemoryState>,
+    snapshot_nonce: u64,
+    /// Executions outlive reverts so their ids stay unique and resolvable
+    executions: HashMap<String, ExecutionResult>,
+    execution_nonce: u64,
+    event_bus: EventBus,
+    entropy: Entropy,
+}
+
+/// Blockchain runtime that keeps all state in memory
+pub struct InMemoryRuntime {
+    blockchain_id: String,
+    capabilities: RuntimeCapabilities,
+    environments: RwLock<HashMap<String, InMemoryEnvironment>>,
+    environment_nonce: AtomicU64,
+    entropy: Option<Entropy>,
+}
+
+impl InMemoryRuntime {
+    /// Create a new in-memory runtime
+    pub fn new(blockchain_id: String) -> Self {
+        Self {
+            blockchain_id,
+            capabilities: RuntimeCapabilities {
+                supports_time_travel: true,
+                supports_snapshots: true,
+                supports_event_subscriptions: true,
+                supports_access_control: true,
+                supports_tracing: true,
+                ..RuntimeCapabilities::default()
+            },
+            environments: RwLock::new(HashMap::new()),
+            environment_nonce: AtomicU64::new(0),
+            entropy: None,
+        }
+    }
+
+    /// Take time and randomness for every environment from `entropy`,
+    /// ignoring `deterministic_seed`
+    pub fn with_entropy(mut self, entropy: Entropy) -> Self {
+        self.entropy = Some(entropy);
+        self
+    }
+
+    /// Get a copy of the current state of an environment
+    pub fn state(&self, env: &RuntimeEnvironment) -> Result<InMemoryState> {
+        self.with_environment(env, |environment| Ok(environment.state.clone()))
+    }
+
+    /// Set the balance of an account, creating it if needed
+    pub fn set_balance(&self, env: &RuntimeEnvironment, address: &str, balance: u128) -> Result<()> {
+        self.with_environment_mut(env, |environment| {
+            environment
+                .state
+                .accounts
+                .entry(address.to_string())
+                .or_default()
+                .balance = balance;
+            Ok(())
+        })
+    }
+
+    fn with_environment<T>(
+        &self,
+        env: &RuntimeEnvironment,
+        f: impl FnOnce(&InMemoryEnvironment) -> Result<T>,
+    ) -> Result<T> {
+        let environments = self
+            .environments
+            .read()
+            .map_err(|_| RuntimeError::Backend("In-memory runtime state is poisoned".to_string()))?;
+        let environment = environments
+            .get(&env.environment_id)
+            .ok_or_else(|| RuntimeError::EnvironmentNotFound(env.environment_id.clone()))?;
+        f(environment)
+    }
+
+    fn with_environment_mut<T>(
+        &self,
+        env: &RuntimeEnvironment,
+        f: impl FnOnce(&mut InMemoryEnvironment) -> Result<T>,
+    ) -> Result<T> {
+        let mut environments = self
+            .environments
+            .write()
+            .map_err(|_| RuntimeError::Backend("In-memory runtime state is poisoned".to_string()))?;
+        let environment = environments
+            .get_mut(&env.environment_id)
+            .ok_or_else(|| RuntimeError::EnvironmentNotFound(env.environment_id.clone()))?;
+        f(environment)
+    }
+
+    /// Environment security configuration carrying its live access control policy
+    fn security_config(&self, env: &RuntimeEnvironment) -> Result<SecurityConfig> {
+        self.with_environment(env, |environment| {
+            Ok(SecurityConfig {
+                access_control: environment.state.access_control.clone(),
+                ..environment.config.security_config.clone()
+            })
+        })
+    }
+
+    fn entropy(&self, env: &RuntimeEnvironment) -> Result<Entropy> {
+        self.with_environment(env, |environment| Ok(environment.entropy.clone()))
+    }
+
+    /// Stream an execution's events to subscribers once it is kept
+    fn publish(&self, env: &RuntimeEnvironment, result: &ExecutionResult) -> Result<()> {
+        self.with_environment_mut(env, |environment| {
+            environment.event_bus.publish(&result.events);
+            Ok(())
+        })
+    }
+
+    /// Run an execution in an environment, leaving its events unpublished
+    fn run_execution(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+    ) -> Result<(ExecutionResult, BlockInfo)> {
+        self.with_environment_mut(env, |environment| environment.run(&env.environment_id, code_path, inputs))
+    }
+}
+
+impl InMemoryEnvironment {
+    /// Apply the inputs' parameters as storage writes, returning the block they were included in
+    ///
+    /// Events are recorded but not published, as the caller may still roll
+    /// the execution back.
+    fn run(
+        &mut self,
+        environment_id: &str,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+    ) -> Result<(ExecutionResult, BlockInfo)> {
+        let clock = self.entropy.clock().clone();
+        let started = clock.now_millis();
+        self.execution_nonce += 1;
+        let execution_id = format!("{}_exec_{}", environment_id, self.execution_nonce);
+        let state = &mut self.state;
+
+        let sender = inputs
+            .context
+            .sender
+            .clone()
+            .unwrap_or_else(|| DEFAULT_SENDER_ADDRESS.to_string());
+        state.accounts.entry(sender).or_default().nonce += 1;
+
+        // Apply parameters in a stable order so state changes are reproducible
+        let mut parameters: Vec<_> = inputs.parameters.iter().collect();
+        parameters.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut state_changes = Vec::new();
+        for (name, value) in parameters {
+            let key = format!("{}:{}", code_path.display(), name);
+            let old_value = state.storage.get(&key).cloned();
+            if old_value.as_ref() == Some(value) {
+                continue;
+            }
+
+            let change_type = if value.is_null() {
+                if old_value.is_none() {
+                    continue;
+                }
+                state.storage.remove(&key);
+                StateChangeType::Deleted
+            } else if old_value.is_some() {
+                state.storage.insert(key.clone(), value.clone());
+                StateChangeType::Updated
+            } else {
+                state.storage.insert(key.clone(), value.clone());
+                StateChangeType::Created
+            };
+
+            state_changes.push(StateChange {
+                key,
+                old_value,
+                new_value: value.clone(),
+                change_type,
+            });
+        }
+
+        let block = state.clock.info();
+        let timestamp = inputs.context.timestamp.unwrap_or(block.timestamp);
+        let event = state.push_event(&inputs.target_function, timestamp, inputs.parameters.clone());
+
+        let mut result = ExecutionResult::new(execution_id.clone(), true);
+        result.execution_time_ms = clock.now_millis().saturating_sub(started);
+        result.metrics.insert("state_changes".to_string(), serde_json::json!(state_changes.len()));
+        result.metrics.insert("events".to_string(), serde_json::json!(1));
+        result.metrics.insert(
+            "block_number".to_string(),
+            serde_json::json!(inputs.context.block_number.unwrap_or(block.number)),
+        );
+        result.metrics.insert("execution_time".to_string(), serde_json::json!(result.execution_time_ms));
+        result.security_context.memory_used = state_changes
+            .iter()
+            .map(|change| change.new_value.to_string().len() as u64)
+            .sum();
+        result.state_changes = state_changes;
+        result.events = vec![event];
+
+        self.executions.insert(execution_id, result.clone());
+        Ok((
+            result,
+            BlockInfo {
+                number: inputs.context.block_number.unwrap_or(block.number),
+                timestamp,
+            },
+        ))
+    }
+}
+
+impl InMemoryState {
+    fn push_event(
+        &mut self,
+        event_type: &str,
+        timestamp: u64,
+        data: HashMap<String, serde_json::Value>,
+    ) -> RuntimeEvent {
+        let event = RuntimeEvent {
+            event_id: format!("event_{}", self.events.len() + 1),
+            event_type: event_type.to_string(),
+            timestamp,
+            data,
+        };
+        self.events.push(event.clone());
+        event
+    }
+}
+
+#[async_trait]
+impl BlockchainRuntime for InMemoryRuntime {
+    fn blockchain_id(&self) -> &str {
+        &self.blockchain_id
+    }
+
+    async fn create_environment(&self, config: RuntimeConfig) -> Result<RuntimeEnvironment> {
+        config.validate()?;
+
+        let nonce = self.environment_nonce.fetch_add(1, Ordering::SeqCst) + 1;
+        let environment_id = format!("inmem_{}", nonce);
+        info!("Creating in-memory environment {}", environment_id);
+
+        let entropy = self.entropy.clone().unwrap_or_else(|| Entropy::from_config(&config));
+        let clock = BlockClock::new(1, entropy.clock().now_secs(), &config);
+        let access_control = config.security_config.access_control.clone();
+        self.environments
+            .write()
+            .map_err(|_| RuntimeError::Backend("In-memory runtime state is poisoned".to_string()))?
+            .insert(
+                environment_id.clone(),
+                InMemoryEnvironment {
+                    config,
+                    state: InMemoryState {
+                        clock,
+                        access_control,
+                        ..InMemoryState::default()
+                    },
+                    snapshots: BTreeMap::new(),
+                    snapshot_nonce: 0,
+                    executions: HashMap::new(),
+                    execution_nonce: 0,
+                    event_bus: EventBus::default(),
+                    entropy,
+                },
+            );
+
+        Ok(RuntimeEnvironment {
+            endpoint_url: format!("memory://{}", environment_id),
+            environment_id,
+            blockchain_id: self.blockchain_id.clone(),
+            runtime_type: RuntimeType::InMemory,
+            state: EnvironmentState::Ready,
+            metadata: HashMap::new(),
+        })
+    }
+
+    async fn execute(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+    ) -> Result<ExecutionResult> {
+        let (result, _) = self.run_execution(env, code_path, inputs)?;
+        self.publish(env, &result)?;
+        Ok(result)
+    }
+
+    async fn execute_traced(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+    ) -> Result<ExecutionTrace> {
+        let config = self.with_environment(env, |environment| Ok(environment.config.clone()))?;
+        let (result, block) = self.run_execution(env, code_path, inputs)?;
+        self.publish(env, &result)?;
+        let sender = inputs.context.sender.as_deref().unwrap_or(DEFAULT_SENDER_ADDRESS);
+        let mut call_tree = CallFrame::new(code_path.display().to_string(), &inputs.target_function, sender);
+        for change in &result.state_changes {
+            call_tree = call_tree.read(&change.key).write(&change.key);
+        }
+
+        let mut trace = ExecutionTrace::record(&self.blockchain_id, config, code_path, inputs, block, result).await;
+        trace.call_tree = Some(call_tree);
+        Ok(trace)
+    }
+
+    async fn deploy_contract(
+        &self,
+        env: &RuntimeEnvironment,
+        bytecode: &[u8],
+        constructor_args: &[u8],
+    ) -> Result<String> {
+        if bytecode.is_empty() {
+            bail!("Cannot deploy empty bytecode");
+        }
+
+        self.with_environment_mut(env, |environment| {
+            let state = &mut environment.state;
+            let deployer = state.accounts.entry(DEFAULT_SENDER_ADDRESS.to_string()).or_default();
+            deployer.nonce += 1;
+
+            // Addresses are derived from the deployer nonce, so every fresh
+            // environment hands out the same sequence of addresses
+            let address = format!("0x{:040x}", deployer.nonce);
+            state.contracts.insert(
+                address.clone(),
+                InMemoryContract {
+                    address: address.clone(),
+                    deployer: DEFAULT_SENDER_ADDRESS.to_string(),
+                    bytecode: bytecode.to_vec(),
+                    constructor_args: constructor_args.to_vec(),
+                    storage: HashMap::new(),
+                },
+            );
+
+            let timestamp = state.clock.info().timestamp;
+            let event = state.push_event(
+                "ContractDeployed",
+                timestamp,
+                HashMap::from([
+                    ("address".to_string(), serde_json::json!(address)),
+                    ("bytecode_size".to_string(), serde_json::json!(bytecode.len())),
+                ]),
+            );
+            environment.event_bus.publish([&event]);
+
+            Ok(address)
+        })
+    }
+
+    async fn call_function(
+        &self,
+        env: &RuntimeEnvironment,
+        contract_address: &str,
+        function: &str,
+        args: &[u8],
+    ) -> Result<Vec<u8>> {
+        self.with_environment_mut(env, |environment| {
+            let state = &mut environment.state;
+            let contract = state
+                .contracts
+                .get_mut(contract_address)
+                .ok_or_else(|| anyhow!("No contract deployed at {}", contract_address))?;
+
+            // A call with arguments stores them in the function's slot, a call
+            // without arguments reads the slot back
+            let output = if args.is_empty() {
+                match contract.storage.get(function).and_then(|v| v.as_str()) {
+                    Some(stored) => hex::decode(stored.trim_start_matches("0x"))?,
+                    None => Vec::new(),
+                }
+            } else {
+                contract
+                    .storage
+                    .insert(function.to_string(), serde_json::json!(format!("0x{}", hex::encode(args))));
+                Vec::new()
+            };
+
+            let timestamp = state.clock.info().timestamp;
+            let event = state.push_event(
+                "FunctionCalled",
+                timestamp,
+                HashMap::from([
+                    ("address".to_string(), serde_json::json!(contract_address)),
+                    ("function".to_string(), serde_json::json!(function)),
+                ]),
+            );
+            environment.event_bus.publish([&event]);
+
+            Ok(output)
+        })
+    }
+
+    fn metrics_definition(&self) -> Vec<RuntimeMetricDefinition> {
+        vec![
+            RuntimeMetricDefinition {
+                name: "state_changes".to_string(),
+                description: "Storage entries changed by execution".to_string(),
+                unit: "entries".to_string(),
+                metric_type: MetricType::Custom("state_changes".to_string()),
+            },
+            RuntimeMetricDefinition {
+                name: "events".to_string(),
+                description: "Events emitted during execution".to_string(),
+                unit: "events".to_string(),
+                metric_type: MetricType::Custom("events".to_string()),
+            },
+            RuntimeMetricDefinition {
+                name: "block_number".to_string(),
+                description: "Block the execution was included in".to_string(),
+                unit: "block".to_string(),
+                metric_type: MetricType::Custom("block_number".to_string()),
+            },
+            RuntimeMetricDefinition {
+                name: "execution_time".to_string(),
+                description: "Time taken to execute".to_string(),
+                unit: "ms".to_string(),
+                metric_type: MetricType::Time,
+            },
+        ]
+    }
+
+    async fn monitor(
+        &self,
+        env: &RuntimeEnvironment,
+        execution_id: &str,
+    ) -> Result<Vec<RuntimeEvent>> {
+        self.with_environment(env, |environment| {
+            environment
+                .executions
+                .get(execution_id)
+                .map(|result| result.events.clone())
+                .ok_or_else(|| RuntimeError::ExecutionNotFound(execution_id.to_string()).into())
+        })
+    }
+
+    async fn destroy(&self, env: RuntimeEnvironment) -> Result<()> {
+        info!("Destroying in-memory environment {}", env.environment_id);
+        self.environments
+            .write()
+            .map_err(|_| RuntimeError::Backend("In-memory runtime state is poisoned".to_string()))?
+            .remove(&env.environment_id)
+            .map(|_| ())
+            .ok_or_else(|| RuntimeError::EnvironmentNotFound(env.environment_id.clone()).into())
+    }
+
+    async fn is_available(&self) -> bool {
+        true
+    }
+
+    fn capabilities(&self) -> RuntimeCapabilities {
+        self.capabilities.clone()
+    }
+
+    async fn snapshot(&self, env: &RuntimeEnvironment) -> Result<SnapshotId> {
+        self.with_environment_mut(env, |environment| {
+            environment.snapshot_nonce += 1;
+            let id = environment.snapshot_nonce;
+            environment.snapshots.insert(id, environment.state.clone());
+            Ok(SnapshotId(id))
+        })
+    }
+
+    async fn revert(&self, env: &RuntimeEnvironment, snapshot: SnapshotId) -> Result<()> {
+        self.with_environment_mut(env, |environment| {
+            let state = environment
+                .snapshots
+                .get(&snapshot.0)
+                .cloned()
+                .ok_or_else(|| RuntimeError::SnapshotNotFound {
+                    environment_id: env.environment_id.clone(),
+                    snapshot,
+                })?;
+            environment.snapshots.split_off(&(snapshot.0 + 1));
+            environment.state = state;
+            Ok(())
+        })
+    }
+
+    async fn subscribe(&self, env: &RuntimeEnvironment, filter: EventFilter) -> Result<EventSubscription> {
+        self.with_environment_mut(env, |environment| Ok(environment.event_bus.subscribe(filter)))
+    }
+
+    async fn current_block(&self, env: &RuntimeEnvironment) -> Result<BlockInfo> {
+        self.with_environment(env, |environment| Ok(environment.state.clock.info()))
+    }
+
+    async fn advance_time(&self, env: &RuntimeEnvironment, seconds: u64) -> Result<BlockInfo> {
+        self.with_environment_mut(env, |environment| Ok(environment.state.clock.advance_time(seconds)?))
+    }
+
+    async fn set_next_block_timestamp(&self, env: &RuntimeEnvironment, timestamp: u64) -> Result<()> {
+        self.with_environment_mut(env, |environment| {
+            Ok(environment.state.clock.set_next_block_timestamp(timestamp)?)
+        })
+    }
+
+    async fn mine_blocks(&self, env: &RuntimeEnvironment, count: u64) -> Result<BlockInfo> {
+        self.with_environment_mut(env, |environment| Ok(environment.state.clock.mine(count)?))
+    }
+
+    async fn grant_role(&self, env: &RuntimeEnvironment, account: &str, role: &str) -> Result<()> {
+        self.with_environment_mut(env, |environment| Ok(environment.state.access_control.grant_role(account, role)?))
+    }
+
+    async fn revoke_role(&self, env: &RuntimeEnvironment, account: &str, role: &str) -> Result<bool> {
+        self.with_environment_mut(env, |environment| Ok(environment.state.access_control.revoke_role(account, role)))
+    }
+
+    async fn execute_secure(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+        security_config: &SecurityConfig,
+    ) -> Result<ExecutionResult> {
+        // The state is held from the snapshot to the rollback, so no other
+        // operation can interleave and be lost when the execution is undone
+        let (result, verdict) = self.with_environment_mut(env, |environment| {
+            let enforcer = SecurityEnforcer::with_clock(
+                SecurityConfig {
+                    access_control: environment.state.access_control.clone(),
+                    ..security_config.clone()
+                },
+                environment.entropy.clock().clone(),
+            );
+            let before = enforcer.policy().can_abort().then(|| environment.state.clone());
+            let (mut result, _) = environment.run(&env.environment_id, code_path, inputs)?;
+
+            let context = &mut result.security_context;
+            context.call_depth = 1;
+            context.call_stack.push(inputs.target_function.clone());
+
+            let caller = inputs.context.sender.as_deref().unwrap_or(DEFAULT_SENDER_ADDRESS);
+            let verdict = enforcer.enforce(&mut result, &inputs.target_function, caller, None);
+            match (&verdict, before) {
+                // Subscribers never see events of an execution rolled back
+                (Err(_), Some(state)) => {
+                    environment.state = state;
+                    result.events.clear();
+                }
+                _ => environment.event_bus.publish(&result.events),
+            }
+            environment
+                .executions
+                .insert(result.execution_id.clone(), result.clone());
+            Ok((result, verdict))
+        })?;
+
+        verdict?;
+        Ok(result)
+    }
+
+    async fn check_reentrancy(
+        &self,
+        env: &RuntimeEnvironment,
+        function_name: &str,
+        caller: &str,
+        call_stack: &[String],
+    ) -> Result<bool> {
+        let validator = SecurityValidator::new(self.security_config(env)?);
+        Ok(validator.check_reentrancy(function_name, caller, call_stack).is_err())
+    }
+
+    async fn detect_overflow(
+        &self,
+        env: &RuntimeEnvironment,
+        operation: &str,
+        operands: &[i64],
+    ) -> Result<bool> {
+        let validator = SecurityValidator::new(self.security_config(env)?);
+        Ok(validator.detect_overflow(operation, operands).is_err())
+    }
+
+    async fn verify_access_control(
+        &self,
+        env: &RuntimeEnvironment,
+        function_name: &str,
+        caller: &str,
+        required_role: Option<&str>,
+    ) -> Result<bool> {
+        let validator = SecurityValidator::new(self.security_config(env)?);
+        Ok(validator.verify_access_control(function_name, caller, required_role).is_ok())
+    }
+
+    async fn enforce_resource_limits(
+        &self,
+        env: &RuntimeEnvironment,
+        gas_used: u64,
+        memory_used: u64,
+        call_depth: u32,
+        external_calls: u32,
+        security_config: &SecurityConfig,
+    ) -> Result<Vec<SecurityViolation>> {
+        let clock = self.entropy(env)?.clock().clone();
+        Ok(SecurityEnforcer::with_clock(security_config.clone(), clock).check_resource_limits(
+            gas_used,
+            memory_used,
+            call_depth,
+            external_calls,
+        ))
+    }
+
+    async fn get_security_report(
+        &self,
+        env: &RuntimeEnvironment,
+        execution_id: &str,
+    ) -> Result<SecurityReport> {
+        self.with_environment(env, |environment| {
+            let result = environment
+                .executions
+                .get(execution_id)
+                .ok_or_else(|| RuntimeError::ExecutionNotFound(execution_id.to_string()))?;
+            let config = SecurityConfig {
+                access_control: environment.state.access_control.clone(),
+                ..environment.config.security_config.clone()
+            };
+            Ok(SecurityReport::with_clock(result, &config, environment.entropy.clock().as_ref()))
+        })
+    }
+}
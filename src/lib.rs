@@ -34,30 +34,81 @@
 //! ```
 
 // Re-export main types and traits
+pub use access_control::*;
+pub use arithmetic::*;
 pub use config::*;
+pub use determinism::*;
+#[cfg(unix)]
+pub use docker::*;
+pub use enforcement::*;
+pub use error::*;
+pub use events::*;
+pub use evm::*;
+pub use in_memory::*;
+pub use lifecycle::*;
+pub use limits::*;
+pub use pool::*;
+pub use process::*;
+pub use reentrancy::*;
+pub use registry::*;
+pub use report::*;
+pub use rpc::*;
 pub use runtime::*;
+pub use sarif::*;
 pub use security::*;
+pub use svm::*;
+pub use trace::*;
 pub use types::*;
 
 // Module declarations
+mod access_control;
+mod arithmetic;
+mod block_clock;
 mod config;
 mod constants;
+mod determinism;
+#[cfg(unix)]
+mod docker;
+mod enforcement;
+mod error;
+mod events;
+mod evm;
+mod http;
+mod in_memory;
+mod lifecycle;
+mod limits;
+mod pool;
+mod process;
+mod reentrancy;
+mod registry;
+mod report;
+mod rpc;
 mod runtime;
+mod sarif;
 mod security;
+mod svm;
+mod trace;
 mod types;
 
 // Optional tracing
 #[cfg(feature = "tracing")]
-use tracing::info;
+pub(crate) use tracing::info;
 
 #[cfg(not(feature = "tracing"))]
 macro_rules! info {
     ($($arg:tt)*) => {};
 }
 
+#[cfg(not(feature = "tracing"))]
+pub(crate) use info;
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashMap;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
     #[test]
     fn test_runtime_config_default() {
@@ -94,6 +145,8 @@ mod tests {
             state_changes: vec![],
             events: vec![],
             execution_time_ms: 150,
+            security_context: SecureExecutionContext::default(),
+            security_violations: vec![],
         };
 
         assert!(result.success);
@@ -189,11 +242,13 @@ mod tests {
 
     #[test]
     fn test_secure_execution_context() {
-        let mut context = SecureExecutionContext::default();
-        context.call_depth = 5;
-        context.external_call_count = 10;
-        context.gas_used = 1000;
-        context.memory_used = 1024;
+        let mut context = SecureExecutionContext {
+            call_depth: 5,
+            external_call_count: 10,
+            gas_used: 1000,
+            memory_used: 1024,
+            ..Default::default()
+        };
         context.call_stack.push("function1".to_string());
         context.call_stack.push("function2".to_string());
 
@@ -223,6 +278,196 @@ mod tests {
         assert!(check.has_permission);
     }
 
+    #[test]
+    fn test_access_control_policy_roles() {
+        let mut policy = AccessControlPolicy::new();
+        policy.define_role("minter", &[]).unwrap();
+        policy.define_role("pauser", &[]).unwrap();
+        policy.define_role("admin", &["minter", "pauser"]).unwrap();
+        policy.require_role("mint", "minter").unwrap();
+
+        assert!(policy.define_role("minter", &["admin"]).is_err());
+        assert!(policy.define_role("owner", &["auditor"]).is_err());
+        assert!(policy.grant_role("0xAB", "auditor").is_err());
+
+        policy.grant_role("0xAB", "admin").unwrap();
+        assert!(policy.has_role("0xab", "minter"));
+        assert!(policy.has_role("0xAb", "pauser"));
+        assert!(!policy.has_role("0xcd", "minter"));
+        assert_eq!(policy.required_role("mint"), Some("minter"));
+        assert!(policy.validate().is_ok());
+
+        assert!(policy.revoke_role("0xab", "admin"));
+        assert!(!policy.revoke_role("0xab", "admin"));
+        assert!(!policy.has_role("0xab", "minter"));
+    }
+
+    #[test]
+    fn test_security_validator_checks_access_control_policy() {
+        let mut config = SecurityConfig::default();
+        config.access_control.define_role("owner", &[]).unwrap();
+        config.access_control.require_role("withdraw", "owner").unwrap();
+        config.access_control.grant_role("0x123", "owner").unwrap();
+        let validator = SecurityValidator::new(config);
+
+        assert!(validator.verify_access_control("withdraw", "0x123", None).is_ok());
+        assert!(validator.verify_access_control("deposit", "0x456", None).is_ok());
+        let violation = validator.verify_access_control("withdraw", "0x456admin", None).unwrap_err();
+        assert_eq!(violation.violation_type, SecurityViolationType::AccessControlViolation);
+        assert_eq!(violation.context["required_role"], serde_json::json!("owner"));
+
+        let mut context = SecureExecutionContext::default();
+        assert!(validator.record_access_control(&mut context, "withdraw", "0x456", None).is_err());
+        assert!(validator.record_access_control(&mut context, "withdraw", "0x123", None).is_ok());
+        assert_eq!(context.access_control_checks.len(), 2);
+        assert!(!context.access_control_checks[0].has_permission);
+        assert!(context.access_control_checks[1].has_permission);
+        assert_eq!(context.access_control_checks[1].required_role.as_deref(), Some("owner"));
+    }
+
+    #[test]
+    fn test_arithmetic_evaluation_across_widths() {
+        use primitive_types::U256;
+
+        let wrapped = |op: ArithmeticOperation, ty: IntegerType, lhs: i64, rhs: i64| {
+            let outcome = op.evaluate(ty, lhs.into(), rhs.into());
+            (outcome.wrapped.to_string(), outcome.fault)
+        };
+        let overflow = Some(ArithmeticFault::Overflow);
+        let underflow = Some(ArithmeticFault::Underflow);
+
+        assert_eq!(wrapped(ArithmeticOperation::Add, IntegerType::U8, 200, 100), ("44".to_string(), overflow));
+        assert_eq!(wrapped(ArithmeticOperation::Sub, IntegerType::U64, 1, 2), (u64::MAX.to_string(), underflow));
+        assert_eq!(wrapped(ArithmeticOperation::Sub, IntegerType::I8, -128, 1), ("127".to_string(), underflow));
+        assert_eq!(wrapped(ArithmeticOperation::Mul, IntegerType::I16, -200, 200), ("25536".to_string(), underflow));
+        assert_eq!(wrapped(ArithmeticOperation::Div, IntegerType::I8, -128, -1), ("-128".to_string(), overflow));
+        assert_eq!(
+            wrapped(ArithmeticOperation::Div, IntegerType::U256, 5, 0),
+            ("0".to_string(), Some(ArithmeticFault::DivisionByZero))
+        );
+        assert_eq!(wrapped(ArithmeticOperation::Mod, IntegerType::I8, -7, 3), ("-1".to_string(), None));
+        assert_eq!(wrapped(ArithmeticOperation::Exp, IntegerType::U8, 3, 5), ("243".to_string(), None));
+        assert_eq!(wrapped(ArithmeticOperation::Exp, IntegerType::U8, 3, 6), ("217".to_string(), overflow));
+        assert_eq!(wrapped(ArithmeticOperation::Exp, IntegerType::I8, -2, 7), ("-128".to_string(), None));
+        assert_eq!(wrapped(ArithmeticOperation::Exp, IntegerType::U256, 2, 256), ("0".to_string(), overflow));
+        assert_eq!(wrapped(ArithmeticOperation::Exp, IntegerType::I256, -3, 1001).1, underflow);
+        assert_eq!(wrapped(ArithmeticOperation::Shl, IntegerType::U8, 1, 7), ("128".to_string(), None));
+        assert_eq!(wrapped(ArithmeticOperation::Shl, IntegerType::I8, 1, 7), ("-128".to_string(), overflow));
+        assert_eq!(wrapped(ArithmeticOperation::Shl, IntegerType::U128, 1, 600), ("0".to_string(), overflow));
+        assert_eq!(wrapped(ArithmeticOperation::Shr, IntegerType::I8, -5, 1), ("-3".to_string(), None));
+        assert_eq!(wrapped(ArithmeticOperation::Shr, IntegerType::I64, -5, 1000), ("-1".to_string(), None));
+        assert_eq!(
+            wrapped(ArithmeticOperation::Add, IntegerType::U8, 300, 1).1,
+            Some(ArithmeticFault::OperandOutOfRange)
+        );
+
+        let max = IntegerValue::from(U256::MAX);
+        let outcome = ArithmeticOperation::Add.evaluate(IntegerType::U256, max, 1u8.into());
+        assert_eq!((outcome.wrapped, outcome.fault), (IntegerValue::default(), overflow));
+        assert_eq!(IntegerType::I256.min_value().to_string(), format!("-{}", U256::one() << 255));
+
+        assert_eq!("uint24".parse::<IntegerType>().unwrap(), IntegerType::new(false, 24).unwrap());
+        assert_eq!("int".parse::<IntegerType>().unwrap(), IntegerType::I256);
+        assert!("u7".parse::<IntegerType>().is_err());
+        assert_eq!("-0x10".parse::<IntegerValue>().unwrap(), IntegerValue::from(-16));
+    }
+
+    #[test]
+    fn test_security_validator_reports_arithmetic_faults() {
+        let validator = SecurityValidator::new(SecurityConfig::default());
+        assert!(validator.detect_overflow("add", &[i64::MAX, 1]).is_err());
+        assert!(validator.detect_overflow("sub", &[0, 1]).is_ok());
+        assert!(validator.detect_overflow("shr", &[i64::MIN, 1]).is_ok());
+
+        let violation = validator
+            .check_arithmetic(ArithmeticOperation::Sub, IntegerType::U256, 0u8.into(), 1u8.into())
+            .unwrap_err();
+        assert_eq!(violation.violation_type, SecurityViolationType::IntegerOverflow);
+        assert_eq!(violation.severity, SecuritySeverity::High);
+        assert_eq!(violation.context["fault"], serde_json::json!("underflow"));
+        assert_eq!(violation.context["integer_type"], serde_json::json!("u256"));
+        assert_eq!(violation.context["lhs"], serde_json::json!("0"));
+        assert_eq!(violation.context["wrapped_result"], serde_json::json!(IntegerType::U256.max_value()));
+
+        let checked = SecurityValidator::new(SecurityConfig {
+            integer_type: Some(IntegerType::U8),
+            arithmetic_mode: ArithmeticMode::Checked,
+            ..SecurityConfig::default()
+        });
+        let violation = checked.detect_overflow("*", &[16, 16]).unwrap_err();
+        assert_eq!(violation.severity, SecuritySeverity::Medium);
+        assert!(violation.description.contains("reverts"));
+        let violation = checked.detect_overflow("<<", &[16, 4]).unwrap_err();
+        assert_eq!(violation.context["reverts"], serde_json::json!(false));
+
+        let permissive = SecurityValidator::new(SecurityConfig::permissive());
+        let wrapped = permissive.check_arithmetic(ArithmeticOperation::Add, IntegerType::U8, 255u8.into(), 1u8.into());
+        assert_eq!(wrapped.unwrap(), IntegerValue::default());
+    }
+
+    #[test]
+    fn test_reentrancy_detection_distinguishes_kinds() {
+        // Vault::withdraw reads the balance, calls the attacker, then writes the balance
+        let withdraw = |reentry: CallFrame| {
+            CallFrame::new("vault", "withdraw", "0xuser")
+                .read("balance")
+                .call(CallFrame::new("attacker", "receive", "vault").call(reentry))
+                .write("balance")
+        };
+
+        let same = withdraw(CallFrame::new("vault", "withdraw", "attacker").read("balance").write("balance"));
+        let cross = withdraw(CallFrame::new("vault", "transfer", "attacker").read("balance").write("balance"));
+        let read_only = withdraw(CallFrame::new("vault", "balance_of", "attacker").static_call().read("balance"));
+        let unrelated_view = withdraw(CallFrame::new("vault", "owner", "attacker").static_call().read("owner"));
+
+        let findings = same.reentrancies();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].kind, ReentrancyKind::SameFunction);
+        assert_eq!(findings[0].stale_state, vec!["balance".to_string()]);
+        assert_eq!(findings[0].call_path, vec!["vault::withdraw", "attacker::receive", "vault::withdraw"]);
+        assert_eq!(cross.reentrancies()[0].kind, ReentrancyKind::CrossFunction);
+        assert_eq!(read_only.reentrancies()[0].kind, ReentrancyKind::ReadOnly);
+        assert!(unrelated_view.reentrancies().is_empty());
+
+        // Checks-effects-interactions: the write happens before the external call
+        let safe = CallFrame::new("vault", "withdraw", "0xuser")
+            .read("balance")
+            .write("balance")
+            .call(
+                CallFrame::new("attacker", "receive", "vault")
+                    .call(CallFrame::new("vault", "withdraw", "attacker").write("balance")),
+            );
+        assert!(safe.reentrancies().is_empty());
+
+        // A contract calling itself between the read and the write is not re-entered
+        let self_call = CallFrame::new("vault", "withdraw", "0xuser")
+            .read("balance")
+            .call(CallFrame::new("vault", "withdraw", "vault").read("balance").write("balance"))
+            .write("balance");
+        assert!(self_call.reentrancies().is_empty());
+
+        // Library code run through a delegate call acts on the caller's own state
+        let library = CallFrame::new("vault", "withdraw", "0xuser")
+            .read("balance")
+            .call(
+                CallFrame::new("vault", "safe_math", "vault")
+                    .delegate_call()
+                    .read("balance")
+                    .write("balance"),
+            )
+            .write("balance");
+        assert!(library.reentrancies().is_empty());
+
+        let validator = SecurityValidator::new(SecurityConfig::default());
+        let violations = validator.detect_reentrancy(&read_only);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].violation_type, SecurityViolationType::ReentrancyAttack);
+        assert_eq!(violations[0].severity, SecuritySeverity::High);
+        assert_eq!(violations[0].context["reentrancy_kind"], serde_json::json!("read_only"));
+        assert_eq!(violations[0].context["reentered_function"], serde_json::json!("balance_of"));
+        assert!(SecurityValidator::new(SecurityConfig::permissive()).detect_reentrancy(&same).is_empty());
+    }
+
     #[test]
     fn test_execution_result_with_security() {
         let security_context = SecureExecutionContext::default();
@@ -290,5 +535,2438 @@ mod tests {
         assert_eq!(deserialized.reentrancy_protection, security_config.reentrancy_protection);
         assert_eq!(deserialized.overflow_detection, security_config.overflow_detection);
     }
-}
 
+    fn execution_inputs(target_function: &str, parameters: &[(&str, serde_json::Value)]) -> ExecutionInputs {
+        ExecutionInputs {
+            target_function: target_function.to_string(),
+            parameters: parameters
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+            context: ExecutionContext {
+                sender: Some("0x00000000000000000000000000000000000000ab".to_string()),
+                block_number: None,
+                timestamp: Some(1_700_000_000),
+                extra: HashMap::new(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_limited_runtime_enforces_memory_and_timeout() {
+        let runtime = LimitedRuntime::new(InMemoryRuntime::new("ethereum".to_string()));
+        let config = RuntimeConfig {
+            memory_limit_mb: 1,
+            ..RuntimeConfig::default()
+        };
+        let env = runtime.create_environment(config).await.unwrap();
+        let limits = runtime.limits(&env).unwrap();
+        assert_eq!(limits.memory_limit_bytes, 1024 * 1024);
+        assert_eq!(limits.timeout, Duration::from_secs(300));
+
+        let code_path = Path::new("blob.sol");
+        let small = runtime
+            .execute(&env, code_path, &execution_inputs("store", &[("data", serde_json::json!("ab"))]))
+            .await
+            .unwrap();
+        assert!(small.security_violations.is_empty());
+
+        let blob = "ab".repeat(1024 * 1024);
+        let large = runtime
+            .execute(&env, code_path, &execution_inputs("store", &[("data", serde_json::json!(blob))]))
+            .await
+            .unwrap();
+        assert!(large.security_context.memory_used > limits.memory_limit_bytes);
+        assert_eq!(large.security_violations.len(), 1);
+        assert_eq!(large.security_violations[0].violation_type, SecurityViolationType::MemoryLimitExceeded);
+        assert_eq!(large.security_context.security_violations.len(), 1);
+
+        runtime
+            .set_limits(&env, ResourceLimits { timeout: Duration::from_millis(10), ..limits })
+            .unwrap();
+        let timeout = runtime
+            .limits(&env)
+            .unwrap()
+            .run(tokio::time::sleep(Duration::from_secs(5)))
+            .await
+            .unwrap_err();
+        assert!(matches!(timeout, RuntimeError::Timeout(_)));
+
+        runtime.destroy(env.clone()).await.unwrap();
+        assert!(runtime.limits(&env).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_limited_runtime_records_timed_out_executions() {
+        // A node that accepts transactions but never mines them
+        let url = serve_json_rpc(|method, _| match method {
+            "eth_chainId" => Ok(serde_json::json!("0x7a69")),
+            "eth_accounts" => Ok(serde_json::json!(["0x00000000000000000000000000000000000000ab"])),
+            "eth_sendTransaction" => Ok(serde_json::json!("0xe1")),
+            "eth_getTransactionReceipt" => Ok(serde_json::Value::Null),
+            _ => Err((-32601, format!("{} does not exist", method))),
+        })
+        .await;
+        let runtime = LimitedRuntime::new(RpcRuntime::new("ethereum".to_string()));
+        let mut config = RuntimeConfig::default();
+        config.blockchain_config.insert("rpc_url".to_string(), serde_json::json!(url));
+        let env = runtime.create_environment(config).await.unwrap();
+        let limits = runtime.limits(&env).unwrap();
+        runtime
+            .set_limits(&env, ResourceLimits { timeout: Duration::from_millis(50), ..limits })
+            .unwrap();
+
+        let inputs = execution_inputs("", &[("to", serde_json::json!("0x00000000000000000000000000000000000000c0"))]);
+        let result = runtime.execute(&env, Path::new("pending.bin"), &inputs).await.unwrap();
+        assert!(!result.success);
+        assert_eq!(result.security_violations[0].violation_type, SecurityViolationType::ResourceLimitExceeded);
+
+        assert!(runtime.monitor(&env, &result.execution_id).await.unwrap().is_empty());
+        let report = runtime.get_security_report(&env, &result.execution_id).await.unwrap();
+        assert_eq!(report.summary.execution_id, result.execution_id);
+        assert!(!report.verdict.passed);
+        assert_eq!(report.by_type[0].violation_type, SecurityViolationType::ResourceLimitExceeded);
+    }
+
+    /// In-memory runtime whose traced executions only start after a delay
+    struct SlowTracingRuntime {
+        inner: InMemoryRuntime,
+        delay: Duration,
+    }
+
+    #[async_trait::async_trait]
+    impl BlockchainRuntime for SlowTracingRuntime {
+        fn blockchain_id(&self) -> &str {
+            self.inner.blockchain_id()
+        }
+
+        async fn create_environment(&self, config: RuntimeConfig) -> anyhow::Result<RuntimeEnvironment> {
+            self.inner.create_environment(config).await
+        }
+
+        async fn execute(
+            &self,
+            env: &RuntimeEnvironment,
+            code_path: &Path,
+            inputs: &ExecutionInputs,
+        ) -> anyhow::Result<ExecutionResult> {
+            self.inner.execute(env, code_path, inputs).await
+        }
+
+        async fn execute_traced(
+            &self,
+            env: &RuntimeEnvironment,
+            code_path: &Path,
+            inputs: &ExecutionInputs,
+        ) -> anyhow::Result<ExecutionTrace> {
+            tokio::time::sleep(self.delay).await;
+            self.inner.execute_traced(env, code_path, inputs).await
+        }
+
+        async fn deploy_contract(
+            &self,
+            env: &RuntimeEnvironment,
+            bytecode: &[u8],
+            constructor_args: &[u8],
+        ) -> anyhow::Result<String> {
+            self.inner.deploy_contract(env, bytecode, constructor_args).await
+        }
+
+        async fn call_function(
+            &self,
+            env: &RuntimeEnvironment,
+            contract_address: &str,
+            function: &str,
+            args: &[u8],
+        ) -> anyhow::Result<Vec<u8>> {
+            self.inner.call_function(env, contract_address, function, args).await
+        }
+
+        fn metrics_definition(&self) -> Vec<RuntimeMetricDefinition> {
+            self.inner.metrics_definition()
+        }
+
+        async fn monitor(&self, env: &RuntimeEnvironment, execution_id: &str) -> anyhow::Result<Vec<RuntimeEvent>> {
+            self.inner.monitor(env, execution_id).await
+        }
+
+        async fn destroy(&self, env: RuntimeEnvironment) -> anyhow::Result<()> {
+            self.inner.destroy(env).await
+        }
+
+        async fn is_available(&self) -> bool {
+            self.inner.is_available().await
+        }
+
+        fn capabilities(&self) -> RuntimeCapabilities {
+            self.inner.capabilities()
+        }
+
+        async fn current_block(&self, env: &RuntimeEnvironment) -> anyhow::Result<BlockInfo> {
+            self.inner.current_block(env).await
+        }
+
+        async fn execute_secure(
+            &self,
+            env: &RuntimeEnvironment,
+            code_path: &Path,
+            inputs: &ExecutionInputs,
+            security_config: &SecurityConfig,
+        ) -> anyhow::Result<ExecutionResult> {
+            self.inner.execute_secure(env, code_path, inputs, security_config).await
+        }
+
+        async fn check_reentrancy(
+            &self,
+            env: &RuntimeEnvironment,
+            function_name: &str,
+            caller: &str,
+            call_stack: &[String],
+        ) -> anyhow::Result<bool> {
+            self.inner.check_reentrancy(env, function_name, caller, call_stack).await
+        }
+
+        async fn detect_overflow(&self, env: &RuntimeEnvironment, operation: &str, operands: &[i64]) -> anyhow::Result<bool> {
+            self.inner.detect_overflow(env, operation, operands).await
+        }
+
+        async fn verify_access_control(
+            &self,
+            env: &RuntimeEnvironment,
+            function_name: &str,
+            caller: &str,
+            required_role: Option<&str>,
+        ) -> anyhow::Result<bool> {
+            self.inner.verify_access_control(env, function_name, caller, required_role).await
+        }
+
+        async fn enforce_resource_limits(
+            &self,
+            env: &RuntimeEnvironment,
+            gas_used: u64,
+            memory_used: u64,
+            call_depth: u32,
+            external_calls: u32,
+            security_config: &SecurityConfig,
+        ) -> anyhow::Result<Vec<SecurityViolation>> {
+            self.inner
+                .enforce_resource_limits(env, gas_used, memory_used, call_depth, external_calls, security_config)
+                .await
+        }
+
+        async fn get_security_report(&self, env: &RuntimeEnvironment, execution_id: &str) -> anyhow::Result<SecurityReport> {
+            self.inner.get_security_report(env, execution_id).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_limited_runtime_times_out_traced_executions() {
+        let runtime = LimitedRuntime::new(SlowTracingRuntime {
+            inner: InMemoryRuntime::new("ethereum".to_string()),
+            delay: Duration::from_secs(5),
+        });
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let limits = runtime.limits(&env).unwrap();
+        runtime
+            .set_limits(&env, ResourceLimits { timeout: Duration::from_millis(50), ..limits })
+            .unwrap();
+        let block = runtime.current_block(&env).await.unwrap();
+
+        let inputs = execution_inputs("mint", &[("supply", serde_json::json!(1))]);
+        let trace = runtime.execute_traced(&env, Path::new("token.sol"), &inputs).await.unwrap();
+        assert!(!trace.result.success);
+        assert!(trace.result.execution_id.starts_with(&format!("{}_timeout_", env.environment_id)));
+        assert_eq!(trace.result.security_violations[0].violation_type, SecurityViolationType::ResourceLimitExceeded);
+        assert_eq!(trace.block, block);
+        assert_eq!(trace.inputs.context.block_number, Some(block.number));
+        assert!(runtime.inner().inner.state(&env).unwrap().storage.is_empty());
+
+        let report = runtime.get_security_report(&env, &trace.result.execution_id).await.unwrap();
+        assert!(!report.verdict.passed);
+        assert_eq!(report.by_type[0].violation_type, SecurityViolationType::ResourceLimitExceeded);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_execute_records_state_changes() {
+        let runtime = InMemoryRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        assert_eq!(env.runtime_type, RuntimeType::InMemory);
+
+        let code_path = Path::new("token.sol");
+        let first = runtime
+            .execute(&env, code_path, &execution_inputs("mint", &[("supply", serde_json::json!(100))]))
+            .await
+            .unwrap();
+        assert!(first.success);
+        assert_eq!(first.state_changes.len(), 1);
+        assert_eq!(first.state_changes[0].key, "token.sol:supply");
+        assert_eq!(first.state_changes[0].change_type, StateChangeType::Created);
+
+        let second = runtime
+            .execute(&env, code_path, &execution_inputs("mint", &[("supply", serde_json::json!(250))]))
+            .await
+            .unwrap();
+        assert_eq!(second.state_changes[0].change_type, StateChangeType::Updated);
+        assert_eq!(second.state_changes[0].old_value, Some(serde_json::json!(100)));
+
+        let third = runtime
+            .execute(&env, code_path, &execution_inputs("burn", &[("supply", serde_json::Value::Null)]))
+            .await
+            .unwrap();
+        assert_eq!(third.state_changes[0].change_type, StateChangeType::Deleted);
+
+        let state = runtime.state(&env).unwrap();
+        assert!(state.storage.is_empty());
+        assert_eq!(state.accounts["0x00000000000000000000000000000000000000ab"].nonce, 3);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_trace_replays_in_fresh_environment() {
+        let runtime = InMemoryRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let inputs = execution_inputs("mint", &[("supply", serde_json::json!(100))]);
+
+        let trace = runtime.execute_traced(&env, Path::new("token.sol"), &inputs).await.unwrap();
+        assert!(trace.code.is_none());
+        assert!(trace.instructions.is_empty());
+        assert_eq!(trace.inputs.context.timestamp, Some(1_700_000_000));
+        assert_eq!(trace.storage_accesses().len(), 2);
+        assert_eq!(trace.storage_accesses()[1].key, "token.sol:supply");
+
+        let replay = trace.replay(&runtime).await.unwrap();
+        assert!(replay.is_exact());
+        assert_eq!(replay.replayed.result.events[0].timestamp, trace.result.events[0].timestamp);
+
+        // Changed code is replayed from a copy under the same file name
+        let directory = std::env::temp_dir().join(format!("blockchain-runtime-token-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let code_path = directory.join("token.sol");
+        std::fs::write(&code_path, "contract Token {}").unwrap();
+        let recorded = runtime.execute_traced(&env, &code_path, &inputs).await.unwrap();
+        assert_eq!(recorded.code, Some(hex::encode("contract Token {}")));
+        std::fs::write(&code_path, "contract Changed {}").unwrap();
+        let replay = recorded.replay(&runtime).await.unwrap();
+        let copy = Path::new(&replay.replayed.call_tree.as_ref().unwrap().contract).to_path_buf();
+        assert_ne!(copy, code_path);
+        assert_eq!(copy.file_name().unwrap(), "token.sol");
+        assert!(!copy.exists());
+        std::fs::remove_dir_all(directory).unwrap();
+
+        let changed = execution_inputs("mint", &[("supply", serde_json::json!(7))]);
+        let other = runtime.execute(&env, Path::new("token.sol"), &changed).await.unwrap();
+        assert_eq!(diff_state_changes(&trace.result.state_changes, &other.state_changes).len(), 1);
+        assert_eq!(
+            diff_state_changes(&trace.result.state_changes, &[]),
+            [StateChangeDiff::Missing { expected: trace.result.state_changes[0].clone() }]
+        );
+
+        let default_runtime = DefaultBlockchainRuntime::new("ethereum".to_string());
+        let default_env = default_runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let error = default_runtime
+            .execute_traced(&default_env, Path::new("token.sol"), &inputs)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            RuntimeError::find(&error),
+            Some(RuntimeError::UnsupportedCapability { capability: Capability::Tracing, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_monitor_returns_execution_events() {
+        let runtime = InMemoryRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+
+        let result = runtime
+            .execute(&env, Path::new("vault.sol"), &execution_inputs("deposit", &[("amount", serde_json::json!(5))]))
+            .await
+            .unwrap();
+        let events = runtime.monitor(&env, &result.execution_id).await.unwrap();
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_type, "deposit");
+        assert_eq!(events[0].timestamp, 1_700_000_000);
+        assert_eq!(events[0].data["amount"], serde_json::json!(5));
+        assert!(runtime.monitor(&env, "missing").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_deploy_and_call_are_deterministic() {
+        let runtime = InMemoryRuntime::new("ethereum".to_string());
+        let first_env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let second_env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        assert_ne!(first_env.environment_id, second_env.environment_id);
+
+        let first = runtime.deploy_contract(&first_env, &[0x60, 0x80], &[]).await.unwrap();
+        let second = runtime.deploy_contract(&second_env, &[0x60, 0x80], &[]).await.unwrap();
+        assert_eq!(first, second);
+        assert_ne!(first, runtime.deploy_contract(&first_env, &[0x60, 0x80], &[]).await.unwrap());
+
+        assert!(runtime.call_function(&first_env, &first, "owner", &[]).await.unwrap().is_empty());
+        runtime.call_function(&first_env, &first, "owner", &[0xaa, 0xbb]).await.unwrap();
+        assert_eq!(runtime.call_function(&first_env, &first, "owner", &[]).await.unwrap(), vec![0xaa, 0xbb]);
+        assert!(runtime.call_function(&second_env, &second, "owner", &[]).await.unwrap().is_empty());
+        assert!(runtime.call_function(&first_env, "0xdead", "owner", &[]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_destroy_removes_environment() {
+        let runtime = InMemoryRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+
+        runtime.destroy(env.clone()).await.unwrap();
+        assert!(runtime.state(&env).is_err());
+        assert!(runtime
+            .execute(&env, Path::new("token.sol"), &execution_inputs("mint", &[]))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_snapshot_and_revert() {
+        let runtime = InMemoryRuntime::new("ethereum".to_string());
+        assert!(runtime.capabilities().supports_snapshots);
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let code_path = Path::new("token.sol");
+
+        runtime
+            .execute(&env, code_path, &execution_inputs("mint", &[("supply", serde_json::json!(100))]))
+            .await
+            .unwrap();
+        let snapshot = runtime.snapshot(&env).await.unwrap();
+
+        for supply in [250, 400] {
+            runtime
+                .execute(&env, code_path, &execution_inputs("mint", &[("supply", serde_json::json!(supply))]))
+                .await
+                .unwrap();
+            let later = runtime.snapshot(&env).await.unwrap();
+            assert!(later > snapshot);
+
+            runtime.revert(&env, snapshot).await.unwrap();
+            let state = runtime.state(&env).unwrap();
+            assert_eq!(state.storage["token.sol:supply"], serde_json::json!(100));
+            assert_eq!(state.accounts["0x00000000000000000000000000000000000000ab"].nonce, 1);
+            let error = runtime.revert(&env, later).await.unwrap_err();
+            assert!(matches!(RuntimeError::find(&error), Some(RuntimeError::SnapshotNotFound { .. })));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_execution_ids_survive_revert() {
+        let runtime = InMemoryRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let code_path = Path::new("token.sol");
+        let snapshot = runtime.snapshot(&env).await.unwrap();
+
+        let first = runtime
+            .execute(&env, code_path, &execution_inputs("mint", &[("supply", serde_json::json!(100))]))
+            .await
+            .unwrap();
+        runtime.revert(&env, snapshot).await.unwrap();
+        let second = runtime
+            .execute(&env, code_path, &execution_inputs("burn", &[("supply", serde_json::json!(0))]))
+            .await
+            .unwrap();
+
+        assert_ne!(first.execution_id, second.execution_id);
+        let events = runtime.monitor(&env, &first.execution_id).await.unwrap();
+        assert_eq!(events[0].event_type, "mint");
+        let events = runtime.monitor(&env, &second.execution_id).await.unwrap();
+        assert_eq!(events[0].event_type, "burn");
+        let report = runtime.get_security_report(&env, &first.execution_id).await.unwrap();
+        assert_eq!(report.summary.execution_id, first.execution_id);
+    }
+
+    #[tokio::test]
+    async fn test_default_runtime_does_not_support_snapshots() {
+        let runtime = DefaultBlockchainRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+
+        assert!(!runtime.capabilities().supports_snapshots);
+        assert!(runtime.snapshot(&env).await.is_err());
+        assert!(runtime.revert(&env, SnapshotId(1)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_time_travel() {
+        let runtime = InMemoryRuntime::new("ethereum".to_string());
+        assert!(runtime.capabilities().supports_time_travel);
+        let mut config = RuntimeConfig::default();
+        config.blockchain_config.insert("block_time".to_string(), serde_json::json!(5));
+        let env = runtime.create_environment(config).await.unwrap();
+
+        let start = runtime.current_block(&env).await.unwrap();
+        assert_eq!(start.number, 1);
+        assert_eq!(runtime.advance_time(&env, 60).await.unwrap().timestamp, start.timestamp + 60);
+        let mined = runtime.mine_blocks(&env, 2).await.unwrap();
+        assert_eq!(mined.number, 3);
+        assert_eq!(mined.timestamp, start.timestamp + 70);
+
+        let mut inputs = execution_inputs("claim", &[]);
+        inputs.context.timestamp = None;
+        let result = runtime.execute(&env, Path::new("vesting.sol"), &inputs).await.unwrap();
+        assert_eq!(result.events[0].timestamp, mined.timestamp);
+        assert_eq!(result.metrics["block_number"], serde_json::json!(3));
+
+        inputs.context.block_number = Some(100);
+        let result = runtime.execute(&env, Path::new("vesting.sol"), &inputs).await.unwrap();
+        assert_eq!(result.metrics["block_number"], serde_json::json!(100));
+        assert_eq!(runtime.current_block(&env).await.unwrap(), mined);
+
+        // Only the first block takes the pending timestamp
+        runtime.set_next_block_timestamp(&env, mined.timestamp + 100).await.unwrap();
+        let mined = runtime.mine_blocks(&env, 3).await.unwrap();
+        assert_eq!((mined.number, mined.timestamp), (6, start.timestamp + 70 + 100 + 10));
+
+        // Mining in one step fails fast on overflow and leaves the clock alone
+        for error in [
+            runtime.mine_blocks(&env, u64::MAX).await.unwrap_err(),
+            runtime.advance_time(&env, u64::MAX).await.unwrap_err(),
+            runtime.set_next_block_timestamp(&env, mined.timestamp).await.unwrap_err(),
+        ] {
+            assert!(matches!(RuntimeError::find(&error), Some(RuntimeError::InvalidInput(_))));
+        }
+        assert_eq!(runtime.current_block(&env).await.unwrap(), mined);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_does_not_estimate_gas() {
+        let runtime = InMemoryRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+
+        assert!(!runtime.capabilities().supports_gas_estimation);
+        assert!(runtime
+            .estimate_gas(&env, Path::new("token.sol"), &execution_inputs("mint", &[]))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_default_runtime_does_not_support_time_travel() {
+        let runtime = DefaultBlockchainRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+
+        assert!(!runtime.capabilities().supports_time_travel);
+        assert!(runtime.current_block(&env).await.is_err());
+        assert!(runtime.advance_time(&env, 60).await.is_err());
+        assert!(runtime.set_next_block_timestamp(&env, 0).await.is_err());
+        assert!(runtime.mine_blocks(&env, 1).await.is_err());
+    }
+
+    #[test]
+    fn test_event_filter_matching() {
+        let event = RuntimeEvent {
+            event_id: "event_1".to_string(),
+            event_type: "Transfer".to_string(),
+            timestamp: 0,
+            data: HashMap::from([("to".to_string(), serde_json::json!("0xbob"))]),
+        };
+
+        assert!(EventFilter::new().matches(&event));
+        assert!(EventFilter::new().event_type("Approval").event_type("Transfer").matches(&event));
+        assert!(!EventFilter::new().event_type("Approval").matches(&event));
+        assert!(EventFilter::new().data_key("to").matches(&event));
+        assert!(!EventFilter::new().data_key("from").matches(&event));
+        assert!(EventFilter::new().data_value("to", serde_json::json!("0xbob")).matches(&event));
+        assert!(!EventFilter::new().data_value("to", serde_json::json!("0xeve")).matches(&event));
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_subscription_streams_filtered_events() {
+        use tokio_stream::StreamExt;
+
+        let runtime = InMemoryRuntime::new("ethereum".to_string());
+        assert!(runtime.capabilities().supports_event_subscriptions);
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let mut mints = runtime
+            .subscribe(&env, EventFilter::new().event_type("mint").data_key("supply"))
+            .await
+            .unwrap();
+        let all = runtime.subscribe(&env, EventFilter::new()).await.unwrap();
+
+        let code_path = Path::new("token.sol");
+        for inputs in [
+            execution_inputs("mint", &[("supply", serde_json::json!(100))]),
+            execution_inputs("burn", &[("supply", serde_json::json!(50))]),
+            execution_inputs("mint", &[("owner", serde_json::json!("0xab"))]),
+        ] {
+            runtime.execute(&env, code_path, &inputs).await.unwrap();
+        }
+        runtime.destroy(env).await.unwrap();
+
+        let event = mints.next().await.unwrap();
+        assert_eq!(event.event_type, "mint");
+        assert_eq!(event.data["supply"], serde_json::json!(100));
+        assert!(mints.next().await.is_none());
+        assert_eq!(all.collect::<Vec<_>>().await.len(), 3);
+    }
+
+    #[test]
+    fn test_runtime_registry_selects_by_capability() {
+        use std::sync::Arc;
+
+        let mut registry = RuntimeRegistry::new();
+        assert!(registry.is_empty());
+        registry.register(Arc::new(DefaultBlockchainRuntime::new("bitcoin".to_string())));
+        registry.register(Arc::new(InMemoryRuntime::new("solana".to_string())));
+        assert!(registry
+            .register(Arc::new(EvmRuntime::new("ethereum".to_string())))
+            .is_none());
+
+        assert_eq!(registry.blockchain_ids(), vec!["bitcoin", "ethereum", "solana"]);
+        assert_eq!(registry.get("solana").unwrap().blockchain_id(), "solana");
+        assert!(registry.get("cosmos").is_none());
+        assert!(registry.capabilities()["ethereum"].supports_gas_estimation);
+        assert!(!registry.capabilities()["bitcoin"].supports(Capability::Snapshots));
+
+        let snapshots: Vec<_> = registry
+            .find_all(&[Capability::Snapshots])
+            .iter()
+            .map(|runtime| runtime.blockchain_id().to_string())
+            .collect();
+        assert_eq!(snapshots, vec!["ethereum", "solana"]);
+        let picked = registry.find(&[Capability::GasEstimation, Capability::TimeTravel]).unwrap();
+        assert_eq!(picked.blockchain_id(), "ethereum");
+        assert_eq!(registry.find(&[]).unwrap().blockchain_id(), "bitcoin");
+
+        let replaced = registry.register(Arc::new(InMemoryRuntime::new("ethereum".to_string())));
+        assert!(replaced.unwrap().capabilities().supports_gas_estimation);
+        assert!(registry.find(&[Capability::GasEstimation]).is_none());
+        assert!(registry.unregister("bitcoin").is_some());
+        assert_eq!(registry.len(), 2);
+    }
+
+    #[test]
+    fn test_config_validation_reports_field() {
+        let error = RuntimeConfigBuilder::new().timeout_seconds(0).build().unwrap_err();
+        assert!(matches!(&error, RuntimeError::InvalidConfig { field, .. } if field == "timeout_seconds"));
+
+        let mut config = RuntimeConfig::default();
+        config.security_config.max_gas_limit = 0;
+        match config.validate() {
+            Err(RuntimeError::InvalidConfig { field, message }) => {
+                assert_eq!(field, "security_config.max_gas_limit");
+                assert_eq!(message, "Maximum gas limit cannot be zero");
+            }
+            other => panic!("unexpected validation result: {:?}", other),
+        }
+        assert!(RuntimeConfig::default().validate().is_ok());
+    }
+
+    fn write_config(name: &str, contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("blockchain_runtime_{}_{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_config_from_file_formats() {
+        let files = [
+            write_config(
+                "config.toml",
+                "timeout_seconds = 90\nnetwork_mode = \"testnet\"\n\n[security_config]\nmax_gas_limit = 500000\n\n[blockchain_config]\nchain_id = 5\n",
+            ),
+            write_config(
+                "config.json",
+                r#"{"timeout_seconds": 90, "network_mode": "Testnet", "security_config": {"max_gas_limit": 500000}, "blockchain_config": {"chain_id": 5}}"#,
+            ),
+            write_config(
+                "config.yaml",
+                "timeout_seconds: 90\nnetwork_mode: testnet\nsecurity_config:\n  max_gas_limit: 500000\nblockchain_config:\n  chain_id: 5\n",
+            ),
+        ];
+
+        for path in files {
+            let config = RuntimeConfig::from_file(&path).unwrap();
+            assert_eq!(config.timeout_seconds, 90);
+            assert_eq!(config.network_mode, NetworkMode::Testnet);
+            assert_eq!(config.security_config.max_gas_limit, 500_000);
+            assert_eq!(config.blockchain_config["chain_id"], serde_json::json!(5));
+            // Fields the file leaves out keep their defaults
+            assert_eq!(config.memory_limit_mb, RuntimeConfig::default().memory_limit_mb);
+            assert!(config.security_config.reentrancy_protection);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_config_from_file_rejects_invalid_files() {
+        let typo = write_config("typo.toml", "[security_config]\nmax_gas_limt = 1\n");
+        let zero = write_config("zero.json", r#"{"memory_limit_mb": 0}"#);
+        let ini = write_config("config.ini", "timeout_seconds=1");
+
+        for (path, expected_field) in [
+            (&typo, "security_config.max_gas_limt".to_string()),
+            (&zero, "memory_limit_mb".to_string()),
+            (&ini, ini.display().to_string()),
+        ] {
+            match RuntimeConfig::from_file(path) {
+                Err(RuntimeError::InvalidConfig { field, .. }) => assert_eq!(field, expected_field),
+                other => panic!("unexpected result for {}: {:?}", path.display(), other),
+            }
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_config_environment_overrides() {
+        let config = RuntimeConfig::testing()
+            .with_overrides([
+                ("BLOCKCHAIN_RUNTIME_TIMEOUT_SECONDS", "120"),
+                ("BLOCKCHAIN_RUNTIME_NETWORK_MODE", "mainnet_fork"),
+                ("BLOCKCHAIN_RUNTIME_SECURITY_CONFIG__OVERFLOW_DETECTION", "true"),
+                ("BLOCKCHAIN_RUNTIME_SECURITY_CONFIG__MAX_CALL_DEPTH", "64"),
+                ("BLOCKCHAIN_RUNTIME_SECURITY_CONFIG__INTEGER_TYPE", "uint128"),
+                ("BLOCKCHAIN_RUNTIME_SECURITY_CONFIG__ARITHMETIC_MODE", "checked"),
+                ("BLOCKCHAIN_RUNTIME_BLOCKCHAIN_CONFIG__rpc_url", "http://localhost:8545"),
+                ("BLOCKCHAIN_RUNTIME_BLOCKCHAIN_CONFIG__chain_id", "31337"),
+                ("BLOCKCHAIN_RUNTIME_BLOCKCHAIN_CONFIG__forkUrl", "http://localhost:8546"),
+                ("UNRELATED_TIMEOUT_SECONDS", "1"),
+            ])
+            .unwrap();
+
+        assert_eq!(config.timeout_seconds, 120);
+        assert_eq!(config.memory_limit_mb, 256);
+        assert_eq!(config.network_mode, NetworkMode::MainnetFork);
+        assert!(config.security_config.overflow_detection);
+        assert_eq!(config.security_config.max_call_depth, 64);
+        assert_eq!(config.security_config.integer_type, Some(IntegerType::U128));
+        assert_eq!(config.security_config.arithmetic_mode, ArithmeticMode::Checked);
+        assert_eq!(config.blockchain_config["rpc_url"], serde_json::json!("http://localhost:8545"));
+        assert_eq!(config.blockchain_config["chain_id"], serde_json::json!(31337));
+        assert_eq!(config.blockchain_config["forkUrl"], serde_json::json!("http://localhost:8546"));
+
+        for (name, value) in [
+            ("BLOCKCHAIN_RUNTIME_TIMEOUT_SECONDS", "0"),
+            ("BLOCKCHAIN_RUNTIME_TIMEOUT_SECONDS", "soon"),
+            ("BLOCKCHAIN_RUNTIME_TIMEOUT", "5"),
+            ("BLOCKCHAIN_RUNTIME_SECURITY__MAX_CALL_DEPTH", "5"),
+            ("BLOCKCHAIN_RUNTIME_SECURITY_CONFIG__INTEGER_TYPE", "u7"),
+        ] {
+            let result = RuntimeConfig::default().with_overrides([(name, value)]);
+            assert!(matches!(result, Err(RuntimeError::InvalidConfig { .. })), "{}={}", name, value);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_runtime_errors_can_be_matched() {
+        let runtime = InMemoryRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        runtime.destroy(env.clone()).await.unwrap();
+
+        let error = runtime.snapshot(&env).await.unwrap_err();
+        assert!(matches!(
+            RuntimeError::find(&error),
+            Some(RuntimeError::EnvironmentNotFound(id)) if *id == env.environment_id
+        ));
+
+        let config = RuntimeConfig {
+            memory_limit_mb: 0,
+            ..RuntimeConfig::default()
+        };
+        let error = runtime.create_environment(config).await.unwrap_err();
+        assert!(matches!(RuntimeError::find(&error), Some(RuntimeError::InvalidConfig { .. })));
+
+        let runtime = DefaultBlockchainRuntime::new("bitcoin".to_string());
+        let error = runtime.mine_blocks(&env, 1).await.unwrap_err();
+        assert_eq!(error.to_string(), "Runtime bitcoin does not support time travel");
+        assert!(matches!(
+            RuntimeError::find(&error.context("mining failed")),
+            Some(RuntimeError::UnsupportedCapability { capability: Capability::TimeTravel, .. })
+        ));
+
+        let runtime = InMemoryRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let error = runtime.monitor(&env, "exec_404").await.unwrap_err();
+        assert!(matches!(RuntimeError::find(&error), Some(RuntimeError::ExecutionNotFound(id)) if id == "exec_404"));
+        let error = runtime.get_security_report(&env, "exec_404").await.unwrap_err();
+        assert!(matches!(RuntimeError::find(&error), Some(RuntimeError::ExecutionNotFound(_))));
+        let error = runtime.revert(&env, SnapshotId(404)).await.unwrap_err();
+        assert!(matches!(
+            RuntimeError::find(&error),
+            Some(RuntimeError::SnapshotNotFound { snapshot: SnapshotId(404), .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_role_grants_gate_secure_execution() {
+        let mut config = RuntimeConfig::default();
+        config.security_config.access_control.define_role("minter", &[]).unwrap();
+        config.security_config.access_control.require_role("mint", "minter").unwrap();
+        let runtime = InMemoryRuntime::new("ethereum".to_string());
+        assert!(runtime.capabilities().supports_access_control);
+        let env = runtime.create_environment(config.clone()).await.unwrap();
+        let code_path = Path::new("token.sol");
+        let inputs = execution_inputs("mint", &[("supply", serde_json::json!(100))]);
+        let sender = "0x00000000000000000000000000000000000000AB";
+
+        let denied = runtime
+            .execute_secure(&env, code_path, &inputs, &config.security_config)
+            .await
+            .unwrap();
+        assert_eq!(denied.security_context.access_control_checks.len(), 1);
+        assert!(denied
+            .security_violations
+            .iter()
+            .any(|v| v.violation_type == SecurityViolationType::AccessControlViolation));
+        assert!(!runtime.verify_access_control(&env, "mint", sender, None).await.unwrap());
+
+        let snapshot = runtime.snapshot(&env).await.unwrap();
+        runtime.grant_role(&env, sender, "minter").await.unwrap();
+        assert!(runtime.grant_role(&env, sender, "burner").await.is_err());
+        assert!(runtime.verify_access_control(&env, "mint", sender, None).await.unwrap());
+        let allowed = runtime
+            .execute_secure(&env, code_path, &inputs, &config.security_config)
+            .await
+            .unwrap();
+        assert!(allowed.security_context.access_control_checks[0].has_permission);
+        assert!(!allowed.has_security_violations());
+        let report = runtime.get_security_report(&env, &allowed.execution_id).await.unwrap();
+        assert!(report.verdict.passed);
+        assert!(report.verdict.reasons.is_empty());
+        assert!(report.to_string().contains("Verdict: PASS"));
+
+        runtime.revert(&env, snapshot).await.unwrap();
+        assert!(!runtime.verify_access_control(&env, "mint", sender, None).await.unwrap());
+        runtime.grant_role(&env, sender, "minter").await.unwrap();
+        assert!(runtime.revoke_role(&env, sender, "minter").await.unwrap());
+        assert!(!runtime.verify_access_control(&env, "mint", sender, None).await.unwrap());
+
+        let default_runtime = DefaultBlockchainRuntime::new("ethereum".to_string());
+        let default_env = default_runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        assert!(default_runtime.grant_role(&default_env, sender, "minter").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_enforcement_collects_violations_and_applies_policy() {
+        let policy = EnforcementPolicy::abort_at(SecuritySeverity::High);
+        assert_eq!(policy.action(&SecuritySeverity::Critical), ViolationAction::Abort);
+        assert_eq!(policy.action(&SecuritySeverity::Medium), ViolationAction::Continue);
+        let policy = policy.with_action(SecuritySeverity::Critical, ViolationAction::Continue);
+        assert_eq!(policy.action(&SecuritySeverity::Critical), ViolationAction::Continue);
+        assert!(policy.can_abort());
+        assert!(!EnforcementPolicy::report_only().can_abort());
+        assert_eq!(SecurityConfig::strict().enforcement, EnforcementPolicy::abort_at(SecuritySeverity::High));
+
+        let security_config = SecurityConfig::strict();
+        let enforcer = SecurityEnforcer::new(security_config.clone());
+        let violations = enforcer.check_resource_limits(u64::MAX, u64::MAX, u32::MAX, u32::MAX);
+        assert_eq!(violations.len(), 4);
+        assert_eq!(enforcer.decide(&violations), ViolationAction::Abort);
+        assert_eq!(enforcer.decide(&[]), ViolationAction::Continue);
+
+        let runtime = DefaultBlockchainRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let reported = runtime
+            .enforce_resource_limits(&env, u64::MAX, 0, u32::MAX, 0, &security_config)
+            .await
+            .unwrap();
+        let types: Vec<_> = reported.iter().map(|v| v.violation_type.clone()).collect();
+        assert_eq!(types, [SecurityViolationType::CallDepthExceeded, SecurityViolationType::GasLimitExceeded]);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_execute_secure_aborts_by_policy() {
+        use tokio_stream::StreamExt;
+
+        let mut config = RuntimeConfig::default();
+        config.security_config.access_control.define_role("minter", &[]).unwrap();
+        config.security_config.access_control.require_role("mint", "minter").unwrap();
+        let runtime = InMemoryRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(config.clone()).await.unwrap();
+        let code_path = Path::new("token.sol");
+        let inputs = execution_inputs("mint", &[("supply", serde_json::json!(100))]);
+        let events = runtime.subscribe(&env, EventFilter::new()).await.unwrap();
+
+        let aborting = SecurityConfig {
+            enforcement: EnforcementPolicy::abort_at(SecuritySeverity::Medium),
+            ..config.security_config.clone()
+        };
+        let error = runtime.execute_secure(&env, code_path, &inputs, &aborting).await.unwrap_err();
+        let Some(RuntimeError::SecurityViolation(violation)) = RuntimeError::find(&error) else {
+            panic!("expected a security violation, got {error:?}");
+        };
+        assert_eq!(violation.violation_type, SecurityViolationType::AccessControlViolation);
+        assert!(error.to_string().contains("Access denied"));
+        let execution_id = violation.context["execution_id"].as_str().unwrap();
+        assert!(runtime.monitor(&env, execution_id).await.unwrap().is_empty());
+        assert!(runtime.state(&env).unwrap().storage.is_empty());
+        let report = runtime.get_security_report(&env, execution_id).await.unwrap();
+        assert!(!report.summary.success);
+        assert!(report.summary.error.as_deref().unwrap().contains("Access denied"));
+        assert_eq!(report.summary.state_changes, 0);
+        assert_eq!(report.summary.events, 0);
+        assert_eq!(report.violations.len(), 1);
+        assert!(!report.verdict.passed);
+
+        let reported = runtime
+            .execute_secure(&env, code_path, &inputs, &config.security_config)
+            .await
+            .unwrap();
+        assert!(reported.success);
+        assert_eq!(reported.security_violations.len(), 1);
+        assert_eq!(runtime.state(&env).unwrap().storage.len(), 1);
+
+        // Only the execution that was kept reached subscribers
+        runtime.destroy(env).await.unwrap();
+        let streamed: Vec<_> = events.collect().await;
+        assert_eq!(streamed.len(), 1);
+        assert_eq!(streamed[0].event_id, reported.events[0].event_id);
+    }
+
+    #[tokio::test]
+    async fn test_environment_manager_enforces_lifecycle() {
+        let manager = EnvironmentManager::new(InMemoryRuntime::new("ethereum".to_string()));
+        let env = manager
+            .create_environment(RuntimeConfig::default().with_deterministic_seed(1))
+            .await
+            .unwrap();
+        assert_eq!(env.state, EnvironmentState::Ready);
+        let code_path = Path::new("token.sol");
+        let inputs = execution_inputs("mint", &[("supply", serde_json::json!(1))]);
+        assert!(manager.execute(&env, code_path, &inputs).await.unwrap().success);
+
+        manager.pause(&env, "maintenance").unwrap();
+        let error = manager.execute(&env, code_path, &inputs).await.unwrap_err();
+        assert!(matches!(
+            RuntimeError::find(&error),
+            Some(RuntimeError::InvalidTransition {
+                from: EnvironmentState::Paused,
+                to: EnvironmentState::Running,
+                ..
+            })
+        ));
+        manager.resume(&env, "maintenance done").unwrap();
+
+        let backend = manager.backend_environment(&env).unwrap();
+        manager.stop(&env, "idle").await.unwrap();
+        assert_eq!(manager.state(&env).unwrap(), EnvironmentState::Stopped);
+        assert!(manager.runtime().state(&backend).is_err());
+        assert!(manager.execute(&env, code_path, &inputs).await.is_err());
+        assert!(manager.pause(&env, "idle").is_err());
+
+        let restarted = manager.restart(&env, "needed again").await.unwrap();
+        assert_eq!(restarted.environment_id, env.environment_id);
+        let backend = manager.backend_environment(&env).unwrap();
+        assert_ne!(backend.environment_id, env.environment_id);
+        assert!(manager.runtime().state(&backend).unwrap().storage.is_empty());
+
+        let history = manager.history(&env).unwrap();
+        let states: Vec<EnvironmentState> = history.iter().map(|transition| transition.to.clone()).collect();
+        assert_eq!(
+            states,
+            [
+                EnvironmentState::Ready,
+                EnvironmentState::Running,
+                EnvironmentState::Ready,
+                EnvironmentState::Paused,
+                EnvironmentState::Ready,
+                EnvironmentState::Stopped,
+                EnvironmentState::Creating,
+                EnvironmentState::Ready,
+            ]
+        );
+        assert_eq!(history[2].reason, "execution finished");
+        assert_eq!(history[3].reason, "maintenance");
+        assert_eq!(history[0].timestamp, 1_700_000_000);
+        assert_eq!(
+            manager.environment(&env).unwrap().metadata[LAST_TRANSITION_METADATA_KEY],
+            serde_json::json!(history[7])
+        );
+
+        // Only the latest transitions are kept
+        for _ in 0..STATE_HISTORY_CAPACITY {
+            manager.pause(&env, "flapping").unwrap();
+            manager.resume(&env, "flapping").unwrap();
+        }
+        let history = manager.history(&env).unwrap();
+        assert_eq!(history.len(), STATE_HISTORY_CAPACITY);
+        assert_eq!(history[0].to, EnvironmentState::Paused);
+        assert_eq!(history[STATE_HISTORY_CAPACITY - 1].to, EnvironmentState::Ready);
+        assert_eq!(
+            manager.environment(&env).unwrap().metadata[LAST_TRANSITION_METADATA_KEY],
+            serde_json::json!(history[STATE_HISTORY_CAPACITY - 1])
+        );
+
+        let other = manager.create_environment(RuntimeConfig::default()).await.unwrap();
+        let other_backend = manager.backend_environment(&other).unwrap();
+        manager.destroy(other).await.unwrap();
+        assert!(manager.runtime().state(&other_backend).is_err());
+        assert_eq!(manager.environments().unwrap().len(), 1);
+
+        // The remaining environment is destroyed without blocking this runtime
+        drop(manager);
+    }
+
+    #[tokio::test]
+    async fn test_environment_pool_leases_and_resets() {
+        let error = EnvironmentPool::new(
+            InMemoryRuntime::new("ethereum".to_string()),
+            RuntimeConfig::default(),
+            PoolConfig::new(3, 2),
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(matches!(RuntimeError::find(&error), Some(RuntimeError::InvalidConfig { field, .. }) if field == "warm_size"));
+
+        let pool = EnvironmentPool::new(
+            InMemoryRuntime::new("ethereum".to_string()),
+            RuntimeConfig::default(),
+            PoolConfig::new(1, 2).with_reset(ResetStrategy::Revert),
+        )
+        .await
+        .unwrap();
+        assert_eq!(pool.reset_strategy(), ResetStrategy::Revert);
+        assert_eq!(pool.idle_count(), 1);
+        let code_path = Path::new("token.sol");
+        let inputs = execution_inputs("mint", &[("supply", serde_json::json!(1))]);
+
+        let first = pool.lease().await.unwrap();
+        let second = pool.lease().await.unwrap();
+        assert_ne!(first.environment_id, second.environment_id);
+        assert_eq!(pool.leased_count(), 2);
+        assert!(pool.try_lease().await.unwrap().is_none());
+        first.runtime().execute(&first, code_path, &inputs).await.unwrap();
+        let first_id = first.environment_id.clone();
+
+        // Waiters are served in order once a lease is returned
+        let waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let pool = pool.clone();
+                tokio::spawn(async move { pool.lease().await.unwrap() })
+            })
+            .collect();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(waiters.iter().all(|waiter| !waiter.is_finished()));
+
+        first.release().await;
+        let mut waiters = waiters.into_iter();
+        let reused = waiters.next().unwrap().await.unwrap();
+        assert_eq!(reused.environment_id, first_id);
+        assert!(pool.runtime().state(&reused).unwrap().storage.is_empty());
+
+        drop(second);
+        let last = waiters.next().unwrap().await.unwrap();
+        drop((reused, last));
+
+        let recreating = EnvironmentPool::new(
+            InMemoryRuntime::new("ethereum".to_string()),
+            RuntimeConfig::default(),
+            PoolConfig::new(1, 1),
+        )
+        .await
+        .unwrap();
+        assert_eq!(recreating.reset_strategy(), ResetStrategy::Recreate);
+        let lease = recreating.lease().await.unwrap();
+        let stale = lease.environment().clone();
+        lease.release().await;
+        let lease = recreating.lease().await.unwrap();
+        assert_ne!(lease.environment_id, stale.environment_id);
+        assert!(recreating.runtime().state(&stale).is_err());
+        lease.release().await;
+
+        pool.shutdown().await.unwrap();
+        assert!(pool.lease().await.is_err());
+        recreating.shutdown().await.unwrap();
+        assert_eq!(recreating.idle_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_deterministic_mode_is_reproducible() {
+        let mut config = RuntimeConfig::default().with_deterministic_seed(7);
+        config.security_config.access_control.define_role("minter", &[]).unwrap();
+        config.security_config.access_control.require_role("mint", "minter").unwrap();
+        let parameters: Vec<(String, serde_json::Value)> =
+            (0..16).map(|i| (format!("param_{}", i), serde_json::json!(i))).collect();
+        let parameters: Vec<(&str, serde_json::Value)> =
+            parameters.iter().map(|(name, value)| (name.as_str(), value.clone())).collect();
+        let mut inputs = execution_inputs("mint", &parameters);
+        inputs.context.timestamp = None;
+
+        let mut runs = Vec::new();
+        for _ in 0..2 {
+            let runtime = InMemoryRuntime::new("ethereum".to_string());
+            let env = runtime.create_environment(config.clone()).await.unwrap();
+            let result = runtime
+                .execute_secure(&env, Path::new("token.sol"), &inputs, &config.security_config)
+                .await
+                .unwrap();
+            let report = runtime.get_security_report(&env, &result.execution_id).await.unwrap();
+            runs.push((serde_json::to_string(&result).unwrap(), report.to_json().unwrap()));
+
+            assert_eq!(result.execution_time_ms, 0);
+            assert_eq!(result.events[0].timestamp, 1_700_000_000);
+            assert_eq!(result.security_violations[0].timestamp, 1_700_000_000);
+            assert_eq!(result.security_context.access_control_checks[0].check_timestamp, 1_700_000_000);
+        }
+        assert_eq!(runs[0], runs[1]);
+
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let runtime = InMemoryRuntime::new("ethereum".to_string())
+            .with_entropy(Entropy::new(clock.clone(), Arc::new(SeededRandom::new(7))));
+        clock.advance(Duration::from_secs(500));
+        let env = runtime.create_environment(config).await.unwrap();
+        assert_eq!(runtime.current_block(&env).await.unwrap().timestamp, 1_500);
+
+        // Environments created at the same fixed time still get distinct ids
+        let runtime = DefaultBlockchainRuntime::new("ethereum".to_string());
+        let config = RuntimeConfig::default().with_deterministic_seed(1);
+        let first = runtime.create_environment(config.clone()).await.unwrap();
+        let second = runtime.create_environment(config).await.unwrap();
+        assert_eq!((first.environment_id.as_str(), second.environment_id.as_str()), ("env_1", "env_2"));
+    }
+
+    #[test]
+    fn test_sarif_export_maps_violations() {
+        let mut context = SecurityContext::new(SecurityConfig::default());
+        context.add_violation(SecurityViolation::new(
+            SecurityViolationType::CallDepthExceeded,
+            "Call depth 2000 exceeds maximum 1024".to_string(),
+            SecuritySeverity::Medium,
+            1_700_000_000,
+        ));
+        context.add_violation(context.validator().validate_call_depth(u32::MAX).unwrap_err());
+
+        let mut result = ExecutionResult::new("exec_1".to_string(), true);
+        let mut denied = SecurityViolation::new(
+            SecurityViolationType::AccessControlViolation,
+            "Access denied".to_string(),
+            SecuritySeverity::Low,
+            1_700_000_000,
+        );
+        denied.context.insert("function_name".to_string(), serde_json::json!("mint"));
+        result.add_security_violation(denied);
+
+        let mut exporter = SarifExporter::with_tool("scanner", "1.2.3");
+        exporter
+            .add_context(Path::new("contracts/Deep.sol"), &context)
+            .add_execution(Path::new("/work/my contracts/Token#2 ü.sol"), &result);
+        assert_eq!(exporter.result_count(), 3);
+
+        let json: serde_json::Value = serde_json::from_str(&exporter.to_json().unwrap()).unwrap();
+        assert_eq!(json["version"], "2.1.0");
+        assert_eq!(json["$schema"], SARIF_SCHEMA);
+        let run = &json["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "scanner");
+        let rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0]["id"], "call-depth-exceeded");
+        assert_eq!(rules[0]["defaultConfiguration"]["level"], "error");
+        assert_eq!(rules[0]["properties"]["security-severity"], "8.0");
+        assert_eq!(rules[1]["id"], "access-control-violation");
+
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results[0]["level"], "warning");
+        assert_eq!(results[1]["level"], "error");
+        assert_eq!(results[2]["level"], "note");
+        assert_eq!(results[2]["ruleIndex"], 1);
+        assert_eq!(results[0]["locations"][0]["physicalLocation"]["artifactLocation"]["uri"], "contracts/Deep.sol");
+        let location = &results[2]["locations"][0];
+        assert_eq!(
+            location["physicalLocation"]["artifactLocation"]["uri"],
+            "file:///work/my%20contracts/Token%232%20%C3%BC.sol"
+        );
+        assert_eq!(location["logicalLocations"][0]["name"], "mint");
+        assert_eq!(results[2]["message"]["text"], "Access denied");
+
+        let mut exporter = SarifExporter::with_tool("scanner", "1.2.3");
+        exporter.add_execution(Path::new("build:1/100% Token.sol"), &result);
+        let json: serde_json::Value = serde_json::from_str(&exporter.to_json().unwrap()).unwrap();
+        let location = &json["runs"][0]["results"][0]["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "build%3A1/100%25%20Token.sol");
+    }
+
+    // Creation code for a counter that increments slot 0, logs the new value under topic 0xaa and returns it
+    const COUNTER_BYTECODE: &str = "6019600c60003960196000f36000546001018060005560005260aa60206000a160206000f3";
+
+    // Creation code for a contract that calls itself until the first calldata word reaches 2
+    const REENTRANT_BYTECODE: &str =
+        "6020600c60003960206000f360003580600211600b57005b60010160005260006000602060006000305af100";
+
+    // Creation code for a contract that calls its caller back with a first calldata word of one
+    const BOUNCER_BYTECODE: &str = "6013600c60003960136000f3600160005260006000602060006000335af100";
+
+    // Creation code for a contract that reads slot 0, calls `bouncer` when the first calldata
+    // word is zero, and only then stores the value it read plus one
+    fn stale_reentrant_bytecode(bouncer: &str) -> String {
+        format!(
+            "603c600c600039603c6000f3600054600035801515603357600160005260006000602060006000\
+             73{}5af1505b5060010160005500",
+            bouncer.trim_start_matches("0x")
+        )
+    }
+
+    // Creation code for a contract that writes slot 0 and then reverts
+    const REVERTING_BYTECODE: &str = "600a600c600039600a6000f3600160005560006000fd";
+
+    // Creation code for a contract that jumps back to its first instruction forever
+    const LOOP_BYTECODE: &str = "6004600c60003960046000f35b600056";
+
+    // Creation code for a contract returning transient slot 1 before and after storing 42 in it,
+    // an MCOPY of the second word and BLOBHASH(0) as four words
+    const TRANSIENT_BYTECODE: &str = "6023600c60003960236000f360015c600052602a60015d60015c602052\
+                                      6020602060405e60004960605260806000f3";
+
+    // Creation code for a contract that sets slot 0 to one and back to zero
+    const REFUNDING_BYTECODE: &str = "600b600c600039600b6000f36001600055600060005500";
+
+    // Creation code for a contract returning the block timestamp and number as two words
+    const BLOCK_BYTECODE: &str = "600d600c600039600d6000f3426000524360205260406000f3";
+
+    fn write_bytecode(name: &str, bytecode: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("blockchain-runtime-{}-{}.bin", name, std::process::id()));
+        std::fs::write(&path, bytecode).unwrap();
+        path
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_code_paths_are_validated_before_reading() {
+        let runtime = EvmRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let inputs = execution_inputs("", &[]);
+
+        let long = std::path::PathBuf::from("a".repeat(5000));
+        let error = runtime.execute(&env, &long, &inputs).await.unwrap_err();
+        assert!(matches!(RuntimeError::find(&error), Some(RuntimeError::InvalidInput(_))));
+
+        let dir = std::env::temp_dir().join(format!("blockchain-runtime-links-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (first, second) = (dir.join("first.bin"), dir.join("second.bin"));
+        let _ = std::fs::remove_file(&first);
+        let _ = std::fs::remove_file(&second);
+        std::os::unix::fs::symlink(&second, &first).unwrap();
+        std::os::unix::fs::symlink(&first, &second).unwrap();
+        let error = runtime.execute(&env, &first, &inputs).await.unwrap_err();
+        assert!(matches!(RuntimeError::find(&error), Some(RuntimeError::InvalidInput(_))));
+        assert!(error.to_string().contains("symlinks"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_evm_deploy_and_call() {
+        let runtime = EvmRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+
+        let address = runtime
+            .deploy_contract(&env, &hex::decode(COUNTER_BYTECODE).unwrap(), &[])
+            .await
+            .unwrap();
+        let first = runtime.call_function(&env, &address, "", &[]).await.unwrap();
+        let second = runtime.call_function(&env, &address, "", &[]).await.unwrap();
+
+        assert_eq!(first.len(), 32);
+        assert_eq!(first[31], 1);
+        assert_eq!(second[31], 2);
+        let account = runtime.account(&env, &address).unwrap().unwrap();
+        assert_eq!(account.storage.len(), 1);
+        assert_eq!(account.code.len(), 0x19);
+    }
+
+    #[tokio::test]
+    async fn test_evm_execute_reports_gas_state_and_logs() {
+        let runtime = EvmRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let code_path = write_bytecode("counter", COUNTER_BYTECODE);
+
+        let first = runtime.execute(&env, &code_path, &execution_inputs("", &[])).await.unwrap();
+        assert!(first.success);
+        assert!(first.state_changes.iter().any(|c| c.key.ends_with(":code")));
+        assert_eq!(first.events.len(), 1);
+        assert!(first.events[0].event_type.ends_with("aa"));
+
+        let second = runtime.execute(&env, &code_path, &execution_inputs("", &[])).await.unwrap();
+        assert_eq!(second.state_changes.len(), 1);
+        assert_eq!(second.state_changes[0].change_type, StateChangeType::Updated);
+        let gas_used = second.metrics["gas_used"].as_u64().unwrap();
+        assert!(gas_used > 21_000);
+        assert_eq!(second.security_context.gas_used, gas_used);
+        assert_eq!(second.security_context.call_depth, 1);
+        assert_eq!(runtime.monitor(&env, &second.execution_id).await.unwrap().len(), 1);
+
+        std::fs::remove_file(code_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_evm_execute_secure_tracks_call_frames() {
+        let runtime = EvmRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let code_path = write_bytecode("reentrant", REENTRANT_BYTECODE);
+        let inputs = execution_inputs("", &[("calldata", serde_json::json!(format!("0x{}", "00".repeat(32))))]);
+
+        let result = runtime
+            .execute_secure(&env, &code_path, &inputs, &SecurityConfig::default())
+            .await
+            .unwrap();
+
+        assert!(result.success);
+        assert_eq!(result.security_context.call_depth, 3);
+        assert_eq!(result.security_context.external_call_count, 2);
+        assert_eq!(result.security_context.call_stack.len(), 3);
+        assert!(result.security_context.access_control_checks[0].has_permission);
+        assert!(runtime.detect_overflow(&env, "sub", &[0, 1]).await.unwrap());
+        assert!(!runtime.detect_overflow(&env, "add", &[i64::MAX, 1]).await.unwrap());
+        assert!(!runtime.detect_overflow(&env, "add", &[-1, 2]).await.unwrap());
+        assert!(!runtime.detect_overflow(&env, "mul", &[i64::MIN, -1]).await.unwrap());
+        let mut narrow = RuntimeConfig::default();
+        narrow.security_config.integer_type = Some(IntegerType::I64);
+        let narrow = runtime.create_environment(narrow).await.unwrap();
+        assert!(runtime.detect_overflow(&narrow, "add", &[i64::MAX, 1]).await.unwrap());
+        // Re-entry without state read before the call and written after it is safe
+        assert!(!result
+            .security_violations
+            .iter()
+            .any(|v| v.violation_type == SecurityViolationType::ReentrancyAttack));
+
+        std::fs::remove_file(code_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_evm_execute_secure_detects_stale_state_reentrancy() {
+        let runtime = EvmRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let bouncer = runtime
+            .deploy_contract(&env, &hex::decode(BOUNCER_BYTECODE).unwrap(), &[])
+            .await
+            .unwrap();
+        let code_path = write_bytecode("stale_reentrant", &stale_reentrant_bytecode(&bouncer));
+        let inputs = execution_inputs("", &[("calldata", serde_json::json!(format!("0x{}", "00".repeat(32))))]);
+
+        let result = runtime
+            .execute_secure(&env, &code_path, &inputs, &SecurityConfig::default())
+            .await
+            .unwrap();
+
+        assert!(result.success);
+        let violations: Vec<_> = result
+            .security_violations
+            .iter()
+            .filter(|v| v.violation_type == SecurityViolationType::ReentrancyAttack)
+            .collect();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].severity, SecuritySeverity::Critical);
+        assert_eq!(violations[0].context["reentrancy_kind"], serde_json::json!("same_function"));
+        assert_eq!(violations[0].context["stale_state"], serde_json::json!([format!("0x{:064x}", 0)]));
+        assert_eq!(violations[0].context["call_path"].as_array().unwrap().len(), 3);
+
+        let report = runtime.get_security_report(&env, &result.execution_id).await.unwrap();
+        assert!(report.summary.success);
+        assert!(!report.verdict.passed);
+        assert_eq!(report.by_type.len(), 1);
+        assert_eq!(report.by_type[0].violation_type, SecurityViolationType::ReentrancyAttack);
+        assert_eq!(report.by_type[0].highest_severity, SecuritySeverity::Critical);
+        assert_eq!(report.by_severity, SeverityCounts { critical: 1, ..SeverityCounts::default() });
+        assert_eq!(report.by_severity.highest(), Some(SecuritySeverity::Critical));
+        assert_eq!(report.access_control.len(), 1);
+        assert_eq!(report.resources.call_depth, 3);
+        assert_eq!(report.resources.max_gas_limit, Some(SecurityConfig::default().max_gas_limit));
+
+        let json: SecurityReport = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json.verdict, report.verdict);
+        let text = report.to_string();
+        assert!(text.contains("Verdict: FAIL"));
+        assert!(text.contains("ReentrancyAttack: 1 (highest Critical)"));
+        assert!(text.contains("ALLOW 0x00000000000000000000000000000000000000ab"));
+
+        std::fs::remove_file(code_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_evm_trace_records_and_replays() {
+        let runtime = EvmRuntime::new("ethereum".to_string());
+        assert!(runtime.capabilities().supports_tracing);
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let code_path = write_bytecode("traced_counter", COUNTER_BYTECODE);
+
+        let first = runtime.execute_traced(&env, &code_path, &execution_inputs("", &[])).await.unwrap();
+        assert!(first.result.success);
+        assert_eq!(first.version, EXECUTION_TRACE_VERSION);
+        assert_eq!(first.inputs.context.block_number, Some(first.block.number));
+        assert_eq!(first.instructions[0].op, "PUSH1");
+        assert!(first.instructions.iter().any(|i| i.op == "SSTORE"));
+        assert!(first.instructions.iter().any(|i| i.op == "LOG1"));
+        let accesses: Vec<_> = first.storage_accesses().into_iter().map(|a| a.write).collect();
+        assert_eq!(accesses, [false, true]);
+
+        let trace_path = std::env::temp_dir().join(format!("blockchain-runtime-trace-{}.json", std::process::id()));
+        first.save(&trace_path).unwrap();
+        let loaded = ExecutionTrace::load(&trace_path).unwrap();
+        assert_eq!(loaded.instructions, first.instructions);
+        assert_eq!(loaded.result.state_changes, first.result.state_changes);
+
+        // The recorded code is used once the original file changes or is gone
+        std::fs::write(&code_path, LOOP_BYTECODE).unwrap();
+        let replay = loaded.replay(&runtime).await.unwrap();
+        assert!(replay.is_exact(), "{:?}", replay.differences);
+        assert_eq!(replay.replayed.code_path, code_path);
+        assert_eq!(std::fs::read_to_string(&code_path).unwrap(), LOOP_BYTECODE);
+        let leftovers = std::fs::read_dir(std::env::temp_dir())
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                name.starts_with(&format!("blockchain-runtime-replay-{}-", std::process::id()))
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+        std::fs::remove_file(&code_path).unwrap();
+        let replay = loaded.replay(&runtime).await.unwrap();
+        assert!(replay.is_exact(), "{:?}", replay.differences);
+
+        // A later execution depends on state the trace does not hold
+        std::fs::write(&code_path, COUNTER_BYTECODE).unwrap();
+        let second = runtime.execute_traced(&env, &code_path, &execution_inputs("", &[])).await.unwrap();
+        let replay = second.replay(&runtime).await.unwrap();
+        assert!(!replay.outcome_matches);
+        assert!(replay
+            .differences
+            .iter()
+            .any(|d| matches!(d, StateChangeDiff::Changed { expected, .. } if expected.key == second.result.state_changes[0].key)));
+        assert!(replay.differences.iter().any(|d| matches!(d, StateChangeDiff::Unexpected { .. })));
+
+        let mut future = serde_json::to_value(&first).unwrap();
+        future["version"] = serde_json::json!(EXECUTION_TRACE_VERSION + 1);
+        std::fs::write(&trace_path, future.to_string()).unwrap();
+        assert!(matches!(ExecutionTrace::load(&trace_path), Err(RuntimeError::InvalidInput(_))));
+
+        std::fs::remove_file(trace_path).unwrap();
+        std::fs::remove_file(code_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_evm_deterministic_mode_is_reproducible() {
+        let code_path = write_bytecode("deterministic", COUNTER_BYTECODE);
+        let mut inputs = execution_inputs("", &[]);
+        inputs.context.timestamp = None;
+
+        let mut runs = Vec::new();
+        for seed in [42, 42, 43] {
+            let runtime = EvmRuntime::new("ethereum".to_string());
+            let config = RuntimeConfig::default().with_deterministic_seed(seed);
+            let env = runtime.create_environment(config).await.unwrap();
+            let result = runtime.execute(&env, &code_path, &inputs).await.unwrap();
+            assert_eq!(result.execution_time_ms, 0);
+            runs.push((serde_json::to_string(&result).unwrap(), runtime.block(&env).unwrap().prevrandao));
+        }
+        assert_eq!(runs[0], runs[1]);
+        assert_ne!(runs[0].1, runs[2].1);
+
+        std::fs::remove_file(code_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_evm_execution_times_out() {
+        let runtime = EvmRuntime::new("ethereum".to_string());
+        let config = RuntimeConfig {
+            timeout_seconds: 1,
+            ..RuntimeConfig::default()
+        };
+        let env = runtime.create_environment(config).await.unwrap();
+        let other = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let code_path = write_bytecode("loop", LOOP_BYTECODE);
+        let inputs = execution_inputs("", &[("gas_limit", serde_json::json!(u64::MAX))]);
+
+        // Another environment keeps working while the loop runs
+        let looping = async {
+            let result = runtime.execute(&env, &code_path, &inputs).await.unwrap();
+            (result, std::time::Instant::now())
+        };
+        let counting = async {
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+            let address = runtime
+                .deploy_contract(&other, &hex::decode(COUNTER_BYTECODE).unwrap(), &[])
+                .await
+                .unwrap();
+            runtime.call_function(&other, &address, "", &[]).await.unwrap();
+            std::time::Instant::now()
+        };
+        let ((result, looped_until), counted_at) = tokio::join!(looping, counting);
+        assert!(counted_at < looped_until);
+
+        assert!(!result.success);
+        let violation = result
+            .security_violations
+            .iter()
+            .find(|v| v.violation_type == SecurityViolationType::ResourceLimitExceeded)
+            .unwrap();
+        assert_eq!(violation.context["timeout_ms"], serde_json::json!(1000));
+        assert_eq!(result.security_context.security_violations.len(), 1);
+
+        std::fs::remove_file(code_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_evm_rollback_keeps_concurrent_changes() {
+        let runtime = EvmRuntime::new("ethereum".to_string());
+        let config = RuntimeConfig {
+            timeout_seconds: 1,
+            ..RuntimeConfig::default()
+        };
+        let env = runtime.create_environment(config).await.unwrap();
+        let code_path = write_bytecode("aborted_loop", LOOP_BYTECODE);
+        let inputs = execution_inputs("", &[("gas_limit", serde_json::json!(u64::MAX))]);
+        let account = "0x00000000000000000000000000000000000000aa";
+        let strict = SecurityConfig::strict();
+
+        // The timeout aborts the execution while the balance is set
+        let looping = runtime.execute_secure(&env, &code_path, &inputs, &strict);
+        let funding = async {
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+            runtime.set_balance(&env, account, 7).unwrap();
+        };
+        let (result, _) = tokio::join!(looping, funding);
+        let error = result.unwrap_err();
+        assert!(matches!(RuntimeError::find(&error), Some(RuntimeError::SecurityViolation(_))));
+
+        let balance = runtime.account(&env, account).unwrap().unwrap().balance;
+        assert_eq!(balance, primitive_types::U256::from(7));
+        std::fs::remove_file(code_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_evm_follows_the_cancun_schedule() {
+        use primitive_types::U256;
+
+        let runtime = EvmRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+
+        // Transient storage lasts for one transaction only
+        let address = runtime
+            .deploy_contract(&env, &hex::decode(TRANSIENT_BYTECODE).unwrap(), &[])
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            let output = runtime.call_function(&env, &address, "", &[]).await.unwrap();
+            let words: Vec<U256> = output.chunks(32).map(U256::from_big_endian).collect();
+            assert_eq!(words, [U256::zero(), U256::from(42), U256::from(42), U256::zero()]);
+        }
+        assert!(runtime.account(&env, &address).unwrap().unwrap().storage.is_empty());
+
+        // A cold slot set and cleared again costs 21000 + 12 + 2100 + 20000 + 100,
+        // less the 19900 refund capped at a fifth of that
+        let code_path = write_bytecode("refunding", REFUNDING_BYTECODE);
+        let result = runtime.execute(&env, &code_path, &execution_inputs("", &[])).await.unwrap();
+        assert!(result.success);
+        assert_eq!(result.metrics["gas_used"], serde_json::json!(43_212 - 43_212 / 5));
+        assert!(result.state_changes.iter().all(|change| !change.key.contains(":storage:")));
+        std::fs::remove_file(code_path).unwrap();
+
+        let config = RuntimeConfig::default().with_blockchain_config("hardfork".to_string(), serde_json::json!("london"));
+        let error = runtime.create_environment(config).await.unwrap_err();
+        assert!(matches!(RuntimeError::find(&error), Some(RuntimeError::InvalidConfig { .. })));
+        let config = RuntimeConfig::default().with_blockchain_config("hardfork".to_string(), serde_json::json!("cancun"));
+        assert!(runtime.create_environment(config).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_evm_revert_rolls_back_storage() {
+        let runtime = EvmRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+
+        let address = runtime
+            .deploy_contract(&env, &hex::decode(REVERTING_BYTECODE).unwrap(), &[])
+            .await
+            .unwrap();
+        let error = runtime.call_function(&env, &address, "", &[]).await.unwrap_err();
+
+        assert!(error.to_string().contains("execution reverted"));
+        assert!(runtime.account(&env, &address).unwrap().unwrap().storage.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_evm_snapshot_and_revert() {
+        let runtime = EvmRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let code_path = write_bytecode("snapshot_counter", COUNTER_BYTECODE);
+
+        let snapshot = runtime.snapshot(&env).await.unwrap();
+        let mut execution_ids = Vec::new();
+        for _ in 0..2 {
+            let first = runtime.execute(&env, &code_path, &execution_inputs("", &[])).await.unwrap();
+            let second = runtime.execute(&env, &code_path, &execution_inputs("", &[])).await.unwrap();
+            assert_eq!(first.return_value, Some(serde_json::json!(format!("0x{:064x}", 1))));
+            assert_eq!(second.return_value, Some(serde_json::json!(format!("0x{:064x}", 2))));
+
+            runtime.revert(&env, snapshot).await.unwrap();
+            execution_ids.extend([first.execution_id, second.execution_id]);
+        }
+        // Executions from reverted runs keep their ids and stay resolvable
+        assert_eq!(execution_ids.iter().collect::<std::collections::HashSet<_>>().len(), 4);
+        for execution_id in &execution_ids {
+            assert!(runtime.monitor(&env, execution_id).await.is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_evm_time_travel() {
+        let runtime = EvmRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let code_path = write_bytecode("block_values", BLOCK_BYTECODE);
+        let mut inputs = execution_inputs("", &[]);
+        inputs.context.timestamp = None;
+        let block_words = |number: u64, timestamp: u64| {
+            Some(serde_json::json!(format!("0x{:064x}{:064x}", timestamp, number)))
+        };
+
+        let start = runtime.current_block(&env).await.unwrap();
+        let result = runtime.execute(&env, &code_path, &inputs).await.unwrap();
+        assert_eq!(result.return_value, block_words(1, start.timestamp));
+
+        let snapshot = runtime.snapshot(&env).await.unwrap();
+        runtime.advance_time(&env, 3600).await.unwrap();
+        assert!(runtime.set_next_block_timestamp(&env, start.timestamp).await.is_err());
+        runtime.set_next_block_timestamp(&env, start.timestamp + 86_400).await.unwrap();
+        let mined = runtime.mine_blocks(&env, 3).await.unwrap();
+        assert_eq!(mined.number, 4);
+        assert_eq!(mined.timestamp, start.timestamp + 86_400 + 24);
+        assert_eq!(runtime.block(&env).unwrap().timestamp, mined.timestamp);
+
+        let result = runtime.execute(&env, &code_path, &inputs).await.unwrap();
+        assert_eq!(result.return_value, block_words(4, mined.timestamp));
+
+        inputs.context.block_number = Some(77);
+        inputs.context.timestamp = Some(5);
+        let result = runtime.execute(&env, &code_path, &inputs).await.unwrap();
+        assert_eq!(result.return_value, block_words(77, 5));
+        assert_eq!(runtime.current_block(&env).await.unwrap(), mined);
+
+        runtime.revert(&env, snapshot).await.unwrap();
+        assert_eq!(runtime.current_block(&env).await.unwrap(), start);
+    }
+
+    #[tokio::test]
+    async fn test_evm_estimate_gas_does_not_commit() {
+        let runtime = EvmRuntime::new("ethereum".to_string());
+        assert!(runtime.capabilities().supports_gas_estimation);
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let code_path = write_bytecode("estimate_counter", COUNTER_BYTECODE);
+
+        let estimate = runtime.estimate_gas(&env, &code_path, &execution_inputs("", &[])).await.unwrap();
+        assert!(estimate.success);
+        assert!(estimate.gas_limit >= estimate.gas_used);
+        assert_eq!(estimate.frames.len(), 1);
+        assert_eq!(estimate.frames[0].depth, 1);
+        assert_eq!(estimate.frames[0].self_gas_used, estimate.frames[0].gas_used);
+        assert!(estimate.frames[0].gas_used < estimate.gas_used);
+
+        let result = runtime.execute(&env, &code_path, &execution_inputs("", &[])).await.unwrap();
+        assert_eq!(result.return_value, Some(serde_json::json!(format!("0x{:064x}", 1))));
+        assert_eq!(result.metrics["gas_used"], serde_json::json!(estimate.gas_used));
+    }
+
+    #[tokio::test]
+    async fn test_evm_estimate_gas_breaks_down_nested_frames() {
+        let runtime = EvmRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let code_path = write_bytecode("estimate_reentrant", REENTRANT_BYTECODE);
+        let calldata = ("calldata", serde_json::json!(format!("0x{}", "00".repeat(32))));
+
+        let estimate = runtime
+            .estimate_gas(&env, &code_path, &execution_inputs("", std::slice::from_ref(&calldata)))
+            .await
+            .unwrap();
+        assert!(estimate.success);
+        assert_eq!(estimate.frames.iter().map(|f| f.depth).collect::<Vec<_>>(), vec![1, 2, 3]);
+        let self_total: u64 = estimate.frames.iter().map(|f| f.self_gas_used).sum();
+        assert_eq!(self_total, estimate.frames[0].gas_used);
+
+        // The estimate is the lowest limit that succeeds
+        for (gas_limit, success) in [(estimate.gas_limit, true), (estimate.gas_limit - 1, false)] {
+            let inputs = execution_inputs("", &[calldata.clone(), ("gas_limit", serde_json::json!(gas_limit))]);
+            let result = runtime.execute(&env, &code_path, &inputs).await.unwrap();
+            assert_eq!(result.success, success);
+        }
+
+        let code_path = write_bytecode("estimate_reverting", REVERTING_BYTECODE);
+        let estimate = runtime.estimate_gas(&env, &code_path, &execution_inputs("", &[])).await.unwrap();
+        assert!(!estimate.success);
+        assert!(estimate.error.unwrap().contains("execution reverted"));
+    }
+
+    #[tokio::test]
+    async fn test_evm_subscription_streams_logs() {
+        use tokio_stream::StreamExt;
+
+        let runtime = EvmRuntime::new("ethereum".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let code_path = write_bytecode("subscribed_counter", COUNTER_BYTECODE);
+        let topic = format!("0x{:064x}", 0xaa);
+        let mut logs = runtime.subscribe(&env, EventFilter::new().event_type(topic.clone())).await.unwrap();
+
+        for expected in 1..=2u64 {
+            runtime.execute(&env, &code_path, &execution_inputs("", &[])).await.unwrap();
+            let event = logs.next().await.unwrap();
+            assert_eq!(event.event_type, topic);
+            assert_eq!(event.data["data"], serde_json::json!(format!("0x{:064x}", expected)));
+        }
+    }
+
+    /// Read an HTTP request, returning its request line and headers, and its body
+    async fn read_http_request<S: tokio::io::AsyncRead + Unpin>(stream: &mut S) -> (String, Vec<u8>) {
+        use tokio::io::AsyncReadExt;
+
+        let mut raw = Vec::new();
+        let mut buffer = [0u8; 4096];
+        let body_start = loop {
+            let read = stream.read(&mut buffer).await.unwrap();
+            raw.extend_from_slice(&buffer[..read]);
+            if let Some(end) = raw.windows(4).position(|w| w == b"\r\n\r\n") {
+                break end + 4;
+            }
+        };
+        let head = String::from_utf8_lossy(&raw[..body_start]).to_string();
+        let length: usize = head
+            .to_lowercase()
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .map(|length| length.trim().parse().unwrap())
+            .unwrap_or(0);
+        while raw.len() < body_start + length {
+            let read = stream.read(&mut buffer).await.unwrap();
+            raw.extend_from_slice(&buffer[..read]);
+        }
+        (head, raw[body_start..].to_vec())
+    }
+
+    /// Serve JSON-RPC over HTTP on a local port, answering each call with the handler
+    async fn serve_json_rpc<F>(handler: F) -> String
+    where
+        F: Fn(&str, &serde_json::Value) -> Result<serde_json::Value, (i64, String)> + Send + Sync + 'static,
+    {
+        serve_json_rpc_on(tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap(), handler)
+    }
+
+    fn serve_json_rpc_on<F>(listener: tokio::net::TcpListener, handler: F) -> String
+    where
+        F: Fn(&str, &serde_json::Value) -> Result<serde_json::Value, (i64, String)> + Send + Sync + 'static,
+    {
+        use tokio::io::AsyncWriteExt;
+
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handler = Arc::new(handler);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    let (_, body) = read_http_request(&mut stream).await;
+                    let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                    let method = request["method"].as_str().unwrap();
+                    let response = match handler(method, &request["params"]) {
+                        Ok(result) => serde_json::json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+                        Err((code, message)) => serde_json::json!({
+                            "jsonrpc": "2.0",
+                            "id": request["id"],
+                            "error": { "code": code, "message": message },
+                        }),
+                    };
+                    let body = serde_json::to_vec(&response).unwrap();
+                    let head = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+                        body.len()
+                    );
+                    stream.write_all(head.as_bytes()).await.unwrap();
+                    stream.write_all(&body).await.unwrap();
+                });
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_rpc_runtime_maps_json_rpc_responses() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let contract = "0x00000000000000000000000000000000000000c0";
+        let receipt_polls = Arc::new(AtomicUsize::new(0));
+        let polls = receipt_polls.clone();
+        let url = serve_json_rpc(move |method, params| match method {
+            "eth_chainId" => Ok(serde_json::json!("0x7a69")),
+            "eth_accounts" => Ok(serde_json::json!(["0x00000000000000000000000000000000000000ab"])),
+            "eth_sendTransaction" if params[0].get("to").is_none() => Ok(serde_json::json!("0xd1")),
+            "eth_sendTransaction" => {
+                assert_eq!(params[0]["to"], serde_json::json!(contract));
+                assert_eq!(params[0]["value"], serde_json::json!("0x5"));
+                Ok(serde_json::json!("0xe1"))
+            }
+            "eth_getTransactionReceipt" => {
+                // The call is pending on the first poll
+                if params[0] == "0xe1" && polls.fetch_add(1, Ordering::SeqCst) == 0 {
+                    return Ok(serde_json::Value::Null);
+                }
+                Ok(serde_json::json!({
+                    "status": "0x1",
+                    "gasUsed": "0x5208",
+                    "blockHash": "0xb1",
+                    "blockNumber": "0x2",
+                    "contractAddress": if params[0] == "0xd1" { serde_json::json!(contract) } else { serde_json::Value::Null },
+                }))
+            }
+            "debug_traceTransaction" if params[0] == "0xd1" => Err((-32601, "the method does not exist".to_string())),
+            "debug_traceTransaction" => Ok(serde_json::json!({
+                "pre": { contract: { "balance": "0x10", "nonce": 1, "storage": { "0x01": "0x05", "0x02": "0x07" } } },
+                "post": { contract: { "balance": "0xf", "storage": { "0x01": "0x06" } } },
+            })),
+            "eth_getLogs" => Ok(serde_json::json!([
+                { "address": contract, "topics": ["0xaa"], "data": "0x01", "transactionHash": "0xe1" },
+                { "address": contract, "topics": ["0xbb"], "data": "0x02", "transactionHash": "0xe2" },
+            ])),
+            "eth_estimateGas" if params[0]["data"] == "0xa9cc4718" => Err((3, "execution reverted".to_string())),
+            "eth_estimateGas" => Ok(serde_json::json!("0x5208")),
+            "eth_call" => Ok(serde_json::json!("0x2a")),
+            _ => Err((-32601, format!("{} does not exist", method))),
+        })
+        .await;
+
+        let runtime = RpcRuntime::new("ethereum".to_string());
+        let mut config = RuntimeConfig::default();
+        config.blockchain_config.insert("rpc_url".to_string(), serde_json::json!(url));
+        let env = runtime.create_environment(config.clone()).await.unwrap();
+        assert_eq!(env.endpoint_url, url);
+        assert_eq!(env.runtime_type, RuntimeType::Remote);
+        assert_eq!(env.metadata["chain_id"], serde_json::json!(31337));
+
+        config.blockchain_config.insert("chain_id".to_string(), serde_json::json!(1));
+        let error = runtime.create_environment(config).await.unwrap_err();
+        assert!(matches!(RuntimeError::find(&error), Some(RuntimeError::InvalidConfig { .. })));
+
+        let code_path = write_bytecode("rpc_counter", COUNTER_BYTECODE);
+        let inputs = execution_inputs("increment()", &[("value", serde_json::json!(5))]);
+        let result = runtime.execute(&env, &code_path, &inputs).await.unwrap();
+        assert!(result.success);
+        assert_eq!(receipt_polls.load(Ordering::SeqCst), 2);
+        assert_eq!(result.metrics["gas_used"], serde_json::json!(21000));
+        assert_eq!(result.return_value, Some(serde_json::json!({ "transaction_hash": "0xe1" })));
+        let changes: Vec<(&str, &StateChangeType)> =
+            result.state_changes.iter().map(|change| (change.key.as_str(), &change.change_type)).collect();
+        let slot = |n: u8| format!("{}:storage:0x{:064x}", contract, n);
+        assert_eq!(
+            changes,
+            [
+                (format!("{}:balance", contract).as_str(), &StateChangeType::Updated),
+                (slot(1).as_str(), &StateChangeType::Updated),
+                (slot(2).as_str(), &StateChangeType::Deleted),
+            ]
+        );
+        assert_eq!(result.state_changes[0].new_value, serde_json::json!("15"));
+        assert_eq!(result.events.len(), 1);
+        assert_eq!(result.events[0].event_type, "0xaa");
+        assert_eq!(runtime.monitor(&env, &result.execution_id).await.unwrap().len(), 1);
+
+        let estimate = runtime.estimate_gas(&env, &code_path, &inputs).await.unwrap();
+        assert!(estimate.success);
+        assert_eq!(estimate.gas_limit, 21000);
+        let failing = execution_inputs("fail()", &[("to", serde_json::json!(contract))]);
+        let estimate = runtime.estimate_gas(&env, &code_path, &failing).await.unwrap();
+        assert!(!estimate.success);
+        assert_eq!(estimate.error.as_deref(), Some("execution reverted"));
+
+        assert_eq!(runtime.call_function(&env, contract, "count()", &[]).await.unwrap(), vec![0x2a]);
+        assert_eq!(runtime.deploy_contract(&env, &[0x60, 0x00], &[]).await.unwrap(), contract);
+
+        runtime.destroy(env.clone()).await.unwrap();
+        assert!(runtime.execute(&env, &code_path, &inputs).await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_mainnet_fork_loads_state_lazily_and_caches_it() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Returns storage slot 0
+        let contract = "0x00000000000000000000000000000000000000f0";
+        let upstream_calls = Arc::new(AtomicUsize::new(0));
+        let calls = upstream_calls.clone();
+        let url = serve_json_rpc(move |method, params| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            let known = params[0] == contract;
+            match method {
+                "eth_chainId" => Ok(serde_json::json!("0x5")),
+                "eth_getBlockByNumber" => {
+                    assert_eq!(params[0], "0x10");
+                    Ok(serde_json::json!({
+                        "timestamp": "0x64",
+                        "gasLimit": "0x1c9c380",
+                        "miner": "0x00000000000000000000000000000000000000c1",
+                        "baseFeePerGas": "0x7",
+                    }))
+                }
+                "eth_getBalance" => Ok(serde_json::json!(if known { "0x3e8" } else { "0x0" })),
+                "eth_getTransactionCount" => Ok(serde_json::json!(if known { "0x1" } else { "0x0" })),
+                "eth_getCode" => Ok(serde_json::json!(if known { "0x60005460005260206000f3" } else { "0x" })),
+                "eth_getStorageAt" if known && params[1] == format!("0x{:064x}", 0) => Ok(serde_json::json!("0x2a")),
+                "eth_getStorageAt" => Ok(serde_json::json!("0x0")),
+                _ => Err((-32601, format!("{} does not exist", method))),
+            }
+        })
+        .await;
+
+        let cache_dir = std::env::temp_dir().join(format!("blockchain-runtime-fork-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&cache_dir);
+        let mut config = RuntimeConfig {
+            network_mode: NetworkMode::MainnetFork,
+            ..RuntimeConfig::default()
+        };
+        config.blockchain_config.insert("fork_block".to_string(), serde_json::json!(16));
+        config
+            .blockchain_config
+            .insert("fork_cache_dir".to_string(), serde_json::json!(cache_dir.to_str().unwrap()));
+
+        let mut online = config.clone();
+        online.blockchain_config.insert("fork_url".to_string(), serde_json::json!(url));
+        let runtime = EvmRuntime::new("ethereum".to_string());
+        let mut mismatched = online.clone();
+        mismatched.blockchain_config.insert("chain_id".to_string(), serde_json::json!(1));
+        let error = runtime.create_environment(mismatched).await.unwrap_err();
+        assert!(matches!(RuntimeError::find(&error), Some(RuntimeError::InvalidConfig { .. })));
+        let env = runtime.create_environment(online).await.unwrap();
+        assert_eq!(env.metadata["fork_block"], serde_json::json!(16));
+        let block = runtime.block(&env).unwrap();
+        assert_eq!((block.number, block.chain_id, block.base_fee), (17, 5, 7.into()));
+        let word = runtime.call_function(&env, contract, "value()", &[]).await.unwrap();
+        assert_eq!(word[31], 0x2a);
+        assert_eq!(runtime.account(&env, contract).unwrap().unwrap().balance, 1000.into());
+        let fetched = upstream_calls.load(Ordering::SeqCst);
+        assert!(fetched > 0);
+        // Written when the call finished, while the runtime is still alive, under the upstream's chain
+        let cached = std::fs::read_to_string(cache_dir.join("5").join("16.json")).unwrap();
+        assert!(cached.contains(contract));
+        let leftovers = std::fs::read_dir(cache_dir.join("5")).unwrap().count();
+        assert_eq!(leftovers, 1);
+
+        // A later run with no upstream is served from the cache of its chain
+        let runtime = EvmRuntime::new("ethereum".to_string());
+        assert!(runtime.create_environment(config.clone()).await.is_err());
+        config.blockchain_config.insert("chain_id".to_string(), serde_json::json!(5));
+        let env = runtime.create_environment(config).await.unwrap();
+        assert_eq!(runtime.call_function(&env, contract, "value()", &[]).await.unwrap(), word);
+        assert_eq!(runtime.account(&env, contract).unwrap().unwrap().balance, 1000.into());
+        assert_eq!(upstream_calls.load(Ordering::SeqCst), fetched);
+
+        let uncached = "0x00000000000000000000000000000000000000f1";
+        let error = runtime.call_function(&env, uncached, "value()", &[]).await.unwrap_err();
+        assert!(matches!(RuntimeError::find(&error), Some(RuntimeError::Backend(_))));
+        let _ = std::fs::remove_dir_all(&cache_dir);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_local_process_runtime_supervises_node() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("blockchain-runtime-node-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let port_file = dir.join("port");
+        let _ = std::fs::remove_file(&port_file);
+        // Stands in for a node: reports its port, writes output and keeps a child alive
+        let stub = dir.join("stub-node");
+        std::fs::write(
+            &stub,
+            "#!/bin/sh\nsleep 30 &\necho \"$2\" > \"$3\"\necho \"listening on $2\"\necho \"warming up\" >&2\nwait\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&stub, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        // Answer JSON-RPC on every port the runtime hands a stub
+        let port_path = port_file.clone();
+        tokio::spawn(async move {
+            let mut served = String::new();
+            loop {
+                match std::fs::read_to_string(&port_path) {
+                    Ok(port) if port.ends_with('\n') && port != served => {
+                        let address = format!("127.0.0.1:{}", port.trim());
+                        let listener = tokio::net::TcpListener::bind(address).await.unwrap();
+                        serve_json_rpc_on(listener, |method, _| match method {
+                            "eth_chainId" => Ok(serde_json::json!("0x7a69")),
+                            "eth_accounts" => Ok(serde_json::json!([])),
+                            _ => Err((-32601, format!("{} does not exist", method))),
+                        });
+                        served = port;
+                    }
+                    _ => tokio::time::sleep(Duration::from_millis(10)).await,
+                }
+            }
+        });
+
+        let runtime = LocalProcessRuntime::new("ethereum".to_string(), &stub)
+            .with_args(["--port", "{port}", port_file.to_str().unwrap()]);
+        assert!(runtime.is_available().await);
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let port = env.metadata["port"].as_u64().unwrap();
+        assert_eq!(env.runtime_type, RuntimeType::LocalProcess);
+        assert_eq!(env.endpoint_url, format!("http://127.0.0.1:{}", port));
+        assert_eq!(env.metadata["chain_id"], serde_json::json!(31337));
+        let pid = env.metadata["pid"].as_u64().unwrap() as libc::pid_t;
+
+        let mut logs = runtime.logs(&env).unwrap();
+        for _ in 0..100 {
+            if logs.len() >= 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            logs = runtime.logs(&env).unwrap();
+        }
+        assert!(logs.contains(&LogLine { stream: LogStream::Stdout, line: format!("listening on {}", port) }));
+        assert!(logs.contains(&LogLine { stream: LogStream::Stderr, line: "warming up".to_string() }));
+
+        // Returns once the node has exited, its whole group including the stub's child killed
+        runtime.destroy(env.clone()).await.unwrap();
+        assert_eq!(unsafe { libc::kill(pid, 0) }, -1);
+        assert!(runtime.logs(&env).is_err());
+
+        let mut config = RuntimeConfig::default();
+        config.blockchain_config.insert("node_binary".to_string(), serde_json::json!("sh"));
+        config
+            .blockchain_config
+            .insert("node_args".to_string(), serde_json::json!(["-c", "echo boom >&2; exit 3"]));
+        let error = runtime.create_environment(config).await.unwrap_err();
+        assert!(matches!(RuntimeError::find(&error), Some(RuntimeError::Backend(_))));
+        assert!(format!("{:#}", error).contains("boom"));
+
+        // Dropping a manager on this single-threaded runtime stops its nodes without hanging
+        let manager = EnvironmentManager::new(runtime);
+        let env = manager.create_environment(RuntimeConfig::default()).await.unwrap();
+        let pid = manager.backend_environment(&env).unwrap().metadata["pid"].as_u64().unwrap() as libc::pid_t;
+        drop(manager);
+        assert_eq!(unsafe { libc::kill(pid, 0) }, -1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_docker_runtime_manages_containers_through_engine_api() {
+        use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+        use std::sync::Mutex;
+        use tokio::io::AsyncWriteExt;
+
+        let socket = std::env::temp_dir().join(format!("blockchain-runtime-docker-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&socket);
+        let listener = tokio::net::UnixListener::bind(&socket).unwrap();
+        let requests = Arc::new(Mutex::new(Vec::<(String, serde_json::Value)>::new()));
+        let (removed, removal) = tokio::sync::watch::channel(false);
+        let recorded = requests.clone();
+        let image_present = Arc::new(AtomicBool::new(false));
+        let host_port = Arc::new(AtomicU16::new(0));
+        let published = host_port.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let (recorded, removed, mut removal, image_present, host_port) =
+                    (recorded.clone(), removed.clone(), removal.clone(), image_present.clone(), published.clone());
+                tokio::spawn(async move {
+                    let (head, body) = read_http_request(&mut stream).await;
+                    let mut request_line = head.split_whitespace();
+                    let method = request_line.next().unwrap().to_string();
+                    let path = request_line.next().unwrap().to_string();
+                    let body: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+                    recorded.lock().unwrap().push((format!("{} {}", method, path), body.clone()));
+
+                    let (status, response) = match (method.as_str(), path.as_str()) {
+                        ("GET", "/_ping") => (200, "OK".to_string()),
+                        ("POST", "/containers/create") if !image_present.load(Ordering::SeqCst) => {
+                            (404, r#"{"message":"No such image: stub-node:latest"}"#.to_string())
+                        }
+                        ("POST", "/images/create?fromImage=stub-node:latest") => {
+                            image_present.store(true, Ordering::SeqCst);
+                            (200, "{\"status\":\"Pulling\"}\n{\"status\":\"Downloaded\"}\n".to_string())
+                        }
+                        ("POST", "/containers/create") if body["Image"] == "broken-node" => {
+                            (201, r#"{"Id":"dead","Warnings":[]}"#.to_string())
+                        }
+                        ("POST", "/containers/create") => {
+                            // The node inside the container answers on the port Docker picks
+                            let node = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+                            host_port.store(node.local_addr().unwrap().port(), Ordering::SeqCst);
+                            serve_json_rpc_on(node, |method, _| match method {
+                                "eth_chainId" => Ok(serde_json::json!("0x7a69")),
+                                "eth_accounts" => Ok(serde_json::json!([])),
+                                _ => Err((-32601, format!("{} does not exist", method))),
+                            });
+                            (201, r#"{"Id":"c0ffee","Warnings":[]}"#.to_string())
+                        }
+                        ("POST", "/containers/c0ffee/start") => (204, String::new()),
+                        ("GET", "/containers/c0ffee/json") => {
+                            let port = host_port.load(Ordering::SeqCst).to_string();
+                            let ports = serde_json::json!({ "8545/tcp": [{ "HostIp": "127.0.0.1", "HostPort": port }] });
+                            (200, serde_json::json!({ "Id": "c0ffee", "NetworkSettings": { "Ports": ports } }).to_string())
+                        }
+                        ("POST", "/containers/dead/start") => (500, r#"{"message":"port is already allocated"}"#.to_string()),
+                        ("DELETE", "/containers/dead?force=true&v=true") => {
+                            (409, r#"{"message":"removal already in progress"}"#.to_string())
+                        }
+                        ("GET", "/containers/c0ffee/logs?follow=true&stdout=true&stderr=true") => {
+                            let frame = |stream: u8, text: &str| {
+                                let mut frame = vec![stream, 0, 0, 0];
+                                frame.extend_from_slice(&(text.len() as u32).to_be_bytes());
+                                frame.extend_from_slice(text.as_bytes());
+                                frame
+                            };
+                            let mut frames = frame(1, "listening\n");
+                            frames.extend(frame(2, "warm"));
+                            frames.extend(frame(2, "ing up\n"));
+                            let head = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n";
+                            stream.write_all(head.as_bytes()).await.unwrap();
+                            stream.write_all(format!("{:x}\r\n", frames.len()).as_bytes()).await.unwrap();
+                            stream.write_all(&frames).await.unwrap();
+                            stream.write_all(b"\r\n").await.unwrap();
+                            // Following ends when the container goes away
+                            let _ = removal.wait_for(|removed| *removed).await;
+                            let _ = stream.write_all(b"0\r\n\r\n").await;
+                            return;
+                        }
+                        ("DELETE", "/containers/c0ffee?force=true&v=true") => {
+                            removed.send_replace(true);
+                            (204, String::new())
+                        }
+                        _ => (404, r#"{"message":"page not found"}"#.to_string()),
+                    };
+                    let head = format!("HTTP/1.1 {} Mock\r\nContent-Length: {}\r\n\r\n", status, response.len());
+                    stream.write_all(head.as_bytes()).await.unwrap();
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                });
+            }
+        });
+
+        let runtime = DockerRuntime::new("ethereum".to_string(), "stub-node:latest")
+            .with_socket(&socket)
+            .with_cmd(["anvil", "--host", "0.0.0.0"]);
+        assert!(runtime.is_available().await);
+        let config = RuntimeConfig {
+            memory_limit_mb: 512,
+            ..RuntimeConfig::default()
+        };
+        let env = runtime.create_environment(config).await.unwrap();
+        assert_eq!(env.runtime_type, RuntimeType::Docker);
+        assert_eq!(env.metadata["container_id"], serde_json::json!("c0ffee"));
+        assert_eq!(env.metadata["chain_id"], serde_json::json!(31337));
+        let port = env.metadata["port"].as_u64().unwrap();
+        assert_eq!(port, host_port.load(Ordering::SeqCst) as u64);
+        assert_eq!(env.endpoint_url, format!("http://127.0.0.1:{}", port));
+
+        let calls: Vec<String> = requests.lock().unwrap().iter().map(|(call, _)| call.clone()).collect();
+        assert_eq!(
+            calls[..5],
+            [
+                "GET /_ping",
+                "POST /containers/create",
+                "POST /images/create?fromImage=stub-node:latest",
+                "POST /containers/create",
+                "POST /containers/c0ffee/start",
+            ]
+        );
+        assert!(calls.contains(&"GET /containers/c0ffee/json".to_string()));
+        let create = requests.lock().unwrap()[3].1.clone();
+        assert_eq!(create["Image"], "stub-node:latest");
+        assert_eq!(create["Cmd"], serde_json::json!(["anvil", "--host", "0.0.0.0"]));
+        assert_eq!(create["HostConfig"]["Memory"], serde_json::json!(512 * 1024 * 1024));
+        assert_eq!(create["HostConfig"]["PortBindings"]["8545/tcp"][0]["HostPort"], "");
+
+        let mut logs = runtime.logs(&env).unwrap();
+        for _ in 0..100 {
+            if logs.len() >= 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            logs = runtime.logs(&env).unwrap();
+        }
+        assert_eq!(
+            logs,
+            [
+                LogLine { stream: LogStream::Stdout, line: "listening".to_string() },
+                LogLine { stream: LogStream::Stderr, line: "warming up".to_string() },
+            ]
+        );
+
+        runtime.destroy(env.clone()).await.unwrap();
+        let calls: Vec<String> = requests.lock().unwrap().iter().map(|(call, _)| call.clone()).collect();
+        assert_eq!(calls.last().unwrap(), "DELETE /containers/c0ffee?force=true&v=true");
+        assert!(runtime.logs(&env).is_err());
+
+        // A failed removal does not hide why the container failed to start
+        let mut config = RuntimeConfig::default();
+        config.blockchain_config.insert("docker_image".to_string(), serde_json::json!("broken-node"));
+        let error = format!("{:#}", runtime.create_environment(config).await.err().unwrap());
+        assert!(error.contains("Container dead from broken-node failed to start and could not be removed"));
+        assert!(error.contains("removal already in progress"));
+        assert!(error.contains("port is already allocated"));
+        let _ = std::fs::remove_file(&socket);
+    }
+
+    /// Encoded SBF instruction
+    fn sbf_insn(opcode: u8, dst: u8, src: u8, offset: i16, imm: i32) -> Vec<u8> {
+        let mut bytes = vec![opcode, src << 4 | dst];
+        bytes.extend_from_slice(&offset.to_le_bytes());
+        bytes.extend_from_slice(&imm.to_le_bytes());
+        bytes
+    }
+
+    /// Program ELF with `text` followed by `rodata` from offset 64, and the
+    /// instructions at `imports` relocated to call the named syscalls
+    fn sbf_elf(text: &[u8], rodata: &[u8], imports: &[(u64, &str)]) -> Vec<u8> {
+        let names = b"\0.text\0.rodata\0.shstrtab\0.dynstr\0.dynsym\0.rel.dyn\0";
+        let mut symbol_names = vec![0u8];
+        let mut symbols = vec![0u8; 24];
+        let mut relocations = Vec::new();
+        for (index, (pc, name)) in imports.iter().enumerate() {
+            // Global symbol without a value, resolved by name
+            symbols.extend_from_slice(&(symbol_names.len() as u32).to_le_bytes());
+            symbols.extend_from_slice(&[0x10, 0, 0, 0]);
+            symbols.extend_from_slice(&[0; 16]);
+            symbol_names.extend_from_slice(name.as_bytes());
+            symbol_names.push(0);
+            relocations.extend_from_slice(&(64 + pc * 8).to_le_bytes());
+            relocations.extend_from_slice(&((index as u64 + 1) << 32 | 10).to_le_bytes());
+        }
+
+        let rodata_at = 64 + text.len() as u64;
+        let symbol_names_at = rodata_at + rodata.len() as u64;
+        let symbols_at = symbol_names_at + symbol_names.len() as u64;
+        let relocations_at = symbols_at + symbols.len() as u64;
+        let names_at = relocations_at + relocations.len() as u64;
+        let sections_at = (names_at + names.len() as u64).next_multiple_of(8);
+
+        let mut elf = b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0".to_vec();
+        for (value, size) in [(3, 2), (247, 2), (1, 4), (64, 8), (0, 8), (sections_at, 8), (0, 4), (64, 2), (56, 2), (0, 2), (64, 2), (7, 2), (3, 2)] {
+            elf.extend_from_slice(&u64::to_le_bytes(value)[..size]);
+        }
+        for part in [text, rodata, &symbol_names, &symbols, &relocations, names] {
+            elf.extend_from_slice(part);
+        }
+        elf.resize(sections_at as usize, 0);
+        // name, type, flags, addr, offset, size, link
+        let sections = [
+            (0, 0, 0, 0, 0, 0, 0),
+            (1, 1, 6, 64, 64, text.len() as u64, 0),
+            (7, 1, 2, rodata_at, rodata_at, rodata.len() as u64, 0),
+            (15, 3, 0, 0, names_at, names.len() as u64, 0),
+            (25, 3, 0, 0, symbol_names_at, symbol_names.len() as u64, 0),
+            (33, 11, 0, 0, symbols_at, symbols.len() as u64, 4),
+            (41, 9, 0, 0, relocations_at, relocations.len() as u64, 5),
+        ];
+        for (name, kind, flags, addr, offset, size, link) in sections {
+            for (value, size) in [(name, 4), (kind, 4), (flags, 8), (addr, 8), (offset, 8), (size, 8), (link, 4), (0, 4), (0, 8), (0, 8)] {
+                elf.extend_from_slice(&u64::to_le_bytes(value)[..size]);
+            }
+        }
+        elf
+    }
+
+    /// SBF program moving 100 lamports from its first account to its second,
+    /// storing 42 in the first account's data, logging through a local
+    /// function and returning the first account's data
+    fn sbf_transfer_program() -> Vec<u8> {
+        let insn = sbf_insn;
+        let text = [
+            insn(0xbf, 6, 1, 0, 0),                  // mov64 r6, r1
+            insn(0x79, 2, 6, 80, 0),                 // ldxdw r2, [r6+80]
+            insn(0x17, 2, 0, 0, 100),                // sub64 r2, 100
+            insn(0x7b, 6, 2, 80, 0),                 // stxdw [r6+80], r2
+            insn(0x79, 3, 6, 10424, 0),              // ldxdw r3, [r6+10424]
+            insn(0x07, 3, 0, 0, 100),                // add64 r3, 100
+            insn(0x7b, 6, 3, 10424, 0),              // stxdw [r6+10424], r3
+            insn(0x7a, 6, 0, 96, 42),                // stdw [r6+96], 42
+            insn(0x85, 0, 1, 0, 6),                  // call log
+            insn(0xbf, 1, 6, 0, 0),                  // mov64 r1, r6
+            insn(0x07, 1, 0, 0, 96),                 // add64 r1, 96
+            insn(0xb7, 2, 0, 0, 8),                  // mov64 r2, 8
+            insn(0x85, 0, 0, 0, 0xa226d3eb_u32 as i32), // call sol_set_return_data
+            insn(0xb7, 0, 0, 0, 0),                  // mov64 r0, 0
+            insn(0x95, 0, 0, 0, 0),                  // exit
+            insn(0x18, 1, 0, 0, 224),                // log: lddw r1, MM_PROGRAM_START + 224
+            insn(0x00, 0, 0, 0, 1),
+            insn(0xb7, 2, 0, 0, 8),                  // mov64 r2, 8
+            insn(0x85, 0, 0, 0, 0x207559bd),         // call sol_log_
+            insn(0x95, 0, 0, 0, 0),                  // exit
+        ]
+        .concat();
+        sbf_elf(&text, b"transfer", &[])
+    }
+
+    /// SBF program returning, in 168 bytes: the address of seeds "Talking" and
+    /// "Squirrels" under the upgradeable loader, the address and bump found for
+    /// "Talking" alone, the clock and rent sysvars and the SHA-256 of
+    /// "TalkingSquirrels"
+    fn sbf_derive_program() -> Vec<u8> {
+        let insn = sbf_insn;
+        // Rodata follows the 44 instruction slots
+        let (talking, squirrels, loader) = (416, 423, 432);
+        let text = [
+            insn(0x18, 1, 0, 0, talking),            // lddw r1, "Talking"
+            insn(0x00, 0, 0, 0, 1),
+            insn(0x7b, 10, 1, -512, 0),              // stxdw [r10-512], r1
+            insn(0x7a, 10, 0, -504, 7),              // stdw [r10-504], 7
+            insn(0x18, 1, 0, 0, squirrels),          // lddw r1, "Squirrels"
+            insn(0x00, 0, 0, 0, 1),
+            insn(0x7b, 10, 1, -496, 0),              // stxdw [r10-496], r1
+            insn(0x7a, 10, 0, -488, 9),              // stdw [r10-488], 9
+            insn(0xbf, 1, 10, 0, 0),                 // mov64 r1, r10
+            insn(0x07, 1, 0, 0, -512),               // add64 r1, -512
+            insn(0xb7, 2, 0, 0, 2),                  // mov64 r2, 2
+            insn(0x18, 3, 0, 0, loader),             // lddw r3, loader
+            insn(0x00, 0, 0, 0, 1),
+            insn(0xbf, 4, 10, 0, 0),                 // mov64 r4, r10
+            insn(0x07, 4, 0, 0, -256),               // add64 r4, -256
+            insn(0x85, 0, 0, 0, 0x9377323c_u32 as i32), // call sol_create_program_address
+            insn(0xbf, 1, 10, 0, 0),                 // mov64 r1, r10
+            insn(0x07, 1, 0, 0, -512),               // add64 r1, -512
+            insn(0xb7, 2, 0, 0, 1),                  // mov64 r2, 1
+            insn(0x18, 3, 0, 0, loader),             // lddw r3, loader
+            insn(0x00, 0, 0, 0, 1),
+            insn(0xbf, 4, 10, 0, 0),                 // mov64 r4, r10
+            insn(0x07, 4, 0, 0, -224),               // add64 r4, -224
+            insn(0xbf, 5, 10, 0, 0),                 // mov64 r5, r10
+            insn(0x07, 5, 0, 0, -192),               // add64 r5, -192
+            insn(0x85, 0, 0, 0, 0x48504a38),         // call sol_try_find_program_address
+            insn(0xbf, 1, 10, 0, 0),                 // mov64 r1, r10
+            insn(0x07, 1, 0, 0, -184),               // add64 r1, -184
+            insn(0x85, 0, 0, 0, 0xd56b5fe9_u32 as i32), // call sol_get_clock_sysvar
+            insn(0xbf, 1, 10, 0, 0),                 // mov64 r1, r10
+            insn(0x07, 1, 0, 0, -144),               // add64 r1, -144
+            insn(0x85, 0, 0, 0, 0xbf7188f6_u32 as i32), // call sol_get_rent_sysvar
+            insn(0xbf, 1, 10, 0, 0),                 // mov64 r1, r10
+            insn(0x07, 1, 0, 0, -512),               // add64 r1, -512
+            insn(0xb7, 2, 0, 0, 2),                  // mov64 r2, 2
+            insn(0xbf, 3, 10, 0, 0),                 // mov64 r3, r10
+            insn(0x07, 3, 0, 0, -120),               // add64 r3, -120
+            insn(0x85, 0, 0, 0, 0x11f49d86),         // call sol_sha256
+            insn(0xbf, 1, 10, 0, 0),                 // mov64 r1, r10
+            insn(0x07, 1, 0, 0, -256),               // add64 r1, -256
+            insn(0xb7, 2, 0, 0, 168),                // mov64 r2, 168
+            insn(0x85, 0, 0, 0, 0xa226d3eb_u32 as i32), // call sol_set_return_data
+            insn(0xb7, 0, 0, 0, 0),                  // mov64 r0, 0
+            insn(0x95, 0, 0, 0, 0),                  // exit
+        ]
+        .concat();
+        // BPFLoaderUpgradeab1e11111111111111111111111
+        let loader = hex::decode("02a8f6914e88a1b0e210153ef763ae2b00c2b93d16c124d2c0537a1004800000").unwrap();
+        sbf_elf(&text, &[b"TalkingSquirrels".as_slice(), &loader].concat(), &[])
+    }
+
+    #[tokio::test]
+    async fn test_svm_runtime_executes_sbf_program() {
+        const SYSTEM_PROGRAM: &str = "11111111111111111111111111111111";
+        const PAYER: &str = "Vote111111111111111111111111111111111111111";
+        const RECIPIENT: &str = "Stake11111111111111111111111111111111111111";
+        let runtime = SvmRuntime::new("solana".to_string());
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let code_path = std::env::temp_dir().join(format!("blockchain-runtime-sbf-{}.so", std::process::id()));
+        std::fs::write(&code_path, sbf_transfer_program()).unwrap();
+        let account = |lamports, owner: &str| SvmAccount {
+            lamports,
+            data: vec![0; 8],
+            owner: owner.to_string(),
+            executable: false,
+        };
+        runtime.set_account(&env, PAYER, account(1_000, SYSTEM_PROGRAM)).unwrap();
+        runtime.set_account(&env, RECIPIENT, account(50, SYSTEM_PROGRAM)).unwrap();
+        let accounts = |recipient_writable| {
+            serde_json::json!([
+                { "pubkey": PAYER, "is_signer": true, "is_writable": true },
+                { "pubkey": RECIPIENT, "is_writable": recipient_writable },
+            ])
+        };
+
+        // The payer is not owned by the program yet, so the debit is rejected
+        let inputs = execution_inputs("transfer", &[("accounts", accounts(true))]);
+        let rejected = runtime.execute(&env, &code_path, &inputs).await.unwrap();
+        assert!(!rejected.success);
+        assert!(rejected.error.unwrap().contains("does not own"));
+        assert!(rejected.state_changes.is_empty());
+        assert_eq!(runtime.account(&env, PAYER).unwrap().unwrap().lamports, 1_000);
+        let program_id = rejected.security_context.call_stack[0].clone();
+        assert!(runtime.account(&env, &program_id).unwrap().unwrap().executable);
+
+        runtime.set_account(&env, PAYER, account(1_000, &program_id)).unwrap();
+        let inputs = execution_inputs("transfer", &[("accounts", accounts(false))]);
+        let read_only = runtime.execute(&env, &code_path, &inputs).await.unwrap();
+        assert!(read_only.error.unwrap().contains("read-only account"));
+
+        let inputs = execution_inputs("transfer", &[("accounts", accounts(true))]);
+        let result = runtime.execute(&env, &code_path, &inputs).await.unwrap();
+        assert!(result.success, "{:?}", result.error);
+        // 19 instructions plus 100 units for each syscall
+        assert_eq!(result.metrics["compute_units"], 219);
+        assert_eq!(result.security_context.gas_used, 219);
+        assert_eq!(result.security_context.call_depth, 2);
+        assert_eq!(result.return_value, Some(serde_json::json!("0x2a00000000000000")));
+        assert_eq!(result.events[0].event_type, "log");
+        assert_eq!(result.events[0].data["message"], "transfer");
+        let changes: HashMap<_, _> = result
+            .state_changes
+            .iter()
+            .map(|change| (change.key.clone(), (change.old_value.clone(), change.new_value.clone())))
+            .collect();
+        assert_eq!(changes.len(), 3);
+        assert_eq!(changes[&format!("{}:lamports", PAYER)], (Some(serde_json::json!(1_000)), serde_json::json!(900)));
+        assert_eq!(changes[&format!("{}:lamports", RECIPIENT)], (Some(serde_json::json!(50)), serde_json::json!(150)));
+        assert_eq!(changes[&format!("{}:data", PAYER)].1, "0x2a00000000000000");
+        assert_eq!(runtime.account(&env, RECIPIENT).unwrap().unwrap().lamports, 150);
+        assert!(runtime
+            .metrics_definition()
+            .iter()
+            .any(|metric| metric.metric_type == MetricType::ComputeUnits));
+
+        let inputs = execution_inputs("transfer", &[("accounts", accounts(true)), ("compute_unit_limit", serde_json::json!(50))]);
+        let exhausted = runtime.execute(&env, &code_path, &inputs).await.unwrap();
+        assert!(exhausted.error.unwrap().contains("compute budget"));
+        assert_eq!(exhausted.metrics["compute_units"], 50);
+        assert_eq!(runtime.account(&env, PAYER).unwrap().unwrap().lamports, 900);
+
+        // Executions reverted away keep their ids, which are not handed out again
+        let snapshot = runtime.snapshot(&env).await.unwrap();
+        let inputs = execution_inputs("transfer", &[("accounts", accounts(true))]);
+        let reverted = runtime.execute(&env, &code_path, &inputs).await.unwrap();
+        runtime.revert(&env, snapshot).await.unwrap();
+        assert_eq!(runtime.account(&env, PAYER).unwrap().unwrap().lamports, 900);
+        let replayed = runtime.execute(&env, &code_path, &inputs).await.unwrap();
+        assert_ne!(reverted.execution_id, replayed.execution_id);
+        for id in [&reverted.execution_id, &replayed.execution_id] {
+            assert_eq!(runtime.monitor(&env, id).await.unwrap()[0].data["message"], "transfer");
+        }
+        let error = runtime.revert(&env, SnapshotId(404)).await.unwrap_err();
+        assert!(matches!(RuntimeError::find(&error), Some(RuntimeError::SnapshotNotFound { .. })));
+        let error = runtime.monitor(&env, "exec_404").await.unwrap_err();
+        assert!(matches!(RuntimeError::find(&error), Some(RuntimeError::ExecutionNotFound(_))));
+
+        let error = runtime.deploy_contract(&env, b"not an elf", &[]).await.unwrap_err();
+        assert!(matches!(RuntimeError::find(&error), Some(RuntimeError::InvalidInput(_))));
+        std::fs::remove_file(code_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_svm_derives_program_addresses_and_reads_sysvars() {
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let runtime = SvmRuntime::new("solana".to_string())
+            .with_entropy(Entropy::new(clock, Arc::new(SeededRandom::new(7))));
+        let env = runtime.create_environment(RuntimeConfig::default()).await.unwrap();
+        let code_path = std::env::temp_dir().join(format!("blockchain-runtime-sbf-derive-{}.so", std::process::id()));
+        std::fs::write(&code_path, sbf_derive_program()).unwrap();
+
+        let result = runtime.execute(&env, &code_path, &execution_inputs("derive", &[])).await.unwrap();
+        assert!(result.success, "{:?}", result.error);
+        let output = result.return_value.unwrap().as_str().unwrap().trim_start_matches("0x").to_string();
+        let (created, found, bump) = (&output[..64], &output[64..128], &output[128..144]);
+        let (clock, rent, digest) = (&output[144..224], &output[224..272], &output[272..]);
+        // 2fnQrngrQT4SeLcdToJAD96phoEjNL2man2kfRLCASVk
+        assert_eq!(created, "18cb1abd4405baf9119d11e46977c210063d6a8dfac767ef671cf7e860968aff");
+        // Bumps 255 and 254 give points on the curve
+        assert_eq!(found, "9468a14a907bde034a307e7d79eebc582fc265fd29d7ebb548eab65e49474ff6");
+        assert_eq!(bump, "fd00000000000000");
+        assert_eq!(clock, ["0000000000000000", "e803000000000000", "0000000000000000", "0000000000000000", "e803000000000000"].concat());
+        assert_eq!(rent, ["980d000000000000", "0000000000000040", "3200000000000000"].concat());
+        assert_eq!(digest, "77816afc49dcd9b74f5877edd3df350aebc78dc317e0413584d1f986ffdc7bcf");
+
+        // 40 instructions, 1,500 units per address attempt, sysvars at 100 plus
+        // their size, and 85 plus 10 per slice for the hash
+        assert_eq!(result.metrics["compute_units"], 40 + 4 * 1_500 + 140 + 124 + 105 + 100);
+        std::fs::remove_file(code_path).unwrap();
+
+        // Syscalls are resolved when the program loads, and only known ones are
+        let text = [sbf_insn(0x85, 0, 0, 0, -1), sbf_insn(0x95, 0, 0, 0, 0)].concat();
+        let logging = sbf_elf(&text, &[], &[(0, "sol_log_compute_units_")]);
+        let program_id = runtime.deploy_contract(&env, &logging, &[]).await.unwrap();
+        assert!(runtime.account(&env, &program_id).unwrap().unwrap().executable);
+        let invoking = sbf_elf(&text, &[], &[(0, "sol_invoke_signed_rust")]);
+        let error = runtime.deploy_contract(&env, &invoking, &[]).await.unwrap_err();
+        assert!(matches!(RuntimeError::find(&error), Some(RuntimeError::InvalidInput(message)) if message.contains("unknown syscall sol_invoke_signed_rust")));
+    }
+}
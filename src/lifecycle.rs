@@ -0,0 +1,408 @@
+//! Lifecycle management of runtime environments
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use crate::config::RuntimeConfig;
+use crate::determinism::{Clock, Entropy};
+use crate::error::{RuntimeError, RuntimeResult};
+use crate::runtime::BlockchainRuntime;
+use crate::security::SecurityConfig;
+use crate::types::{EnvironmentState, ExecutionInputs, ExecutionResult, RuntimeEnvironment};
+
+/// Key of `RuntimeEnvironment::metadata` holding the latest `StateTransition`
+pub const LAST_TRANSITION_METADATA_KEY: &str = "last_transition";
+
+/// Transitions kept per environment, the oldest dropped first
+pub const STATE_HISTORY_CAPACITY: usize = 100;
+
+/// Change of an environment's state
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StateTransition {
+    pub from: EnvironmentState,
+    pub to: EnvironmentState,
+    pub reason: String,
+    /// Unix time in seconds, from the environment's clock
+    pub timestamp: u64,
+}
+
+/// Environment tracked by an `EnvironmentManager`
+struct ManagedEnvironment {
+    /// What callers see, keeping the id it was created with across restarts
+    environment: RuntimeEnvironment,
+    /// Environment in the backend, `None` while stopped
+    backend: Option<RuntimeEnvironment>,
+    config: RuntimeConfig,
+    clock: Arc<dyn Clock>,
+    history: VecDeque<StateTransition>,
+}
+
+impl ManagedEnvironment {
+    fn transition(&mut self, to: EnvironmentState, reason: impl Into<String>) -> RuntimeResult<()> {
+        let from = self.environment.state.clone();
+        if !from.can_transition_to(&to) {
+            return Err(RuntimeError::InvalidTransition {
+                environment_id: self.environment.environment_id.clone(),
+                from,
+                to,
+            });
+        }
+
+        let transition = StateTransition {
+            from,
+            to: to.clone(),
+            reason: reason.into(),
+            timestamp: self.clock.now_secs(),
+        };
+        self.environment.state = to;
+        self.environment
+            .metadata
+            .insert(LAST_TRANSITION_METADATA_KEY.to_string(), serde_json::json!(transition));
+        if self.history.len() == STATE_HISTORY_CAPACITY {
+            self.history.pop_front();
+        }
+        self.history.push_back(transition);
+        Ok(())
+    }
+}
+
+/// Tracks the environments of a runtime through their lifecycle
+///
+/// Operations go through the manager so an environment only runs one at a
+/// time and nothing runs on a paused or stopped environment. Stopping an
+/// environment destroys it in the backend and restarting creates a fresh one
+/// from the same configuration, under the same id. Environments still alive
+/// when the manager is dropped are destroyed.
+pub struct EnvironmentManager<R: BlockchainRuntime> {
+    runtime: R,
+    environments: RwLock<HashMap<String, ManagedEnvironment>>,
+}
+
+impl<R: BlockchainRuntime> EnvironmentManager<R> {
+    /// Manage the environments of a runtime
+    pub fn new(runtime: R) -> Self {
+        Self {
+            runtime,
+            environments: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Get the managed runtime
+    pub fn runtime(&self) -> &R {
+        &self.runtime
+    }
+
+    /// Create an environment and start tracking it
+    pub async fn create_environment(&self, config: RuntimeConfig) -> Result<RuntimeEnvironment> {
+        let backend = self.runtime.create_environment(config.clone()).await?;
+        let mut managed = ManagedEnvironment {
+            environment: RuntimeEnvironment {
+                state: EnvironmentState::Creating,
+                ..backend.clone()
+            },
+            backend: Some(backend),
+            clock: Entropy::from_config(&config).clock().clone(),
+            config,
+            history: VecDeque::new(),
+        };
+        managed.transition(EnvironmentState::Ready, "created")?;
+
+        let environment = managed.environment.clone();
+        self.environments
+            .write()
+            .map_err(|_| poisoned())?
+            .insert(environment.environment_id.clone(), managed);
+        Ok(environment)
+    }
+
+    /// Current view of an environment, with its state and latest transition
+    pub fn environment(&self, env: &RuntimeEnvironment) -> Result<RuntimeEnvironment> {
+        self.with_managed(env, |managed| Ok(managed.environment.clone()))
+    }
+
+    /// Every environment being tracked
+    pub fn environments(&self) -> Result<Vec<RuntimeEnvironment>> {
+        let environments = self.environments.read().map_err(|_| poisoned())?;
+        Ok(environments.values().map(|managed| managed.environment.clone()).collect())
+    }
+
+    pub fn state(&self, env: &RuntimeEnvironment) -> Result<EnvironmentState> {
+        self.with_managed(env, |managed| Ok(managed.environment.state.clone()))
+    }
+
+    /// Latest `STATE_HISTORY_CAPACITY` transitions of an environment, oldest first
+    pub fn history(&self, env: &RuntimeEnvironment) -> Result<Vec<StateTransition>> {
+        self.with_managed(env, |managed| Ok(managed.history.iter().cloned().collect()))
+    }
+
+    /// Environment in the backend, for calling runtime methods the manager does not wrap
+    ///
+    /// Its id changes when the environment is restarted.
+    pub fn backend_environment(&self, env: &RuntimeEnvironment) -> Result<RuntimeEnvironment> {
+        self.with_managed(env, |managed| {
+            managed.backend.clone().ok_or_else(|| {
+                RuntimeError::Backend(format!("Environment {} is stopped", env.environment_id)).into()
+            })
+        })
+    }
+
+    pub async fn execute(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+    ) -> Result<ExecutionResult> {
+        let (backend, mut operation) = self.begin(env, format!("executing {}", inputs.target_function))?;
+        let outcome = self.runtime.execute(&backend, code_path, inputs).await;
+        operation.finish("execution", &outcome);
+        outcome
+    }
+
+    pub async fn execute_secure(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+        security_config: &SecurityConfig,
+    ) -> Result<ExecutionResult> {
+        let (backend, mut operation) = self.begin(env, format!("executing {}", inputs.target_function))?;
+        let outcome = self
+            .runtime
+            .execute_secure(&backend, code_path, inputs, security_config)
+            .await;
+        operation.finish("execution", &outcome);
+        outcome
+    }
+
+    pub async fn deploy_contract(
+        &self,
+        env: &RuntimeEnvironment,
+        bytecode: &[u8],
+        constructor_args: &[u8],
+    ) -> Result<String> {
+        let (backend, mut operation) = self.begin(env, "deploying contract".to_string())?;
+        let outcome = self.runtime.deploy_contract(&backend, bytecode, constructor_args).await;
+        operation.finish("deployment", &outcome);
+        outcome
+    }
+
+    pub async fn call_function(
+        &self,
+        env: &RuntimeEnvironment,
+        contract_address: &str,
+        function: &str,
+        args: &[u8],
+    ) -> Result<Vec<u8>> {
+        let (backend, mut operation) = self.begin(env, format!("calling {}", function))?;
+        let outcome = self
+            .runtime
+            .call_function(&backend, contract_address, function, args)
+            .await;
+        operation.finish("call", &outcome);
+        outcome
+    }
+
+    /// Stop accepting operations until `resume`
+    pub fn pause(&self, env: &RuntimeEnvironment, reason: &str) -> Result<()> {
+        self.transition(env, EnvironmentState::Paused, reason)
+    }
+
+    pub fn resume(&self, env: &RuntimeEnvironment, reason: &str) -> Result<()> {
+        self.transition(env, EnvironmentState::Ready, reason)
+    }
+
+    /// Destroy the environment in the backend, keeping it tracked so it can be restarted
+    pub async fn stop(&self, env: &RuntimeEnvironment, reason: &str) -> Result<()> {
+        let backend = self.with_managed_mut(env, |managed| {
+            managed.transition(EnvironmentState::Stopped, reason)?;
+            Ok(managed.backend.take())
+        })?;
+
+        if let Some(backend) = backend {
+            if let Err(error) = self.runtime.destroy(backend).await {
+                self.transition(env, EnvironmentState::Error, &format!("stop failed: {}", error))?;
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace a stopped or failed environment with a fresh one from the same configuration
+    pub async fn restart(&self, env: &RuntimeEnvironment, reason: &str) -> Result<RuntimeEnvironment> {
+        let (config, stale) = self.with_managed_mut(env, |managed| {
+            managed.transition(EnvironmentState::Creating, reason)?;
+            Ok((managed.config.clone(), managed.backend.take()))
+        })?;
+
+        // A failed environment may already be gone from the backend
+        if let Some(stale) = stale {
+            let _ = self.runtime.destroy(stale).await;
+        }
+
+        match self.runtime.create_environment(config).await {
+            Ok(backend) => self.with_managed_mut(env, |managed| {
+                managed.environment.endpoint_url = backend.endpoint_url.clone();
+                managed.environment.runtime_type = backend.runtime_type.clone();
+                managed.backend = Some(backend);
+                managed.transition(EnvironmentState::Ready, "restarted")?;
+                Ok(managed.environment.clone())
+            }),
+            Err(error) => {
+                self.transition(env, EnvironmentState::Error, &format!("restart failed: {}", error))?;
+                Err(error)
+            }
+        }
+    }
+
+    /// Destroy an environment and stop tracking it
+    ///
+    /// Fails while an operation is running on the environment.
+    pub async fn destroy(&self, env: RuntimeEnvironment) -> Result<()> {
+        let managed = {
+            let mut environments = self.environments.write().map_err(|_| poisoned())?;
+            let managed = environments
+                .get(&env.environment_id)
+                .ok_or_else(|| RuntimeError::EnvironmentNotFound(env.environment_id.clone()))?;
+            let state = &managed.environment.state;
+            if matches!(state, EnvironmentState::Running | EnvironmentState::Creating) {
+                return Err(RuntimeError::InvalidTransition {
+                    environment_id: env.environment_id.clone(),
+                    from: state.clone(),
+                    to: EnvironmentState::Stopped,
+                }
+                .into());
+            }
+            environments.remove(&env.environment_id)
+        };
+
+        match managed.and_then(|managed| managed.backend) {
+            Some(backend) => self.runtime.destroy(backend).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Destroy every tracked environment, returning the first failure
+    pub async fn shutdown(&self) -> Result<()> {
+        let backends = self.take_backends()?;
+        let mut outcome = Ok(());
+        for backend in backends {
+            let destroyed = self.runtime.destroy(backend).await;
+            if outcome.is_ok() {
+                outcome = destroyed;
+            }
+        }
+        outcome
+    }
+
+    /// Move an environment to `Running`, returning its backend environment and
+    /// a guard that moves it back when the operation ends
+    fn begin(&self, env: &RuntimeEnvironment, reason: String) -> Result<(RuntimeEnvironment, Operation<'_, R>)> {
+        let backend = self.with_managed_mut(env, |managed| {
+            managed.transition(EnvironmentState::Running, reason)?;
+            Ok(managed.backend.clone())
+        })?;
+        let operation = Operation {
+            manager: self,
+            env: env.clone(),
+            reason: "cancelled".to_string(),
+        };
+        // Only ready environments can start running, and those always have a backend
+        Ok((backend.expect("running environment has a backend"), operation))
+    }
+
+    fn transition(&self, env: &RuntimeEnvironment, to: EnvironmentState, reason: &str) -> Result<()> {
+        self.with_managed_mut(env, |managed| Ok(managed.transition(to, reason)?))
+    }
+
+    fn take_backends(&self) -> Result<Vec<RuntimeEnvironment>> {
+        let mut environments = self.environments.write().map_err(|_| poisoned())?;
+        Ok(environments.drain().filter_map(|(_, managed)| managed.backend).collect())
+    }
+
+    fn with_managed<T>(&self, env: &RuntimeEnvironment, f: impl FnOnce(&ManagedEnvironment) -> Result<T>) -> Result<T> {
+        let environments = self.environments.read().map_err(|_| poisoned())?;
+        let managed = environments
+            .get(&env.environment_id)
+            .ok_or_else(|| RuntimeError::EnvironmentNotFound(env.environment_id.clone()))?;
+        f(managed)
+    }
+
+    fn with_managed_mut<T>(
+        &self,
+        env: &RuntimeEnvironment,
+        f: impl FnOnce(&mut ManagedEnvironment) -> Result<T>,
+    ) -> Result<T> {
+        let mut environments = self.environments.write().map_err(|_| poisoned())?;
+        let managed = environments
+            .get_mut(&env.environment_id)
+            .ok_or_else(|| RuntimeError::EnvironmentNotFound(env.environment_id.clone()))?;
+        f(managed)
+    }
+}
+
+/// Destroys the environments still tracked
+impl<R: BlockchainRuntime> Drop for EnvironmentManager<R> {
+    fn drop(&mut self) {
+        let backends = match self.take_backends() {
+            Ok(backends) if !backends.is_empty() => backends,
+            _ => return,
+        };
+        let runtime = &self.runtime;
+        block_on_isolated(async {
+            for backend in backends {
+                let _ = runtime.destroy(backend).await;
+            }
+        });
+    }
+}
+
+/// Run a future to completion on a separate thread with its own async runtime
+///
+/// Lets `Drop` implementations finish async cleanup whether or not they are
+/// called from inside a runtime, without blocking that runtime's reactor.
+pub(crate) fn block_on_isolated<F>(future: F) -> Option<F::Output>
+where
+    F: Future + Send,
+    F::Output: Send,
+{
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                let executor = tokio::runtime::Builder::new_current_thread().enable_all().build().ok()?;
+                Some(executor.block_on(future))
+            })
+            .join()
+            .ok()
+            .flatten()
+    })
+}
+
+/// Operation running on an environment, returning it to `Ready` when dropped
+struct Operation<'a, R: BlockchainRuntime> {
+    manager: &'a EnvironmentManager<R>,
+    env: RuntimeEnvironment,
+    reason: String,
+}
+
+impl<R: BlockchainRuntime> Operation<'_, R> {
+    fn finish<T>(&mut self, operation: &str, outcome: &Result<T>) {
+        self.reason = match outcome {
+            Ok(_) => format!("{} finished", operation),
+            Err(error) => format!("{} failed: {}", operation, error),
+        };
+    }
+}
+
+impl<R: BlockchainRuntime> Drop for Operation<'_, R> {
+    fn drop(&mut self) {
+        let _ = self.manager.transition(&self.env, EnvironmentState::Ready, &self.reason);
+    }
+}
+
+fn poisoned() -> RuntimeError {
+    RuntimeError::Backend("Environment manager state is poisoned".to_string())
+}
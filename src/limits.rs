@@ -0,0 +1,445 @@
+//! Enforcement of execution timeouts and memory limits
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::config::RuntimeConfig;
+use crate::determinism::{Clock, Entropy};
+use crate::error::{RuntimeError, RuntimeResult};
+use crate::events::{EventFilter, EventSubscription};
+use crate::report::SecurityReport;
+use crate::runtime::BlockchainRuntime;
+use crate::security::SecurityConfig;
+use crate::trace::ExecutionTrace;
+use crate::types::{
+    BlockInfo, ExecutionInputs, ExecutionResult, GasEstimate, RuntimeCapabilities, RuntimeEnvironment,
+    RuntimeEvent, RuntimeMetricDefinition, SecurityViolation, SecurityViolationType, SecuritySeverity,
+    SnapshotId,
+};
+
+/// Time and memory an execution may use
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ResourceLimits {
+    pub timeout: Duration,
+    pub memory_limit_bytes: u64,
+}
+
+impl ResourceLimits {
+    /// Limits set by `timeout_seconds` and `memory_limit_mb`
+    pub fn from_config(config: &RuntimeConfig) -> Self {
+        Self {
+            timeout: Duration::from_secs(config.timeout_seconds),
+            memory_limit_bytes: config.memory_limit_mb.saturating_mul(1024 * 1024),
+        }
+    }
+
+    /// Violation reported for an execution cancelled at the timeout
+    pub fn timeout_violation(&self, function_name: &str, clock: &dyn Clock) -> SecurityViolation {
+        let mut violation = SecurityViolation::new(
+            SecurityViolationType::ResourceLimitExceeded,
+            format!("Execution of {} timed out after {:?}", function_name, self.timeout),
+            SecuritySeverity::High,
+            clock.now_secs(),
+        );
+        violation.context.extend([
+            ("limit".to_string(), serde_json::json!("timeout_seconds")),
+            ("function_name".to_string(), serde_json::json!(function_name)),
+            ("timeout_ms".to_string(), serde_json::json!(self.timeout.as_millis() as u64)),
+        ]);
+        violation
+    }
+
+    /// Check the memory an execution used against the limit
+    pub fn check_memory(&self, memory_used: u64, clock: &dyn Clock) -> Result<(), SecurityViolation> {
+        if memory_used <= self.memory_limit_bytes {
+            return Ok(());
+        }
+
+        let mut violation = SecurityViolation::new(
+            SecurityViolationType::MemoryLimitExceeded,
+            format!("Memory usage {} exceeds limit {}", memory_used, self.memory_limit_bytes),
+            SecuritySeverity::High,
+            clock.now_secs(),
+        );
+        violation.context.extend([
+            ("limit".to_string(), serde_json::json!("memory_limit_mb")),
+            ("memory_used".to_string(), serde_json::json!(memory_used)),
+            ("memory_limit_bytes".to_string(), serde_json::json!(self.memory_limit_bytes)),
+        ]);
+        Err(violation)
+    }
+
+    /// Run a future, cancelling it once the timeout expires
+    pub async fn run<T>(&self, future: impl Future<Output = T>) -> RuntimeResult<T> {
+        tokio::time::timeout(self.timeout, future)
+            .await
+            .map_err(|_| RuntimeError::Timeout(self.timeout))
+    }
+
+    /// Record a memory violation in an execution result
+    fn account_memory(&self, result: &mut ExecutionResult, clock: &dyn Clock) {
+        if let Err(violation) = self.check_memory(result.security_context.memory_used, clock) {
+            result.security_context.security_violations.push(violation.clone());
+            result.add_security_violation(violation);
+        }
+    }
+}
+
+/// Runtime wrapper enforcing each environment's `timeout_seconds` and `memory_limit_mb`
+///
+/// Executions and function calls are cancelled when they run past the
+/// timeout. Cancellation takes effect at the wrapped backend's next await
+/// point, so backends doing long synchronous work should bound it themselves,
+/// as the EVM backend does.
+pub struct LimitedRuntime<R> {
+    inner: R,
+    limits: RwLock<HashMap<String, EnvironmentLimits>>,
+    timeout_nonce: AtomicU64,
+}
+
+/// Limits of an environment and the clock its violations are stamped with
+struct EnvironmentLimits {
+    limits: ResourceLimits,
+    clock: Arc<dyn Clock>,
+    config: RuntimeConfig,
+    /// Executions cancelled at the timeout, which the wrapped runtime never recorded
+    timed_out: HashMap<String, ExecutionResult>,
+}
+
+impl<R: BlockchainRuntime> LimitedRuntime<R> {
+    /// Wrap a runtime
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            limits: RwLock::new(HashMap::new()),
+            timeout_nonce: AtomicU64::new(0),
+        }
+    }
+
+    /// Get the wrapped runtime
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// Unwrap the runtime
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Limits applied to an environment
+    pub fn limits(&self, env: &RuntimeEnvironment) -> Result<ResourceLimits> {
+        self.with_limits(env, |environment| environment.limits)
+    }
+
+    /// Replace the limits of an environment created through this wrapper
+    pub fn set_limits(&self, env: &RuntimeEnvironment, limits: ResourceLimits) -> Result<()> {
+        self.with_limits_mut(env, |environment| environment.limits = limits)
+    }
+
+    fn clock(&self, env: &RuntimeEnvironment) -> Result<Arc<dyn Clock>> {
+        self.with_limits(env, |environment| environment.clock.clone())
+    }
+
+    fn with_limits<T>(&self, env: &RuntimeEnvironment, f: impl FnOnce(&EnvironmentLimits) -> T) -> Result<T> {
+        self.limits
+            .read()
+            .map_err(|_| RuntimeError::Backend("Resource limits are poisoned".to_string()))?
+            .get(&env.environment_id)
+            .map(f)
+            .ok_or_else(|| RuntimeError::EnvironmentNotFound(env.environment_id.clone()).into())
+    }
+
+    fn with_limits_mut<T>(&self, env: &RuntimeEnvironment, f: impl FnOnce(&mut EnvironmentLimits) -> T) -> Result<T> {
+        self.limits
+            .write()
+            .map_err(|_| RuntimeError::Backend("Resource limits are poisoned".to_string()))?
+            .get_mut(&env.environment_id)
+            .map(f)
+            .ok_or_else(|| RuntimeError::EnvironmentNotFound(env.environment_id.clone()).into())
+    }
+
+    /// Result of an execution cancelled at the timeout
+    fn timed_out(&self, env: &RuntimeEnvironment, execution_id: &str) -> Result<Option<ExecutionResult>> {
+        self.with_limits(env, |environment| environment.timed_out.get(execution_id).cloned())
+    }
+
+    /// Run an execution under the limits, turning a timeout into a failed result
+    async fn limited_execution(
+        &self,
+        env: &RuntimeEnvironment,
+        inputs: &ExecutionInputs,
+        execution: impl Future<Output = Result<ExecutionResult>>,
+    ) -> Result<ExecutionResult> {
+        let limits = self.limits(env)?;
+        let clock = self.clock(env)?;
+        let mut result = match limits.run(execution).await {
+            Ok(result) => result?,
+            Err(_) => return self.record_timeout(env, inputs, &limits, clock.as_ref()),
+        };
+        limits.account_memory(&mut result, clock.as_ref());
+        Ok(result)
+    }
+
+    /// Record a failed result for an execution cancelled at the timeout
+    fn record_timeout(
+        &self,
+        env: &RuntimeEnvironment,
+        inputs: &ExecutionInputs,
+        limits: &ResourceLimits,
+        clock: &dyn Clock,
+    ) -> Result<ExecutionResult> {
+        let nonce = self.timeout_nonce.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut result = ExecutionResult::new(format!("{}_timeout_{}", env.environment_id, nonce), false);
+        result.error = Some(format!("Execution timed out after {:?}", limits.timeout));
+        result.execution_time_ms = limits.timeout.as_millis() as u64;
+        let violation = limits.timeout_violation(&inputs.target_function, clock);
+        result.security_context.security_violations.push(violation.clone());
+        result.add_security_violation(violation);
+        self.with_limits_mut(env, |environment| {
+            environment.timed_out.insert(result.execution_id.clone(), result.clone())
+        })?;
+        Ok(result)
+    }
+}
+
+#[async_trait]
+impl<R: BlockchainRuntime> BlockchainRuntime for LimitedRuntime<R> {
+    fn blockchain_id(&self) -> &str {
+        self.inner.blockchain_id()
+    }
+
+    async fn create_environment(&self, config: RuntimeConfig) -> Result<RuntimeEnvironment> {
+        let limits = EnvironmentLimits {
+            limits: ResourceLimits::from_config(&config),
+            clock: Entropy::from_config(&config).clock().clone(),
+            config: config.clone(),
+            timed_out: HashMap::new(),
+        };
+        let env = self.inner.create_environment(config).await?;
+        self.limits
+            .write()
+            .map_err(|_| RuntimeError::Backend("Resource limits are poisoned".to_string()))?
+            .insert(env.environment_id.clone(), limits);
+        Ok(env)
+    }
+
+    async fn execute(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+    ) -> Result<ExecutionResult> {
+        self.limited_execution(env, inputs, self.inner.execute(env, code_path, inputs))
+            .await
+    }
+
+    async fn execute_traced(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+    ) -> Result<ExecutionTrace> {
+        let limits = self.limits(env)?;
+        let clock = self.clock(env)?;
+        // The block the execution builds on, pinned in the trace of a timed out execution
+        let block = self.inner.current_block(env).await;
+        let mut trace = match limits.run(self.inner.execute_traced(env, code_path, inputs)).await {
+            Ok(trace) => trace?,
+            Err(_) => {
+                let result = self.record_timeout(env, inputs, &limits, clock.as_ref())?;
+                let config = self.with_limits(env, |environment| environment.config.clone())?;
+                return Ok(ExecutionTrace::record(self.blockchain_id(), config, code_path, inputs, block?, result).await);
+            }
+        };
+        limits.account_memory(&mut trace.result, clock.as_ref());
+        Ok(trace)
+    }
+
+    async fn deploy_contract(
+        &self,
+        env: &RuntimeEnvironment,
+        bytecode: &[u8],
+        constructor_args: &[u8],
+    ) -> Result<String> {
+        self.inner.deploy_contract(env, bytecode, constructor_args).await
+    }
+
+    async fn call_function(
+        &self,
+        env: &RuntimeEnvironment,
+        contract_address: &str,
+        function: &str,
+        args: &[u8],
+    ) -> Result<Vec<u8>> {
+        let limits = self.limits(env)?;
+        limits
+            .run(self.inner.call_function(env, contract_address, function, args))
+            .await?
+    }
+
+    fn metrics_definition(&self) -> Vec<RuntimeMetricDefinition> {
+        self.inner.metrics_definition()
+    }
+
+    async fn monitor(
+        &self,
+        env: &RuntimeEnvironment,
+        execution_id: &str,
+    ) -> Result<Vec<RuntimeEvent>> {
+        match self.timed_out(env, execution_id)? {
+            Some(result) => Ok(result.events),
+            None => self.inner.monitor(env, execution_id).await,
+        }
+    }
+
+    async fn destroy(&self, env: RuntimeEnvironment) -> Result<()> {
+        let environment_id = env.environment_id.clone();
+        self.inner.destroy(env).await?;
+        self.limits
+            .write()
+            .map_err(|_| RuntimeError::Backend("Resource limits are poisoned".to_string()))?
+            .remove(&environment_id);
+        Ok(())
+    }
+
+    async fn is_available(&self) -> bool {
+        self.inner.is_available().await
+    }
+
+    fn capabilities(&self) -> RuntimeCapabilities {
+        self.inner.capabilities()
+    }
+
+    async fn subscribe(&self, env: &RuntimeEnvironment, filter: EventFilter) -> Result<EventSubscription> {
+        self.inner.subscribe(env, filter).await
+    }
+
+    async fn snapshot(&self, env: &RuntimeEnvironment) -> Result<SnapshotId> {
+        self.inner.snapshot(env).await
+    }
+
+    async fn revert(&self, env: &RuntimeEnvironment, snapshot: SnapshotId) -> Result<()> {
+        self.inner.revert(env, snapshot).await
+    }
+
+    async fn estimate_gas(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+    ) -> Result<GasEstimate> {
+        self.inner.estimate_gas(env, code_path, inputs).await
+    }
+
+    async fn current_block(&self, env: &RuntimeEnvironment) -> Result<BlockInfo> {
+        self.inner.current_block(env).await
+    }
+
+    async fn advance_time(&self, env: &RuntimeEnvironment, seconds: u64) -> Result<BlockInfo> {
+        self.inner.advance_time(env, seconds).await
+    }
+
+    async fn set_next_block_timestamp(&self, env: &RuntimeEnvironment, timestamp: u64) -> Result<()> {
+        self.inner.set_next_block_timestamp(env, timestamp).await
+    }
+
+    async fn mine_blocks(&self, env: &RuntimeEnvironment, count: u64) -> Result<BlockInfo> {
+        self.inner.mine_blocks(env, count).await
+    }
+
+    async fn grant_role(&self, env: &RuntimeEnvironment, account: &str, role: &str) -> Result<()> {
+        self.inner.grant_role(env, account, role).await
+    }
+
+    async fn revoke_role(&self, env: &RuntimeEnvironment, account: &str, role: &str) -> Result<bool> {
+        self.inner.revoke_role(env, account, role).await
+    }
+
+    async fn execute_secure(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+        security_config: &SecurityConfig,
+    ) -> Result<ExecutionResult> {
+        self.limited_execution(
+            env,
+            inputs,
+            self.inner.execute_secure(env, code_path, inputs, security_config),
+        )
+        .await
+    }
+
+    async fn check_reentrancy(
+        &self,
+        env: &RuntimeEnvironment,
+        function_name: &str,
+        caller: &str,
+        call_stack: &[String],
+    ) -> Result<bool> {
+        self.inner.check_reentrancy(env, function_name, caller, call_stack).await
+    }
+
+    async fn detect_overflow(
+        &self,
+        env: &RuntimeEnvironment,
+        operation: &str,
+        operands: &[i64],
+    ) -> Result<bool> {
+        self.inner.detect_overflow(env, operation, operands).await
+    }
+
+    async fn verify_access_control(
+        &self,
+        env: &RuntimeEnvironment,
+        function_name: &str,
+        caller: &str,
+        required_role: Option<&str>,
+    ) -> Result<bool> {
+        self.inner
+            .verify_access_control(env, function_name, caller, required_role)
+            .await
+    }
+
+    async fn enforce_resource_limits(
+        &self,
+        env: &RuntimeEnvironment,
+        gas_used: u64,
+        memory_used: u64,
+        call_depth: u32,
+        external_calls: u32,
+        security_config: &SecurityConfig,
+    ) -> Result<Vec<SecurityViolation>> {
+        let mut violations = self
+            .inner
+            .enforce_resource_limits(env, gas_used, memory_used, call_depth, external_calls, security_config)
+            .await?;
+        if let Err(violation) = self.limits(env)?.check_memory(memory_used, self.clock(env)?.as_ref()) {
+            violations.push(violation);
+        }
+        Ok(violations)
+    }
+
+    async fn get_security_report(
+        &self,
+        env: &RuntimeEnvironment,
+        execution_id: &str,
+    ) -> Result<SecurityReport> {
+        let timed_out = self.with_limits(env, |environment| {
+            environment.timed_out.get(execution_id).map(|result| {
+                SecurityReport::with_clock(result, &environment.config.security_config, environment.clock.as_ref())
+            })
+        })?;
+        match timed_out {
+            Some(report) => Ok(report),
+            None => self.inner.get_security_report(env, execution_id).await,
+        }
+    }
+}
@@ -0,0 +1,346 @@
+//! Pool of pre-warmed runtime environments
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, TryAcquireError};
+use tokio::task::JoinSet;
+
+use crate::config::RuntimeConfig;
+use crate::error::RuntimeError;
+use crate::lifecycle::block_on_isolated;
+use crate::runtime::BlockchainRuntime;
+use crate::types::{RuntimeEnvironment, SnapshotId};
+
+/// How a returned environment is brought back to its initial state
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResetStrategy {
+    /// Revert to a snapshot taken right after the environment was created
+    ///
+    /// Only chain state is reverted: event subscriptions and recorded
+    /// executions of earlier leases stay on the environment.
+    Revert,
+    /// Destroy the environment and create a fresh one
+    #[default]
+    Recreate,
+}
+
+/// Sizing and reset behaviour of an `EnvironmentPool`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PoolConfig {
+    /// Environments created up front
+    pub warm_size: usize,
+    /// Environments that may exist at once, idle or leased
+    pub max_size: usize,
+    /// Reset strategy, `Recreate` unless set
+    #[serde(default)]
+    pub reset: ResetStrategy,
+}
+
+impl PoolConfig {
+    /// Pre-warm `warm_size` environments and allow up to `max_size`
+    pub fn new(warm_size: usize, max_size: usize) -> Self {
+        Self {
+            warm_size,
+            max_size,
+            reset: ResetStrategy::default(),
+        }
+    }
+
+    /// Reset returned environments with a strategy
+    pub fn with_reset(mut self, reset: ResetStrategy) -> Self {
+        self.reset = reset;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), RuntimeError> {
+        if self.max_size == 0 {
+            return Err(RuntimeError::invalid_config("max_size", "must be at least 1"));
+        }
+        if self.warm_size > self.max_size {
+            return Err(RuntimeError::invalid_config(
+                "warm_size",
+                format!("{} exceeds max_size {}", self.warm_size, self.max_size),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Idle environment with the snapshot it is reverted to
+struct PooledEnvironment {
+    environment: RuntimeEnvironment,
+    snapshot: Option<SnapshotId>,
+}
+
+struct PoolInner<R: BlockchainRuntime> {
+    runtime: R,
+    config: RuntimeConfig,
+    reset: ResetStrategy,
+    max_size: usize,
+    /// One permit per environment that may exist, handed out in request order
+    permits: Arc<Semaphore>,
+    idle: Mutex<VecDeque<PooledEnvironment>>,
+}
+
+impl<R: BlockchainRuntime> PoolInner<R> {
+    async fn create(&self) -> Result<PooledEnvironment> {
+        let environment = self.runtime.create_environment(self.config.clone()).await?;
+        let snapshot = match self.reset {
+            ResetStrategy::Revert => match self.runtime.snapshot(&environment).await {
+                Ok(snapshot) => Some(snapshot),
+                Err(error) => {
+                    let _ = self.runtime.destroy(environment).await;
+                    return Err(error);
+                }
+            },
+            ResetStrategy::Recreate => None,
+        };
+        Ok(PooledEnvironment { environment, snapshot })
+    }
+
+    async fn reset(&self, pooled: PooledEnvironment) -> Result<PooledEnvironment> {
+        match pooled.snapshot {
+            Some(snapshot) => match self.runtime.revert(&pooled.environment, snapshot).await {
+                Ok(()) => Ok(pooled),
+                Err(error) => {
+                    let _ = self.runtime.destroy(pooled.environment).await;
+                    Err(error)
+                }
+            },
+            None => {
+                self.runtime.destroy(pooled.environment).await?;
+                self.create().await
+            }
+        }
+    }
+
+    /// Reset a returned environment and make it idle, dropping it if that fails
+    ///
+    /// The lease's permit is only released afterwards, so the next waiter
+    /// finds the environment ready.
+    async fn give_back(&self, pooled: PooledEnvironment, permit: OwnedSemaphorePermit) {
+        if self.permits.is_closed() {
+            let _ = self.runtime.destroy(pooled.environment).await;
+        } else if let Ok(pooled) = self.reset(pooled).await {
+            if let Ok(mut idle) = self.idle.lock() {
+                idle.push_back(pooled);
+            }
+        }
+        drop(permit);
+    }
+
+    fn take_idle(&self) -> Vec<RuntimeEnvironment> {
+        match self.idle.lock() {
+            Ok(mut idle) => idle.drain(..).map(|pooled| pooled.environment).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+/// Destroys the idle environments once every lease has been returned
+impl<R: BlockchainRuntime> Drop for PoolInner<R> {
+    fn drop(&mut self) {
+        let environments = self.take_idle();
+        if environments.is_empty() {
+            return;
+        }
+        let runtime = &self.runtime;
+        block_on_isolated(async {
+            for environment in environments {
+                let _ = runtime.destroy(environment).await;
+            }
+        });
+    }
+}
+
+/// Pool of environments created from one configuration and leased to tasks
+///
+/// Creating an environment is the expensive step for most backends, so the
+/// pool creates them ahead of time and resets them when their lease is
+/// returned instead of destroying them. At most `max_size` environments exist
+/// at once; tasks leasing from a full pool wait in the order they asked.
+/// Cloning the pool shares it.
+pub struct EnvironmentPool<R: BlockchainRuntime> {
+    inner: Arc<PoolInner<R>>,
+}
+
+impl<R: BlockchainRuntime> Clone for EnvironmentPool<R> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<R: BlockchainRuntime + 'static> EnvironmentPool<R> {
+    /// Create a pool and pre-warm its environments concurrently
+    pub async fn new(runtime: R, config: RuntimeConfig, pool_config: PoolConfig) -> Result<Self> {
+        pool_config.validate()?;
+        let pool = Self {
+            inner: Arc::new(PoolInner {
+                runtime,
+                config,
+                reset: pool_config.reset,
+                max_size: pool_config.max_size,
+                permits: Arc::new(Semaphore::new(pool_config.max_size)),
+                idle: Mutex::new(VecDeque::with_capacity(pool_config.max_size)),
+            }),
+        };
+
+        let mut warming = JoinSet::new();
+        for _ in 0..pool_config.warm_size {
+            let inner = pool.inner.clone();
+            warming.spawn(async move { inner.create().await });
+        }
+        let mut outcome = Ok(());
+        while let Some(created) = warming.join_next().await {
+            let created = match created {
+                Ok(created) => created,
+                Err(error) => Err(RuntimeError::Backend(error.to_string()).into()),
+            };
+            match created {
+                Ok(pooled) => pool.inner.idle.lock().map_err(|_| poisoned())?.push_back(pooled),
+                Err(error) if outcome.is_ok() => outcome = Err(error),
+                Err(_) => {}
+            }
+        }
+        // Environments warmed before a failure are destroyed with the pool
+        outcome.map(|_| pool)
+    }
+
+    /// Lease an environment, waiting for one to be returned if the pool is full
+    pub async fn lease(&self) -> Result<EnvironmentLease<R>> {
+        let permit = self.inner.permits.clone().acquire_owned().await.map_err(|_| shut_down())?;
+        self.lease_with(permit).await
+    }
+
+    /// Lease an environment if the pool is not full, without waiting
+    pub async fn try_lease(&self) -> Result<Option<EnvironmentLease<R>>> {
+        match self.inner.permits.clone().try_acquire_owned() {
+            Ok(permit) => self.lease_with(permit).await.map(Some),
+            Err(TryAcquireError::NoPermits) => Ok(None),
+            Err(TryAcquireError::Closed) => Err(shut_down().into()),
+        }
+    }
+
+    async fn lease_with(&self, permit: OwnedSemaphorePermit) -> Result<EnvironmentLease<R>> {
+        let idle = self.inner.idle.lock().map_err(|_| poisoned())?.pop_front();
+        let pooled = match idle {
+            Some(pooled) => pooled,
+            None => self.inner.create().await?,
+        };
+        Ok(EnvironmentLease {
+            pool: self.inner.clone(),
+            pooled: Some(pooled),
+            permit: Some(permit),
+        })
+    }
+}
+
+impl<R: BlockchainRuntime> EnvironmentPool<R> {
+    /// Get the pooled runtime
+    pub fn runtime(&self) -> &R {
+        &self.inner.runtime
+    }
+
+    pub fn reset_strategy(&self) -> ResetStrategy {
+        self.inner.reset
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.inner.max_size
+    }
+
+    /// Environments waiting to be leased
+    pub fn idle_count(&self) -> usize {
+        self.inner.idle.lock().map(|idle| idle.len()).unwrap_or(0)
+    }
+
+    /// Leases currently held, including those being reset
+    pub fn leased_count(&self) -> usize {
+        self.inner.max_size - self.inner.permits.available_permits()
+    }
+
+    /// Stop leasing and destroy the idle environments, returning the first failure
+    ///
+    /// Pending and future leases fail, and environments still leased are
+    /// destroyed when they are returned.
+    pub async fn shutdown(&self) -> Result<()> {
+        self.inner.permits.close();
+        let mut outcome = Ok(());
+        for environment in self.inner.take_idle() {
+            let destroyed = self.inner.runtime.destroy(environment).await;
+            if outcome.is_ok() {
+                outcome = destroyed;
+            }
+        }
+        outcome
+    }
+}
+
+/// Environment leased from an `EnvironmentPool`
+///
+/// Dropping the lease resets the environment in the background and returns
+/// it to the pool; `release` does the same and waits for it.
+pub struct EnvironmentLease<R: BlockchainRuntime + 'static> {
+    pool: Arc<PoolInner<R>>,
+    pooled: Option<PooledEnvironment>,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl<R: BlockchainRuntime + 'static> EnvironmentLease<R> {
+    pub fn environment(&self) -> &RuntimeEnvironment {
+        // Only taken when the lease is released or dropped
+        &self.pooled.as_ref().expect("lease holds an environment").environment
+    }
+
+    /// Get the runtime the environment belongs to
+    pub fn runtime(&self) -> &R {
+        &self.pool.runtime
+    }
+
+    /// Reset the environment and return it to the pool
+    pub async fn release(mut self) {
+        if let (Some(pooled), Some(permit)) = (self.pooled.take(), self.permit.take()) {
+            self.pool.give_back(pooled, permit).await;
+        }
+    }
+}
+
+impl<R: BlockchainRuntime + 'static> Deref for EnvironmentLease<R> {
+    type Target = RuntimeEnvironment;
+
+    fn deref(&self) -> &RuntimeEnvironment {
+        self.environment()
+    }
+}
+
+impl<R: BlockchainRuntime + 'static> Drop for EnvironmentLease<R> {
+    fn drop(&mut self) {
+        let (Some(pooled), Some(permit)) = (self.pooled.take(), self.permit.take()) else {
+            return;
+        };
+        let pool = self.pool.clone();
+        let give_back = async move { pool.give_back(pooled, permit).await };
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(give_back);
+            }
+            Err(_) => {
+                block_on_isolated(give_back);
+            }
+        }
+    }
+}
+
+fn poisoned() -> RuntimeError {
+    RuntimeError::Backend("Environment pool state is poisoned".to_string())
+}
+
+fn shut_down() -> RuntimeError {
+    RuntimeError::Backend("Environment pool is shut down".to_string())
+}
@@ -0,0 +1,488 @@
+//! Local node process backend
+//!
+//! Starts a node executable such as `anvil` or `hardhat node` for each
+//! environment on a free local port, waits until it answers JSON-RPC and then
+//! runs everything through `RpcRuntime`. The node leads its own process
+//! group, so `destroy` also stops anything it spawned.
+//!
+//! `blockchain_config` understands the following keys:
+//!
+//! - `node_binary`: executable to run instead of the runtime's
+//! - `node_args`: arguments to run it with, `{port}` being replaced by the allocated port
+//! - `startup_timeout_seconds`: time the node has to answer JSON-RPC
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::{Child, Command};
+use tokio::task::JoinHandle;
+
+use crate::config::RuntimeConfig;
+use crate::constants::{
+    DEFAULT_NODE_STARTUP_TIMEOUT_SECONDS, MAX_NODE_LOG_LINES, NODE_EXIT_POLL_INTERVAL_MS, NODE_READY_POLL_INTERVAL_MS,
+    NODE_STOP_TIMEOUT_SECONDS,
+};
+use crate::error::RuntimeError;
+use crate::info;
+use crate::report::SecurityReport;
+use crate::rpc::{JsonRpcClient, RpcRuntime};
+use crate::runtime::BlockchainRuntime;
+use crate::security::SecurityConfig;
+use crate::types::{
+    ExecutionInputs, ExecutionResult, GasEstimate, LogLine, LogStream, RuntimeCapabilities, RuntimeEnvironment,
+    RuntimeEvent, RuntimeMetricDefinition, RuntimeType, SecurityViolation,
+};
+
+/// Lines of output quoted when a node fails to start
+const FAILURE_LOG_LINES: usize = 20;
+
+/// Output of a managed node, keeping the most recent lines
+#[derive(Debug, Clone, Default)]
+pub(crate) struct NodeLogs {
+    lines: Arc<Mutex<VecDeque<LogLine>>>,
+}
+
+impl NodeLogs {
+    pub(crate) fn push(&self, stream: LogStream, line: String) {
+        if let Ok(mut lines) = self.lines.lock() {
+            if lines.len() == MAX_NODE_LOG_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(LogLine { stream, line });
+        }
+    }
+
+    pub(crate) fn lines(&self) -> Vec<LogLine> {
+        self.lines.lock().map(|lines| lines.iter().cloned().collect()).unwrap_or_default()
+    }
+
+    /// Last lines of output, for error messages
+    pub(crate) fn tail(&self) -> String {
+        let lines = self.lines();
+        let start = lines.len().saturating_sub(FAILURE_LOG_LINES);
+        lines[start..].iter().map(|line| line.line.as_str()).collect::<Vec<_>>().join("\n")
+    }
+
+    /// Record every line read from `reader` until it closes
+    pub(crate) fn capture<R>(&self, stream: LogStream, reader: R) -> JoinHandle<()>
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        let logs = self.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(reader).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                logs.push(stream, line);
+            }
+        })
+    }
+}
+
+/// Ask the operating system for a local port nothing listens on
+pub(crate) fn free_port() -> Result<u16> {
+    let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).context("Failed to allocate a port")?;
+    Ok(listener.local_addr()?.port())
+}
+
+/// Array of strings under a `blockchain_config` key
+pub(crate) fn string_list(config: &RuntimeConfig, key: &str) -> Result<Option<Vec<String>>> {
+    config
+        .blockchain_config
+        .get(key)
+        .map(|values| {
+            values
+                .as_array()
+                .and_then(|values| values.iter().map(|value| value.as_str().map(str::to_string)).collect())
+                .ok_or_else(|| {
+                    RuntimeError::invalid_config(format!("blockchain_config.{}", key), "must be an array of strings")
+                        .into()
+                })
+        })
+        .transpose()
+}
+
+/// Time a node has to become ready under `config`
+pub(crate) fn startup_timeout(config: &RuntimeConfig) -> Result<Duration> {
+    let seconds = match config.blockchain_config.get("startup_timeout_seconds") {
+        Some(seconds) => seconds.as_u64().ok_or_else(|| {
+            RuntimeError::invalid_config("blockchain_config.startup_timeout_seconds", "must be a number of seconds")
+        })?,
+        None => DEFAULT_NODE_STARTUP_TIMEOUT_SECONDS,
+    };
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Poll a node until it answers `eth_chainId`
+///
+/// `exited` is asked between probes whether the node has stopped, and
+/// returns how it did so the wait can fail early.
+pub(crate) async fn wait_for_node(
+    endpoint_url: &str,
+    timeout: Duration,
+    mut exited: impl FnMut() -> Option<String>,
+) -> Result<()> {
+    let client = JsonRpcClient::new(endpoint_url)?;
+    let deadline = Instant::now() + timeout;
+    loop {
+        if client.call("eth_chainId", serde_json::json!([])).await.is_ok() {
+            return Ok(());
+        }
+        if let Some(reason) = exited() {
+            return Err(RuntimeError::Backend(format!("Node {} before answering at {}", reason, endpoint_url)).into());
+        }
+        if Instant::now() >= deadline {
+            return Err(RuntimeError::Timeout(timeout).into());
+        }
+        tokio::time::sleep(Duration::from_millis(NODE_READY_POLL_INTERVAL_MS)).await;
+    }
+}
+
+/// Node process started for one environment
+struct NodeProcess {
+    child: Child,
+    logs: NodeLogs,
+    capture: Vec<JoinHandle<()>>,
+}
+
+impl NodeProcess {
+    /// Kill the node's process group and wait for the node to exit
+    ///
+    /// The exit is polled rather than awaited, and output capture aborted, as
+    /// both rely on the runtime that started the node, which may be the one
+    /// blocked dropping it.
+    async fn stop(mut self) -> Result<()> {
+        self.kill();
+        for capture in self.capture.drain(..) {
+            capture.abort();
+        }
+        let timeout = Duration::from_secs(NODE_STOP_TIMEOUT_SECONDS);
+        let deadline = Instant::now() + timeout;
+        while self.child.try_wait().context("Failed to wait for node process")?.is_none() {
+            if Instant::now() >= deadline {
+                return Err(RuntimeError::Timeout(timeout).into());
+            }
+            tokio::time::sleep(Duration::from_millis(NODE_EXIT_POLL_INTERVAL_MS)).await;
+        }
+        Ok(())
+    }
+
+    /// Kill the node and wait until everything it wrote is in its logs
+    ///
+    /// Only for the runtime that started the node, which runs the capture.
+    async fn stop_with_output(mut self) -> Result<()> {
+        self.kill();
+        for capture in self.capture.drain(..) {
+            let _ = capture.await;
+        }
+        self.stop().await
+    }
+
+    fn kill(&mut self) {
+        #[cfg(unix)]
+        if let Some(pid) = self.child.id() {
+            // The node leads its own group, whose id is its pid
+            unsafe {
+                libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = self.child.start_kill();
+    }
+}
+
+impl Drop for NodeProcess {
+    fn drop(&mut self) {
+        // Nodes still running when their runtime goes away are not left behind
+        if matches!(self.child.try_wait(), Ok(None)) {
+            self.kill();
+        }
+    }
+}
+
+/// Blockchain runtime running a local node process per environment
+pub struct LocalProcessRuntime {
+    rpc: RpcRuntime,
+    binary: PathBuf,
+    args: Vec<String>,
+    processes: Mutex<HashMap<String, NodeProcess>>,
+}
+
+impl LocalProcessRuntime {
+    /// Create a runtime starting `binary` with `--port {port}`
+    pub fn new(blockchain_id: String, binary: impl Into<PathBuf>) -> Self {
+        Self {
+            rpc: RpcRuntime::new(blockchain_id),
+            binary: binary.into(),
+            args: vec!["--port".to_string(), "{port}".to_string()],
+            processes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Start the node with other arguments, `{port}` being replaced by the allocated port
+    pub fn with_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Output the environment's node has written so far
+    pub fn logs(&self, env: &RuntimeEnvironment) -> Result<Vec<LogLine>> {
+        let processes = self.processes.lock().map_err(|_| poisoned())?;
+        processes
+            .get(&env.environment_id)
+            .map(|process| process.logs.lines())
+            .ok_or_else(|| RuntimeError::EnvironmentNotFound(env.environment_id.clone()).into())
+    }
+
+    /// JSON-RPC runtime driving the nodes, for access to their clients
+    pub fn rpc(&self) -> &RpcRuntime {
+        &self.rpc
+    }
+
+    fn command(&self, config: &RuntimeConfig, port: u16) -> Result<(PathBuf, Vec<String>)> {
+        let binary = match config.blockchain_config.get("node_binary") {
+            Some(binary) => PathBuf::from(
+                binary
+                    .as_str()
+                    .ok_or_else(|| RuntimeError::invalid_config("blockchain_config.node_binary", "must be a path"))?,
+            ),
+            None => self.binary.clone(),
+        };
+        let args = string_list(config, "node_args")?.unwrap_or_else(|| self.args.clone());
+        let port = port.to_string();
+        Ok((binary, args.iter().map(|arg| arg.replace("{port}", &port)).collect()))
+    }
+}
+
+#[async_trait]
+impl BlockchainRuntime for LocalProcessRuntime {
+    fn blockchain_id(&self) -> &str {
+        self.rpc.blockchain_id()
+    }
+
+    async fn create_environment(&self, config: RuntimeConfig) -> Result<RuntimeEnvironment> {
+        config.validate()?;
+        let timeout = startup_timeout(&config)?;
+        let port = free_port()?;
+        let (binary, args) = self.command(&config, port)?;
+        info!("Starting {} on port {}", binary.display(), port);
+
+        let mut command = Command::new(&binary);
+        command
+            .args(&args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        #[cfg(unix)]
+        command.process_group(0);
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("Failed to start {}", binary.display()))?;
+
+        let logs = NodeLogs::default();
+        let mut capture = Vec::new();
+        if let Some(stdout) = child.stdout.take() {
+            capture.push(logs.capture(LogStream::Stdout, stdout));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            capture.push(logs.capture(LogStream::Stderr, stderr));
+        }
+        let pid = child.id();
+        let mut process = NodeProcess { child, logs, capture };
+
+        let endpoint_url = format!("http://127.0.0.1:{}", port);
+        let ready = wait_for_node(&endpoint_url, timeout, || {
+            match process.child.try_wait() {
+                Ok(Some(status)) => Some(format!("exited with {}", status)),
+                _ => None,
+            }
+        })
+        .await;
+        let attached = match ready {
+            Ok(()) => self.rpc.attach(&endpoint_url, config, RuntimeType::LocalProcess).await,
+            Err(error) => Err(error),
+        };
+        let mut env = match attached {
+            Ok(env) => env,
+            Err(error) => {
+                let logs = process.logs.clone();
+                process.stop_with_output().await?;
+                return Err(error.context(format!(
+                    "{} failed to start, last output:\n{}",
+                    binary.display(),
+                    logs.tail()
+                )));
+            }
+        };
+
+        let command: Vec<String> = std::iter::once(binary.display().to_string()).chain(args).collect();
+        env.metadata.insert("pid".to_string(), serde_json::json!(pid));
+        env.metadata.insert("port".to_string(), serde_json::json!(port));
+        env.metadata.insert("command".to_string(), serde_json::json!(command));
+        self.processes
+            .lock()
+            .map_err(|_| poisoned())?
+            .insert(env.environment_id.clone(), process);
+        Ok(env)
+    }
+
+    async fn execute(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+    ) -> Result<ExecutionResult> {
+        self.rpc.execute(env, code_path, inputs).await
+    }
+
+    async fn estimate_gas(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+    ) -> Result<GasEstimate> {
+        self.rpc.estimate_gas(env, code_path, inputs).await
+    }
+
+    async fn deploy_contract(
+        &self,
+        env: &RuntimeEnvironment,
+        bytecode: &[u8],
+        constructor_args: &[u8],
+    ) -> Result<String> {
+        self.rpc.deploy_contract(env, bytecode, constructor_args).await
+    }
+
+    async fn call_function(
+        &self,
+        env: &RuntimeEnvironment,
+        contract_address: &str,
+        function: &str,
+        args: &[u8],
+    ) -> Result<Vec<u8>> {
+        self.rpc.call_function(env, contract_address, function, args).await
+    }
+
+    fn metrics_definition(&self) -> Vec<RuntimeMetricDefinition> {
+        self.rpc.metrics_definition()
+    }
+
+    async fn monitor(
+        &self,
+        env: &RuntimeEnvironment,
+        execution_id: &str,
+    ) -> Result<Vec<RuntimeEvent>> {
+        self.rpc.monitor(env, execution_id).await
+    }
+
+    async fn destroy(&self, env: RuntimeEnvironment) -> Result<()> {
+        info!("Stopping node of environment {}", env.environment_id);
+        let process = self.processes.lock().map_err(|_| poisoned())?.remove(&env.environment_id);
+        self.rpc.destroy(env).await?;
+        match process {
+            Some(process) => process.stop().await,
+            None => Ok(()),
+        }
+    }
+
+    async fn is_available(&self) -> bool {
+        resolve_binary(&self.binary).is_some()
+    }
+
+    fn capabilities(&self) -> RuntimeCapabilities {
+        self.rpc.capabilities()
+    }
+
+    async fn grant_role(&self, env: &RuntimeEnvironment, account: &str, role: &str) -> Result<()> {
+        self.rpc.grant_role(env, account, role).await
+    }
+
+    async fn revoke_role(&self, env: &RuntimeEnvironment, account: &str, role: &str) -> Result<bool> {
+        self.rpc.revoke_role(env, account, role).await
+    }
+
+    async fn execute_secure(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+        security_config: &SecurityConfig,
+    ) -> Result<ExecutionResult> {
+        self.rpc.execute_secure(env, code_path, inputs, security_config).await
+    }
+
+    async fn check_reentrancy(
+        &self,
+        env: &RuntimeEnvironment,
+        function_name: &str,
+        caller: &str,
+        call_stack: &[String],
+    ) -> Result<bool> {
+        self.rpc.check_reentrancy(env, function_name, caller, call_stack).await
+    }
+
+    async fn detect_overflow(
+        &self,
+        env: &RuntimeEnvironment,
+        operation: &str,
+        operands: &[i64],
+    ) -> Result<bool> {
+        self.rpc.detect_overflow(env, operation, operands).await
+    }
+
+    async fn verify_access_control(
+        &self,
+        env: &RuntimeEnvironment,
+        function_name: &str,
+        caller: &str,
+        required_role: Option<&str>,
+    ) -> Result<bool> {
+        self.rpc
+            .verify_access_control(env, function_name, caller, required_role)
+            .await
+    }
+
+    async fn enforce_resource_limits(
+        &self,
+        env: &RuntimeEnvironment,
+        gas_used: u64,
+        memory_used: u64,
+        call_depth: u32,
+        external_calls: u32,
+        security_config: &SecurityConfig,
+    ) -> Result<Vec<SecurityViolation>> {
+        self.rpc
+            .enforce_resource_limits(env, gas_used, memory_used, call_depth, external_calls, security_config)
+            .await
+    }
+
+    async fn get_security_report(
+        &self,
+        env: &RuntimeEnvironment,
+        execution_id: &str,
+    ) -> Result<SecurityReport> {
+        self.rpc.get_security_report(env, execution_id).await
+    }
+}
+
+/// Path of an executable, looked up on `PATH` when given by name only
+fn resolve_binary(binary: &Path) -> Option<PathBuf> {
+    if binary.components().count() > 1 {
+        return binary.is_file().then(|| binary.to_path_buf());
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(binary))
+        .find(|candidate| candidate.is_file())
+}
+
+fn poisoned() -> RuntimeError {
+    RuntimeError::Backend("Local process runtime state is poisoned".to_string())
+}
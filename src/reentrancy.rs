@@ -0,0 +1,200 @@
+//! Call frame model for cross-function, cross-contract and read-only reentrancy detection
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Call frame observed during execution
+///
+/// Steps are kept in execution order so reads and writes can be placed
+/// before or after the external calls the frame makes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct CallFrame {
+    /// Contract whose state the frame reads and writes
+    pub contract: String,
+    pub function: String,
+    pub caller: String,
+    /// Entered through a static call, which cannot write state
+    #[serde(default)]
+    pub is_static: bool,
+    /// Entered through a delegate call or call code, running other code on the caller's state
+    #[serde(default)]
+    pub is_delegate: bool,
+    #[serde(default)]
+    pub steps: Vec<FrameStep>,
+}
+
+/// State access or sub-call made by a frame
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameStep {
+    Read(String),
+    Write(String),
+    Call(CallFrame),
+}
+
+/// How a contract was re-entered
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReentrancyKind {
+    /// The function with the pending external call was entered again
+    SameFunction,
+    /// Another function of the same contract was entered
+    CrossFunction,
+    /// A frame that writes nothing read state the pending frame has yet to write
+    ReadOnly,
+}
+
+impl std::fmt::Display for ReentrancyKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ReentrancyKind::SameFunction => "Reentrancy",
+            ReentrancyKind::CrossFunction => "Cross-function reentrancy",
+            ReentrancyKind::ReadOnly => "Read-only reentrancy",
+        })
+    }
+}
+
+/// Re-entry into a contract while one of its frames holds stale state
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReentrancyFinding {
+    pub kind: ReentrancyKind,
+    pub contract: String,
+    /// Function whose external call allowed the re-entry
+    pub function: String,
+    pub reentered_function: String,
+    /// Caller of the re-entered frame
+    pub caller: String,
+    /// State read before the external call and written after it returned
+    pub stale_state: Vec<String>,
+    /// Frames from the interrupted one to the re-entered one, as `contract::function`
+    pub call_path: Vec<String>,
+}
+
+impl CallFrame {
+    /// Create a frame without any steps
+    pub fn new(contract: impl Into<String>, function: impl Into<String>, caller: impl Into<String>) -> Self {
+        Self {
+            contract: contract.into(),
+            function: function.into(),
+            caller: caller.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Mark the frame as entered through a static call
+    pub fn static_call(mut self) -> Self {
+        self.is_static = true;
+        self
+    }
+
+    /// Mark the frame as entered through a delegate call, which is not a re-entry
+    pub fn delegate_call(mut self) -> Self {
+        self.is_delegate = true;
+        self
+    }
+
+    /// Record a read of a state key
+    pub fn read(mut self, key: impl Into<String>) -> Self {
+        self.steps.push(FrameStep::Read(key.into()));
+        self
+    }
+
+    /// Record a write of a state key
+    pub fn write(mut self, key: impl Into<String>) -> Self {
+        self.steps.push(FrameStep::Write(key.into()));
+        self
+    }
+
+    /// Record a sub-call
+    pub fn call(mut self, frame: CallFrame) -> Self {
+        self.steps.push(FrameStep::Call(frame));
+        self
+    }
+
+    /// Find re-entries into a contract whose state was read before an external call and written after it
+    ///
+    /// Only a contract entered again through a different contract counts, so
+    /// calls a contract makes to itself and delegate calls are not reported. A
+    /// re-entered frame that writes nothing is only reported when it reads
+    /// some of that stale state.
+    pub fn reentrancies(&self) -> Vec<ReentrancyFinding> {
+        let mut findings = Vec::new();
+        self.collect_reentrancies(&mut Vec::new(), &mut findings);
+        findings
+    }
+
+    fn collect_reentrancies<'a>(&'a self, pending: &mut Vec<(&'a CallFrame, usize)>, findings: &mut Vec<ReentrancyFinding>) {
+        for (index, step) in self.steps.iter().enumerate() {
+            let FrameStep::Call(callee) = step else {
+                continue;
+            };
+            pending.push((self, index));
+
+            for (position, (outer, call_index)) in pending.iter().enumerate() {
+                if callee.is_delegate || outer.contract != callee.contract {
+                    continue;
+                }
+                let through_other_contract = pending[position + 1..]
+                    .iter()
+                    .any(|(frame, _)| frame.contract != callee.contract);
+                if !through_other_contract {
+                    continue;
+                }
+                let written_after = state_keys(&outer.steps[call_index + 1..], false);
+                let stale_state: Vec<String> = state_keys(&outer.steps[..*call_index], true)
+                    .intersection(&written_after)
+                    .map(|key| key.to_string())
+                    .collect();
+                if stale_state.is_empty() {
+                    continue;
+                }
+
+                let read_only = callee.is_static || state_keys(&callee.steps, false).is_empty();
+                let kind = if read_only {
+                    let reads = state_keys(&callee.steps, true);
+                    if !stale_state.iter().any(|key| reads.contains(key.as_str())) {
+                        continue;
+                    }
+                    ReentrancyKind::ReadOnly
+                } else if callee.function == outer.function {
+                    ReentrancyKind::SameFunction
+                } else {
+                    ReentrancyKind::CrossFunction
+                };
+
+                findings.push(ReentrancyFinding {
+                    kind,
+                    contract: callee.contract.clone(),
+                    function: outer.function.clone(),
+                    reentered_function: callee.function.clone(),
+                    caller: callee.caller.clone(),
+                    stale_state,
+                    call_path: pending[position..]
+                        .iter()
+                        .map(|(frame, _)| frame.label())
+                        .chain(std::iter::once(callee.label()))
+                        .collect(),
+                });
+            }
+
+            callee.collect_reentrancies(pending, findings);
+            pending.pop();
+        }
+    }
+
+    fn label(&self) -> String {
+        format!("{}::{}", self.contract, self.function)
+    }
+}
+
+/// Keys read, or written, by the steps themselves rather than their sub-calls
+fn state_keys(steps: &[FrameStep], reads: bool) -> BTreeSet<&str> {
+    steps
+        .iter()
+        .filter_map(|step| match step {
+            FrameStep::Read(key) if reads => Some(key.as_str()),
+            FrameStep::Write(key) if !reads => Some(key.as_str()),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,83 @@
+//! Registry of runtimes for dispatching work across blockchains
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::runtime::BlockchainRuntime;
+use crate::types::{Capability, RuntimeCapabilities};
+
+/// Runtimes keyed by their `blockchain_id()`
+///
+/// Iteration and selection follow blockchain id order, so picking a runtime
+/// by capability is deterministic.
+#[derive(Clone, Default)]
+pub struct RuntimeRegistry {
+    runtimes: BTreeMap<String, Arc<dyn BlockchainRuntime>>,
+}
+
+impl RuntimeRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a runtime, returning the one it replaces for the same blockchain
+    pub fn register(&mut self, runtime: Arc<dyn BlockchainRuntime>) -> Option<Arc<dyn BlockchainRuntime>> {
+        self.runtimes.insert(runtime.blockchain_id().to_string(), runtime)
+    }
+
+    /// Remove the runtime for a blockchain
+    pub fn unregister(&mut self, blockchain_id: &str) -> Option<Arc<dyn BlockchainRuntime>> {
+        self.runtimes.remove(blockchain_id)
+    }
+
+    /// Get the runtime for a blockchain
+    pub fn get(&self, blockchain_id: &str) -> Option<Arc<dyn BlockchainRuntime>> {
+        self.runtimes.get(blockchain_id).cloned()
+    }
+
+    /// Check whether a blockchain has a runtime
+    pub fn contains(&self, blockchain_id: &str) -> bool {
+        self.runtimes.contains_key(blockchain_id)
+    }
+
+    /// Blockchain ids of all registered runtimes
+    pub fn blockchain_ids(&self) -> Vec<&str> {
+        self.runtimes.keys().map(String::as_str).collect()
+    }
+
+    /// Number of registered runtimes
+    pub fn len(&self) -> usize {
+        self.runtimes.len()
+    }
+
+    /// Check whether no runtime is registered
+    pub fn is_empty(&self) -> bool {
+        self.runtimes.is_empty()
+    }
+
+    /// Capabilities of every registered runtime
+    pub fn capabilities(&self) -> BTreeMap<String, RuntimeCapabilities> {
+        self.runtimes
+            .iter()
+            .map(|(id, runtime)| (id.clone(), runtime.capabilities()))
+            .collect()
+    }
+
+    /// All runtimes supporting every required capability
+    pub fn find_all(&self, required: &[Capability]) -> Vec<Arc<dyn BlockchainRuntime>> {
+        self.runtimes
+            .values()
+            .filter(|runtime| runtime.capabilities().supports_all(required))
+            .cloned()
+            .collect()
+    }
+
+    /// First runtime supporting every required capability
+    pub fn find(&self, required: &[Capability]) -> Option<Arc<dyn BlockchainRuntime>> {
+        self.runtimes
+            .values()
+            .find(|runtime| runtime.capabilities().supports_all(required))
+            .cloned()
+    }
+}
@@ -0,0 +1,273 @@
+//! Structured security report for an execution
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::determinism::{Clock, SystemClock};
+use crate::security::SecurityConfig;
+use crate::types::{
+    AccessControlCheck, ExecutionResult, SecurityViolation, SecurityViolationType, SecuritySeverity,
+};
+
+/// Security findings for one execution, judged against a `SecurityConfig`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecurityReport {
+    pub summary: ExecutionSummary,
+    pub verdict: SecurityVerdict,
+    /// Every violation, in the order it was raised
+    pub violations: Vec<SecurityViolation>,
+    /// Violation counts per type, in order of first occurrence
+    pub by_type: Vec<ViolationTypeSummary>,
+    pub by_severity: SeverityCounts,
+    pub access_control: Vec<AccessControlCheck>,
+    pub resources: ResourceUsage,
+    pub generated_at: u64,
+}
+
+/// What the execution did, independent of its findings
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ExecutionSummary {
+    pub execution_id: String,
+    pub success: bool,
+    pub error: Option<String>,
+    pub execution_time_ms: u64,
+    pub state_changes: usize,
+    pub events: usize,
+}
+
+/// Violations of one type
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ViolationTypeSummary {
+    pub violation_type: SecurityViolationType,
+    pub count: usize,
+    pub highest_severity: SecuritySeverity,
+}
+
+/// Number of violations of each severity
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SeverityCounts {
+    pub critical: usize,
+    pub high: usize,
+    pub medium: usize,
+    pub low: usize,
+}
+
+impl SeverityCounts {
+    pub fn total(&self) -> usize {
+        self.critical + self.high + self.medium + self.low
+    }
+
+    /// Most severe level with at least one violation
+    pub fn highest(&self) -> Option<SecuritySeverity> {
+        [
+            (self.critical, SecuritySeverity::Critical),
+            (self.high, SecuritySeverity::High),
+            (self.medium, SecuritySeverity::Medium),
+            (self.low, SecuritySeverity::Low),
+        ]
+        .into_iter()
+        .find(|(count, _)| *count > 0)
+        .map(|(_, severity)| severity)
+    }
+
+    fn add(&mut self, severity: &SecuritySeverity) {
+        match severity {
+            SecuritySeverity::Critical => self.critical += 1,
+            SecuritySeverity::High => self.high += 1,
+            SecuritySeverity::Medium => self.medium += 1,
+            SecuritySeverity::Low => self.low += 1,
+        }
+    }
+}
+
+/// High-water marks of the execution next to the configured limits
+///
+/// A limit is `None` when the configuration does not enforce it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ResourceUsage {
+    pub call_depth: u32,
+    pub max_call_depth: Option<u32>,
+    pub external_calls: u32,
+    pub max_external_calls: Option<u32>,
+    pub gas_used: u64,
+    pub max_gas_limit: Option<u64>,
+    pub memory_used: u64,
+    pub max_memory_bytes: Option<u64>,
+}
+
+impl ResourceUsage {
+    /// Descriptions of every limit the usage exceeds
+    pub fn exceeded(&self) -> Vec<String> {
+        let mut exceeded = Vec::new();
+        let mut check = |name: &str, used: u64, limit: Option<u64>| {
+            if let Some(limit) = limit.filter(|limit| used > *limit) {
+                exceeded.push(format!("{} {} exceeds {}", name, used, limit));
+            }
+        };
+        check("call depth", self.call_depth.into(), self.max_call_depth.map(u64::from));
+        check("external calls", self.external_calls.into(), self.max_external_calls.map(u64::from));
+        check("gas used", self.gas_used, self.max_gas_limit);
+        check("memory used", self.memory_used, self.max_memory_bytes);
+        exceeded
+    }
+}
+
+/// Whether the execution meets the security configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SecurityVerdict {
+    pub passed: bool,
+    /// Why the execution failed, empty when it passed
+    pub reasons: Vec<String>,
+}
+
+impl SecurityReport {
+    /// Build the report for an execution result
+    ///
+    /// The execution fails the verdict when it raised any violation or its
+    /// resource usage exceeds a limit the configuration enforces.
+    pub fn new(result: &ExecutionResult, config: &SecurityConfig) -> Self {
+        Self::with_clock(result, config, &SystemClock)
+    }
+
+    /// Build the report, taking `generated_at` from a custom clock
+    pub fn with_clock(result: &ExecutionResult, config: &SecurityConfig, clock: &dyn Clock) -> Self {
+        let context = &result.security_context;
+
+        let mut by_type: Vec<ViolationTypeSummary> = Vec::new();
+        let mut by_severity = SeverityCounts::default();
+        for violation in &result.security_violations {
+            by_severity.add(&violation.severity);
+            match by_type.iter_mut().find(|group| group.violation_type == violation.violation_type) {
+                Some(group) => {
+                    group.count += 1;
+                    if violation.severity > group.highest_severity {
+                        group.highest_severity = violation.severity.clone();
+                    }
+                }
+                None => by_type.push(ViolationTypeSummary {
+                    violation_type: violation.violation_type.clone(),
+                    count: 1,
+                    highest_severity: violation.severity.clone(),
+                }),
+            }
+        }
+
+        let resources = ResourceUsage {
+            call_depth: context.call_depth,
+            max_call_depth: Some(config.max_call_depth),
+            external_calls: context.external_call_count,
+            max_external_calls: Some(config.max_external_calls),
+            gas_used: context.gas_used,
+            max_gas_limit: config.gas_limit_enforcement.then_some(config.max_gas_limit),
+            memory_used: context.memory_used,
+            max_memory_bytes: config.memory_limit_enforcement.then_some(config.max_memory_bytes),
+        };
+
+        let mut reasons = resources.exceeded();
+        reasons.extend(
+            by_type
+                .iter()
+                .map(|group| format!("{} {:?} violation(s)", group.count, group.violation_type)),
+        );
+
+        Self {
+            summary: ExecutionSummary {
+                execution_id: result.execution_id.clone(),
+                success: result.success,
+                error: result.error.clone(),
+                execution_time_ms: result.execution_time_ms,
+                state_changes: result.state_changes.len(),
+                events: result.events.len(),
+            },
+            verdict: SecurityVerdict {
+                passed: reasons.is_empty(),
+                reasons,
+            },
+            violations: result.security_violations.clone(),
+            by_type,
+            by_severity,
+            access_control: context.access_control_checks.clone(),
+            resources,
+            generated_at: clock.now_secs(),
+        }
+    }
+
+    /// Serialize the report as pretty-printed JSON
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+/// Human-readable form of the report
+impl fmt::Display for SecurityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let summary = &self.summary;
+        writeln!(f, "Security report for execution {}", summary.execution_id)?;
+        writeln!(f, "Verdict: {}", if self.verdict.passed { "PASS" } else { "FAIL" })?;
+        for reason in &self.verdict.reasons {
+            writeln!(f, "  - {}", reason)?;
+        }
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "Execution: {} in {} ms, {} state change(s), {} event(s)",
+            if summary.success { "succeeded" } else { "failed" },
+            summary.execution_time_ms,
+            summary.state_changes,
+            summary.events
+        )?;
+        if let Some(error) = &summary.error {
+            writeln!(f, "Error: {}", error)?;
+        }
+
+        let resources = &self.resources;
+        let limit = |limit: Option<u64>| limit.map_or("unlimited".to_string(), |limit| limit.to_string());
+        writeln!(f)?;
+        writeln!(f, "Resources:")?;
+        writeln!(f, "  call depth:     {} / {}", resources.call_depth, limit(resources.max_call_depth.map(u64::from)))?;
+        writeln!(
+            f,
+            "  external calls: {} / {}",
+            resources.external_calls,
+            limit(resources.max_external_calls.map(u64::from))
+        )?;
+        writeln!(f, "  gas:            {} / {}", resources.gas_used, limit(resources.max_gas_limit))?;
+        writeln!(f, "  memory:         {} / {} bytes", resources.memory_used, limit(resources.max_memory_bytes))?;
+
+        let counts = &self.by_severity;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "Violations: {} (critical {}, high {}, medium {}, low {})",
+            counts.total(),
+            counts.critical,
+            counts.high,
+            counts.medium,
+            counts.low
+        )?;
+        for group in &self.by_type {
+            writeln!(f, "  {:?}: {} (highest {:?})", group.violation_type, group.count, group.highest_severity)?;
+        }
+        for violation in &self.violations {
+            writeln!(f, "  [{:?}] {}", violation.severity, violation.description)?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Access control checks: {}", self.access_control.len())?;
+        for check in &self.access_control {
+            writeln!(
+                f,
+                "  {} {} -> {}{}",
+                if check.has_permission { "ALLOW" } else { "DENY " },
+                check.caller,
+                check.function_name,
+                check
+                    .required_role
+                    .as_ref()
+                    .map_or(String::new(), |role| format!(" (requires {})", role))
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+//! JSON-RPC 2.0 client over HTTP
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::http::{self, HttpUrl};
+
+/// Error object returned by a JSON-RPC server
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, thiserror::Error)]
+#[error("JSON-RPC error {code}: {message}")]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+impl JsonRpcError {
+    /// Code servers return for methods they do not implement
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+}
+
+/// Client for one JSON-RPC endpoint
+///
+/// Failed calls carry a `JsonRpcError` when the server answered with an
+/// error object, so callers can tell those apart from transport failures.
+#[derive(Debug)]
+pub struct JsonRpcClient {
+    endpoint_url: String,
+    url: HttpUrl,
+    next_id: AtomicU64,
+}
+
+impl JsonRpcClient {
+    /// Create a client for an `http://` endpoint
+    pub fn new(endpoint_url: &str) -> Result<Self> {
+        Ok(Self {
+            endpoint_url: endpoint_url.to_string(),
+            url: HttpUrl::parse(endpoint_url)?,
+            next_id: AtomicU64::new(1),
+        })
+    }
+
+    pub fn endpoint_url(&self) -> &str {
+        &self.endpoint_url
+    }
+
+    /// Call a method and return its result
+    pub async fn call(&self, method: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+        let response = http::send_tcp(
+            &self.url,
+            "POST",
+            &[("Content-Type", "application/json")],
+            &serde_json::to_vec(&request)?,
+        )
+        .await
+        .with_context(|| format!("{} request to {} failed", method, self.endpoint_url))?;
+
+        let mut body: serde_json::Value = serde_json::from_slice(&response.body).with_context(|| {
+            format!("{} returned HTTP {} without a JSON-RPC response", method, response.status)
+        })?;
+        if let Some(error) = body.get("error").filter(|error| !error.is_null()) {
+            let error: JsonRpcError = serde_json::from_value(error.clone())
+                .map_err(|_| anyhow!("{} returned a malformed error: {}", method, error))?;
+            return Err(anyhow::Error::new(error).context(format!("{} failed", method)));
+        }
+        if !response.is_success() {
+            return Err(anyhow!("{} returned HTTP {}", method, response.status));
+        }
+        body.get_mut("result")
+            .map(serde_json::Value::take)
+            .ok_or_else(|| anyhow!("{} returned neither a result nor an error", method))
+    }
+}
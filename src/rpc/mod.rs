@@ -0,0 +1,807 @@
+//! Ethereum JSON-RPC backend
+//!
+//! Talks standard Ethereum JSON-RPC to the node at an environment's
+//! `endpoint_url`, which `create_environment` takes from
+//! `blockchain_config.rpc_url`. The runtime does not manage the node, so
+//! destroying an environment only forgets it.
+//!
+//! `execute` sends a transaction and waits for its receipt. Without a
+//! `raw_transaction` parameter the transaction is sent with
+//! `eth_sendTransaction` from the execution sender, or the node's first
+//! account, which dev nodes keep unlocked; the creation bytecode at
+//! `code_path` is deployed the same way on first use. Events come from
+//! `eth_getLogs` and state changes from `debug_traceTransaction` with the
+//! prestate tracer, and are left empty on nodes that do not serve it.
+//!
+//! `ExecutionInputs::parameters` understands the keys of the EVM backend
+//! (`calldata`, `args`, `value`, `gas_limit`) and also:
+//!
+//! - `to`: address of an already deployed contract to call instead of `code_path`
+//! - `raw_transaction`: hex encoded signed transaction sent as is
+
+mod client;
+
+pub use client::{JsonRpcClient, JsonRpcError};
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use primitive_types::U256;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::access_control::AccessControlPolicy;
+use crate::arithmetic::IntegerType;
+use crate::config::RuntimeConfig;
+use crate::constants::{DEFAULT_RPC_ENDPOINT_URL, DEFAULT_SENDER_ADDRESS, RPC_RECEIPT_POLL_INTERVAL_MS};
+use crate::determinism::Entropy;
+use crate::enforcement::SecurityEnforcer;
+use crate::error::RuntimeError;
+use crate::evm::{encode_call, format_address, format_word, function_selector, load_bytecode, parse_address, parse_u256};
+use crate::info;
+use crate::limits::ResourceLimits;
+use crate::report::SecurityReport;
+use crate::runtime::BlockchainRuntime;
+use crate::security::{SecurityConfig, SecurityValidator};
+use crate::types::{
+    EnvironmentState, ExecutionInputs, ExecutionResult, GasEstimate, MetricType, RuntimeCapabilities,
+    RuntimeEnvironment, RuntimeEvent, RuntimeMetricDefinition, RuntimeType, SecurityViolation, StateChange,
+    StateChangeType,
+};
+
+/// Environment bookkeeping for the JSON-RPC backend
+struct RpcEnvironment {
+    config: RuntimeConfig,
+    client: Arc<JsonRpcClient>,
+    /// First account of the node, used when an execution names no sender
+    default_sender: Option<String>,
+    deployments: HashMap<PathBuf, String>,
+    executions: HashMap<String, ExecutionResult>,
+    execution_nonce: u64,
+    access_control: AccessControlPolicy,
+    entropy: Entropy,
+}
+
+/// Blockchain runtime backed by an Ethereum node reached over JSON-RPC
+pub struct RpcRuntime {
+    blockchain_id: String,
+    endpoint_url: String,
+    capabilities: RuntimeCapabilities,
+    environments: RwLock<HashMap<String, RpcEnvironment>>,
+    environment_nonce: AtomicU64,
+}
+
+impl RpcRuntime {
+    /// Create a runtime for nodes at the default endpoint
+    pub fn new(blockchain_id: String) -> Self {
+        Self {
+            blockchain_id,
+            endpoint_url: DEFAULT_RPC_ENDPOINT_URL.to_string(),
+            capabilities: RuntimeCapabilities {
+                supports_gas_estimation: true,
+                ..RuntimeCapabilities::default()
+            },
+            environments: RwLock::new(HashMap::new()),
+            environment_nonce: AtomicU64::new(0),
+        }
+    }
+
+    /// Use an endpoint for configurations that do not set `blockchain_config.rpc_url`
+    pub fn with_endpoint(mut self, endpoint_url: impl Into<String>) -> Self {
+        self.endpoint_url = endpoint_url.into();
+        self
+    }
+
+    /// Create an environment for the node at an endpoint
+    ///
+    /// Backends that start their own node attach to it with this, passing the
+    /// runtime type they manage it as.
+    pub async fn attach(
+        &self,
+        endpoint_url: &str,
+        config: RuntimeConfig,
+        runtime_type: RuntimeType,
+    ) -> Result<RuntimeEnvironment> {
+        config.validate()?;
+        let client = JsonRpcClient::new(endpoint_url)?;
+
+        let chain_id = quantity(&client.call("eth_chainId", serde_json::json!([])).await?)?;
+        if let Some(expected) = config.blockchain_config.get("chain_id").and_then(|v| v.as_u64()) {
+            if expected != chain_id {
+                return Err(RuntimeError::invalid_config(
+                    "blockchain_config.chain_id",
+                    format!("node at {} serves chain {}, not {}", endpoint_url, chain_id, expected),
+                )
+                .into());
+            }
+        }
+        // Nodes without unlocked accounts may not serve eth_accounts at all
+        let default_sender = client
+            .call("eth_accounts", serde_json::json!([]))
+            .await
+            .ok()
+            .and_then(|accounts| accounts.get(0).and_then(|a| a.as_str()).map(str::to_string));
+
+        let nonce = self.environment_nonce.fetch_add(1, Ordering::SeqCst) + 1;
+        let environment_id = format!("rpc_{}", nonce);
+        info!("Attaching RPC environment {} to {}", environment_id, endpoint_url);
+
+        let mut metadata = HashMap::from([("chain_id".to_string(), serde_json::json!(chain_id))]);
+        if let Some(sender) = &default_sender {
+            metadata.insert("default_sender".to_string(), serde_json::json!(sender));
+        }
+        let entropy = Entropy::from_config(&config);
+        self.environments
+            .write()
+            .map_err(|_| poisoned())?
+            .insert(
+                environment_id.clone(),
+                RpcEnvironment {
+                    access_control: config.security_config.access_control.clone(),
+                    config,
+                    client: Arc::new(client),
+                    default_sender,
+                    deployments: HashMap::new(),
+                    executions: HashMap::new(),
+                    execution_nonce: 0,
+                    entropy,
+                },
+            );
+
+        Ok(RuntimeEnvironment {
+            environment_id,
+            blockchain_id: self.blockchain_id.clone(),
+            runtime_type,
+            endpoint_url: endpoint_url.to_string(),
+            state: EnvironmentState::Ready,
+            metadata,
+        })
+    }
+
+    /// Client for the node of an environment, for methods the runtime does not wrap
+    pub fn client(&self, env: &RuntimeEnvironment) -> Result<Arc<JsonRpcClient>> {
+        self.with_environment(env, |environment| Ok(environment.client.clone()))
+    }
+
+    fn with_environment<T>(
+        &self,
+        env: &RuntimeEnvironment,
+        f: impl FnOnce(&RpcEnvironment) -> Result<T>,
+    ) -> Result<T> {
+        let environments = self.environments.read().map_err(|_| poisoned())?;
+        let environment = environments
+            .get(&env.environment_id)
+            .ok_or_else(|| RuntimeError::EnvironmentNotFound(env.environment_id.clone()))?;
+        f(environment)
+    }
+
+    fn with_environment_mut<T>(
+        &self,
+        env: &RuntimeEnvironment,
+        f: impl FnOnce(&mut RpcEnvironment) -> Result<T>,
+    ) -> Result<T> {
+        let mut environments = self.environments.write().map_err(|_| poisoned())?;
+        let environment = environments
+            .get_mut(&env.environment_id)
+            .ok_or_else(|| RuntimeError::EnvironmentNotFound(env.environment_id.clone()))?;
+        f(environment)
+    }
+
+    fn security_config(&self, env: &RuntimeEnvironment) -> Result<SecurityConfig> {
+        self.with_environment(env, |environment| {
+            Ok(SecurityConfig {
+                access_control: environment.access_control.clone(),
+                ..environment.config.security_config.clone()
+            })
+        })
+    }
+
+    fn entropy(&self, env: &RuntimeEnvironment) -> Result<Entropy> {
+        self.with_environment(env, |environment| Ok(environment.entropy.clone()))
+    }
+
+    /// Sender of an execution, falling back to the node's first account
+    fn sender(&self, env: &RuntimeEnvironment, sender: Option<&str>) -> Result<String> {
+        if let Some(sender) = sender {
+            return Ok(sender.to_string());
+        }
+        self.with_environment(env, |environment| {
+            environment
+                .default_sender
+                .clone()
+                .ok_or_else(|| anyhow!("No sender given and {} has no accounts", env.endpoint_url))
+        })
+    }
+
+    /// Address an execution calls, deploying the code behind `code_path` on first use
+    async fn target(
+        &self,
+        env: &RuntimeEnvironment,
+        client: &JsonRpcClient,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+        sender: &str,
+    ) -> Result<(String, Vec<StateChange>)> {
+        if let Some(to) = inputs.parameters.get("to") {
+            let to = to.as_str().ok_or_else(|| anyhow!("to must be an address string"))?;
+            return Ok((format_address(&parse_address(to)?), Vec::new()));
+        }
+        let deployed = self.with_environment(env, |environment| Ok(environment.deployments.get(code_path).cloned()))?;
+        if let Some(address) = deployed {
+            return Ok((address, Vec::new()));
+        }
+
+        let bytecode = load_bytecode(code_path).await?;
+        let hash = send_transaction(client, serde_json::json!({ "from": sender, "data": hex_data(&bytecode) })).await?;
+        let receipt = wait_for_receipt(client, &hash).await?;
+        let address = match receipt.get("contractAddress").and_then(|a| a.as_str()) {
+            Some(address) if succeeded(&receipt) => address.to_lowercase(),
+            _ => bail!("Deployment of {} failed in transaction {}", code_path.display(), hash),
+        };
+        let state_changes = trace_state_changes(client, &hash).await?;
+        self.with_environment_mut(env, |environment| {
+            environment.deployments.insert(code_path.to_path_buf(), address.clone());
+            Ok(())
+        })?;
+        Ok((address, state_changes))
+    }
+
+    async fn run_execution(
+        &self,
+        env: &RuntimeEnvironment,
+        client: &JsonRpcClient,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+    ) -> Result<ExecutionResult> {
+        let (hash, mut state_changes) = match inputs.parameters.get("raw_transaction") {
+            Some(raw) => {
+                let raw = raw.as_str().ok_or_else(|| anyhow!("raw_transaction must be a hex string"))?;
+                let hash = client.call("eth_sendRawTransaction", serde_json::json!([raw])).await?;
+                (string(&hash)?, Vec::new())
+            }
+            None => {
+                let sender = self.sender(env, inputs.context.sender.as_deref())?;
+                let (to, deployment) = self.target(env, client, code_path, inputs, &sender).await?;
+                let request = call_request(&sender, &to, &inputs.target_function, &inputs.parameters)?;
+                (send_transaction(client, request).await?, deployment)
+            }
+        };
+
+        let receipt = wait_for_receipt(client, &hash).await?;
+        let success = succeeded(&receipt);
+        let gas_used = quantity(&receipt["gasUsed"])?;
+        state_changes.extend(trace_state_changes(client, &hash).await?);
+        let logs = client
+            .call("eth_getLogs", serde_json::json!([{ "blockHash": receipt["blockHash"] }]))
+            .await?;
+
+        let (execution_id, timestamp) = self.with_environment_mut(env, |environment| {
+            environment.execution_nonce += 1;
+            Ok((
+                format!("{}_exec_{}", env.environment_id, environment.execution_nonce),
+                environment.entropy.clock().now_secs(),
+            ))
+        })?;
+
+        let mut result = ExecutionResult::new(execution_id.clone(), success);
+        result.return_value = Some(serde_json::json!({ "transaction_hash": hash }));
+        if !success {
+            result.error = Some(format!("Transaction {} reverted", hash));
+        }
+        result.state_changes = state_changes;
+        result.metrics.insert("gas_used".to_string(), serde_json::json!(gas_used));
+        result.security_context.gas_used = gas_used;
+        result.events = logs
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .filter(|log| log.get("transactionHash").and_then(|h| h.as_str()) == Some(hash.as_str()))
+            .enumerate()
+            .map(|(index, log)| log_event(&execution_id, index, log, timestamp))
+            .collect();
+        Ok(result)
+    }
+}
+
+#[async_trait]
+impl BlockchainRuntime for RpcRuntime {
+    fn blockchain_id(&self) -> &str {
+        &self.blockchain_id
+    }
+
+    async fn create_environment(&self, config: RuntimeConfig) -> Result<RuntimeEnvironment> {
+        let endpoint_url = config
+            .blockchain_config
+            .get("rpc_url")
+            .and_then(|url| url.as_str())
+            .unwrap_or(&self.endpoint_url)
+            .to_string();
+        self.attach(&endpoint_url, config, RuntimeType::Remote).await
+    }
+
+    async fn execute(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+    ) -> Result<ExecutionResult> {
+        let (client, config, clock) = self.with_environment(env, |environment| {
+            Ok((
+                environment.client.clone(),
+                environment.config.clone(),
+                environment.entropy.clock().clone(),
+            ))
+        })?;
+        let started_ms = clock.now_millis();
+        let limits = ResourceLimits::from_config(&config);
+        let mut result = limits.run(self.run_execution(env, &client, code_path, inputs)).await??;
+
+        result.execution_time_ms = clock.now_millis().saturating_sub(started_ms);
+        result
+            .metrics
+            .insert("execution_time".to_string(), serde_json::json!(result.execution_time_ms));
+        self.with_environment_mut(env, |environment| {
+            environment.executions.insert(result.execution_id.clone(), result.clone());
+            Ok(())
+        })?;
+        Ok(result)
+    }
+
+    async fn estimate_gas(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+    ) -> Result<GasEstimate> {
+        let client = self.client(env)?;
+        let to = match inputs.parameters.get("to") {
+            Some(to) => format_address(&parse_address(
+                to.as_str().ok_or_else(|| anyhow!("to must be an address string"))?,
+            )?),
+            None => self
+                .with_environment(env, |environment| Ok(environment.deployments.get(code_path).cloned()))?
+                .ok_or_else(|| anyhow!("{} is not deployed; execute it first or pass `to`", code_path.display()))?,
+        };
+        let sender = self.sender(env, inputs.context.sender.as_deref())?;
+        let request = call_request(&sender, &to, &inputs.target_function, &inputs.parameters)?;
+        let gas_limit = inputs
+            .parameters
+            .get("gas_limit")
+            .map(|v| parse_u256(v).map(|g| g.low_u64()))
+            .transpose()?;
+
+        match client.call("eth_estimateGas", serde_json::json!([request])).await {
+            Ok(estimate) => {
+                let gas = quantity(&estimate)?;
+                Ok(GasEstimate {
+                    gas_used: gas,
+                    gas_limit: gas,
+                    success: true,
+                    error: None,
+                    frames: Vec::new(),
+                })
+            }
+            // The node rejects calls that revert at any gas limit
+            Err(error) => match error.downcast_ref::<JsonRpcError>() {
+                Some(rpc_error) => Ok(GasEstimate {
+                    gas_used: 0,
+                    gas_limit: gas_limit.unwrap_or_default(),
+                    success: false,
+                    error: Some(rpc_error.message.clone()),
+                    frames: Vec::new(),
+                }),
+                None => Err(error),
+            },
+        }
+    }
+
+    async fn deploy_contract(
+        &self,
+        env: &RuntimeEnvironment,
+        bytecode: &[u8],
+        constructor_args: &[u8],
+    ) -> Result<String> {
+        if bytecode.is_empty() {
+            bail!("Cannot deploy empty bytecode");
+        }
+        let mut data = bytecode.to_vec();
+        data.extend_from_slice(constructor_args);
+
+        let client = self.client(env)?;
+        let sender = self.sender(env, None)?;
+        let hash = send_transaction(&client, serde_json::json!({ "from": sender, "data": hex_data(&data) })).await?;
+        let receipt = wait_for_receipt(&client, &hash).await?;
+        match receipt.get("contractAddress").and_then(|a| a.as_str()) {
+            Some(address) if succeeded(&receipt) => Ok(address.to_lowercase()),
+            _ => bail!("Deployment failed in transaction {}", hash),
+        }
+    }
+
+    async fn call_function(
+        &self,
+        env: &RuntimeEnvironment,
+        contract_address: &str,
+        function: &str,
+        args: &[u8],
+    ) -> Result<Vec<u8>> {
+        let mut data = function_selector(function)?;
+        data.extend_from_slice(args);
+        let mut request = serde_json::json!({
+            "to": format_address(&parse_address(contract_address)?),
+            "data": hex_data(&data),
+        });
+        if let Ok(sender) = self.sender(env, None) {
+            request["from"] = serde_json::json!(sender);
+        }
+
+        let client = self.client(env)?;
+        let output = client
+            .call("eth_call", serde_json::json!([request, "latest"]))
+            .await
+            .with_context(|| format!("Call to {}::{} failed", contract_address, function))?;
+        bytes(&output)
+    }
+
+    fn metrics_definition(&self) -> Vec<RuntimeMetricDefinition> {
+        vec![
+            RuntimeMetricDefinition {
+                name: "gas_used".to_string(),
+                description: "Gas consumed during execution".to_string(),
+                unit: "gas".to_string(),
+                metric_type: MetricType::Gas,
+            },
+            RuntimeMetricDefinition {
+                name: "execution_time".to_string(),
+                description: "Time taken to execute".to_string(),
+                unit: "ms".to_string(),
+                metric_type: MetricType::Time,
+            },
+        ]
+    }
+
+    async fn monitor(
+        &self,
+        env: &RuntimeEnvironment,
+        execution_id: &str,
+    ) -> Result<Vec<RuntimeEvent>> {
+        self.with_environment(env, |environment| {
+            environment
+                .executions
+                .get(execution_id)
+                .map(|result| result.events.clone())
+                .ok_or_else(|| RuntimeError::ExecutionNotFound(execution_id.to_string()).into())
+        })
+    }
+
+    async fn destroy(&self, env: RuntimeEnvironment) -> Result<()> {
+        info!("Detaching RPC environment {}", env.environment_id);
+        self.environments
+            .write()
+            .map_err(|_| poisoned())?
+            .remove(&env.environment_id)
+            .map(|_| ())
+            .ok_or_else(|| RuntimeError::EnvironmentNotFound(env.environment_id.clone()).into())
+    }
+
+    async fn is_available(&self) -> bool {
+        match JsonRpcClient::new(&self.endpoint_url) {
+            Ok(client) => client.call("eth_chainId", serde_json::json!([])).await.is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn capabilities(&self) -> RuntimeCapabilities {
+        self.capabilities.clone()
+    }
+
+    async fn grant_role(&self, env: &RuntimeEnvironment, account: &str, role: &str) -> Result<()> {
+        self.with_environment_mut(env, |environment| Ok(environment.access_control.grant_role(account, role)?))
+    }
+
+    async fn revoke_role(&self, env: &RuntimeEnvironment, account: &str, role: &str) -> Result<bool> {
+        self.with_environment_mut(env, |environment| Ok(environment.access_control.revoke_role(account, role)))
+    }
+
+    /// Execute and check the result
+    ///
+    /// The node has already committed the transaction when the checks run,
+    /// so an aborting policy fails the execution without undoing its effects.
+    /// The failed result is still recorded before the violation is returned.
+    async fn execute_secure(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+        security_config: &SecurityConfig,
+    ) -> Result<ExecutionResult> {
+        let enforcer = SecurityEnforcer::with_clock(
+            SecurityConfig {
+                access_control: self.security_config(env)?.access_control,
+                ..security_config.clone()
+            },
+            self.entropy(env)?.clock().clone(),
+        );
+        let mut result = self.execute(env, code_path, inputs).await?;
+
+        let context = &mut result.security_context;
+        context.call_depth = 1;
+        context.call_stack.push(inputs.target_function.clone());
+
+        let caller = inputs.context.sender.as_deref().unwrap_or(DEFAULT_SENDER_ADDRESS);
+        let verdict = enforcer.enforce(&mut result, &inputs.target_function, caller, None);
+
+        self.with_environment_mut(env, |environment| {
+            environment.executions.insert(result.execution_id.clone(), result.clone());
+            Ok(())
+        })?;
+        verdict?;
+        Ok(result)
+    }
+
+    async fn check_reentrancy(
+        &self,
+        env: &RuntimeEnvironment,
+        function_name: &str,
+        caller: &str,
+        call_stack: &[String],
+    ) -> Result<bool> {
+        let validator = SecurityValidator::new(self.security_config(env)?);
+        Ok(validator.check_reentrancy(function_name, caller, call_stack).is_err())
+    }
+
+    async fn detect_overflow(
+        &self,
+        env: &RuntimeEnvironment,
+        operation: &str,
+        operands: &[i64],
+    ) -> Result<bool> {
+        let mut config = self.security_config(env)?;
+        config.integer_type.get_or_insert(IntegerType::U256);
+        let validator = SecurityValidator::new(config);
+        Ok(validator.detect_overflow(operation, operands).is_err())
+    }
+
+    async fn verify_access_control(
+        &self,
+        env: &RuntimeEnvironment,
+        function_name: &str,
+        caller: &str,
+        required_role: Option<&str>,
+    ) -> Result<bool> {
+        let validator = SecurityValidator::new(self.security_config(env)?);
+        Ok(validator.verify_access_control(function_name, caller, required_role).is_ok())
+    }
+
+    async fn enforce_resource_limits(
+        &self,
+        env: &RuntimeEnvironment,
+        gas_used: u64,
+        memory_used: u64,
+        call_depth: u32,
+        external_calls: u32,
+        security_config: &SecurityConfig,
+    ) -> Result<Vec<SecurityViolation>> {
+        let clock = self.entropy(env)?.clock().clone();
+        Ok(SecurityEnforcer::with_clock(security_config.clone(), clock).check_resource_limits(
+            gas_used,
+            memory_used,
+            call_depth,
+            external_calls,
+        ))
+    }
+
+    async fn get_security_report(
+        &self,
+        env: &RuntimeEnvironment,
+        execution_id: &str,
+    ) -> Result<SecurityReport> {
+        let config = self.security_config(env)?;
+        self.with_environment(env, |environment| {
+            let result = environment
+                .executions
+                .get(execution_id)
+                .ok_or_else(|| RuntimeError::ExecutionNotFound(execution_id.to_string()))?;
+            Ok(SecurityReport::with_clock(result, &config, environment.entropy.clock().as_ref()))
+        })
+    }
+}
+
+/// Transaction object calling `target_function` on `to`
+fn call_request(
+    sender: &str,
+    to: &str,
+    target_function: &str,
+    parameters: &HashMap<String, serde_json::Value>,
+) -> Result<serde_json::Value> {
+    let mut request = serde_json::json!({
+        "from": sender,
+        "to": to,
+        "data": hex_data(&encode_call(target_function, parameters)?),
+    });
+    if let Some(value) = parameters.get("value") {
+        request["value"] = serde_json::json!(format!("{:#x}", parse_u256(value)?));
+    }
+    if let Some(gas_limit) = parameters.get("gas_limit") {
+        request["gas"] = serde_json::json!(format!("{:#x}", parse_u256(gas_limit)?));
+    }
+    Ok(request)
+}
+
+async fn send_transaction(client: &JsonRpcClient, request: serde_json::Value) -> Result<String> {
+    string(&client.call("eth_sendTransaction", serde_json::json!([request])).await?)
+}
+
+/// Poll for the receipt of a transaction until the node has mined it
+async fn wait_for_receipt(client: &JsonRpcClient, hash: &str) -> Result<serde_json::Value> {
+    loop {
+        let receipt = client
+            .call("eth_getTransactionReceipt", serde_json::json!([hash]))
+            .await?;
+        if !receipt.is_null() {
+            return Ok(receipt);
+        }
+        tokio::time::sleep(Duration::from_millis(RPC_RECEIPT_POLL_INTERVAL_MS)).await;
+    }
+}
+
+/// State changes of a mined transaction, empty if the node does not trace
+async fn trace_state_changes(client: &JsonRpcClient, hash: &str) -> Result<Vec<StateChange>> {
+    let tracer = serde_json::json!({ "tracer": "prestateTracer", "tracerConfig": { "diffMode": true } });
+    match client
+        .call("debug_traceTransaction", serde_json::json!([hash, tracer]))
+        .await
+    {
+        Ok(diff) => prestate_changes(&diff),
+        Err(error) if error.downcast_ref::<JsonRpcError>().is_some() => Ok(Vec::new()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Map a prestate tracer diff to state changes keyed like the EVM backend's
+///
+/// `pre` holds the accounts a transaction modified as they were before it and
+/// `post` only the fields it changed. Storage slots missing from `post` were
+/// cleared.
+fn prestate_changes(diff: &serde_json::Value) -> Result<Vec<StateChange>> {
+    let empty = serde_json::Map::new();
+    let accounts = |side: &str| diff.get(side).and_then(|v| v.as_object()).unwrap_or(&empty);
+    let (pre, post) = (accounts("pre"), accounts("post"));
+    let addresses: BTreeSet<&String> = pre.keys().chain(post.keys()).collect();
+
+    let mut changes = Vec::new();
+    for address in addresses {
+        let address_key = format_address(&parse_address(address)?);
+        let field = |side: &serde_json::Map<String, serde_json::Value>, name: &str| {
+            side.get(address).and_then(|account| account.get(name)).cloned()
+        };
+
+        if let Some(new) = field(post, "balance") {
+            let old = field(pre, "balance").map(|v| balance(&v)).transpose()?.flatten();
+            push_change(&mut changes, format!("{}:balance", address_key), old, balance(&new)?);
+        }
+        if let Some(new) = field(post, "code") {
+            let old = field(pre, "code").map(|v| code(&v)).transpose()?.flatten();
+            push_change(&mut changes, format!("{}:code", address_key), old, code(&new)?);
+        }
+
+        let slots = |side: &serde_json::Map<String, serde_json::Value>| {
+            field(side, "storage")
+                .and_then(|storage| storage.as_object().cloned())
+                .unwrap_or_default()
+        };
+        let (old_slots, new_slots) = (slots(pre), slots(post));
+        let keys: BTreeSet<&String> = old_slots.keys().chain(new_slots.keys()).collect();
+        for slot in keys {
+            let old = old_slots.get(slot).map(word).transpose()?.flatten();
+            let new = new_slots.get(slot).map(word).transpose()?.flatten();
+            let slot = format_word(&parse_u256(&serde_json::json!(slot))?);
+            push_change(&mut changes, format!("{}:storage:{}", address_key, slot), old, new);
+        }
+    }
+    Ok(changes)
+}
+
+fn push_change(
+    changes: &mut Vec<StateChange>,
+    key: String,
+    old: Option<serde_json::Value>,
+    new: Option<serde_json::Value>,
+) {
+    let change_type = match (&old, &new) {
+        (None, None) => return,
+        (Some(o), Some(n)) if o == n => return,
+        (None, Some(_)) => StateChangeType::Created,
+        (Some(_), None) => StateChangeType::Deleted,
+        (Some(_), Some(_)) => StateChangeType::Updated,
+    };
+    changes.push(StateChange {
+        key,
+        old_value: old,
+        new_value: new.unwrap_or(serde_json::Value::Null),
+        change_type,
+    });
+}
+
+/// Decimal balance, `None` when zero
+fn balance(value: &serde_json::Value) -> Result<Option<serde_json::Value>> {
+    let balance = parse_u256(value)?;
+    Ok((!balance.is_zero()).then(|| serde_json::json!(balance.to_string())))
+}
+
+/// Storage word, `None` when zero
+fn word(value: &serde_json::Value) -> Result<Option<serde_json::Value>> {
+    let word = parse_u256(value)?;
+    Ok((!word.is_zero()).then(|| serde_json::json!(format_word(&word))))
+}
+
+/// Hex code, `None` when empty
+fn code(value: &serde_json::Value) -> Result<Option<serde_json::Value>> {
+    let code = bytes(value)?;
+    Ok((!code.is_empty()).then(|| serde_json::json!(hex_data(&code))))
+}
+
+fn log_event(execution_id: &str, index: usize, log: &serde_json::Value, timestamp: u64) -> RuntimeEvent {
+    let topics: Vec<serde_json::Value> = log
+        .get("topics")
+        .and_then(|topics| topics.as_array())
+        .cloned()
+        .unwrap_or_default();
+    RuntimeEvent {
+        event_id: format!("{}_log_{}", execution_id, index),
+        event_type: topics
+            .first()
+            .and_then(|topic| topic.as_str())
+            .unwrap_or("anonymous")
+            .to_string(),
+        timestamp,
+        data: HashMap::from([
+            ("address".to_string(), log.get("address").cloned().unwrap_or_default()),
+            ("topics".to_string(), serde_json::json!(topics)),
+            ("data".to_string(), log.get("data").cloned().unwrap_or_else(|| serde_json::json!("0x"))),
+        ]),
+    }
+}
+
+fn succeeded(receipt: &serde_json::Value) -> bool {
+    receipt
+        .get("status")
+        .map(|status| quantity(status).map(|s| s == 1).unwrap_or(false))
+        .unwrap_or(false)
+}
+
+/// Parse a hex quantity such as `0x1a`
+fn quantity(value: &serde_json::Value) -> Result<u64> {
+    let text = string(value)?;
+    let digits = text
+        .strip_prefix("0x")
+        .ok_or_else(|| anyhow!("{} is not a hex quantity", text))?;
+    let quantity = U256::from_str_radix(if digits.is_empty() { "0" } else { digits }, 16)
+        .map_err(|_| anyhow!("{} is not a hex quantity", text))?;
+    if quantity > U256::from(u64::MAX) {
+        bail!("{} does not fit in 64 bits", text);
+    }
+    Ok(quantity.low_u64())
+}
+
+fn bytes(value: &serde_json::Value) -> Result<Vec<u8>> {
+    let text = string(value)?;
+    hex::decode(text.trim_start_matches("0x")).with_context(|| format!("{} is not hex data", text))
+}
+
+fn string(value: &serde_json::Value) -> Result<String> {
+    value
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("Expected a string, got {}", value))
+}
+
+fn hex_data(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+fn poisoned() -> RuntimeError {
+    RuntimeError::Backend("RPC runtime state is poisoned".to_string())
+}
@@ -3,15 +3,26 @@
 use anyhow::Result;
 use async_trait::async_trait;
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::config::RuntimeConfig;
+use crate::constants::{DEFAULT_RPC_ENDPOINT_URL, DEFAULT_SENDER_ADDRESS};
+use crate::enforcement::SecurityEnforcer;
+use crate::error::RuntimeError;
+use crate::events::{EventFilter, EventSubscription};
+use crate::report::SecurityReport;
 use crate::security::SecurityConfig;
+use crate::trace::ExecutionTrace;
 use crate::types::{
     RuntimeEnvironment, ExecutionInputs, ExecutionResult, RuntimeCapabilities,
-    RuntimeMetricDefinition, RuntimeEvent, SecurityViolation
+    RuntimeMetricDefinition, RuntimeEvent, SecurityViolation, SnapshotId, BlockInfo, GasEstimate, Capability
 };
 
 /// Main blockchain runtime trait
+///
+/// Methods return `anyhow::Result`. Failures raised by this crate carry a
+/// `RuntimeError`, which `RuntimeError::find` recovers for matching on the
+/// cause, e.g. `RuntimeError::SecurityViolation` from `execute_secure`.
 #[async_trait]
 pub trait BlockchainRuntime: Send + Sync {
     /// Get the blockchain identifier
@@ -64,7 +75,87 @@ pub trait BlockchainRuntime: Send + Sync {
     /// Get runtime capabilities
     fn capabilities(&self) -> RuntimeCapabilities;
 
+    /// Subscribe to events of an environment as they occur
+    async fn subscribe(
+        &self,
+        _env: &RuntimeEnvironment,
+        _filter: EventFilter,
+    ) -> Result<EventSubscription> {
+        Err(unsupported(self.blockchain_id(), Capability::EventSubscriptions))
+    }
+
+    /// Capture the current state of an environment
+    async fn snapshot(&self, _env: &RuntimeEnvironment) -> Result<SnapshotId> {
+        Err(unsupported(self.blockchain_id(), Capability::Snapshots))
+    }
+
+    /// Restore an environment to a snapshot.
+    ///
+    /// The snapshot stays valid so it can be reverted to again, while any
+    /// snapshots taken after it are discarded.
+    async fn revert(&self, _env: &RuntimeEnvironment, _snapshot: SnapshotId) -> Result<()> {
+        Err(unsupported(self.blockchain_id(), Capability::Snapshots))
+    }
+
+    /// Dry-run inputs without committing state and estimate the gas they need
+    async fn estimate_gas(
+        &self,
+        _env: &RuntimeEnvironment,
+        _code_path: &Path,
+        _inputs: &ExecutionInputs,
+    ) -> Result<GasEstimate> {
+        Err(unsupported(self.blockchain_id(), Capability::GasEstimation))
+    }
+
+    /// Get the number and timestamp of the current block
+    async fn current_block(&self, _env: &RuntimeEnvironment) -> Result<BlockInfo> {
+        Err(unsupported(self.blockchain_id(), Capability::TimeTravel))
+    }
+
+    /// Move the block timestamp forward without mining a block
+    async fn advance_time(&self, _env: &RuntimeEnvironment, _seconds: u64) -> Result<BlockInfo> {
+        Err(unsupported(self.blockchain_id(), Capability::TimeTravel))
+    }
+
+    /// Set the timestamp of the next mined block
+    async fn set_next_block_timestamp(&self, _env: &RuntimeEnvironment, _timestamp: u64) -> Result<()> {
+        Err(unsupported(self.blockchain_id(), Capability::TimeTravel))
+    }
+
+    /// Mine empty blocks, advancing the block number and timestamp
+    async fn mine_blocks(&self, _env: &RuntimeEnvironment, _count: u64) -> Result<BlockInfo> {
+        Err(unsupported(self.blockchain_id(), Capability::TimeTravel))
+    }
+
+    /// Execute code and record a trace that can be saved and replayed
+    ///
+    /// The trace holds the call frames with their storage reads and writes,
+    /// the instructions executed where the backend exposes them, and the
+    /// result with its state changes and events.
+    async fn execute_traced(
+        &self,
+        _env: &RuntimeEnvironment,
+        _code_path: &Path,
+        _inputs: &ExecutionInputs,
+    ) -> Result<ExecutionTrace> {
+        Err(unsupported(self.blockchain_id(), Capability::Tracing))
+    }
+
+    /// Grant a role defined by the environment's access control policy to an account
+    async fn grant_role(&self, _env: &RuntimeEnvironment, _account: &str, _role: &str) -> Result<()> {
+        Err(unsupported(self.blockchain_id(), Capability::AccessControl))
+    }
+
+    /// Revoke a directly granted role, returning whether the account held it
+    async fn revoke_role(&self, _env: &RuntimeEnvironment, _account: &str, _role: &str) -> Result<bool> {
+        Err(unsupported(self.blockchain_id(), Capability::AccessControl))
+    }
+
     /// Execute code with security checks
+    ///
+    /// When the configuration's `enforcement` policy aborts, the failed
+    /// execution is recorded and `RuntimeError::SecurityViolation` returned
+    /// with the execution id in the violation's context.
     async fn execute_secure(
         &self,
         env: &RuntimeEnvironment,
@@ -99,7 +190,7 @@ pub trait BlockchainRuntime: Send + Sync {
         required_role: Option<&str>,
     ) -> Result<bool>;
 
-    /// Enforce resource limits
+    /// Check call depth, external calls, gas and memory, returning every violation
     async fn enforce_resource_limits(
         &self,
         env: &RuntimeEnvironment,
@@ -115,13 +206,23 @@ pub trait BlockchainRuntime: Send + Sync {
         &self,
         env: &RuntimeEnvironment,
         execution_id: &str,
-    ) -> Result<std::collections::HashMap<String, serde_json::Value>>;
+    ) -> Result<SecurityReport>;
+}
+
+/// Error returned by provided methods a runtime does not override
+fn unsupported(blockchain_id: &str, capability: Capability) -> anyhow::Error {
+    RuntimeError::UnsupportedCapability {
+        blockchain_id: blockchain_id.to_string(),
+        capability,
+    }
+    .into()
 }
 
 /// Default implementation of blockchain runtime
 pub struct DefaultBlockchainRuntime {
     blockchain_id: String,
     capabilities: RuntimeCapabilities,
+    environment_nonce: AtomicU64,
 }
 
 impl DefaultBlockchainRuntime {
@@ -130,6 +231,7 @@ impl DefaultBlockchainRuntime {
         Self {
             blockchain_id,
             capabilities: RuntimeCapabilities::default(),
+            environment_nonce: AtomicU64::new(0),
         }
     }
 
@@ -138,6 +240,7 @@ impl DefaultBlockchainRuntime {
         Self {
             blockchain_id,
             capabilities,
+            environment_nonce: AtomicU64::new(0),
         }
     }
 }
@@ -148,13 +251,14 @@ impl BlockchainRuntime for DefaultBlockchainRuntime {
         &self.blockchain_id
     }
 
-    async fn create_environment(&self, config: RuntimeConfig) -> Result<RuntimeEnvironment> {
+    async fn create_environment(&self, _config: RuntimeConfig) -> Result<RuntimeEnvironment> {
         // In a real implementation, this would create the actual runtime environment
+        let nonce = self.environment_nonce.fetch_add(1, Ordering::SeqCst) + 1;
         Ok(RuntimeEnvironment {
-            environment_id: format!("env_{}", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis()),
+            environment_id: format!("env_{}", nonce),
             blockchain_id: self.blockchain_id.clone(),
             runtime_type: crate::types::RuntimeType::LocalProcess,
-            endpoint_url: "http://localhost:8545".to_string(),
+            endpoint_url: DEFAULT_RPC_ENDPOINT_URL.to_string(),
             state: crate::types::EnvironmentState::Ready,
             metadata: std::collections::HashMap::new(),
         })
@@ -236,13 +340,15 @@ impl BlockchainRuntime for DefaultBlockchainRuntime {
         env: &RuntimeEnvironment,
         code_path: &Path,
         inputs: &ExecutionInputs,
-        _security_config: &SecurityConfig,
+        security_config: &SecurityConfig,
     ) -> Result<ExecutionResult> {
         // In a real implementation, this would execute with security checks
         let mut result = self.execute(env, code_path, inputs).await?;
         
         // Add security context
         result.security_context = crate::types::SecureExecutionContext::default();
+        let caller = inputs.context.sender.as_deref().unwrap_or(DEFAULT_SENDER_ADDRESS);
+        SecurityEnforcer::new(security_config.clone()).enforce(&mut result, &inputs.target_function, caller, None)?;
         
         Ok(result)
     }
@@ -282,22 +388,26 @@ impl BlockchainRuntime for DefaultBlockchainRuntime {
     async fn enforce_resource_limits(
         &self,
         _env: &RuntimeEnvironment,
-        _gas_used: u64,
-        _memory_used: u64,
-        _call_depth: u32,
-        _external_calls: u32,
-        _security_config: &SecurityConfig,
+        gas_used: u64,
+        memory_used: u64,
+        call_depth: u32,
+        external_calls: u32,
+        security_config: &SecurityConfig,
     ) -> Result<Vec<SecurityViolation>> {
-        // In a real implementation, this would enforce resource limits
-        Ok(vec![])
+        Ok(SecurityEnforcer::new(security_config.clone()).check_resource_limits(
+            gas_used,
+            memory_used,
+            call_depth,
+            external_calls,
+        ))
     }
 
     async fn get_security_report(
         &self,
         _env: &RuntimeEnvironment,
-        _execution_id: &str,
-    ) -> Result<std::collections::HashMap<String, serde_json::Value>> {
-        // In a real implementation, this would generate a security report
-        Ok(std::collections::HashMap::new())
+        execution_id: &str,
+    ) -> Result<SecurityReport> {
+        // In a real implementation, this would look up the execution's result
+        Err(RuntimeError::ExecutionNotFound(execution_id.to_string()).into())
     }
 }
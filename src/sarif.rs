@@ -0,0 +1,336 @@
+//! SARIF 2.1.0 export of security violations for code-scanning tools
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::security::SecurityContext;
+use crate::types::{ExecutionResult, SecurityViolation, SecurityViolationType, SecuritySeverity};
+
+/// Version of the SARIF format produced
+pub const SARIF_VERSION: &str = "2.1.0";
+
+/// Schema of the SARIF format produced
+pub const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Collects violations from executions into a single SARIF run
+///
+/// Each `SecurityViolationType` becomes a rule, each violation a result
+/// located at the code path that was executed.
+#[derive(Debug, Clone)]
+pub struct SarifExporter {
+    tool_name: String,
+    tool_version: String,
+    rules: Vec<SarifRule>,
+    /// Most severe violation seen for each rule
+    rule_severities: Vec<SecuritySeverity>,
+    results: Vec<SarifResult>,
+}
+
+impl SarifExporter {
+    /// Create an exporter reporting this crate as the tool
+    pub fn new() -> Self {
+        Self::with_tool(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+    }
+
+    /// Create an exporter reporting another tool name and version
+    pub fn with_tool(name: impl Into<String>, version: impl Into<String>) -> Self {
+        Self {
+            tool_name: name.into(),
+            tool_version: version.into(),
+            rules: Vec::new(),
+            rule_severities: Vec::new(),
+            results: Vec::new(),
+        }
+    }
+
+    /// Add violations raised while executing the code at `code_path`
+    pub fn add_violations(&mut self, code_path: &Path, violations: &[SecurityViolation]) -> &mut Self {
+        let uri = artifact_uri(code_path);
+        for violation in violations {
+            let rule_index = self.rule_index(violation);
+            let function_name = violation.context.get("function_name").and_then(|name| name.as_str());
+            self.results.push(SarifResult {
+                rule_id: sarif_rule_id(&violation.violation_type),
+                rule_index,
+                level: level(&violation.severity).to_string(),
+                message: SarifMessage {
+                    text: violation.description.clone(),
+                },
+                locations: vec![SarifLocation {
+                    physical_location: SarifPhysicalLocation {
+                        artifact_location: SarifArtifactLocation { uri: uri.clone() },
+                    },
+                    logical_locations: function_name
+                        .map(|name| {
+                            vec![SarifLogicalLocation {
+                                name: name.to_string(),
+                                kind: "function".to_string(),
+                            }]
+                        })
+                        .unwrap_or_default(),
+                }],
+                properties: BTreeMap::from([
+                    ("severity".to_string(), serde_json::json!(violation.severity)),
+                    ("timestamp".to_string(), serde_json::json!(violation.timestamp)),
+                    ("context".to_string(), serde_json::json!(violation.context)),
+                ]),
+            });
+        }
+        self
+    }
+
+    /// Add the violations of an execution of the code at `code_path`
+    pub fn add_execution(&mut self, code_path: &Path, result: &ExecutionResult) -> &mut Self {
+        self.add_violations(code_path, &result.security_violations)
+    }
+
+    /// Add the violations tracked by a security context for the code at `code_path`
+    pub fn add_context(&mut self, code_path: &Path, context: &SecurityContext) -> &mut Self {
+        self.add_violations(code_path, context.violations())
+    }
+
+    /// Number of results collected so far
+    pub fn result_count(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Build the SARIF log
+    pub fn to_log(&self) -> SarifLog {
+        SarifLog {
+            schema: SARIF_SCHEMA.to_string(),
+            version: SARIF_VERSION.to_string(),
+            runs: vec![SarifRun {
+                tool: SarifTool {
+                    driver: SarifDriver {
+                        name: self.tool_name.clone(),
+                        version: self.tool_version.clone(),
+                        information_uri: env!("CARGO_PKG_REPOSITORY").to_string(),
+                        rules: self.rules.clone(),
+                    },
+                },
+                results: self.results.clone(),
+            }],
+        }
+    }
+
+    /// Serialize the SARIF log as pretty-printed JSON
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&self.to_log())
+    }
+
+    /// Index of the violation's rule, adding the rule on first use
+    ///
+    /// A rule's default level and security severity follow the most severe
+    /// violation seen for it.
+    fn rule_index(&mut self, violation: &SecurityViolation) -> usize {
+        let id = sarif_rule_id(&violation.violation_type);
+        match self.rules.iter().position(|rule| rule.id == id) {
+            Some(index) => {
+                if violation.severity > self.rule_severities[index] {
+                    self.rules[index].set_severity(&violation.severity);
+                    self.rule_severities[index] = violation.severity.clone();
+                }
+                index
+            }
+            None => {
+                let mut rule = SarifRule {
+                    name: format!("{:?}", violation.violation_type),
+                    short_description: SarifMessage {
+                        text: rule_description(&violation.violation_type).to_string(),
+                    },
+                    default_configuration: SarifConfiguration {
+                        level: String::new(),
+                    },
+                    properties: BTreeMap::from([("tags".to_string(), serde_json::json!(["security"]))]),
+                    id,
+                };
+                rule.set_severity(&violation.severity);
+                self.rules.push(rule);
+                self.rule_severities.push(violation.severity.clone());
+                self.rules.len() - 1
+            }
+        }
+    }
+}
+
+impl SarifRule {
+    fn set_severity(&mut self, severity: &SecuritySeverity) {
+        self.default_configuration.level = level(severity).to_string();
+        self.properties.insert(
+            "security-severity".to_string(),
+            serde_json::json!(format!("{:.1}", security_severity(severity))),
+        );
+    }
+}
+
+impl Default for SarifExporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Rule id of a violation type, e.g. `reentrancy-attack`
+pub fn sarif_rule_id(violation_type: &SecurityViolationType) -> String {
+    let name = format!("{:?}", violation_type);
+    let mut id = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            id.push('-');
+        }
+        id.push(c.to_ascii_lowercase());
+    }
+    id
+}
+
+fn rule_description(violation_type: &SecurityViolationType) -> &'static str {
+    match violation_type {
+        SecurityViolationType::ReentrancyAttack => "Contract re-entered while holding stale state",
+        SecurityViolationType::IntegerOverflow => "Arithmetic overflow, underflow or division by zero",
+        SecurityViolationType::AccessControlViolation => "Caller lacks the role a function requires",
+        SecurityViolationType::ResourceLimitExceeded => "Execution exceeded a runtime resource limit",
+        SecurityViolationType::SandboxViolation => "Execution escaped its sandbox",
+        SecurityViolationType::CallDepthExceeded => "Call depth exceeded the configured maximum",
+        SecurityViolationType::ExternalCallLimitExceeded => "External calls exceeded the configured maximum",
+        SecurityViolationType::GasLimitExceeded => "Gas usage exceeded the configured maximum",
+        SecurityViolationType::MemoryLimitExceeded => "Memory usage exceeded the configured maximum",
+    }
+}
+
+/// SARIF level of a severity
+fn level(severity: &SecuritySeverity) -> &'static str {
+    match severity {
+        SecuritySeverity::Critical | SecuritySeverity::High => "error",
+        SecuritySeverity::Medium => "warning",
+        SecuritySeverity::Low => "note",
+    }
+}
+
+/// CVSS-style score code-scanning tools use to rank security rules
+fn security_severity(severity: &SecuritySeverity) -> f32 {
+    match severity {
+        SecuritySeverity::Critical => 9.5,
+        SecuritySeverity::High => 8.0,
+        SecuritySeverity::Medium => 5.5,
+        SecuritySeverity::Low => 2.0,
+    }
+}
+
+/// URI reference for a code path, relative paths staying relative
+fn artifact_uri(code_path: &Path) -> String {
+    let path = code_path.to_string_lossy().replace('\\', "/");
+    let absolute = code_path.is_absolute();
+    let encoded: Vec<String> = path.split('/').map(|segment| encode_segment(segment, absolute)).collect();
+    let path = encoded.join("/");
+    if absolute {
+        format!("file://{}{}", if path.starts_with('/') { "" } else { "/" }, path)
+    } else {
+        path
+    }
+}
+
+/// Percent-encode a path segment, keeping characters RFC 3986 allows in one
+///
+/// A colon would read as a scheme in a relative reference, so it is only kept
+/// in absolute paths such as `C:/contracts`.
+fn encode_segment(segment: &str, keep_colon: bool) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*' | b'+' | b',' | b';' | b'=' | b'@' => {
+                encoded.push(byte as char)
+            }
+            b':' if keep_colon => encoded.push(':'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Top-level SARIF document
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SarifLog {
+    #[serde(rename = "$schema")]
+    pub schema: String,
+    pub version: String,
+    pub runs: Vec<SarifRun>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SarifRun {
+    pub tool: SarifTool,
+    pub results: Vec<SarifResult>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SarifTool {
+    pub driver: SarifDriver,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifDriver {
+    pub name: String,
+    pub version: String,
+    pub information_uri: String,
+    pub rules: Vec<SarifRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifRule {
+    pub id: String,
+    pub name: String,
+    pub short_description: SarifMessage,
+    pub default_configuration: SarifConfiguration,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub properties: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SarifConfiguration {
+    pub level: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SarifMessage {
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifResult {
+    pub rule_id: String,
+    pub rule_index: usize,
+    pub level: String,
+    pub message: SarifMessage,
+    pub locations: Vec<SarifLocation>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub properties: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifLocation {
+    pub physical_location: SarifPhysicalLocation,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub logical_locations: Vec<SarifLogicalLocation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifPhysicalLocation {
+    pub artifact_location: SarifArtifactLocation,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SarifArtifactLocation {
+    pub uri: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SarifLogicalLocation {
+    pub name: String,
+    pub kind: String,
+}
@@ -1,10 +1,19 @@
 //! Security configuration and validation for blockchain runtime
 
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::path::Path;
+use std::sync::Arc;
 
+use crate::access_control::AccessControlPolicy;
+use crate::arithmetic::{ArithmeticMode, ArithmeticOperation, IntegerType, IntegerValue};
 use crate::constants::*;
-use crate::types::{SecurityViolation, SecurityViolationType, SecuritySeverity};
+use crate::determinism::{Clock, SystemClock};
+use crate::enforcement::EnforcementPolicy;
+use crate::error::{RuntimeError, RuntimeResult};
+use crate::reentrancy::{CallFrame, ReentrancyKind};
+use crate::types::{
+    AccessControlCheck, SecureExecutionContext, SecurityViolation, SecurityViolationType, SecuritySeverity,
+};
 
 /// Security configuration for runtime execution
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +38,18 @@ pub struct SecurityConfig {
     pub memory_limit_enforcement: bool,
     /// Maximum memory usage in bytes
     pub max_memory_bytes: u64,
+    /// Roles and grants used by access control verification
+    #[serde(default)]
+    pub access_control: AccessControlPolicy,
+    /// Integer type overflow detection assumes, or the backend's native type when unset
+    #[serde(default)]
+    pub integer_type: Option<IntegerType>,
+    /// Whether the analysed code wraps or reverts on arithmetic faults
+    #[serde(default)]
+    pub arithmetic_mode: ArithmeticMode,
+    /// Whether `execute_secure` aborts or continues on violations of each severity
+    #[serde(default)]
+    pub enforcement: EnforcementPolicy,
 }
 
 impl Default for SecurityConfig {
@@ -44,6 +65,10 @@ impl Default for SecurityConfig {
             max_gas_limit: DEFAULT_MAX_GAS_LIMIT,
             memory_limit_enforcement: true,
             max_memory_bytes: DEFAULT_MAX_MEMORY_BYTES,
+            access_control: AccessControlPolicy::default(),
+            integer_type: None,
+            arithmetic_mode: ArithmeticMode::default(),
+            enforcement: EnforcementPolicy::report_only(),
         }
     }
 }
@@ -67,6 +92,10 @@ impl SecurityConfig {
             max_gas_limit: DEFAULT_MAX_GAS_LIMIT,
             memory_limit_enforcement: true,
             max_memory_bytes: DEFAULT_MAX_MEMORY_BYTES,
+            access_control: AccessControlPolicy::default(),
+            integer_type: None,
+            arithmetic_mode: ArithmeticMode::default(),
+            enforcement: EnforcementPolicy::report_only(),
         }
     }
 
@@ -83,6 +112,10 @@ impl SecurityConfig {
             max_gas_limit: u64::MAX,
             memory_limit_enforcement: false,
             max_memory_bytes: u64::MAX,
+            access_control: AccessControlPolicy::default(),
+            integer_type: None,
+            arithmetic_mode: ArithmeticMode::default(),
+            enforcement: EnforcementPolicy::report_only(),
         }
     }
 
@@ -99,6 +132,10 @@ impl SecurityConfig {
             max_gas_limit: 1_000_000, // More restrictive
             memory_limit_enforcement: true,
             max_memory_bytes: 10 * 1024 * 1024, // 10MB
+            access_control: AccessControlPolicy::default(),
+            integer_type: None,
+            arithmetic_mode: ArithmeticMode::default(),
+            enforcement: EnforcementPolicy::abort_at(SecuritySeverity::High),
         }
     }
 }
@@ -106,12 +143,18 @@ impl SecurityConfig {
 /// Security validator for runtime operations
 pub struct SecurityValidator {
     config: SecurityConfig,
+    clock: Arc<dyn Clock>,
 }
 
 impl SecurityValidator {
     /// Create a new security validator
     pub fn new(config: SecurityConfig) -> Self {
-        Self { config }
+        Self::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// Create a validator stamping violations and checks with a custom clock
+    pub fn with_clock(config: SecurityConfig, clock: Arc<dyn Clock>) -> Self {
+        Self { config, clock }
     }
 
     /// Validate call depth against security limits
@@ -185,76 +228,175 @@ impl SecurityValidator {
         }
     }
 
+    /// Report re-entries into contracts holding stale state across an external call
+    pub fn detect_reentrancy(&self, call_tree: &CallFrame) -> Vec<SecurityViolation> {
+        if !self.config.reentrancy_protection {
+            return Vec::new();
+        }
+
+        call_tree
+            .reentrancies()
+            .into_iter()
+            .map(|finding| {
+                let mut violation = self.create_violation(
+                    SecurityViolationType::ReentrancyAttack,
+                    format!(
+                        "{} into {}: {} re-entered while {} has not yet written {}",
+                        finding.kind,
+                        finding.contract,
+                        finding.reentered_function,
+                        finding.function,
+                        finding.stale_state.join(", ")
+                    ),
+                    if finding.kind == ReentrancyKind::ReadOnly {
+                        SecuritySeverity::High
+                    } else {
+                        SecuritySeverity::Critical
+                    },
+                );
+                violation.context.extend([
+                    ("reentrancy_kind".to_string(), serde_json::json!(finding.kind)),
+                    ("contract".to_string(), serde_json::json!(finding.contract)),
+                    ("function_name".to_string(), serde_json::json!(finding.function)),
+                    ("reentered_function".to_string(), serde_json::json!(finding.reentered_function)),
+                    ("caller".to_string(), serde_json::json!(finding.caller)),
+                    ("stale_state".to_string(), serde_json::json!(finding.stale_state)),
+                    ("call_path".to_string(), serde_json::json!(finding.call_path)),
+                ]);
+                violation
+            })
+            .collect()
+    }
+
     /// Detect integer overflow
+    ///
+    /// Operands are read as the configured integer type, `i64` when unset.
+    /// Unknown operations and missing operands are not checked.
     pub fn detect_overflow(&self, operation: &str, operands: &[i64]) -> Result<bool, SecurityViolation> {
-        if !self.config.overflow_detection {
+        let (Ok(operation), [lhs, rhs, ..]) = (operation.parse::<ArithmeticOperation>(), operands) else {
             return Ok(false);
-        }
-
-        // Simple overflow detection for basic operations
-        match operation {
-            "add" | "+" => {
-                if operands.len() >= 2 {
-                    let result = operands[0].checked_add(operands[1]);
-                    if result.is_none() {
-                        return Err(self.create_violation(
-                            SecurityViolationType::IntegerOverflow,
-                            format!("Integer overflow detected in addition: {} + {}", operands[0], operands[1]),
-                            SecuritySeverity::High,
-                        ));
-                    }
-                }
-            }
-            "multiply" | "*" => {
-                if operands.len() >= 2 {
-                    let result = operands[0].checked_mul(operands[1]);
-                    if result.is_none() {
-                        return Err(self.create_violation(
-                            SecurityViolationType::IntegerOverflow,
-                            format!("Integer overflow detected in multiplication: {} * {}", operands[0], operands[1]),
-                            SecuritySeverity::High,
-                        ));
-                    }
-                }
-            }
-            _ => {}
-        }
+        };
+        let integer_type = self.config.integer_type.unwrap_or(IntegerType::I64);
+        self.check_arithmetic(operation, integer_type, IntegerValue::from(*lhs), IntegerValue::from(*rhs))
+            .map(|_| false)
+    }
 
-        Ok(false)
+    /// Check an operation at a fixed width, returning its result under wrapping semantics
+    pub fn check_arithmetic(
+        &self,
+        operation: ArithmeticOperation,
+        integer_type: IntegerType,
+        lhs: IntegerValue,
+        rhs: IntegerValue,
+    ) -> Result<IntegerValue, SecurityViolation> {
+        let outcome = operation.evaluate(integer_type, lhs, rhs);
+        let Some(fault) = outcome.fault.filter(|_| self.config.overflow_detection) else {
+            return Ok(outcome.wrapped);
+        };
+
+        let reverts = self.config.arithmetic_mode == ArithmeticMode::Checked && operation.is_checked();
+        let consequence = if reverts {
+            "reverts under checked arithmetic".to_string()
+        } else {
+            format!("wraps to {}", outcome.wrapped)
+        };
+        let mut violation = self.create_violation(
+            SecurityViolationType::IntegerOverflow,
+            format!(
+                "{} in {} {}: {} {} {} {}",
+                fault,
+                integer_type,
+                operation.name(),
+                lhs,
+                operation.symbol(),
+                rhs,
+                consequence
+            ),
+            if reverts { SecuritySeverity::Medium } else { SecuritySeverity::High },
+        );
+        violation.context.extend([
+            ("operation".to_string(), serde_json::json!(operation)),
+            ("integer_type".to_string(), serde_json::json!(integer_type)),
+            ("lhs".to_string(), serde_json::json!(lhs)),
+            ("rhs".to_string(), serde_json::json!(rhs)),
+            ("fault".to_string(), serde_json::json!(fault)),
+            ("arithmetic_mode".to_string(), serde_json::json!(self.config.arithmetic_mode)),
+            ("wrapped_result".to_string(), serde_json::json!(outcome.wrapped)),
+            ("reverts".to_string(), serde_json::json!(reverts)),
+        ]);
+        Err(violation)
     }
 
     /// Verify access control
+    ///
+    /// The required role defaults to the one the access control policy assigns
+    /// to the function; calls without a required role are always allowed.
     pub fn verify_access_control(&self, function_name: &str, caller: &str, required_role: Option<&str>) -> Result<bool, SecurityViolation> {
-        if !self.config.access_control_verification {
-            return Ok(true);
+        let check = self.check_access(function_name, caller, required_role);
+        if check.has_permission {
+            Ok(true)
+        } else {
+            Err(self.access_violation(&check))
         }
+    }
 
-        // Simple access control check - in a real implementation, this would be more sophisticated
-        if let Some(role) = required_role {
-            if role == "admin" && !caller.ends_with("admin") {
-                return Err(self.create_violation(
-                    SecurityViolationType::AccessControlViolation,
-                    format!("Access denied: {} does not have {} role for function {}", caller, role, function_name),
-                    SecuritySeverity::Medium,
-                ));
-            }
+    /// Decide whether a caller may call a function without raising a violation
+    pub fn check_access(&self, function_name: &str, caller: &str, required_role: Option<&str>) -> AccessControlCheck {
+        let policy = &self.config.access_control;
+        let required_role = required_role.or_else(|| policy.required_role(function_name));
+        let has_permission = !self.config.access_control_verification
+            || required_role.is_none_or(|role| policy.has_role(caller, role));
+
+        AccessControlCheck {
+            function_name: function_name.to_string(),
+            caller: caller.to_string(),
+            required_role: required_role.map(str::to_string),
+            has_permission,
+            check_timestamp: self.clock.now_secs(),
         }
+    }
+
+    /// Verify access control and record the check in an execution context
+    pub fn record_access_control(
+        &self,
+        context: &mut SecureExecutionContext,
+        function_name: &str,
+        caller: &str,
+        required_role: Option<&str>,
+    ) -> Result<bool, SecurityViolation> {
+        let check = self.check_access(function_name, caller, required_role);
+        let outcome = if check.has_permission {
+            Ok(true)
+        } else {
+            Err(self.access_violation(&check))
+        };
+        context.access_control_checks.push(check);
+        outcome
+    }
 
-        Ok(true)
+    fn access_violation(&self, check: &AccessControlCheck) -> SecurityViolation {
+        let mut violation = self.create_violation(
+            SecurityViolationType::AccessControlViolation,
+            format!(
+                "Access denied: {} does not have {} role for function {}",
+                check.caller,
+                check.required_role.as_deref().unwrap_or_default(),
+                check.function_name
+            ),
+            SecuritySeverity::Medium,
+        );
+        violation
+            .context
+            .insert("function_name".to_string(), serde_json::json!(check.function_name));
+        violation
+            .context
+            .insert("required_role".to_string(), serde_json::json!(check.required_role));
+        violation
     }
 
     /// Create a security violation
     fn create_violation(&self, violation_type: SecurityViolationType, description: String, severity: SecuritySeverity) -> SecurityViolation {
-        SecurityViolation {
-            violation_type,
-            description,
-            severity,
-            timestamp: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-            context: std::collections::HashMap::new(),
-        }
+        SecurityViolation::new(violation_type, description, severity, self.clock.now_secs())
     }
 }
 
@@ -317,3 +459,36 @@ impl SecurityContext {
         (critical, high, medium, low)
     }
 }
+
+/// Check a code path before reading it
+///
+/// The path must fit in `MAX_PATH_LENGTH` bytes, and symlinks it leads
+/// through must resolve within `MAX_SYMLINK_CHAIN_LENGTH` hops.
+pub(crate) fn validate_code_path(path: &Path) -> RuntimeResult<()> {
+    if path.as_os_str().len() > MAX_PATH_LENGTH {
+        return Err(RuntimeError::InvalidInput(format!(
+            "Code path is longer than {} bytes",
+            MAX_PATH_LENGTH
+        )));
+    }
+    let mut current = path.to_path_buf();
+    for _ in 0..MAX_SYMLINK_CHAIN_LENGTH {
+        match std::fs::symlink_metadata(&current) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                let target = std::fs::read_link(&current).map_err(|error| {
+                    RuntimeError::InvalidInput(format!("Cannot resolve {}: {}", current.display(), error))
+                })?;
+                current = match current.parent() {
+                    Some(parent) if target.is_relative() => parent.join(target),
+                    _ => target,
+                };
+            }
+            _ => return Ok(()),
+        }
+    }
+    Err(RuntimeError::InvalidInput(format!(
+        "Code path {} goes through more than {} symlinks",
+        path.display(),
+        MAX_SYMLINK_CHAIN_LENGTH
+    )))
+}
@@ -0,0 +1,304 @@
+//! Loader for SBF program ELF files
+//!
+//! Allocated sections are copied into a read-only image at their addresses,
+//! which the virtual machine maps at `MM_PROGRAM_START`. Dynamic relocations
+//! are applied to the image: addresses are rebased onto the program region,
+//! and calls are rewritten to the murmur3 hash of the syscall name or of the
+//! target instruction index, which the virtual machine resolves.
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+
+use super::vm::{Syscall, MM_PROGRAM_START};
+use crate::constants::SVM_MAX_PROGRAM_SIZE;
+use crate::error::RuntimeError;
+
+const EM_BPF: u16 = 247;
+const EM_SBPF: u16 = 263;
+const SHT_REL: u32 = 9;
+const SHT_NOBITS: u32 = 8;
+const SHF_ALLOC: u64 = 2;
+const STT_FUNC: u8 = 2;
+const R_BPF_64_64: u32 = 1;
+const R_BPF_64_RELATIVE: u32 = 8;
+const R_BPF_64_32: u32 = 10;
+
+/// Program ready to run
+#[derive(Debug, Clone)]
+pub(crate) struct Executable {
+    /// Read-only image mapped at `MM_PROGRAM_START`
+    pub image: Vec<u8>,
+    /// Offset of the text section in the image
+    pub text_offset: usize,
+    /// Number of 8 byte instruction slots in the text section
+    pub instruction_count: usize,
+    /// Instruction index execution starts at
+    pub entry_pc: usize,
+    /// Instruction index of every function, by the hash calls refer to it by
+    pub functions: HashMap<u32, usize>,
+}
+
+impl Executable {
+    /// Make the function at `pc` callable, returning the hash calls use for it
+    fn register_function(&mut self, pc: usize) -> u32 {
+        let hash = murmur3_32(&(pc as u64).to_le_bytes());
+        self.functions.insert(hash, pc);
+        hash
+    }
+
+    /// 8 bytes of the instruction slot at `pc`
+    pub fn instruction(&self, pc: usize) -> Option<[u8; 8]> {
+        if pc >= self.instruction_count {
+            return None;
+        }
+        let start = self.text_offset + pc * 8;
+        self.image[start..start + 8].try_into().ok()
+    }
+}
+
+struct Section {
+    name: String,
+    kind: u32,
+    flags: u64,
+    addr: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+}
+
+/// Parse and relocate a program ELF
+pub(crate) fn load(elf: &[u8]) -> Result<Executable> {
+    let invalid = |message: &str| RuntimeError::InvalidInput(format!("Invalid program ELF: {}", message));
+    if elf.len() < 64 || &elf[..4] != b"\x7fELF" {
+        return Err(invalid("missing ELF header").into());
+    }
+    if elf[4] != 2 || elf[5] != 1 {
+        return Err(invalid("not a 64-bit little-endian ELF").into());
+    }
+    let machine = u16_at(elf, 18)?;
+    if machine != EM_BPF && machine != EM_SBPF {
+        return Err(invalid(&format!("machine {} is not BPF", machine)).into());
+    }
+    let entry = u64_at(elf, 24)?;
+    let section_offset = u64_at(elf, 40)? as usize;
+    let section_count = u16_at(elf, 60)? as usize;
+    let names_index = u16_at(elf, 62)? as usize;
+
+    let names_offset = u64_at(elf, section_offset + names_index * 64 + 24)? as usize;
+
+    let mut sections = Vec::with_capacity(section_count);
+    for index in 0..section_count {
+        let header = section_offset + index * 64;
+        sections.push(Section {
+            name: name_at(elf, names_offset + u32_at(elf, header)? as usize)?,
+            kind: u32_at(elf, header + 4)?,
+            flags: u64_at(elf, header + 8)?,
+            addr: u64_at(elf, header + 16)?,
+            offset: u64_at(elf, header + 24)?,
+            size: u64_at(elf, header + 32)?,
+            link: u32_at(elf, header + 40)?,
+        });
+    }
+
+    let text = sections
+        .iter()
+        .find(|section| section.name == ".text")
+        .ok_or_else(|| invalid("no .text section"))?;
+    let image_size = sections
+        .iter()
+        .filter(|section| section.flags & SHF_ALLOC != 0)
+        .map(|section| section.addr.saturating_add(section.size))
+        .max()
+        .unwrap_or_default();
+    if image_size > SVM_MAX_PROGRAM_SIZE as u64 {
+        return Err(invalid("program is too large").into());
+    }
+    let mut image = vec![0u8; image_size as usize];
+    for section in sections.iter().filter(|s| s.flags & SHF_ALLOC != 0 && s.kind != SHT_NOBITS) {
+        let data = bytes_at(elf, section.offset as usize, section.size as usize)?;
+        image[section.addr as usize..(section.addr + section.size) as usize].copy_from_slice(data);
+    }
+
+    let text_range = text.addr..text.addr + text.size;
+    if entry < text.addr || entry >= text_range.end || !(entry - text.addr).is_multiple_of(8) {
+        return Err(invalid("entry point is outside .text").into());
+    }
+    let mut executable = Executable {
+        image,
+        text_offset: text.addr as usize,
+        instruction_count: (text.size / 8) as usize,
+        entry_pc: ((entry - text.addr) / 8) as usize,
+        functions: HashMap::new(),
+    };
+
+    for relocations in sections.iter().filter(|section| section.kind == SHT_REL) {
+        let symbols = sections
+            .get(relocations.link as usize)
+            .ok_or_else(|| invalid("relocations without a symbol table"))?;
+        let strings = sections
+            .get(symbols.link as usize)
+            .ok_or_else(|| invalid("symbol table without a string table"))?;
+        for entry in 0..(relocations.size / 16) as usize {
+            let at = relocations.offset as usize + entry * 16;
+            let offset = u64_at(elf, at)? as usize;
+            let info = u64_at(elf, at + 8)?;
+            let symbol = symbols.offset as usize + (info >> 32) as usize * 24;
+            let kind = info as u32;
+            relocate(&mut executable, elf, kind, offset, symbol, strings, &text_range).map_err(|error| {
+                RuntimeError::InvalidInput(format!("Invalid program ELF: relocation at {:#x}: {}", offset, error))
+            })?;
+        }
+    }
+    fixup_relative_calls(&mut executable)?;
+    Ok(executable)
+}
+
+/// Rewrite calls by relative offset, which the compiler emits for functions
+/// in the same object, to calls by hash
+fn fixup_relative_calls(executable: &mut Executable) -> Result<()> {
+    for pc in 0..executable.instruction_count {
+        let slot = executable.text_offset + pc * 8;
+        let insn = &executable.image[slot..slot + 8];
+        let imm = i32::from_le_bytes([insn[4], insn[5], insn[6], insn[7]]);
+        if insn[0] != 0x85 || insn[1] >> 4 != 1 || imm == -1 {
+            continue;
+        }
+        let target = pc as i64 + 1 + imm as i64;
+        if target < 0 || target >= executable.instruction_count as i64 {
+            return Err(RuntimeError::InvalidInput(format!(
+                "Invalid program ELF: call at instruction {} leaves .text",
+                pc
+            ))
+            .into());
+        }
+        let hash = executable.register_function(target as usize);
+        executable.image[slot + 1] &= 0x0f;
+        executable.image[slot + 4..slot + 8].copy_from_slice(&hash.to_le_bytes());
+    }
+    Ok(())
+}
+
+fn relocate(
+    executable: &mut Executable,
+    elf: &[u8],
+    kind: u32,
+    offset: usize,
+    symbol: usize,
+    strings: &Section,
+    text: &std::ops::Range<u64>,
+) -> Result<()> {
+    let image = &mut executable.image;
+    match kind {
+        R_BPF_64_64 => {
+            // lddw loading a symbol address, with the addend in its immediate
+            let address = u64_at(elf, symbol + 8)?.wrapping_add(u32_at(image, offset + 4)? as u64);
+            write_lddw(image, offset, rebase(address))
+        }
+        R_BPF_64_RELATIVE if text.contains(&(offset as u64)) => {
+            let address = u32_at(image, offset + 4)? as u64 | (u32_at(image, offset + 12)? as u64) << 32;
+            write_lddw(image, offset, rebase(address))
+        }
+        R_BPF_64_RELATIVE => {
+            let address = u64_at(image, offset)?;
+            image
+                .get_mut(offset..offset + 8)
+                .context("out of bounds")?
+                .copy_from_slice(&rebase(address).to_le_bytes());
+            Ok(())
+        }
+        R_BPF_64_32 => {
+            let value = u64_at(elf, symbol + 8)?;
+            let is_function = elf.get(symbol + 4).is_some_and(|info| info & 0xf == STT_FUNC);
+            let hash = if is_function && value != 0 {
+                if !text.contains(&value) {
+                    bail!("call target outside .text");
+                }
+                executable.register_function(((value - text.start) / 8) as usize)
+            } else {
+                let name = name_at(elf, strings.offset as usize + u32_at(elf, symbol)? as usize)?;
+                let hash = murmur3_32(name.as_bytes());
+                if Syscall::from_hash(hash).is_none() {
+                    bail!("unknown syscall {}", name);
+                }
+                hash
+            };
+            executable
+                .image
+                .get_mut(offset + 4..offset + 8)
+                .context("out of bounds")?
+                .copy_from_slice(&hash.to_le_bytes());
+            Ok(())
+        }
+        _ => bail!("unsupported relocation type {}", kind),
+    }
+}
+
+/// Address of something in the image as seen by the program
+fn rebase(address: u64) -> u64 {
+    if address < MM_PROGRAM_START {
+        address + MM_PROGRAM_START
+    } else {
+        address
+    }
+}
+
+/// Split a 64-bit value over the immediates of an `lddw` pair
+fn write_lddw(image: &mut [u8], offset: usize, value: u64) -> Result<()> {
+    let slots = image.get_mut(offset..offset + 16).context("out of bounds")?;
+    slots[4..8].copy_from_slice(&(value as u32).to_le_bytes());
+    slots[12..16].copy_from_slice(&((value >> 32) as u32).to_le_bytes());
+    Ok(())
+}
+
+/// 32-bit murmur3 hash with a zero seed, which symbols are called by
+pub(crate) fn murmur3_32(data: &[u8]) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+    let scramble = |k: u32| k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+
+    let mut hash = 0u32;
+    let chunks = data.chunks_exact(4);
+    let tail = chunks.remainder();
+    for chunk in chunks {
+        hash ^= scramble(u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+        hash = hash.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+    if !tail.is_empty() {
+        let k = tail.iter().rev().fold(0u32, |k, byte| (k << 8) | *byte as u32);
+        hash ^= scramble(k);
+    }
+    hash ^= data.len() as u32;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^ (hash >> 16)
+}
+
+fn bytes_at(data: &[u8], offset: usize, len: usize) -> Result<&[u8]> {
+    offset
+        .checked_add(len)
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(|| RuntimeError::InvalidInput("Invalid program ELF: truncated".to_string()).into())
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16> {
+    Ok(u16::from_le_bytes(bytes_at(data, offset, 2)?.try_into()?))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(bytes_at(data, offset, 4)?.try_into()?))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Result<u64> {
+    Ok(u64::from_le_bytes(bytes_at(data, offset, 8)?.try_into()?))
+}
+
+/// NUL-terminated string at `offset`
+fn name_at(elf: &[u8], offset: usize) -> Result<String> {
+    let rest = elf
+        .get(offset..)
+        .ok_or_else(|| RuntimeError::InvalidInput("Invalid program ELF: truncated".to_string()))?;
+    let end = rest.iter().position(|byte| *byte == 0).unwrap_or(rest.len());
+    Ok(String::from_utf8_lossy(&rest[..end]).into_owned())
+}
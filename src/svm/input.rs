@@ -0,0 +1,108 @@
+//! Instruction input in the aligned loader layout
+//!
+//! The input region starts with the number of accounts, followed by each
+//! account and then the instruction data and program id. An account listed
+//! twice is written once; later positions only carry the index of the first.
+
+use std::collections::BTreeMap;
+
+use super::pubkey::Pubkey;
+use super::Account;
+use crate::constants::{SVM_MAX_ACCOUNT_DATA_LENGTH, SVM_MAX_PERMITTED_DATA_INCREASE};
+
+const NON_DUPLICATE: u8 = u8::MAX;
+const RENT_EXEMPT_EPOCH: u64 = u64::MAX;
+
+/// Account passed to an instruction
+#[derive(Debug, Clone)]
+pub(crate) struct AccountMeta {
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+/// Where an account was written in the input
+pub(crate) struct SerializedAccount {
+    pub pubkey: Pubkey,
+    /// Offset of the owner, which the lamports and data follow
+    pub offset: usize,
+    pub original_data_len: usize,
+}
+
+/// Serialize accounts and instruction data for the program's entrypoint
+pub(crate) fn serialize(
+    metas: &[AccountMeta],
+    accounts: &BTreeMap<Pubkey, Account>,
+    data: &[u8],
+    program_id: &Pubkey,
+) -> (Vec<u8>, Vec<SerializedAccount>) {
+    let mut input = Vec::new();
+    let mut layout: Vec<SerializedAccount> = Vec::new();
+    input.extend_from_slice(&(metas.len() as u64).to_le_bytes());
+
+    for (index, meta) in metas.iter().enumerate() {
+        if let Some(first) = metas[..index].iter().position(|m| m.pubkey == meta.pubkey) {
+            input.push(first as u8);
+            input.extend_from_slice(&[0; 7]);
+            continue;
+        }
+        let account = &accounts[&meta.pubkey];
+        // Privileges of an account listed more than once are merged
+        let same = metas.iter().filter(|m| m.pubkey == meta.pubkey);
+        let is_signer = same.clone().any(|m| m.is_signer);
+        let is_writable = same.clone().any(|m| m.is_writable);
+
+        input.extend_from_slice(&[NON_DUPLICATE, is_signer as u8, is_writable as u8, account.executable as u8]);
+        input.extend_from_slice(&[0; 4]);
+        input.extend_from_slice(&meta.pubkey.0);
+        layout.push(SerializedAccount {
+            pubkey: meta.pubkey,
+            offset: input.len(),
+            original_data_len: account.data.len(),
+        });
+        input.extend_from_slice(&account.owner.0);
+        input.extend_from_slice(&account.lamports.to_le_bytes());
+        input.extend_from_slice(&(account.data.len() as u64).to_le_bytes());
+        input.extend_from_slice(&account.data);
+        // Room for the program to grow the data, keeping what follows aligned
+        let padding = SVM_MAX_PERMITTED_DATA_INCREASE + (8 - account.data.len() % 8) % 8;
+        input.resize(input.len() + padding, 0);
+        input.extend_from_slice(&RENT_EXEMPT_EPOCH.to_le_bytes());
+    }
+
+    input.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    input.extend_from_slice(data);
+    input.extend_from_slice(&program_id.0);
+    (input, layout)
+}
+
+/// Read back the owner, lamports and data of every account the program was given
+pub(crate) fn deserialize(
+    input: &[u8],
+    layout: &[SerializedAccount],
+    before: &BTreeMap<Pubkey, Account>,
+) -> Result<BTreeMap<Pubkey, Account>, String> {
+    let mut after = BTreeMap::new();
+    for serialized in layout {
+        let at = serialized.offset;
+        let owner: [u8; 32] = input[at..at + 32].try_into().unwrap_or_default();
+        let lamports = u64::from_le_bytes(input[at + 32..at + 40].try_into().unwrap_or_default());
+        let data_len = u64::from_le_bytes(input[at + 40..at + 48].try_into().unwrap_or_default());
+        if data_len > (serialized.original_data_len + SVM_MAX_PERMITTED_DATA_INCREASE) as u64
+            || data_len > SVM_MAX_ACCOUNT_DATA_LENGTH as u64
+        {
+            return Err(format!("Account {} data grew to {} bytes, beyond what is permitted", serialized.pubkey, data_len));
+        }
+        let data = input[at + 48..at + 48 + data_len as usize].to_vec();
+        after.insert(
+            serialized.pubkey,
+            Account {
+                lamports,
+                data,
+                owner: Pubkey(owner),
+                executable: before[&serialized.pubkey].executable,
+            },
+        );
+    }
+    Ok(after)
+}
@@ -0,0 +1,829 @@
+//! Embedded SVM backend
+//!
+//! Runs Solana programs compiled to SBF in process. `deploy_contract` loads a
+//! program ELF and returns its program id, `execute` deploys the ELF stored at
+//! `code_path` on first use before running one instruction against it, and
+//! `call_function` runs an instruction without accounts whose data is `args`.
+//! Compute units consumed are reported as the `compute_units` metric, program
+//! logs as `log` events, and the lamports, data and owners the program changes
+//! as state changes. An instruction that fails leaves every account untouched.
+//! Programs see a clock at slot zero reading the environment's time.
+//!
+//! `ExecutionInputs::parameters` understands the following keys:
+//!
+//! - `data`: hex encoded instruction data
+//! - `accounts`: array of account addresses, which are passed writable, or of
+//!   `{"pubkey", "is_signer", "is_writable"}` objects
+//! - `compute_unit_limit`: compute budget of the instruction, defaulting to 200,000
+
+mod elf;
+mod input;
+mod pubkey;
+mod vm;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use crate::access_control::AccessControlPolicy;
+use crate::arithmetic::IntegerType;
+use crate::config::RuntimeConfig;
+use crate::constants::{DEFAULT_SENDER_ADDRESS, SVM_DEFAULT_COMPUTE_UNIT_LIMIT, SVM_MAX_COMPUTE_UNIT_LIMIT};
+use crate::determinism::Entropy;
+use crate::enforcement::SecurityEnforcer;
+use crate::error::RuntimeError;
+use crate::events::{EventBus, EventFilter, EventSubscription};
+use crate::evm::keccak256;
+use crate::info;
+use crate::report::SecurityReport;
+use crate::runtime::BlockchainRuntime;
+use crate::security::{validate_code_path, SecurityConfig, SecurityValidator};
+use crate::types::{
+    EnvironmentState, ExecutionInputs, ExecutionResult, MetricType, RuntimeCapabilities, RuntimeEnvironment,
+    RuntimeEvent, RuntimeMetricDefinition, RuntimeType, SecurityViolation, SnapshotId, StateChange, StateChangeType,
+};
+use elf::Executable;
+use input::AccountMeta;
+use pubkey::Pubkey;
+
+/// Loader owning every deployed program
+const BPF_LOADER_ID: &str = "BPFLoader2111111111111111111111111111111111";
+
+/// Account stored in the SVM world state
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SvmAccount {
+    pub lamports: u64,
+    pub data: Vec<u8>,
+    /// Base58 address of the owning program
+    pub owner: String,
+    pub executable: bool,
+}
+
+/// Account as the runtime keeps it, with the owner decoded
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Account {
+    lamports: u64,
+    data: Vec<u8>,
+    owner: Pubkey,
+    executable: bool,
+}
+
+impl Account {
+    fn is_empty(&self) -> bool {
+        self.lamports == 0 && self.data.is_empty() && self.owner == Pubkey::default() && !self.executable
+    }
+}
+
+/// Everything about an environment that snapshots capture
+#[derive(Clone)]
+struct SvmWorld {
+    accounts: BTreeMap<Pubkey, Account>,
+    programs: HashMap<Pubkey, Arc<Executable>>,
+    deployments: HashMap<PathBuf, Pubkey>,
+    deployment_nonce: u64,
+    access_control: AccessControlPolicy,
+}
+
+/// Environment bookkeeping for the SVM backend
+struct SvmEnvironment {
+    config: RuntimeConfig,
+    world: SvmWorld,
+    snapshots: BTreeMap<u64, SvmWorld>,
+    snapshot_nonce: u64,
+    /// Executions outlive reverts so their ids stay unique and resolvable
+    executions: HashMap<String, ExecutionResult>,
+    execution_nonce: u64,
+    event_bus: EventBus,
+    entropy: Entropy,
+}
+
+/// Blockchain runtime backed by an embedded SBF interpreter
+pub struct SvmRuntime {
+    blockchain_id: String,
+    capabilities: RuntimeCapabilities,
+    environments: RwLock<HashMap<String, SvmEnvironment>>,
+    environment_nonce: AtomicU64,
+    entropy: Option<Entropy>,
+}
+
+impl SvmRuntime {
+    /// Create a new SVM runtime
+    pub fn new(blockchain_id: String) -> Self {
+        Self {
+            blockchain_id,
+            capabilities: RuntimeCapabilities {
+                supports_snapshots: true,
+                supports_event_subscriptions: true,
+                supports_access_control: true,
+                ..RuntimeCapabilities::default()
+            },
+            environments: RwLock::new(HashMap::new()),
+            environment_nonce: AtomicU64::new(0),
+            entropy: None,
+        }
+    }
+
+    /// Take time and randomness for every environment from `entropy`,
+    /// ignoring `deterministic_seed`
+    pub fn with_entropy(mut self, entropy: Entropy) -> Self {
+        self.entropy = Some(entropy);
+        self
+    }
+
+    /// Get an account from the world state
+    pub fn account(&self, env: &RuntimeEnvironment, pubkey: &str) -> Result<Option<SvmAccount>> {
+        let pubkey: Pubkey = pubkey.parse()?;
+        self.with_environment(env, |environment| {
+            Ok(environment.world.accounts.get(&pubkey).map(|account| SvmAccount {
+                lamports: account.lamports,
+                data: account.data.clone(),
+                owner: account.owner.to_string(),
+                executable: account.executable,
+            }))
+        })
+    }
+
+    /// Create or replace an account
+    pub fn set_account(&self, env: &RuntimeEnvironment, pubkey: &str, account: SvmAccount) -> Result<()> {
+        let pubkey: Pubkey = pubkey.parse()?;
+        let account = Account {
+            lamports: account.lamports,
+            data: account.data,
+            owner: account.owner.parse()?,
+            executable: account.executable,
+        };
+        self.with_environment_mut(env, |environment| {
+            environment.world.accounts.insert(pubkey, account);
+            Ok(())
+        })
+    }
+
+    fn with_environment<T>(
+        &self,
+        env: &RuntimeEnvironment,
+        f: impl FnOnce(&SvmEnvironment) -> Result<T>,
+    ) -> Result<T> {
+        let environments = self
+            .environments
+            .read()
+            .map_err(|_| RuntimeError::Backend("SVM runtime state is poisoned".to_string()))?;
+        let environment = environments
+            .get(&env.environment_id)
+            .ok_or_else(|| RuntimeError::EnvironmentNotFound(env.environment_id.clone()))?;
+        f(environment)
+    }
+
+    fn with_environment_mut<T>(
+        &self,
+        env: &RuntimeEnvironment,
+        f: impl FnOnce(&mut SvmEnvironment) -> Result<T>,
+    ) -> Result<T> {
+        let mut environments = self
+            .environments
+            .write()
+            .map_err(|_| RuntimeError::Backend("SVM runtime state is poisoned".to_string()))?;
+        let environment = environments
+            .get_mut(&env.environment_id)
+            .ok_or_else(|| RuntimeError::EnvironmentNotFound(env.environment_id.clone()))?;
+        f(environment)
+    }
+
+    /// Environment security configuration carrying its live access control policy
+    fn security_config(&self, env: &RuntimeEnvironment) -> Result<SecurityConfig> {
+        self.with_environment(env, |environment| {
+            Ok(SecurityConfig {
+                access_control: environment.world.access_control.clone(),
+                ..environment.config.security_config.clone()
+            })
+        })
+    }
+
+    fn entropy(&self, env: &RuntimeEnvironment) -> Result<Entropy> {
+        self.with_environment(env, |environment| Ok(environment.entropy.clone()))
+    }
+
+    /// Stream an execution's events to subscribers once it is kept
+    fn publish(&self, env: &RuntimeEnvironment, result: &ExecutionResult) -> Result<()> {
+        self.with_environment_mut(env, |environment| {
+            environment.event_bus.publish(&result.events);
+            Ok(())
+        })
+    }
+
+    /// Run an instruction and record its result
+    ///
+    /// Events are recorded but not published, as the caller may still roll
+    /// the execution back.
+    async fn run_execution(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+    ) -> Result<ExecutionResult> {
+        let instruction = PreparedInstruction::new(inputs)?;
+        let elf = self.read_program(env, code_path).await?;
+        self.with_environment_mut(env, |environment| {
+            environment.run(&env.environment_id, code_path, elf, &instruction)
+        })
+    }
+
+    /// Read a program's ELF unless it is already deployed in the environment
+    async fn read_program(&self, env: &RuntimeEnvironment, code_path: &Path) -> Result<Option<Vec<u8>>> {
+        let deployed = self.with_environment(env, |environment| {
+            Ok(environment.world.deployments.contains_key(code_path))
+        })?;
+        if deployed {
+            return Ok(None);
+        }
+        validate_code_path(code_path)?;
+        let elf = tokio::fs::read(code_path)
+            .await
+            .with_context(|| format!("Failed to read program from {}", code_path.display()))?;
+        Ok(Some(elf))
+    }
+}
+
+impl SvmEnvironment {
+    /// Process an instruction, deploying the program from `elf` on first use
+    fn run(
+        &mut self,
+        environment_id: &str,
+        code_path: &Path,
+        elf: Option<Vec<u8>>,
+        instruction: &PreparedInstruction,
+    ) -> Result<ExecutionResult> {
+        let clock = self.entropy.clock().clone();
+        let started_ms = clock.now_millis();
+        let world = &mut self.world;
+        let program_id = match (world.deployments.get(code_path), elf) {
+            (Some(program_id), _) => *program_id,
+            (None, Some(elf)) => {
+                let program_id = world
+                    .deploy(&elf)
+                    .with_context(|| format!("Deployment of {} failed", code_path.display()))?;
+                world.deployments.insert(code_path.to_path_buf(), program_id);
+                program_id
+            }
+            (None, None) => bail!("Program {} was removed while executing", code_path.display()),
+        };
+
+        self.execution_nonce += 1;
+        let execution_id = format!("{}_exec_{}", environment_id, self.execution_nonce);
+        let mut result = world.process_instruction(execution_id, program_id, instruction, clock.now_secs())?;
+        result.execution_time_ms = clock.now_millis().saturating_sub(started_ms);
+        result
+            .metrics
+            .insert("execution_time".to_string(), serde_json::json!(result.execution_time_ms));
+
+        self.executions.insert(result.execution_id.clone(), result.clone());
+        Ok(result)
+    }
+}
+
+/// Instruction described by `ExecutionInputs`
+struct PreparedInstruction {
+    accounts: Vec<AccountMeta>,
+    data: Vec<u8>,
+    compute_unit_limit: u64,
+}
+
+impl PreparedInstruction {
+    fn new(inputs: &ExecutionInputs) -> Result<Self> {
+        let parameters = &inputs.parameters;
+        let data = match parameters.get("data") {
+            Some(data) => {
+                let data = data.as_str().ok_or_else(|| anyhow!("data must be a hex string"))?;
+                hex::decode(data.trim_start_matches("0x")).context("data must be a hex string")?
+            }
+            None => Vec::new(),
+        };
+
+        let accounts = match parameters.get("accounts") {
+            Some(accounts) => accounts
+                .as_array()
+                .ok_or_else(|| anyhow!("accounts must be an array"))?
+                .iter()
+                .map(parse_account_meta)
+                .collect::<Result<Vec<_>>>()?,
+            None => Vec::new(),
+        };
+        // Duplicates refer back to the first position with a single byte
+        if accounts.len() > u8::MAX as usize {
+            return Err(RuntimeError::InvalidInput(format!("{} accounts exceed {}", accounts.len(), u8::MAX)).into());
+        }
+
+        let compute_unit_limit = match parameters.get("compute_unit_limit") {
+            Some(limit) => limit
+                .as_u64()
+                .ok_or_else(|| anyhow!("compute_unit_limit must be an unsigned integer"))?,
+            None => SVM_DEFAULT_COMPUTE_UNIT_LIMIT,
+        };
+        if compute_unit_limit > SVM_MAX_COMPUTE_UNIT_LIMIT {
+            return Err(RuntimeError::InvalidInput(format!(
+                "compute_unit_limit {} exceeds {}",
+                compute_unit_limit, SVM_MAX_COMPUTE_UNIT_LIMIT
+            ))
+            .into());
+        }
+
+        Ok(Self { accounts, data, compute_unit_limit })
+    }
+}
+
+fn parse_account_meta(value: &serde_json::Value) -> Result<AccountMeta> {
+    if let Some(pubkey) = value.as_str() {
+        return Ok(AccountMeta { pubkey: pubkey.parse()?, is_signer: false, is_writable: true });
+    }
+    let pubkey = value
+        .get("pubkey")
+        .and_then(|pubkey| pubkey.as_str())
+        .ok_or_else(|| anyhow!("Account {} has no pubkey", value))?;
+    let flag = |name: &str| value.get(name).and_then(|flag| flag.as_bool()).unwrap_or(false);
+    Ok(AccountMeta {
+        pubkey: pubkey.parse()?,
+        is_signer: flag("is_signer"),
+        is_writable: flag("is_writable"),
+    })
+}
+
+impl SvmWorld {
+    /// Load a program ELF under an id derived from it
+    fn deploy(&mut self, elf: &[u8]) -> Result<Pubkey> {
+        let executable = elf::load(elf)?;
+        self.deployment_nonce += 1;
+        let mut seed = b"program".to_vec();
+        seed.extend_from_slice(&self.deployment_nonce.to_le_bytes());
+        seed.extend_from_slice(elf);
+        let program_id = Pubkey(keccak256(&seed));
+
+        self.accounts.insert(
+            program_id,
+            Account {
+                lamports: 1,
+                data: elf.to_vec(),
+                owner: BPF_LOADER_ID.parse()?,
+                executable: true,
+            },
+        );
+        self.programs.insert(program_id, Arc::new(executable));
+        Ok(program_id)
+    }
+
+    /// Run an instruction, applying its account changes only if it succeeds
+    fn process_instruction(
+        &mut self,
+        execution_id: String,
+        program_id: Pubkey,
+        instruction: &PreparedInstruction,
+        timestamp: u64,
+    ) -> Result<ExecutionResult> {
+        let executable = self
+            .programs
+            .get(&program_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Program {} is not deployed", program_id))?;
+        let before: BTreeMap<Pubkey, Account> = instruction
+            .accounts
+            .iter()
+            .map(|meta| (meta.pubkey, self.accounts.get(&meta.pubkey).cloned().unwrap_or_default()))
+            .collect();
+
+        let (input, layout) = input::serialize(&instruction.accounts, &before, &instruction.data, &program_id);
+        let outcome = vm::run(&executable, input, program_id, instruction.compute_unit_limit, timestamp);
+
+        let mut result = ExecutionResult::new(execution_id.clone(), false);
+        result
+            .metrics
+            .insert("compute_units".to_string(), serde_json::json!(outcome.compute_units));
+        result.return_value = Some(serde_json::json!(format!("0x{}", hex::encode(&outcome.return_data))));
+        result.events = outcome
+            .logs
+            .iter()
+            .enumerate()
+            .map(|(index, message)| RuntimeEvent {
+                event_id: format!("{}_log_{}", execution_id, index),
+                event_type: "log".to_string(),
+                timestamp,
+                data: HashMap::from([
+                    ("program_id".to_string(), serde_json::json!(program_id.to_string())),
+                    ("message".to_string(), serde_json::json!(message)),
+                ]),
+            })
+            .collect();
+        let context = &mut result.security_context;
+        context.call_depth = outcome.max_call_depth;
+        context.gas_used = outcome.compute_units;
+        context.call_stack = vec![program_id.to_string()];
+
+        let after = match outcome.result {
+            Ok(0) => input::deserialize(&outcome.input, &layout, &before)
+                .and_then(|after| verify_changes(&program_id, &instruction.accounts, &before, &after).map(|_| after)),
+            Ok(code) => Err(format!("Program {} returned error code {:#x}", program_id, code)),
+            Err(error) => Err(format!("Program {} failed: {}", program_id, error)),
+        };
+        match after {
+            Ok(after) => {
+                result.success = true;
+                result.state_changes = state_changes(&before, &after);
+                for (pubkey, account) in after {
+                    if account.is_empty() {
+                        self.accounts.remove(&pubkey);
+                    } else {
+                        self.accounts.insert(pubkey, account);
+                    }
+                }
+            }
+            Err(error) => result.error = Some(error),
+        }
+        Ok(result)
+    }
+}
+
+/// Reject account changes a validator would not accept from the program
+fn verify_changes(
+    program_id: &Pubkey,
+    metas: &[AccountMeta],
+    before: &BTreeMap<Pubkey, Account>,
+    after: &BTreeMap<Pubkey, Account>,
+) -> Result<(), String> {
+    for (pubkey, old) in before {
+        let new = &after[pubkey];
+        if new == old {
+            continue;
+        }
+        let writable = metas.iter().any(|meta| meta.pubkey == *pubkey && meta.is_writable);
+        if !writable {
+            return Err(format!("Instruction modified read-only account {}", pubkey));
+        }
+        if old.executable {
+            return Err(format!("Instruction modified executable account {}", pubkey));
+        }
+        if old.owner != *program_id {
+            if new.data != old.data {
+                return Err(format!("Instruction modified data of account {} it does not own", pubkey));
+            }
+            if new.lamports < old.lamports {
+                return Err(format!("Instruction spent lamports of account {} it does not own", pubkey));
+            }
+            if new.owner != old.owner {
+                return Err(format!("Instruction changed the owner of account {} it does not own", pubkey));
+            }
+        } else if new.owner != old.owner && new.data.iter().any(|byte| *byte != 0) {
+            return Err(format!("Instruction assigned account {} without zeroing its data", pubkey));
+        }
+    }
+
+    let total = |accounts: &BTreeMap<Pubkey, Account>| accounts.values().map(|a| a.lamports as u128).sum::<u128>();
+    if total(before) != total(after) {
+        return Err("Instruction changed the total lamports of its accounts".to_string());
+    }
+    Ok(())
+}
+
+/// Lamport, data and owner changes, with empty values treated as absent
+fn state_changes(before: &BTreeMap<Pubkey, Account>, after: &BTreeMap<Pubkey, Account>) -> Vec<StateChange> {
+    let mut changes = Vec::new();
+    for (pubkey, old) in before {
+        let new = &after[pubkey];
+        let fields = [
+            (
+                "lamports",
+                (old.lamports != 0).then(|| serde_json::json!(old.lamports)),
+                (new.lamports != 0).then(|| serde_json::json!(new.lamports)),
+            ),
+            (
+                "data",
+                (!old.data.is_empty()).then(|| serde_json::json!(format!("0x{}", hex::encode(&old.data)))),
+                (!new.data.is_empty()).then(|| serde_json::json!(format!("0x{}", hex::encode(&new.data)))),
+            ),
+            (
+                "owner",
+                Some(serde_json::json!(old.owner.to_string())),
+                Some(serde_json::json!(new.owner.to_string())),
+            ),
+        ];
+        for (field, old, new) in fields {
+            let change_type = match (&old, &new) {
+                (None, None) => continue,
+                (Some(o), Some(n)) if o == n => continue,
+                (None, Some(_)) => StateChangeType::Created,
+                (Some(_), None) => StateChangeType::Deleted,
+                (Some(_), Some(_)) => StateChangeType::Updated,
+            };
+            changes.push(StateChange {
+                key: format!("{}:{}", pubkey, field),
+                old_value: old,
+                new_value: new.unwrap_or(serde_json::Value::Null),
+                change_type,
+            });
+        }
+    }
+    changes
+}
+
+#[async_trait]
+impl BlockchainRuntime for SvmRuntime {
+    fn blockchain_id(&self) -> &str {
+        &self.blockchain_id
+    }
+
+    async fn create_environment(&self, config: RuntimeConfig) -> Result<RuntimeEnvironment> {
+        config.validate()?;
+
+        let nonce = self.environment_nonce.fetch_add(1, Ordering::SeqCst) + 1;
+        let environment_id = format!("svm_{}", nonce);
+        info!("Creating SVM environment {}", environment_id);
+
+        let entropy = self.entropy.clone().unwrap_or_else(|| Entropy::from_config(&config));
+        let access_control = config.security_config.access_control.clone();
+        self.environments
+            .write()
+            .map_err(|_| RuntimeError::Backend("SVM runtime state is poisoned".to_string()))?
+            .insert(
+                environment_id.clone(),
+                SvmEnvironment {
+                    config,
+                    world: SvmWorld {
+                        accounts: BTreeMap::new(),
+                        programs: HashMap::new(),
+                        deployments: HashMap::new(),
+                        deployment_nonce: 0,
+                        access_control,
+                    },
+                    snapshots: BTreeMap::new(),
+                    snapshot_nonce: 0,
+                    executions: HashMap::new(),
+                    execution_nonce: 0,
+                    event_bus: EventBus::default(),
+                    entropy,
+                },
+            );
+
+        Ok(RuntimeEnvironment {
+            endpoint_url: format!("svm://{}", environment_id),
+            environment_id,
+            blockchain_id: self.blockchain_id.clone(),
+            runtime_type: RuntimeType::InMemory,
+            state: EnvironmentState::Ready,
+            metadata: HashMap::new(),
+        })
+    }
+
+    async fn execute(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+    ) -> Result<ExecutionResult> {
+        let result = self.run_execution(env, code_path, inputs).await?;
+        self.publish(env, &result)?;
+        Ok(result)
+    }
+
+    async fn deploy_contract(
+        &self,
+        env: &RuntimeEnvironment,
+        bytecode: &[u8],
+        _constructor_args: &[u8],
+    ) -> Result<String> {
+        if bytecode.is_empty() {
+            bail!("Cannot deploy an empty program");
+        }
+        self.with_environment_mut(env, |environment| Ok(environment.world.deploy(bytecode)?.to_string()))
+    }
+
+    async fn call_function(
+        &self,
+        env: &RuntimeEnvironment,
+        contract_address: &str,
+        function: &str,
+        args: &[u8],
+    ) -> Result<Vec<u8>> {
+        let program_id: Pubkey = contract_address.parse()?;
+        let instruction = PreparedInstruction {
+            accounts: Vec::new(),
+            data: args.to_vec(),
+            compute_unit_limit: SVM_DEFAULT_COMPUTE_UNIT_LIMIT,
+        };
+
+        self.with_environment_mut(env, |environment| {
+            let timestamp = environment.entropy.clock().now_secs();
+            let execution_id = format!("{}_call", env.environment_id);
+            let result = environment
+                .world
+                .process_instruction(execution_id, program_id, &instruction, timestamp)?;
+            if !result.success {
+                bail!(
+                    "Call to {}::{} failed: {}",
+                    contract_address,
+                    function,
+                    result.error.unwrap_or_default()
+                );
+            }
+            let return_data = result.return_value.as_ref().and_then(|value| value.as_str()).unwrap_or_default();
+            Ok(hex::decode(return_data.trim_start_matches("0x"))?)
+        })
+    }
+
+    fn metrics_definition(&self) -> Vec<RuntimeMetricDefinition> {
+        vec![
+            RuntimeMetricDefinition {
+                name: "compute_units".to_string(),
+                description: "Compute units consumed during execution".to_string(),
+                unit: "CU".to_string(),
+                metric_type: MetricType::ComputeUnits,
+            },
+            RuntimeMetricDefinition {
+                name: "execution_time".to_string(),
+                description: "Time taken to execute".to_string(),
+                unit: "ms".to_string(),
+                metric_type: MetricType::Time,
+            },
+        ]
+    }
+
+    async fn monitor(
+        &self,
+        env: &RuntimeEnvironment,
+        execution_id: &str,
+    ) -> Result<Vec<RuntimeEvent>> {
+        self.with_environment(env, |environment| {
+            environment
+                .executions
+                .get(execution_id)
+                .map(|result| result.events.clone())
+                .ok_or_else(|| RuntimeError::ExecutionNotFound(execution_id.to_string()).into())
+        })
+    }
+
+    async fn destroy(&self, env: RuntimeEnvironment) -> Result<()> {
+        info!("Destroying SVM environment {}", env.environment_id);
+        self.environments
+            .write()
+            .map_err(|_| RuntimeError::Backend("SVM runtime state is poisoned".to_string()))?
+            .remove(&env.environment_id)
+            .map(|_| ())
+            .ok_or_else(|| RuntimeError::EnvironmentNotFound(env.environment_id.clone()).into())
+    }
+
+    async fn is_available(&self) -> bool {
+        true
+    }
+
+    fn capabilities(&self) -> RuntimeCapabilities {
+        self.capabilities.clone()
+    }
+
+    async fn snapshot(&self, env: &RuntimeEnvironment) -> Result<SnapshotId> {
+        self.with_environment_mut(env, |environment| {
+            environment.snapshot_nonce += 1;
+            let id = environment.snapshot_nonce;
+            environment.snapshots.insert(id, environment.world.clone());
+            Ok(SnapshotId(id))
+        })
+    }
+
+    async fn revert(&self, env: &RuntimeEnvironment, snapshot: SnapshotId) -> Result<()> {
+        self.with_environment_mut(env, |environment| {
+            let world = environment
+                .snapshots
+                .get(&snapshot.0)
+                .cloned()
+                .ok_or_else(|| RuntimeError::SnapshotNotFound {
+                    environment_id: env.environment_id.clone(),
+                    snapshot,
+                })?;
+            environment.snapshots.split_off(&(snapshot.0 + 1));
+            environment.world = world;
+            Ok(())
+        })
+    }
+
+    async fn subscribe(&self, env: &RuntimeEnvironment, filter: EventFilter) -> Result<EventSubscription> {
+        self.with_environment_mut(env, |environment| Ok(environment.event_bus.subscribe(filter)))
+    }
+
+    async fn grant_role(&self, env: &RuntimeEnvironment, account: &str, role: &str) -> Result<()> {
+        self.with_environment_mut(env, |environment| Ok(environment.world.access_control.grant_role(account, role)?))
+    }
+
+    async fn revoke_role(&self, env: &RuntimeEnvironment, account: &str, role: &str) -> Result<bool> {
+        self.with_environment_mut(env, |environment| Ok(environment.world.access_control.revoke_role(account, role)))
+    }
+
+    async fn execute_secure(
+        &self,
+        env: &RuntimeEnvironment,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+        security_config: &SecurityConfig,
+    ) -> Result<ExecutionResult> {
+        let instruction = PreparedInstruction::new(inputs)?;
+        let elf = self.read_program(env, code_path).await?;
+
+        // The world is held from the snapshot to the rollback, so no other
+        // operation can interleave and be lost when the execution is undone
+        let (result, verdict) = self.with_environment_mut(env, |environment| {
+            let enforcer = SecurityEnforcer::with_clock(
+                SecurityConfig {
+                    access_control: environment.world.access_control.clone(),
+                    ..security_config.clone()
+                },
+                environment.entropy.clock().clone(),
+            );
+            let before = enforcer.policy().can_abort().then(|| environment.world.clone());
+            let mut result = environment.run(&env.environment_id, code_path, elf, &instruction)?;
+
+            let caller = inputs.context.sender.as_deref().unwrap_or(DEFAULT_SENDER_ADDRESS);
+            let verdict = enforcer.enforce(&mut result, &inputs.target_function, caller, None);
+            match (&verdict, before) {
+                // Subscribers never see events of an execution rolled back
+                (Err(_), Some(world)) => {
+                    environment.world = world;
+                    result.events.clear();
+                }
+                _ => environment.event_bus.publish(&result.events),
+            }
+            environment
+                .executions
+                .insert(result.execution_id.clone(), result.clone());
+            Ok((result, verdict))
+        })?;
+
+        verdict?;
+        Ok(result)
+    }
+
+    async fn check_reentrancy(
+        &self,
+        env: &RuntimeEnvironment,
+        function_name: &str,
+        caller: &str,
+        call_stack: &[String],
+    ) -> Result<bool> {
+        let validator = SecurityValidator::new(self.security_config(env)?);
+        Ok(validator.check_reentrancy(function_name, caller, call_stack).is_err())
+    }
+
+    async fn detect_overflow(
+        &self,
+        env: &RuntimeEnvironment,
+        operation: &str,
+        operands: &[i64],
+    ) -> Result<bool> {
+        let mut config = self.security_config(env)?;
+        config.integer_type.get_or_insert(IntegerType::U64);
+        let validator = SecurityValidator::new(config);
+        Ok(validator.detect_overflow(operation, operands).is_err())
+    }
+
+    async fn verify_access_control(
+        &self,
+        env: &RuntimeEnvironment,
+        function_name: &str,
+        caller: &str,
+        required_role: Option<&str>,
+    ) -> Result<bool> {
+        let validator = SecurityValidator::new(self.security_config(env)?);
+        Ok(validator.verify_access_control(function_name, caller, required_role).is_ok())
+    }
+
+    async fn enforce_resource_limits(
+        &self,
+        env: &RuntimeEnvironment,
+        gas_used: u64,
+        memory_used: u64,
+        call_depth: u32,
+        external_calls: u32,
+        security_config: &SecurityConfig,
+    ) -> Result<Vec<SecurityViolation>> {
+        let clock = self.entropy(env)?.clock().clone();
+        Ok(SecurityEnforcer::with_clock(security_config.clone(), clock).check_resource_limits(
+            gas_used,
+            memory_used,
+            call_depth,
+            external_calls,
+        ))
+    }
+
+    async fn get_security_report(
+        &self,
+        env: &RuntimeEnvironment,
+        execution_id: &str,
+    ) -> Result<SecurityReport> {
+        self.with_environment(env, |environment| {
+            let result = environment
+                .executions
+                .get(execution_id)
+                .ok_or_else(|| RuntimeError::ExecutionNotFound(execution_id.to_string()))?;
+            let config = SecurityConfig {
+                access_control: environment.world.access_control.clone(),
+                ..environment.config.security_config.clone()
+            };
+            Ok(SecurityReport::with_clock(result, &config, environment.entropy.clock().as_ref()))
+        })
+    }
+}
@@ -0,0 +1,171 @@
+//! Account addresses written in base58, and the program addresses derived from seeds
+
+use primitive_types::{U256, U512};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::RuntimeError;
+
+const ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Curve constant d of ed25519, -121665/121666 modulo 2^255 - 19
+const EDWARDS_D: &str = "37095705934669439343138083508754565189542113879843219016388785533085940283555";
+
+const SHA256_ROUND_CONSTANTS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// 32 byte account address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub(crate) struct Pubkey(pub [u8; 32]);
+
+impl Pubkey {
+    /// Address `program_id` controls through `seeds`, unless it is a point on
+    /// the ed25519 curve and so could have a private key
+    pub fn create_program_address(seeds: &[&[u8]], program_id: &Pubkey) -> Option<Self> {
+        let mut data = seeds.concat();
+        data.extend_from_slice(&program_id.0);
+        data.extend_from_slice(b"ProgramDerivedAddress");
+        let hash = sha256(&data);
+        (!is_on_curve(&hash)).then_some(Self(hash))
+    }
+}
+
+/// Whether `bytes` decompress to a point on the ed25519 curve
+fn is_on_curve(bytes: &[u8; 32]) -> bool {
+    let p = (U256::one() << 255) - 19;
+    let d = U256::from_dec_str(EDWARDS_D).expect("valid curve constant");
+    let mul = |a: U256, b: U256| U256::try_from(a.full_mul(b) % U512::from(p)).expect("remainder fits in 256 bits");
+
+    // The top bit is the sign of x
+    let y = (U256::from_little_endian(bytes) & ((U256::one() << 255) - 1)) % p;
+    let yy = mul(y, y);
+    let u = (yy + p - 1) % p;
+    let v = (mul(d, yy) + 1) % p;
+
+    // x^2 = u / v has a solution when u * v is zero or a square, by Euler's criterion
+    let w = mul(u, v);
+    let exponent = (p - 1) >> 1;
+    let mut power = U256::one();
+    for bit in (0..256).rev() {
+        power = mul(power, power);
+        if exponent.bit(bit) {
+            power = mul(power, w);
+        }
+    }
+    w.is_zero() || power == U256::one()
+}
+
+/// SHA-256 digest of `data`
+pub(crate) fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    // Zeros up to the 8 byte bit length that completes the last block
+    message.resize((message.len() + 8).next_multiple_of(64) - 8, 0);
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut schedule = [0u32; 64];
+        for (word, bytes) in schedule.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..64 {
+            let (w15, w2) = (schedule[i - 15], schedule[i - 2]);
+            let s0 = w15.rotate_right(7) ^ w15.rotate_right(18) ^ (w15 >> 3);
+            let s1 = w2.rotate_right(17) ^ w2.rotate_right(19) ^ (w2 >> 10);
+            schedule[i] = schedule[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(schedule[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for (constant, word) in SHA256_ROUND_CONSTANTS.iter().zip(schedule) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(*constant)
+                .wrapping_add(word);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+            (h, g, f, e, d, c, b, a) = (g, f, e, d.wrapping_add(t1), c, b, a, t1.wrapping_add(t2));
+        }
+        for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0u8; 32];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+impl fmt::Display for Pubkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let zeros = self.0.iter().take_while(|byte| **byte == 0).count();
+        // Base 58 digits, least significant first
+        let mut digits: Vec<u8> = Vec::with_capacity(44);
+        for byte in &self.0[zeros..] {
+            let mut carry = *byte as u32;
+            for digit in digits.iter_mut() {
+                carry += (*digit as u32) << 8;
+                *digit = (carry % 58) as u8;
+                carry /= 58;
+            }
+            while carry > 0 {
+                digits.push((carry % 58) as u8);
+                carry /= 58;
+            }
+        }
+        let text: String = std::iter::repeat_n('1', zeros)
+            .chain(digits.iter().rev().map(|digit| ALPHABET[*digit as usize] as char))
+            .collect();
+        f.write_str(&text)
+    }
+}
+
+impl FromStr for Pubkey {
+    type Err = RuntimeError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || RuntimeError::InvalidInput(format!("Invalid account address {}", text));
+        let zeros = text.bytes().take_while(|c| *c == b'1').count();
+        // Bytes, least significant first
+        let mut bytes: Vec<u8> = Vec::with_capacity(32);
+        for c in text.bytes().skip(zeros) {
+            let mut carry = ALPHABET.iter().position(|a| *a == c).ok_or_else(invalid)? as u32;
+            for byte in bytes.iter_mut() {
+                carry += (*byte as u32) * 58;
+                *byte = carry as u8;
+                carry >>= 8;
+            }
+            while carry > 0 {
+                bytes.push(carry as u8);
+                carry >>= 8;
+            }
+        }
+        if zeros + bytes.len() != 32 {
+            return Err(invalid());
+        }
+        let mut key = [0u8; 32];
+        for (slot, byte) in key[zeros..].iter_mut().zip(bytes.iter().rev()) {
+            *slot = *byte;
+        }
+        Ok(Self(key))
+    }
+}
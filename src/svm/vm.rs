@@ -0,0 +1,668 @@
+//! Interpreter for SBF bytecode
+//!
+//! Programs see four memory regions: their read-only image, a stack split
+//! into call frames, a heap, and the serialized instruction input. Every
+//! instruction costs one compute unit and syscalls charge their own cost on
+//! top, so running out of budget stops a program the same way a validator
+//! would.
+//!
+//! Only the syscalls in `Syscall` exist. Cross-program invocation is not
+//! among them, so programs that call `sol_invoke_signed_*` or any other
+//! syscall fail to load rather than at the call.
+
+use thiserror::Error;
+
+use super::elf::{murmur3_32, Executable};
+use super::pubkey::{sha256, Pubkey};
+use crate::constants::{SVM_HEAP_SIZE, SVM_MAX_CALL_DEPTH, SVM_MAX_RETURN_DATA, SVM_STACK_FRAME_SIZE};
+use crate::evm::keccak256;
+
+/// Address the program image is mapped at
+pub(crate) const MM_PROGRAM_START: u64 = 0x1_0000_0000;
+const MM_STACK_START: u64 = 0x2_0000_0000;
+const MM_HEAP_START: u64 = 0x3_0000_0000;
+const MM_INPUT_START: u64 = 0x4_0000_0000;
+
+const SYSCALL_BASE_COST: u64 = 100;
+const MEM_OP_BASE_COST: u64 = 10;
+const CPI_BYTES_PER_UNIT: u64 = 250;
+const HASH_BASE_COST: u64 = 85;
+const MAX_HASH_SLICES: u64 = 20_000;
+const CREATE_PROGRAM_ADDRESS_COST: u64 = 1_500;
+const SYSVAR_BASE_COST: u64 = 100;
+const MAX_SEEDS: u64 = 16;
+const MAX_SEED_LEN: u64 = 32;
+
+/// Rent sysvar of mainnet
+const RENT_LAMPORTS_PER_BYTE_YEAR: u64 = 3_480;
+const RENT_EXEMPTION_THRESHOLD: f64 = 2.0;
+const RENT_BURN_PERCENT: u8 = 50;
+
+/// Reasons a program stops before returning
+#[derive(Debug, Error)]
+pub(crate) enum VmError {
+    #[error("Program exceeded its compute budget of {0} units")]
+    ComputeBudgetExceeded(u64),
+    #[error("Access violation: {len} byte {access} at {address:#x}")]
+    AccessViolation { access: &'static str, address: u64, len: u64 },
+    #[error("Division by zero at instruction {0}")]
+    DivideByZero(usize),
+    #[error("Invalid instruction {opcode:#04x} at instruction {pc}")]
+    InvalidInstruction { opcode: u8, pc: usize },
+    #[error("Jump to instruction {0} outside the program")]
+    InvalidJump(i64),
+    #[error("Call to {0:#x} outside the program")]
+    InvalidCallTarget(u64),
+    #[error("Execution ran off the end of the program at instruction {0}")]
+    ExecutionOverrun(usize),
+    #[error("Call depth exceeded {SVM_MAX_CALL_DEPTH} frames")]
+    CallDepthExceeded,
+    #[error("Unresolved call to {hash:#010x} at instruction {pc}")]
+    UnresolvedCall { hash: u32, pc: usize },
+    #[error("Program aborted")]
+    Abort,
+    #[error("Program panicked at {0}")]
+    Panic(String),
+    #[error("Copy between overlapping regions")]
+    CopyOverlapping,
+    #[error("Return data of {0} bytes exceeds {SVM_MAX_RETURN_DATA} bytes")]
+    ReturnDataTooLarge(u64),
+    #[error("Hash over {0} slices exceeds {MAX_HASH_SLICES}")]
+    TooManySlices(u64),
+    #[error("{0} seeds exceed {MAX_SEEDS}")]
+    TooManySeeds(u64),
+    #[error("Seed of {0} bytes exceeds {MAX_SEED_LEN} bytes")]
+    SeedTooLong(u64),
+}
+
+/// Functions the runtime provides to programs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Syscall {
+    Abort,
+    Panic,
+    Log,
+    Log64,
+    LogComputeUnits,
+    LogPubkey,
+    Memcpy,
+    Memmove,
+    Memset,
+    Memcmp,
+    AllocFree,
+    SetReturnData,
+    GetReturnData,
+    Keccak256,
+    Sha256,
+    CreateProgramAddress,
+    TryFindProgramAddress,
+    GetClockSysvar,
+    GetRentSysvar,
+}
+
+impl Syscall {
+    const NAMES: [(Syscall, &'static str); 19] = [
+        (Syscall::Abort, "abort"),
+        (Syscall::Panic, "sol_panic_"),
+        (Syscall::Log, "sol_log_"),
+        (Syscall::Log64, "sol_log_64_"),
+        (Syscall::LogComputeUnits, "sol_log_compute_units_"),
+        (Syscall::LogPubkey, "sol_log_pubkey"),
+        (Syscall::Memcpy, "sol_memcpy_"),
+        (Syscall::Memmove, "sol_memmove_"),
+        (Syscall::Memset, "sol_memset_"),
+        (Syscall::Memcmp, "sol_memcmp_"),
+        (Syscall::AllocFree, "sol_alloc_free_"),
+        (Syscall::SetReturnData, "sol_set_return_data"),
+        (Syscall::GetReturnData, "sol_get_return_data"),
+        (Syscall::Keccak256, "sol_keccak256"),
+        (Syscall::Sha256, "sol_sha256"),
+        (Syscall::CreateProgramAddress, "sol_create_program_address"),
+        (Syscall::TryFindProgramAddress, "sol_try_find_program_address"),
+        (Syscall::GetClockSysvar, "sol_get_clock_sysvar"),
+        (Syscall::GetRentSysvar, "sol_get_rent_sysvar"),
+    ];
+
+    /// Syscall a call instruction refers to by the murmur3 hash of its name
+    pub fn from_hash(hash: u32) -> Option<Self> {
+        Self::NAMES
+            .iter()
+            .find(|(_, name)| murmur3_32(name.as_bytes()) == hash)
+            .map(|(syscall, _)| *syscall)
+    }
+}
+
+/// What a program run left behind
+pub(crate) struct VmOutcome {
+    /// Value of r0 when the entrypoint returned
+    pub result: Result<u64, VmError>,
+    pub compute_units: u64,
+    pub logs: Vec<String>,
+    pub return_data: Vec<u8>,
+    /// Input region, carrying the program's writes to accounts
+    pub input: Vec<u8>,
+    /// Deepest call depth reached
+    pub max_call_depth: u32,
+}
+
+/// Run a program to completion against a serialized input, at a clock
+/// reading `timestamp`
+pub(crate) fn run(
+    executable: &Executable,
+    input: Vec<u8>,
+    program_id: Pubkey,
+    compute_unit_limit: u64,
+    timestamp: u64,
+) -> VmOutcome {
+    let mut vm = Vm {
+        executable,
+        memory: Memory {
+            program: &executable.image,
+            stack: vec![0; SVM_STACK_FRAME_SIZE * SVM_MAX_CALL_DEPTH],
+            heap: vec![0; SVM_HEAP_SIZE],
+            input,
+        },
+        registers: [0; 11],
+        frames: Vec::new(),
+        max_call_depth: 1,
+        compute_unit_limit,
+        compute_units: 0,
+        heap_position: 0,
+        program_id,
+        timestamp,
+        logs: Vec::new(),
+        return_data: Vec::new(),
+    };
+    let result = vm.execute();
+    VmOutcome {
+        result,
+        compute_units: vm.compute_units.min(compute_unit_limit),
+        logs: vm.logs,
+        return_data: vm.return_data,
+        input: vm.memory.input,
+        max_call_depth: vm.max_call_depth,
+    }
+}
+
+struct Memory<'a> {
+    program: &'a [u8],
+    stack: Vec<u8>,
+    heap: Vec<u8>,
+    input: Vec<u8>,
+}
+
+impl Memory<'_> {
+    fn load(&self, address: u64, len: u64) -> Result<&[u8], VmError> {
+        let region: &[u8] = match address >> 32 {
+            1 => self.program,
+            2 => &self.stack,
+            3 => &self.heap,
+            4 => &self.input,
+            _ => &[],
+        };
+        slice(region, address, len).ok_or(VmError::AccessViolation { access: "load", address, len })
+    }
+
+    fn store(&mut self, address: u64, len: u64) -> Result<&mut [u8], VmError> {
+        let region: &mut [u8] = match address >> 32 {
+            2 => &mut self.stack,
+            3 => &mut self.heap,
+            4 => &mut self.input,
+            _ => &mut [],
+        };
+        let range = range(region.len(), address, len).ok_or(VmError::AccessViolation { access: "store", address, len })?;
+        Ok(&mut region[range])
+    }
+
+    fn load_uint(&self, address: u64, size: u64) -> Result<u64, VmError> {
+        let mut bytes = [0u8; 8];
+        bytes[..size as usize].copy_from_slice(self.load(address, size)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn store_uint(&mut self, address: u64, size: u64, value: u64) -> Result<(), VmError> {
+        self.store(address, size)?
+            .copy_from_slice(&value.to_le_bytes()[..size as usize]);
+        Ok(())
+    }
+}
+
+fn range(region_len: usize, address: u64, len: u64) -> Option<std::ops::Range<usize>> {
+    let start = (address & 0xffff_ffff) as usize;
+    let end = start.checked_add(usize::try_from(len).ok()?)?;
+    (end <= region_len).then_some(start..end)
+}
+
+fn slice(region: &[u8], address: u64, len: u64) -> Option<&[u8]> {
+    range(region.len(), address, len).map(|range| &region[range])
+}
+
+/// Registers a call saves for its caller
+struct Frame {
+    saved: [u64; 4],
+    frame_pointer: u64,
+    return_pc: usize,
+}
+
+struct Vm<'a> {
+    executable: &'a Executable,
+    memory: Memory<'a>,
+    registers: [u64; 11],
+    frames: Vec<Frame>,
+    max_call_depth: u32,
+    compute_unit_limit: u64,
+    compute_units: u64,
+    heap_position: u64,
+    program_id: Pubkey,
+    timestamp: u64,
+    logs: Vec<String>,
+    return_data: Vec<u8>,
+}
+
+impl Vm<'_> {
+    fn consume(&mut self, units: u64) -> Result<(), VmError> {
+        self.compute_units = self.compute_units.saturating_add(units);
+        if self.compute_units > self.compute_unit_limit {
+            return Err(VmError::ComputeBudgetExceeded(self.compute_unit_limit));
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self) -> Result<u64, VmError> {
+        self.registers[1] = MM_INPUT_START;
+        self.registers[10] = MM_STACK_START + SVM_STACK_FRAME_SIZE as u64;
+        let mut pc = self.executable.entry_pc;
+
+        loop {
+            let insn = self.executable.instruction(pc).ok_or(VmError::ExecutionOverrun(pc))?;
+            self.consume(1)?;
+            let opcode = insn[0];
+            let dst = (insn[1] & 0x0f) as usize;
+            let src = (insn[1] >> 4) as usize;
+            let offset = i16::from_le_bytes([insn[2], insn[3]]) as i64;
+            let imm = i32::from_le_bytes([insn[4], insn[5], insn[6], insn[7]]);
+            let invalid = VmError::InvalidInstruction { opcode, pc };
+            // r10 only changes through calls
+            let writes_dst = matches!(opcode & 0x07, 0x00 | 0x01 | 0x04 | 0x07);
+            if dst > 10 || src > 10 || (writes_dst && dst == 10) {
+                return Err(invalid);
+            }
+            let size = match (opcode >> 3) & 0x03 {
+                0 => 4,
+                1 => 2,
+                2 => 1,
+                _ => 8,
+            };
+            let mut next = pc + 1;
+
+            match opcode {
+                // lddw spans two slots, the second holding the upper half
+                0x18 => {
+                    let high = self.executable.instruction(pc + 1).ok_or(invalid)?;
+                    let high = u32::from_le_bytes([high[4], high[5], high[6], high[7]]);
+                    self.registers[dst] = imm as u32 as u64 | (high as u64) << 32;
+                    next = pc + 2;
+                }
+                0x61 | 0x69 | 0x71 | 0x79 => {
+                    let address = self.registers[src].wrapping_add(offset as u64);
+                    self.registers[dst] = self.memory.load_uint(address, size)?;
+                }
+                0x62 | 0x6a | 0x72 | 0x7a => {
+                    let address = self.registers[dst].wrapping_add(offset as u64);
+                    self.memory.store_uint(address, size, imm as i64 as u64)?;
+                }
+                0x63 | 0x6b | 0x73 | 0x7b => {
+                    let address = self.registers[dst].wrapping_add(offset as u64);
+                    self.memory.store_uint(address, size, self.registers[src])?;
+                }
+                0x05 => next = jump(pc, offset)?,
+                0x85 => {
+                    let hash = imm as u32;
+                    if let Some(syscall) = Syscall::from_hash(hash) {
+                        self.registers[0] = self.syscall(syscall)?;
+                    } else {
+                        let target = *self
+                            .executable
+                            .functions
+                            .get(&hash)
+                            .ok_or(VmError::UnresolvedCall { hash, pc })?;
+                        self.push_frame(next)?;
+                        next = target;
+                    }
+                }
+                0x8d => {
+                    let address = *self.registers.get(imm as usize).ok_or(invalid)?;
+                    let text_start = MM_PROGRAM_START + self.executable.text_offset as u64;
+                    if address < text_start || !(address - text_start).is_multiple_of(8) {
+                        return Err(VmError::InvalidCallTarget(address));
+                    }
+                    self.push_frame(next)?;
+                    next = ((address - text_start) / 8) as usize;
+                }
+                0x95 => match self.frames.pop() {
+                    None => return Ok(self.registers[0]),
+                    Some(frame) => {
+                        self.registers[6..10].copy_from_slice(&frame.saved);
+                        self.registers[10] = frame.frame_pointer;
+                        next = frame.return_pc;
+                    }
+                },
+                _ if matches!(opcode & 0x07, 0x04 | 0x07) => {
+                    self.registers[dst] = self.alu(opcode, self.registers[dst], self.registers[src], imm, pc)?;
+                }
+                _ if opcode & 0x07 == 0x05 => {
+                    let value = self.registers[dst];
+                    let operand = if opcode & 0x08 != 0 {
+                        self.registers[src]
+                    } else {
+                        imm as i64 as u64
+                    };
+                    let taken = match opcode & 0xf0 {
+                        0x10 => value == operand,
+                        0x20 => value > operand,
+                        0x30 => value >= operand,
+                        0x40 => value & operand != 0,
+                        0x50 => value != operand,
+                        0x60 => (value as i64) > (operand as i64),
+                        0x70 => (value as i64) >= (operand as i64),
+                        0xa0 => value < operand,
+                        0xb0 => value <= operand,
+                        0xc0 => (value as i64) < (operand as i64),
+                        0xd0 => (value as i64) <= (operand as i64),
+                        _ => return Err(invalid),
+                    };
+                    if taken {
+                        next = jump(pc, offset)?;
+                    }
+                }
+                _ => return Err(invalid),
+            }
+            pc = next;
+        }
+    }
+
+    fn alu(&self, opcode: u8, value: u64, register: u64, imm: i32, pc: usize) -> Result<u64, VmError> {
+        let invalid = VmError::InvalidInstruction { opcode, pc };
+        let divide_by_zero = VmError::DivideByZero(pc);
+        let from_register = opcode & 0x08 != 0;
+
+        // Byte swaps keep the class bits of 32-bit operations but act on the full register
+        if opcode & 0xf0 == 0xd0 && opcode & 0x07 == 0x04 {
+            return match (from_register, imm) {
+                (false, 16) => Ok(value as u16 as u64),
+                (false, 32) => Ok(value as u32 as u64),
+                (false, 64) => Ok(value),
+                (true, 16) => Ok((value as u16).swap_bytes() as u64),
+                (true, 32) => Ok((value as u32).swap_bytes() as u64),
+                (true, 64) => Ok(value.swap_bytes()),
+                _ => Err(invalid),
+            };
+        }
+
+        if opcode & 0x07 == 0x07 {
+            let operand = if from_register { register } else { imm as i64 as u64 };
+            return Ok(match opcode & 0xf0 {
+                0x00 => value.wrapping_add(operand),
+                0x10 => value.wrapping_sub(operand),
+                0x20 => value.wrapping_mul(operand),
+                0x30 => value.checked_div(operand).ok_or(divide_by_zero)?,
+                0x40 => value | operand,
+                0x50 => value & operand,
+                0x60 => value.wrapping_shl(operand as u32),
+                0x70 => value.wrapping_shr(operand as u32),
+                0x80 => (value as i64).wrapping_neg() as u64,
+                0x90 => value.checked_rem(operand).ok_or(divide_by_zero)?,
+                0xa0 => value ^ operand,
+                0xb0 => operand,
+                0xc0 => (value as i64).wrapping_shr(operand as u32) as u64,
+                _ => return Err(invalid),
+            });
+        }
+
+        // 32-bit results are zero-extended into the register
+        let value = value as u32;
+        let operand = if from_register { register as u32 } else { imm as u32 };
+        Ok(match opcode & 0xf0 {
+            0x00 => value.wrapping_add(operand),
+            0x10 => value.wrapping_sub(operand),
+            0x20 => value.wrapping_mul(operand),
+            0x30 => value.checked_div(operand).ok_or(divide_by_zero)?,
+            0x40 => value | operand,
+            0x50 => value & operand,
+            0x60 => value.wrapping_shl(operand),
+            0x70 => value.wrapping_shr(operand),
+            0x80 => (value as i32).wrapping_neg() as u32,
+            0x90 => value.checked_rem(operand).ok_or(divide_by_zero)?,
+            0xa0 => value ^ operand,
+            0xb0 => operand,
+            0xc0 => (value as i32).wrapping_shr(operand) as u32,
+            _ => return Err(invalid),
+        } as u64)
+    }
+
+    fn push_frame(&mut self, return_pc: usize) -> Result<(), VmError> {
+        if self.frames.len() + 1 >= SVM_MAX_CALL_DEPTH {
+            return Err(VmError::CallDepthExceeded);
+        }
+        self.frames.push(Frame {
+            saved: [self.registers[6], self.registers[7], self.registers[8], self.registers[9]],
+            frame_pointer: self.registers[10],
+            return_pc,
+        });
+        self.registers[10] += SVM_STACK_FRAME_SIZE as u64;
+        self.max_call_depth = self.max_call_depth.max(self.frames.len() as u32 + 1);
+        Ok(())
+    }
+
+    fn syscall(&mut self, syscall: Syscall) -> Result<u64, VmError> {
+        let [_, a, b, c, d, e, ..] = self.registers;
+        let mem_op_cost = |len: u64| MEM_OP_BASE_COST.max(len / CPI_BYTES_PER_UNIT);
+        match syscall {
+            Syscall::Abort => Err(VmError::Abort),
+            Syscall::Panic => {
+                self.consume(b)?;
+                let file = String::from_utf8_lossy(self.memory.load(a, b)?).into_owned();
+                Err(VmError::Panic(format!("{}:{}:{}", file, c, d)))
+            }
+            Syscall::Log => {
+                self.consume(SYSCALL_BASE_COST.max(b))?;
+                let message = String::from_utf8_lossy(self.memory.load(a, b)?).into_owned();
+                self.logs.push(message);
+                Ok(0)
+            }
+            Syscall::Log64 => {
+                self.consume(SYSCALL_BASE_COST)?;
+                self.logs.push(format!("{:#x}, {:#x}, {:#x}, {:#x}, {:#x}", a, b, c, d, e));
+                Ok(0)
+            }
+            Syscall::LogComputeUnits => {
+                self.consume(SYSCALL_BASE_COST)?;
+                let remaining = self.compute_unit_limit - self.compute_units;
+                self.logs.push(format!("Program consumption: {} units remaining", remaining));
+                Ok(0)
+            }
+            Syscall::LogPubkey => {
+                self.consume(SYSCALL_BASE_COST)?;
+                let key = self.pubkey(a)?;
+                self.logs.push(key.to_string());
+                Ok(0)
+            }
+            Syscall::Memcpy | Syscall::Memmove => {
+                self.consume(mem_op_cost(c))?;
+                if syscall == Syscall::Memcpy && a >> 32 == b >> 32 && a < b.saturating_add(c) && b < a.saturating_add(c) {
+                    return Err(VmError::CopyOverlapping);
+                }
+                let bytes = self.memory.load(b, c)?.to_vec();
+                self.memory.store(a, c)?.copy_from_slice(&bytes);
+                Ok(0)
+            }
+            Syscall::Memset => {
+                self.consume(mem_op_cost(c))?;
+                self.memory.store(a, c)?.fill(b as u8);
+                Ok(0)
+            }
+            Syscall::Memcmp => {
+                self.consume(mem_op_cost(c))?;
+                let left = self.memory.load(a, c)?;
+                let right = self.memory.load(b, c)?;
+                let difference = left
+                    .iter()
+                    .zip(right)
+                    .find(|(l, r)| l != r)
+                    .map(|(l, r)| *l as i32 - *r as i32)
+                    .unwrap_or(0);
+                self.memory.store(d, 4)?.copy_from_slice(&difference.to_le_bytes());
+                Ok(0)
+            }
+            Syscall::AllocFree => {
+                // Bump allocation that never frees
+                if b != 0 {
+                    return Ok(0);
+                }
+                let start = self.heap_position.next_multiple_of(8);
+                match start.checked_add(a) {
+                    Some(end) if end <= SVM_HEAP_SIZE as u64 => {
+                        self.heap_position = end;
+                        Ok(MM_HEAP_START + start)
+                    }
+                    _ => Ok(0),
+                }
+            }
+            Syscall::SetReturnData => {
+                self.consume(b / CPI_BYTES_PER_UNIT + SYSCALL_BASE_COST)?;
+                if b > SVM_MAX_RETURN_DATA as u64 {
+                    return Err(VmError::ReturnDataTooLarge(b));
+                }
+                self.return_data = self.memory.load(a, b)?.to_vec();
+                Ok(0)
+            }
+            Syscall::GetReturnData => {
+                self.consume(SYSCALL_BASE_COST)?;
+                if self.return_data.is_empty() {
+                    return Ok(0);
+                }
+                let len = b.min(self.return_data.len() as u64);
+                self.consume((len + 32) / CPI_BYTES_PER_UNIT)?;
+                let data = self.return_data[..len as usize].to_vec();
+                self.memory.store(a, len)?.copy_from_slice(&data);
+                let program_id = self.program_id.0;
+                self.memory.store(c, 32)?.copy_from_slice(&program_id);
+                Ok(self.return_data.len() as u64)
+            }
+            Syscall::Keccak256 => {
+                let data = self.hash_input(a, b)?;
+                self.memory.store(c, 32)?.copy_from_slice(&keccak256(&data));
+                Ok(0)
+            }
+            Syscall::Sha256 => {
+                let data = self.hash_input(a, b)?;
+                self.memory.store(c, 32)?.copy_from_slice(&sha256(&data));
+                Ok(0)
+            }
+            Syscall::CreateProgramAddress => {
+                self.consume(CREATE_PROGRAM_ADDRESS_COST)?;
+                let seeds = self.seeds(a, b)?;
+                let program_id = self.pubkey(c)?;
+                let seeds: Vec<&[u8]> = seeds.iter().map(Vec::as_slice).collect();
+                match Pubkey::create_program_address(&seeds, &program_id) {
+                    Some(address) => {
+                        self.memory.store(d, 32)?.copy_from_slice(&address.0);
+                        Ok(0)
+                    }
+                    None => Ok(1),
+                }
+            }
+            Syscall::TryFindProgramAddress => {
+                self.consume(CREATE_PROGRAM_ADDRESS_COST)?;
+                let seeds = self.seeds(a, b)?;
+                let program_id = self.pubkey(c)?;
+                // Bump seeds count down from 255, each further attempt paying again
+                for bump in (1..=u8::MAX).rev() {
+                    let bump_seed = [bump];
+                    let mut seeds: Vec<&[u8]> = seeds.iter().map(Vec::as_slice).collect();
+                    seeds.push(&bump_seed);
+                    let address = (seeds.len() as u64 <= MAX_SEEDS)
+                        .then(|| Pubkey::create_program_address(&seeds, &program_id))
+                        .flatten();
+                    if let Some(address) = address {
+                        self.memory.store(d, 32)?.copy_from_slice(&address.0);
+                        self.memory.store(e, 1)?[0] = bump;
+                        return Ok(0);
+                    }
+                    self.consume(CREATE_PROGRAM_ADDRESS_COST)?;
+                }
+                Ok(1)
+            }
+            Syscall::GetClockSysvar => {
+                // Slot, epoch start timestamp, epoch, leader schedule epoch and unix timestamp
+                let clock = [0, self.timestamp, 0, 0, self.timestamp].map(u64::to_le_bytes).concat();
+                self.consume(SYSVAR_BASE_COST + clock.len() as u64)?;
+                self.memory.store(a, clock.len() as u64)?.copy_from_slice(&clock);
+                Ok(0)
+            }
+            Syscall::GetRentSysvar => {
+                // Lamports per byte-year, exemption threshold and burn percent, padded to 8 bytes
+                let mut rent = [
+                    RENT_LAMPORTS_PER_BYTE_YEAR.to_le_bytes(),
+                    RENT_EXEMPTION_THRESHOLD.to_le_bytes(),
+                    [0; 8],
+                ]
+                .concat();
+                rent[16] = RENT_BURN_PERCENT;
+                self.consume(SYSVAR_BASE_COST + rent.len() as u64)?;
+                self.memory.store(a, rent.len() as u64)?.copy_from_slice(&rent);
+                Ok(0)
+            }
+        }
+    }
+
+    /// Address and length of each of `count` slices in the array at `address`
+    fn slices(&self, address: u64, count: u64) -> Result<Vec<(u64, u64)>, VmError> {
+        (0..count)
+            .map(|index| {
+                let slice = address.wrapping_add(index * 16);
+                Ok((self.memory.load_uint(slice, 8)?, self.memory.load_uint(slice.wrapping_add(8), 8)?))
+            })
+            .collect()
+    }
+
+    /// Bytes a hash syscall digests, charged per slice
+    fn hash_input(&mut self, address: u64, count: u64) -> Result<Vec<u8>, VmError> {
+        if count > MAX_HASH_SLICES {
+            return Err(VmError::TooManySlices(count));
+        }
+        self.consume(HASH_BASE_COST)?;
+        let mut data = Vec::new();
+        for (address, len) in self.slices(address, count)? {
+            self.consume(MEM_OP_BASE_COST.max(len / 2))?;
+            data.extend_from_slice(self.memory.load(address, len)?);
+        }
+        Ok(data)
+    }
+
+    /// Seeds of a program address, within the bounds a validator sets
+    fn seeds(&self, address: u64, count: u64) -> Result<Vec<Vec<u8>>, VmError> {
+        if count > MAX_SEEDS {
+            return Err(VmError::TooManySeeds(count));
+        }
+        self.slices(address, count)?
+            .into_iter()
+            .map(|(address, len)| {
+                if len > MAX_SEED_LEN {
+                    return Err(VmError::SeedTooLong(len));
+                }
+                Ok(self.memory.load(address, len)?.to_vec())
+            })
+            .collect()
+    }
+
+    fn pubkey(&self, address: u64) -> Result<Pubkey, VmError> {
+        let key: [u8; 32] = self.memory.load(address, 32)?.try_into().unwrap_or_default();
+        Ok(Pubkey(key))
+    }
+}
+
+/// Instruction a taken jump lands on
+fn jump(pc: usize, offset: i64) -> Result<usize, VmError> {
+    let target = pc as i64 + 1 + offset;
+    target.try_into().map_err(|_| VmError::InvalidJump(target))
+}
@@ -0,0 +1,264 @@
+//! Execution traces that can be saved, reloaded and replayed
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::config::RuntimeConfig;
+use crate::error::{RuntimeError, RuntimeResult};
+use crate::reentrancy::{CallFrame, FrameStep};
+use crate::runtime::BlockchainRuntime;
+use crate::types::{BlockInfo, ExecutionInputs, ExecutionResult, StateChange};
+
+/// Version of the on-disk trace format written by this crate
+///
+/// Traces with a newer version are rejected when loaded; fields added in
+/// later versions must default so older traces keep loading.
+pub const EXECUTION_TRACE_VERSION: u32 = 1;
+
+/// Instruction executed by a backend that exposes them
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TraceInstruction {
+    /// Call depth, the top-level frame being depth 1
+    pub depth: u32,
+    pub pc: u64,
+    /// Mnemonic, such as `SSTORE`
+    pub op: String,
+    /// Gas left before the instruction ran
+    pub gas_remaining: u64,
+}
+
+/// Storage access found in a trace's call tree
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StorageAccess {
+    pub contract: String,
+    pub function: String,
+    pub key: String,
+    pub write: bool,
+}
+
+/// Everything needed to reproduce one execution
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionTrace {
+    pub version: u32,
+    pub blockchain_id: String,
+    /// Configuration of the environment the execution ran in
+    pub config: RuntimeConfig,
+    pub code_path: PathBuf,
+    /// Hex-encoded contents of `code_path` when it was a readable file
+    #[serde(default)]
+    pub code: Option<String>,
+    /// Inputs with the block number and timestamp pinned to `block`
+    pub inputs: ExecutionInputs,
+    pub block: BlockInfo,
+    /// Call frames in execution order, with their storage reads and writes
+    #[serde(default)]
+    pub call_tree: Option<CallFrame>,
+    /// Instructions executed, empty when the backend does not expose them
+    #[serde(default)]
+    pub instructions: Vec<TraceInstruction>,
+    /// Result of the execution, including its state changes and events
+    pub result: ExecutionResult,
+}
+
+impl ExecutionTrace {
+    /// Record an execution, capturing the code at `code_path` if it can be read
+    pub async fn record(
+        blockchain_id: &str,
+        config: RuntimeConfig,
+        code_path: &Path,
+        inputs: &ExecutionInputs,
+        block: BlockInfo,
+        result: ExecutionResult,
+    ) -> Self {
+        let mut inputs = inputs.clone();
+        inputs.context.block_number = Some(block.number);
+        inputs.context.timestamp = Some(block.timestamp);
+
+        Self {
+            version: EXECUTION_TRACE_VERSION,
+            blockchain_id: blockchain_id.to_string(),
+            config,
+            code_path: code_path.to_path_buf(),
+            code: tokio::fs::read(code_path).await.ok().map(hex::encode),
+            inputs,
+            block,
+            call_tree: None,
+            instructions: Vec::new(),
+            result,
+        }
+    }
+
+    /// Storage reads and writes in the order they happened
+    pub fn storage_accesses(&self) -> Vec<StorageAccess> {
+        let mut accesses = Vec::new();
+        if let Some(call_tree) = &self.call_tree {
+            collect_accesses(call_tree, &mut accesses);
+        }
+        accesses
+    }
+
+    /// Write the trace as JSON
+    pub fn save(&self, path: impl AsRef<Path>) -> RuntimeResult<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_vec_pretty(self).map_err(|e| invalid_trace(path, e))?;
+        std::fs::write(path, json).map_err(|e| invalid_trace(path, e))
+    }
+
+    /// Read a trace written by `save`
+    pub fn load(path: impl AsRef<Path>) -> RuntimeResult<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read(path).map_err(|e| invalid_trace(path, e))?;
+        let trace: Self = serde_json::from_slice(&contents).map_err(|e| invalid_trace(path, e))?;
+        if trace.version > EXECUTION_TRACE_VERSION {
+            return Err(invalid_trace(
+                path,
+                format!(
+                    "format version {} is newer than the supported version {}",
+                    trace.version, EXECUTION_TRACE_VERSION
+                ),
+            ));
+        }
+        Ok(trace)
+    }
+
+    /// Re-run the execution in a fresh environment and compare its state changes
+    ///
+    /// The environment is created from the recorded configuration and
+    /// destroyed afterwards. The recorded code is used when `code_path` is
+    /// missing or has changed since recording, written to a temporary
+    /// directory under the original file name. State set up by earlier
+    /// executions is not part of the trace, so only executions that ran first
+    /// in their environment replay exactly.
+    pub async fn replay<R: BlockchainRuntime + ?Sized>(&self, runtime: &R) -> Result<TraceReplay> {
+        let recorded_code = self.code.as_deref().map(hex::decode).transpose()?;
+        let current_code = tokio::fs::read(&self.code_path).await.ok();
+        let materialized = match recorded_code {
+            Some(code) if current_code.as_ref() != Some(&code) => {
+                static REPLAY_NONCE: AtomicU64 = AtomicU64::new(0);
+                let directory = std::env::temp_dir().join(format!(
+                    "blockchain-runtime-replay-{}-{}",
+                    std::process::id(),
+                    REPLAY_NONCE.fetch_add(1, Ordering::SeqCst)
+                ));
+                // Backends may go by the file name or extension
+                let file_name = self.code_path.file_name().unwrap_or("code".as_ref());
+                tokio::fs::create_dir_all(&directory).await?;
+                let path = directory.join(file_name);
+                if let Err(error) = tokio::fs::write(&path, code).await {
+                    let _ = tokio::fs::remove_dir_all(&directory).await;
+                    return Err(error.into());
+                }
+                Some((directory, path))
+            }
+            _ => None,
+        };
+
+        let code_path = materialized.as_ref().map_or(self.code_path.as_path(), |(_, path)| path);
+        let replayed = async {
+            let env = runtime.create_environment(self.config.clone()).await?;
+            let replayed = runtime.execute_traced(&env, code_path, &self.inputs).await;
+            let destroyed = runtime.destroy(env).await;
+            let replayed = replayed?;
+            destroyed?;
+            anyhow::Ok(replayed)
+        }
+        .await;
+        if let Some((directory, _)) = &materialized {
+            let _ = tokio::fs::remove_dir_all(directory).await;
+        }
+        let mut replayed = replayed?;
+        replayed.code_path = self.code_path.clone();
+
+        Ok(TraceReplay {
+            differences: diff_state_changes(&self.result.state_changes, &replayed.result.state_changes),
+            outcome_matches: self.result.success == replayed.result.success
+                && self.result.return_value == replayed.result.return_value,
+            replayed,
+        })
+    }
+}
+
+/// Outcome of replaying a trace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceReplay {
+    pub replayed: ExecutionTrace,
+    /// State changes that differ from the recording
+    pub differences: Vec<StateChangeDiff>,
+    /// Whether success and return value match the recording
+    pub outcome_matches: bool,
+}
+
+impl TraceReplay {
+    /// Whether the replay reproduced the recorded outcome and state changes
+    pub fn is_exact(&self) -> bool {
+        self.outcome_matches && self.differences.is_empty()
+    }
+}
+
+/// Difference between recorded and replayed state changes
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StateChangeDiff {
+    /// Recorded but not replayed
+    Missing { expected: StateChange },
+    /// Replayed but not recorded
+    Unexpected { actual: StateChange },
+    /// Same key, different change
+    Changed { expected: StateChange, actual: StateChange },
+}
+
+/// Compare two lists of state changes, pairing changes by key in order
+pub fn diff_state_changes(expected: &[StateChange], actual: &[StateChange]) -> Vec<StateChangeDiff> {
+    let mut unmatched: Vec<Option<&StateChange>> = actual.iter().map(Some).collect();
+    let mut differences = Vec::new();
+
+    for change in expected {
+        let paired = unmatched
+            .iter_mut()
+            .find(|candidate| candidate.is_some_and(|candidate| candidate.key == change.key))
+            .and_then(Option::take);
+        match paired {
+            Some(actual) if actual == change => {}
+            Some(actual) => differences.push(StateChangeDiff::Changed {
+                expected: change.clone(),
+                actual: actual.clone(),
+            }),
+            None => differences.push(StateChangeDiff::Missing {
+                expected: change.clone(),
+            }),
+        }
+    }
+
+    differences.extend(
+        unmatched
+            .into_iter()
+            .flatten()
+            .map(|actual| StateChangeDiff::Unexpected { actual: actual.clone() }),
+    );
+    differences
+}
+
+fn collect_accesses(frame: &CallFrame, accesses: &mut Vec<StorageAccess>) {
+    for step in &frame.steps {
+        let (key, write) = match step {
+            FrameStep::Read(key) => (key, false),
+            FrameStep::Write(key) => (key, true),
+            FrameStep::Call(callee) => {
+                collect_accesses(callee, accesses);
+                continue;
+            }
+        };
+        accesses.push(StorageAccess {
+            contract: frame.contract.clone(),
+            function: frame.function.clone(),
+            key: key.clone(),
+            write,
+        });
+    }
+}
+
+fn invalid_trace(path: &Path, error: impl std::fmt::Display) -> RuntimeError {
+    RuntimeError::InvalidInput(format!("Execution trace {}: {}", path.display(), error))
+}
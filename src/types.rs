@@ -1,15 +1,18 @@
 //! Core types for blockchain runtime
 
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
 
 use crate::constants::*;
 
 /// Network mode for runtime
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum NetworkMode {
+    #[serde(alias = "local")]
     Local,
+    #[serde(alias = "testnet")]
     Testnet,
+    #[serde(alias = "mainnet_fork")]
     MainnetFork,
 }
 
@@ -20,6 +23,8 @@ pub enum RuntimeType {
     LocalProcess,
     CloudInstance,
     InMemory,
+    /// Node reached over the network that the runtime does not manage
+    Remote,
 }
 
 /// Environment state
@@ -28,10 +33,32 @@ pub enum EnvironmentState {
     Creating,
     Ready,
     Running,
+    /// Kept alive but not accepting operations
+    Paused,
     Stopped,
     Error,
 }
 
+impl EnvironmentState {
+    /// Whether an environment may move from this state to another
+    ///
+    /// Any state except `Stopped` can fail into `Error`; stopped and failed
+    /// environments come back through `Creating`.
+    pub fn can_transition_to(&self, next: &EnvironmentState) -> bool {
+        use EnvironmentState::*;
+        matches!(
+            (self, next),
+            (Creating, Ready)
+                | (Ready, Running | Paused | Stopped)
+                | (Running, Ready)
+                | (Paused, Ready | Stopped)
+                | (Stopped | Error, Creating)
+                | (Error, Stopped)
+                | (Creating | Ready | Running | Paused, Error)
+        )
+    }
+}
+
 /// Metric type for runtime monitoring
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum MetricType {
@@ -64,8 +91,8 @@ pub enum SecurityViolationType {
     MemoryLimitExceeded,
 }
 
-/// Security severity level
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+/// Security severity level, ordered from least to most severe
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub enum SecuritySeverity {
     Low,
     Medium,
@@ -91,6 +118,14 @@ pub struct RuntimeCapabilities {
     pub supports_event_monitoring: bool,
     pub supports_gas_estimation: bool,
     pub supports_time_travel: bool,
+    #[serde(default)]
+    pub supports_snapshots: bool,
+    #[serde(default)]
+    pub supports_event_subscriptions: bool,
+    #[serde(default)]
+    pub supports_access_control: bool,
+    #[serde(default)]
+    pub supports_tracing: bool,
     pub max_execution_time_seconds: u64,
 }
 
@@ -103,11 +138,70 @@ impl Default for RuntimeCapabilities {
             supports_event_monitoring: true,
             supports_gas_estimation: false,
             supports_time_travel: false,
+            supports_snapshots: false,
+            supports_event_subscriptions: false,
+            supports_access_control: false,
+            supports_tracing: false,
             max_execution_time_seconds: DEFAULT_MAX_EXECUTION_TIME_SECONDS,
         }
     }
 }
 
+/// Optional feature a runtime may support
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum Capability {
+    ContractDeployment,
+    FunctionCalls,
+    StateInspection,
+    EventMonitoring,
+    GasEstimation,
+    TimeTravel,
+    Snapshots,
+    EventSubscriptions,
+    AccessControl,
+    Tracing,
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Capability::ContractDeployment => "contract deployment",
+            Capability::FunctionCalls => "function calls",
+            Capability::StateInspection => "state inspection",
+            Capability::EventMonitoring => "event monitoring",
+            Capability::GasEstimation => "gas estimation",
+            Capability::TimeTravel => "time travel",
+            Capability::Snapshots => "snapshots",
+            Capability::EventSubscriptions => "event subscriptions",
+            Capability::AccessControl => "access control",
+            Capability::Tracing => "execution tracing",
+        })
+    }
+}
+
+impl RuntimeCapabilities {
+    /// Check whether a capability is supported
+    pub fn supports(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::ContractDeployment => self.supports_contract_deployment,
+            Capability::FunctionCalls => self.supports_function_calls,
+            Capability::StateInspection => self.supports_state_inspection,
+            Capability::EventMonitoring => self.supports_event_monitoring,
+            Capability::GasEstimation => self.supports_gas_estimation,
+            Capability::TimeTravel => self.supports_time_travel,
+            Capability::Snapshots => self.supports_snapshots,
+            Capability::EventSubscriptions => self.supports_event_subscriptions,
+            Capability::AccessControl => self.supports_access_control,
+            Capability::Tracing => self.supports_tracing,
+        }
+    }
+
+    /// Check whether every capability in the list is supported
+    pub fn supports_all(&self, capabilities: &[Capability]) -> bool {
+        capabilities.iter().all(|capability| self.supports(*capability))
+    }
+}
+
 /// Runtime environment instance
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RuntimeEnvironment {
@@ -116,13 +210,68 @@ pub struct RuntimeEnvironment {
     pub runtime_type: RuntimeType,
     pub endpoint_url: String,
     pub state: EnvironmentState,
+    #[serde(serialize_with = "sorted_map")]
     pub metadata: HashMap<String, serde_json::Value>,
 }
 
+/// Output stream of a node a runtime manages
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+/// Line a managed node wrote to its output
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LogLine {
+    pub stream: LogStream,
+    pub line: String,
+}
+
+/// Number and timestamp of the block an environment is building on
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BlockInfo {
+    pub number: u64,
+    pub timestamp: u64,
+}
+
+/// Gas used by a single call frame
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FrameGasUsage {
+    /// Frame name, such as `0x…::0xa9059cbb`
+    pub frame: String,
+    /// Call depth, the top-level frame being depth 1
+    pub depth: u32,
+    /// Gas used by the frame including its sub-calls
+    pub gas_used: u64,
+    /// Gas used by the frame's own instructions
+    pub self_gas_used: u64,
+    pub success: bool,
+}
+
+/// Result of a gas estimation dry run
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GasEstimate {
+    /// Gas used when running with the full gas limit
+    pub gas_used: u64,
+    /// Lowest gas limit the execution succeeds with, or the limit tried when it fails
+    pub gas_limit: u64,
+    pub success: bool,
+    pub error: Option<String>,
+    /// Per-frame breakdown, in the order the frames were entered
+    pub frames: Vec<FrameGasUsage>,
+}
+
+/// Identifier of an environment snapshot
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SnapshotId(pub u64);
+
 /// Execution inputs
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExecutionInputs {
     pub target_function: String,
+    #[serde(serialize_with = "sorted_map")]
     pub parameters: HashMap<String, serde_json::Value>,
     pub context: ExecutionContext,
 }
@@ -133,11 +282,12 @@ pub struct ExecutionContext {
     pub sender: Option<String>,
     pub block_number: Option<u64>,
     pub timestamp: Option<u64>,
+    #[serde(serialize_with = "sorted_map")]
     pub extra: HashMap<String, serde_json::Value>,
 }
 
 /// State change during execution
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct StateChange {
     pub key: String,
     pub old_value: Option<serde_json::Value>,
@@ -151,6 +301,7 @@ pub struct RuntimeEvent {
     pub event_id: String,
     pub event_type: String,
     pub timestamp: u64,
+    #[serde(serialize_with = "sorted_map")]
     pub data: HashMap<String, serde_json::Value>,
 }
 
@@ -171,11 +322,30 @@ pub struct SecurityViolation {
     pub description: String,
     pub severity: SecuritySeverity,
     pub timestamp: u64,
+    #[serde(serialize_with = "sorted_map")]
     pub context: HashMap<String, serde_json::Value>,
 }
 
+impl SecurityViolation {
+    /// Create a violation at `timestamp`, in seconds since the Unix epoch, with no context
+    pub fn new(
+        violation_type: SecurityViolationType,
+        description: String,
+        severity: SecuritySeverity,
+        timestamp: u64,
+    ) -> Self {
+        Self {
+            violation_type,
+            description,
+            severity,
+            timestamp,
+            context: HashMap::new(),
+        }
+    }
+}
+
 /// Execution context with security tracking
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SecureExecutionContext {
     pub call_depth: u32,
     pub external_call_count: u32,
@@ -186,20 +356,6 @@ pub struct SecureExecutionContext {
     pub security_violations: Vec<SecurityViolation>,
 }
 
-impl Default for SecureExecutionContext {
-    fn default() -> Self {
-        Self {
-            call_depth: 0,
-            external_call_count: 0,
-            gas_used: 0,
-            memory_used: 0,
-            call_stack: Vec::new(),
-            access_control_checks: Vec::new(),
-            security_violations: Vec::new(),
-        }
-    }
-}
-
 /// Execution result with security information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExecutionResult {
@@ -207,6 +363,7 @@ pub struct ExecutionResult {
     pub success: bool,
     pub return_value: Option<serde_json::Value>,
     pub error: Option<String>,
+    #[serde(serialize_with = "sorted_map")]
     pub metrics: HashMap<String, serde_json::Value>,
     pub state_changes: Vec<StateChange>,
     pub events: Vec<RuntimeEvent>,
@@ -249,12 +406,16 @@ impl ExecutionResult {
         self.security_violations
             .iter()
             .map(|v| &v.severity)
-            .max_by(|a, b| {
-                let severity_order = [SecuritySeverity::Low, SecuritySeverity::Medium, SecuritySeverity::High, SecuritySeverity::Critical];
-                let a_order = severity_order.iter().position(|x| x == *a).unwrap_or(0);
-                let b_order = severity_order.iter().position(|x| x == *b).unwrap_or(0);
-                a_order.cmp(&b_order)
-            })
+            .max()
             .cloned()
     }
 }
+
+/// Serialize a map with its keys in order, so output does not depend on hashing
+pub(crate) fn sorted_map<S, V>(map: &HashMap<String, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    V: Serialize,
+{
+    serializer.collect_map(map.iter().collect::<BTreeMap<_, _>>())
+}